@@ -1,12 +1,14 @@
 //! Creates a self-signed RPKI CA certificate and writes it to stdout.
 
+use std::io::Write;
 use chrono::{Duration, Utc};
 use rand::rngs::OsRng;
 use rsa::{PaddingScheme, RsaPrivateKey, RsaPublicKey};
 use rsa::pkcs1::ToRsaPublicKey;
 use sha1::{Digest, Sha1};
 use sha2::Sha256;
-use testnetproto::recipe::{core, der};
+use prototest::recipe::{core, der, Recipe};
+use prototest::recipe::core::literal;
 
 fn main() {
     // Step 1.  Generate a key pair.
@@ -15,215 +17,215 @@ fn main() {
         &mut rng, 2048
     ).expect("failed to generate a key");
     let public_key = RsaPublicKey::from(&private_key);
-    let key_id = Sha1::digest(public_key.to_pkcs1_der().unwrap().as_der());
-    let key_id = key_id.as_slice();
+    let public_key = Vec::from(public_key.to_pkcs1_der().unwrap().as_der());
+    let key_id = Vec::from(Sha1::digest(&public_key).as_slice());
 
     // Step 2. Generate a recipe for the certificate content.
-    let tbs = der::sequence([
-        // version
-        der::explicit(0, der::integer(2)),
-
-        // serialNumber
-        der::integer(12),
-
-        // signature
-        der::sequence([
-            der::oid([1, 2, 840, 113549, 1, 1, 11]),
-            der::null(),
-        ]),
-
-        // issuer
-        der::sequence([
-            der::set([
-                der::sequence([
-                    der::oid([2, 5, 4, 3]),
-                    der::printable_string("deadbeef")
-                ]),
-            ])
-        ]),
-
-        // validity
-        der::sequence([
-            der::x509_time(Utc::now()),
-            der::x509_time(Utc::now() + Duration::days(365)),
-        ]),
-
-        // subject
-        der::sequence([
-            der::set([
-                der::sequence([
-                    der::oid([2, 5, 4, 3]),
-                    der::printable_string("deadbeef")
-                ]),
-            ])
-        ]),
-
-        // subjectPublicKeyInfo
-        der::sequence([
-            // algorithm
-            der::sequence([
-                der::oid([1, 2, 840, 113549, 1, 1, 1]),
+    //
+    // Tuples only implement recipe up to a certain size, so longer
+    // sequences are split into nested tuples.
+    let tbs = der::sequence((
+        (
+            // version
+            der::context(0, der::constructed(der::integer(2u8))),
+
+            // serialNumber
+            der::integer(12u8),
+
+            // signature
+            der::sequence((
+                der::oid([1, 2, 840, 113549, 1, 1, 11]),
                 der::null(),
-            ]),
-
-            // subjectPublicKey
-            der::bitstring(
-                0,
-                Vec::from(public_key.to_pkcs1_der().unwrap().as_der()).into()
-            )
-        ]),
-
-        // extensions
-        der::explicit(3, der::sequence([
-            // BasicConstraints
-            der::sequence([
-                der::oid([2, 5, 29, 19]),
-                der::boolean(true),
-                der::octetstring(
-                    der::sequence([
-                        // cA
-                        der::boolean(true),
-                    ])
+            )),
+
+            // issuer
+            der::sequence(
+                der::set(
+                    der::sequence((
+                        der::oid([2, 5, 4, 3]),
+                        der::printable_string(literal("deadbeef"))
+                    ))
                 )
-            ]),
-
-            // SubjectKeyIdentifier
-            der::sequence([
-                der::oid([2, 5, 29, 14]),
-                der::octetstring(
-                    der::octetstring(
-                        Vec::from(key_id.as_ref()).into()
-                    )
+            ),
+        ),
+        (
+            // validity
+            der::sequence((
+                der::utc_time(Utc::now()),
+                der::utc_time(Utc::now() + Duration::days(365)),
+            )),
+
+            // subject
+            der::sequence(
+                der::set(
+                    der::sequence((
+                        der::oid([2, 5, 4, 3]),
+                        der::printable_string(literal("deadbeef"))
+                    ))
                 )
-            ]),
-
-            // AuthorityKeyIdentifier
-            der::sequence([
-                der::oid([2, 5, 29, 35]),
-                der::octetstring(
-                    der::sequence([
-                        der::value(der::context(false, 0),
-                            Vec::from(key_id.as_ref()).into()
+            ),
+
+            // subjectPublicKeyInfo
+            der::sequence((
+                // algorithm
+                der::sequence((
+                    der::oid([1, 2, 840, 113549, 1, 1, 1]),
+                    der::null(),
+                )),
+
+                // subjectPublicKey
+                der::bitstring(0, literal(public_key))
+            )),
+
+            // extensions
+            der::context(3, der::constructed(der::sequence((
+                (
+                    // BasicConstraints
+                    der::sequence((
+                        der::oid([2, 5, 29, 19]),
+                        der::boolean(true),
+                        der::octetstring(
+                            der::sequence(
+                                // cA
+                                der::boolean(true),
+                            )
                         )
-                    ])
-                )
-            ]),
-
-            // KeyUsage
-            der::sequence([
-                der::oid([2, 5, 29, 15]),
-                der::boolean(true),
-                der::octetstring(
-                    der::bitstring(1, [0b0000_0110].into())
-                )
-            ]),
-
-            // SubjectInfoAccess
-            der::sequence([
-                der::oid([1, 3, 6, 1, 5, 5, 7, 1, 11]),
-                der::octetstring(
-                    der::sequence([
-                        // caRepository
-                        der::sequence([
-                            der::oid([1, 3, 6, 1, 5, 5, 7, 48, 5]),
-                            der::value(
-                                der::context(false, 6),
-                                "rsync://rpki.example.com/ta/".into()
+                    )),
+
+                    // SubjectKeyIdentifier
+                    der::sequence((
+                        der::oid([2, 5, 29, 14]),
+                        der::octetstring(
+                            der::octetstring(literal(key_id.clone()))
+                        )
+                    )),
+
+                    // AuthorityKeyIdentifier
+                    der::sequence((
+                        der::oid([2, 5, 29, 35]),
+                        der::octetstring(
+                            der::sequence(
+                                der::context(0,
+                                    der::simple(literal(key_id))
+                                )
                             )
-                        ]),
-
-                        // rpkiManifest
-                        der::sequence([
-                            der::oid([1, 3, 6, 1, 5, 5, 7, 48, 10]),
-                            der::value(
-                                der::context(false, 6),
-                                "rsync://rpki.example.com/ta/ta.mft".into()
+                        )
+                    )),
+
+                    // KeyUsage
+                    der::sequence((
+                        der::oid([2, 5, 29, 15]),
+                        der::boolean(true),
+                        der::octetstring(
+                            der::bitstring(1, literal([0b0000_0110]))
+                        )
+                    )),
+                ),
+                (
+                    // SubjectInfoAccess
+                    der::sequence((
+                        der::oid([1, 3, 6, 1, 5, 5, 7, 1, 11]),
+                        der::octetstring(
+                            der::sequence((
+                                // caRepository
+                                der::sequence((
+                                    der::oid([1, 3, 6, 1, 5, 5, 7, 48, 5]),
+                                    der::context(6, der::simple(
+                                        literal("rsync://rpki.example.com/ta/")
+                                    ))
+                                )),
+
+                                // rpkiManifest
+                                der::sequence((
+                                    der::oid([1, 3, 6, 1, 5, 5, 7, 48, 10]),
+                                    der::context(6, der::simple(
+                                        literal(
+                                            "rsync://rpki.example.com/ta/ta.mft"
+                                        )
+                                    ))
+                                )),
+                            ))
+                        )
+                    )),
+
+                    // CertificatePolicies
+                    der::sequence((
+                        der::oid([2, 5, 29, 32]),
+                        der::boolean(true),
+                        der::octetstring(
+                            der::sequence(
+                                der::sequence(
+                                    der::oid([1, 3, 6, 1, 5, 5, 7, 14, 2])
+                                )
                             )
-                        ]),
-                    ])
-                )
-            ]),
-
-            // CertificatePolicies
-            der::sequence([
-                der::oid([2, 5, 29, 32]),
-                der::boolean(true),
-                der::octetstring(
-                    der::sequence([
-                        der::sequence([
-                            der::oid([1, 3, 6, 1, 5, 5, 7, 14, 2])
-                        ])
-                    ])
-                )
-            ]),
-
-            // IP Resources
-            der::sequence([
-                der::oid([1, 3, 6, 1, 5, 5, 7, 1, 7]),
-                der::boolean(true),
-                der::octetstring(
-                    der::sequence([ // IPAddrBlocks
-                        der::sequence([ // IPAddressFamily
-                            der::octetstring([0, 1].into()),   // v4
-                            der::sequence([
-                                der::bitstring(0, b"".into()),
-                            ]),
-                        ]),
-                        der::sequence([ // IPAddressFamily
-                            der::octetstring([0, 2].into()),   // v6
-                            der::sequence([
-                                der::bitstring(0, b"".into()),
-                            ]),
-                        ])
-                    ]),
-                )
-            ]),
-
-            // AS Resources
-            der::sequence([
-                der::oid([1, 3, 6, 1, 5, 5, 7, 1, 8]),
-                der::boolean(true),
-                der::octetstring(
-                    der::sequence([ // AsIdentifiers
-                        der::explicit(0,
-                            der::sequence([ // asIdsOrRanges
-                                der::sequence([
-                                    der::integer(0),
-                                    der::integer([
-                                        0, 0xFF, 0xFF, 0xFF, 0xFF
-                                    ])
-                                ].into())
-                            ])
-                        ),
-                    ])
-                )
-            ]),
-        ])),
-    ]);
-
-    // Step 3.  Sign the certificate content.
-    let signature = private_key.sign(
-        PaddingScheme::new_pkcs1v15_sign(
-            Some(rsa::hash::Hash::SHA2_256)
-        ),
-        Sha256::digest(&tbs.to_vec()).as_slice(),
-    ).unwrap();
+                        )
+                    )),
 
-    // Step 4. Create a recipe for the full certificate.
-    let cert = der::sequence([
-        tbs,
-        der::sequence([
+                    // IP Resources
+                    der::sequence((
+                        der::oid([1, 3, 6, 1, 5, 5, 7, 1, 7]),
+                        der::boolean(true),
+                        der::octetstring(
+                            der::sequence(( // IPAddrBlocks
+                                der::sequence(( // IPAddressFamily
+                                    der::octetstring(literal([0, 1])), // v4
+                                    der::sequence(
+                                        der::bitstring(0, core::empty()),
+                                    ),
+                                )),
+                                der::sequence(( // IPAddressFamily
+                                    der::octetstring(literal([0, 2])), // v6
+                                    der::sequence(
+                                        der::bitstring(0, core::empty()),
+                                    ),
+                                ))
+                            )),
+                        )
+                    )),
+
+                    // AS Resources
+                    der::sequence((
+                        der::oid([1, 3, 6, 1, 5, 5, 7, 1, 8]),
+                        der::boolean(true),
+                        der::octetstring(
+                            der::sequence( // AsIdentifiers
+                                der::context(0, der::constructed(
+                                    der::sequence( // asIdsOrRanges
+                                        der::sequence((
+                                            der::integer(0u8),
+                                            der::integer_slice([
+                                                0, 0xFF, 0xFF, 0xFF, 0xFF
+                                            ])
+                                        ))
+                                    )
+                                )),
+                            )
+                        )
+                    )),
+                ),
+            )))),
+        ),
+    ));
+
+    // Step 3. Create a recipe for the full certificate.
+    //
+    // The certificate content is assembled once more for the signature.
+    let cert = der::sequence((
+        &tbs,
+        der::sequence((
             der::oid([1, 2, 840, 113549, 1, 1, 11]),
             der::null(),
-        ]),
-        der::bitstring(0, signature.into()),
-    ]);
-
-    // Step 5. Write the certificate to stdout.
-    let stdout = std::io::stdout();
-    core::write_recipe(
-        &cert,
-        &mut stdout.lock(),
-    ).unwrap();
+        )),
+        der::bitstring(0, core::sign(&tbs, |tbs: &[u8]| {
+            private_key.sign(
+                PaddingScheme::new_pkcs1v15_sign(
+                    Some(rsa::hash::Hash::SHA2_256)
+                ),
+                Sha256::digest(tbs).as_slice(),
+            ).unwrap()
+        })),
+    ));
+
+    // Step 4. Write the certificate to stdout.
+    std::io::stdout().lock().write_all(&cert.to_fragment()).unwrap();
 }
-
@@ -0,0 +1,121 @@
+//! Cyclic redundancy checks.
+//!
+//! This module is crate-private and only contains the CRC variants used
+//! elsewhere in the crate.
+
+
+//------------ Crc32 ---------------------------------------------------------
+
+/// The CRC-32 used by IEEE 802.3, zlib, PNG, and many others.
+///
+/// This is the reflected CRC with the polynomial 0x04C11DB7, an initial
+/// value of all ones and a final XOR with all ones.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Crc32(u32);
+
+impl Crc32 {
+    /// The reflected polynomial.
+    const POLY: u32 = 0xEDB8_8320;
+
+    /// Creates a new CRC calculation.
+    pub fn new() -> Self {
+        Crc32(0xFFFF_FFFF)
+    }
+
+    /// Adds data to the calculation.
+    pub fn update(&mut self, data: &[u8]) {
+        for &octet in data {
+            self.0 ^= u32::from(octet);
+            for _ in 0..8 {
+                self.0 = if self.0 & 1 == 1 {
+                    (self.0 >> 1) ^ Self::POLY
+                }
+                else {
+                    self.0 >> 1
+                }
+            }
+        }
+    }
+
+    /// Returns the CRC of all the data added so far.
+    pub fn value(&self) -> u32 {
+        !self.0
+    }
+
+    /// Returns the CRC of the given data.
+    pub fn checksum(data: &[u8]) -> u32 {
+        let mut res = Self::new();
+        res.update(data);
+        res.value()
+    }
+}
+
+
+//------------ Crc16Modbus ---------------------------------------------------
+
+/// The CRC-16 used by Modbus RTU.
+///
+/// This is the reflected CRC with the polynomial 0x8005 and an initial
+/// value of all ones.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Crc16Modbus(u16);
+
+impl Crc16Modbus {
+    /// The reflected polynomial.
+    const POLY: u16 = 0xA001;
+
+    /// Creates a new CRC calculation.
+    pub fn new() -> Self {
+        Crc16Modbus(0xFFFF)
+    }
+
+    /// Adds data to the calculation.
+    pub fn update(&mut self, data: &[u8]) {
+        for &octet in data {
+            self.0 ^= u16::from(octet);
+            for _ in 0..8 {
+                self.0 = if self.0 & 1 == 1 {
+                    (self.0 >> 1) ^ Self::POLY
+                }
+                else {
+                    self.0 >> 1
+                }
+            }
+        }
+    }
+
+    /// Returns the CRC of all the data added so far.
+    pub fn value(&self) -> u16 {
+        self.0
+    }
+
+    /// Returns the CRC of the given data.
+    pub fn checksum(data: &[u8]) -> u16 {
+        let mut res = Self::new();
+        res.update(data);
+        res.value()
+    }
+}
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn crc32() {
+        assert_eq!(Crc32::checksum(b""), 0);
+        assert_eq!(Crc32::checksum(b"123456789"), 0xCBF4_3926);
+        let mut crc = Crc32::new();
+        crc.update(b"1234");
+        crc.update(b"56789");
+        assert_eq!(crc.value(), 0xCBF4_3926);
+    }
+
+    #[test]
+    fn crc16_modbus() {
+        assert_eq!(Crc16Modbus::checksum(b"123456789"), 0x4B37);
+    }
+}
@@ -4,7 +4,13 @@
 //!
 //! * [recipe]: allow programmatically generating binary payload data,
 //! * [stream]: provides stream socket stand-ins that send and receive data
-//!   according to some prescriped rules.
+//!   according to some prescriped rules,
+//! * [tls13]: helps with scripting encrypted TLS 1.3 conversations (requires
+//!   the `tls13` feature).
 //!
 pub mod recipe;
 pub mod stream;
+#[cfg(feature = "tls13")]
+pub mod tls13;
+
+mod crc;
@@ -101,7 +101,7 @@ impl<C: DerContent> Value<C> {
             // subsequent octets with bit 8 forced to 1.
             let usize_octets = (usize::BITS >> 3) as usize;
             let octets = (
-                ((usize::BITS - length.leading_zeros()) >> 3) + 1
+                (usize::BITS - length.leading_zeros()).div_ceil(8)
             ) as usize;
             target.push((octets as u8) | 0x80);
             target.extend_from_slice(
@@ -492,7 +492,7 @@ impl Value {
             // subsequent octets with bit 8 forced to 1.
             let usize_octets = (usize::BITS >> 3) as usize;
             let octets = (
-                ((usize::BITS - length.leading_zeros()) >> 3) + 1
+                (usize::BITS - length.leading_zeros()).div_ceil(8)
             ) as usize;
             target.push((octets as u8) | 0x80);
             target.extend_from_slice(
@@ -0,0 +1,170 @@
+//! Boundary values for integer fields.
+//!
+//! Parsers tend to break at the edges of the range of an integer field:
+//! zero, the largest and smallest values, and the values where the sign
+//! bit flips. The function [`int_boundaries`] produces a [`RecipeSet`]
+//! with these values for a field of a given width and signedness that can
+//! be fed one by one into the override hook of a builder via
+//! [`OverrideField::override_field`][super::core::OverrideField].
+
+use super::core::{Endian, Recipe, Width, literal, signed_varint, varint};
+use super::set::RecipeSet;
+
+
+//------------ IntEncoding ---------------------------------------------------
+
+/// The encoding of an integer field.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum IntEncoding {
+    /// A field using all octets of its width in the given byte order.
+    Fixed(Endian),
+
+    /// A field using LEB128 encoding.
+    ///
+    /// Signed fields use the two’s complement encoding of
+    /// [`signed_varint`].
+    Varint,
+}
+
+
+//------------ int_boundaries ------------------------------------------------
+
+/// Returns the boundary values of an integer field.
+///
+/// The field has the given _width_ and _encoding._ If _signed_ is true,
+/// it holds a two’s complement integer. The set doesn’t have a baseline.
+/// It contains the variants `zero`, `one`, `max`, and `max-minus-one`.
+/// Signed fields also get `min`, `min-plus-one`, and `minus-one`, while
+/// unsigned fields get `sign-bit` with only the top bit set and
+/// `below-sign-bit` with all other bits set, i.e., the values that flip
+/// the sign if the field is mistakenly read as signed.
+///
+/// Fields using LEB128 encoding additionally get `zero-non-minimal` and
+/// `max-non-minimal` with one redundant octet. If the field is narrower
+/// than 64 bits, they also get `max-plus-one` and, if signed,
+/// `min-minus-one`, which the encoding can express but the field can’t
+/// hold.
+pub fn int_boundaries(
+    width: Width, signed: bool, encoding: IntEncoding
+) -> RecipeSet {
+    let bits = width.octets() as u32 * 8;
+    let (min, max) = if signed {
+        (-(1i128 << (bits - 1)), (1i128 << (bits - 1)) - 1)
+    }
+    else {
+        (0, i128::from(width.max()))
+    };
+    let mut values = vec![
+        ("zero", 0), ("one", 1), ("max", max), ("max-minus-one", max - 1),
+    ];
+    if signed {
+        values.extend_from_slice(&[
+            ("min", min), ("min-plus-one", min + 1), ("minus-one", -1),
+        ]);
+    }
+    else {
+        values.extend_from_slice(&[
+            ("sign-bit", 1 << (bits - 1)),
+            ("below-sign-bit", (1 << (bits - 1)) - 1),
+        ]);
+    }
+
+    let mut res = RecipeSet::new();
+    match encoding {
+        IntEncoding::Fixed(endian) => {
+            for (name, value) in values {
+                let mut data = (value as u128).to_be_bytes()[
+                    16 - width.octets()..
+                ].to_vec();
+                if endian == Endian::Little {
+                    data.reverse()
+                }
+                res.insert(name, literal(data));
+            }
+        }
+        IntEncoding::Varint => {
+            if bits < 64 {
+                values.push(("max-plus-one", max + 1));
+                if signed {
+                    values.push(("min-minus-one", min - 1));
+                }
+            }
+            for (name, value) in values {
+                res.insert(name, leb128(value, signed, 0));
+            }
+            res.insert("zero-non-minimal", leb128(0, signed, 1));
+            res.insert("max-non-minimal", leb128(max, signed, 1));
+        }
+    }
+    res
+}
+
+/// Returns a LEB128 recipe for a value that fits into 64 bits.
+fn leb128(value: i128, signed: bool, extra: usize) -> impl Recipe {
+    if signed {
+        signed_varint(value as i64).non_minimal(extra)
+    }
+    else {
+        varint(value as u64).non_minimal(extra)
+    }
+}
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::recipe::core::{Overrides, Fragment, literal};
+
+    fn get(set: &RecipeSet, name: &str) -> Fragment {
+        set.get(name).unwrap().to_fragment()
+    }
+
+    #[test]
+    fn fixed() {
+        let set = int_boundaries(
+            Width::U16, false, IntEncoding::Fixed(Endian::Big)
+        );
+        assert_eq!(set.len(), 6);
+        assert_eq!(get(&set, "max-minus-one"), b"\xff\xfe");
+        assert_eq!(get(&set, "sign-bit"), b"\x80\x00");
+        assert_eq!(get(&set, "below-sign-bit"), b"\x7f\xff");
+
+        let set = int_boundaries(
+            Width::U32, true, IntEncoding::Fixed(Endian::Little)
+        );
+        assert_eq!(set.len(), 7);
+        assert_eq!(get(&set, "one"), b"\x01\0\0\0");
+        assert_eq!(get(&set, "min"), b"\0\0\0\x80");
+        assert_eq!(get(&set, "minus-one"), b"\xff\xff\xff\xff");
+
+        let set = int_boundaries(
+            Width::U64, false, IntEncoding::Fixed(Endian::Big)
+        );
+        assert_eq!(get(&set, "max"), [0xFF; 8]);
+
+        let mut overrides = Overrides::new();
+        overrides.insert("field", literal(get(&set, "sign-bit")));
+        assert_eq!(
+            overrides.get("field").unwrap().to_fragment(),
+            b"\x80\0\0\0\0\0\0\0"
+        );
+    }
+
+    #[test]
+    fn varints() {
+        let set = int_boundaries(Width::U8, true, IntEncoding::Varint);
+        assert_eq!(set.len(), 11);
+        assert_eq!(get(&set, "max"), b"\xff\x00");
+        assert_eq!(get(&set, "min"), b"\x80\x7f");
+        assert_eq!(get(&set, "max-plus-one"), b"\x80\x01");
+        assert_eq!(get(&set, "zero-non-minimal"), b"\x80\x00");
+
+        let set = int_boundaries(Width::U64, false, IntEncoding::Varint);
+        assert_eq!(set.len(), 8);
+        assert_eq!(
+            get(&set, "max"), b"\xff\xff\xff\xff\xff\xff\xff\xff\xff\x01"
+        );
+    }
+}
@@ -0,0 +1,389 @@
+//! Checksums over the data of a recipe.
+//!
+//! The recipes in this module assemble an inner recipe, calculate a
+//! checksum over its data, and add the checksum before or after the data.
+//!
+//! The [`crc32`] recipe covers the most common case. For negative tests,
+//! an offset can be added to its checksum so that it is wrong by a known
+//! amount. Any other algorithm can be used with [`checksummed`] by
+//! implementing the [`Checksum`] trait for it. This module provides
+//! implementations for the IEEE CRC-32 and, behind the features of the
+//! same name, for two CRC-16 variants (`crc16`), CRC-32C (`crc32c`), and
+//! Adler-32 (`adler32`).
+
+use crate::crc;
+use super::core::{Endian, Fragment, Recipe};
+
+
+//------------ Checksum ------------------------------------------------------
+
+/// A checksum algorithm.
+///
+/// A value of the type holds the state of a calculation. The recipe
+/// [`checksummed`] clones the value it was given for every calculation,
+/// so that value should be in the initial state.
+pub trait Checksum: Clone {
+    /// The octets of a finished checksum.
+    type Output: AsRef<[u8]>;
+
+    /// Adds data to the calculation.
+    fn update(&mut self, data: &[u8]);
+
+    /// Finishes the calculation and returns the checksum.
+    ///
+    /// The checksum is returned in the encoding used on the wire.
+    fn finalize(self) -> Self::Output;
+}
+
+
+//------------ checksummed ---------------------------------------------------
+
+/// Returns a recipe adding a checksum to the data of another recipe.
+///
+/// The checksum over the data of _inner_ is calculated with _algo_ and
+/// appended to the data. Use [`Checksummed::prepend`] to place it before
+/// the data instead.
+pub fn checksummed<C: Checksum, R: Recipe>(
+    algo: C, inner: R
+) -> Checksummed<C, R> {
+    Checksummed { algo, inner, prepend: false }
+}
+
+pub struct Checksummed<C, R> {
+    algo: C,
+    inner: R,
+    prepend: bool,
+}
+
+impl<C, R> Checksummed<C, R> {
+    /// Places the checksum before the data rather than after it.
+    pub fn prepend(mut self) -> Self {
+        self.prepend = true;
+        self
+    }
+}
+
+impl<C: Checksum, R: Recipe> Recipe for Checksummed<C, R> {
+    fn assemble(&self, target: &mut Fragment) {
+        let mark = target.mark();
+        self.inner.assemble(target);
+        let mut algo = self.algo.clone();
+        algo.update(&target[mark.start()..]);
+        let value = algo.finalize();
+        let end = target.len();
+        target.extend_from_slice(value.as_ref());
+        if self.prepend {
+            target.move_before(&mark, end);
+        }
+    }
+}
+
+
+//------------ crc32 ---------------------------------------------------------
+
+/// Returns a recipe adding a CRC-32 to the data of another recipe.
+///
+/// The checksum is the CRC-32 used by IEEE 802.3, zlib, and PNG. By
+/// default, it is appended to the data of _inner_ in big-endian byte
+/// order. This can be changed via [`Crc32::prepend`] and
+/// [`Crc32::endian`]. Note that the Ethernet frame check sequence is
+/// written in little-endian byte order. If the fragment has flipped byte
+/// order enabled, the opposite byte order is used.
+pub fn crc32<R: Recipe>(inner: R) -> Crc32<R> {
+    Crc32 { inner, prepend: false, endian: Endian::Big, offset: 0 }
+}
+
+pub struct Crc32<R> {
+    inner: R,
+    prepend: bool,
+    endian: Endian,
+    offset: u32,
+}
+
+impl<R> Crc32<R> {
+    /// Places the checksum before the data rather than after it.
+    pub fn prepend(mut self) -> Self {
+        self.prepend = true;
+        self
+    }
+
+    /// Sets the byte order of the checksum.
+    pub fn endian(mut self, endian: Endian) -> Self {
+        self.endian = endian;
+        self
+    }
+
+    /// Adds _offset_ to the checksum, wrapping around on overflow.
+    ///
+    /// Any offset other than zero results in a wrong checksum.
+    pub fn offset(mut self, offset: u32) -> Self {
+        self.offset = offset;
+        self
+    }
+}
+
+impl<R: Recipe> Recipe for Crc32<R> {
+    fn assemble(&self, target: &mut Fragment) {
+        let start = target.len();
+        if self.prepend {
+            target.resize(start + 4, 0);
+        }
+        self.inner.assemble(target);
+        if target.is_exceeded() {
+            return
+        }
+        let data_start = if self.prepend { start + 4 } else { start };
+        let value = crc::Crc32::checksum(&target[data_start..])
+            .wrapping_add(self.offset);
+        let value = if (self.endian == Endian::Little)
+            != target.is_endian_flipped()
+        {
+            value.to_le_bytes()
+        }
+        else {
+            value.to_be_bytes()
+        };
+        if self.prepend {
+            target[start..start + 4].copy_from_slice(&value)
+        }
+        else {
+            target.extend_from_slice(&value)
+        }
+    }
+}
+
+
+//------------ Crc32Ieee -----------------------------------------------------
+
+/// The CRC-32 used by IEEE 802.3, zlib, and PNG in big-endian order.
+#[derive(Clone, Copy, Debug)]
+pub struct Crc32Ieee(crc::Crc32);
+
+impl Default for Crc32Ieee {
+    fn default() -> Self {
+        Crc32Ieee(crc::Crc32::new())
+    }
+}
+
+impl Checksum for Crc32Ieee {
+    type Output = [u8; 4];
+
+    fn update(&mut self, data: &[u8]) {
+        self.0.update(data)
+    }
+
+    fn finalize(self) -> Self::Output {
+        self.0.value().to_be_bytes()
+    }
+}
+
+
+//------------ Crc16Modbus ---------------------------------------------------
+
+/// The CRC-16 used by Modbus RTU.
+///
+/// As in Modbus, the checksum is in little-endian order.
+#[cfg(feature = "crc16")]
+#[derive(Clone, Copy, Debug)]
+pub struct Crc16Modbus(crc::Crc16Modbus);
+
+#[cfg(feature = "crc16")]
+impl Default for Crc16Modbus {
+    fn default() -> Self {
+        Crc16Modbus(crc::Crc16Modbus::new())
+    }
+}
+
+#[cfg(feature = "crc16")]
+impl Checksum for Crc16Modbus {
+    type Output = [u8; 2];
+
+    fn update(&mut self, data: &[u8]) {
+        self.0.update(data)
+    }
+
+    fn finalize(self) -> Self::Output {
+        self.0.value().to_le_bytes()
+    }
+}
+
+
+//------------ Crc16CcittFalse -----------------------------------------------
+
+/// The CRC-16 with the CCITT polynomial and an initial value of all ones.
+///
+/// This is the non-reflected CRC with the polynomial 0x1021 and no final
+/// XOR. The checksum is in big-endian order.
+#[cfg(feature = "crc16")]
+#[derive(Clone, Copy, Debug)]
+pub struct Crc16CcittFalse(u16);
+
+#[cfg(feature = "crc16")]
+impl Default for Crc16CcittFalse {
+    fn default() -> Self {
+        Crc16CcittFalse(0xFFFF)
+    }
+}
+
+#[cfg(feature = "crc16")]
+impl Checksum for Crc16CcittFalse {
+    type Output = [u8; 2];
+
+    fn update(&mut self, data: &[u8]) {
+        for &octet in data {
+            self.0 ^= u16::from(octet) << 8;
+            for _ in 0..8 {
+                self.0 = if self.0 & 0x8000 != 0 {
+                    (self.0 << 1) ^ 0x1021
+                }
+                else {
+                    self.0 << 1
+                }
+            }
+        }
+    }
+
+    fn finalize(self) -> Self::Output {
+        self.0.to_be_bytes()
+    }
+}
+
+
+//------------ Crc32c --------------------------------------------------------
+
+/// The CRC-32C with the Castagnoli polynomial used by iSCSI and SCTP.
+///
+/// The checksum is in big-endian order. Note that SCTP writes it in
+/// little-endian order.
+#[cfg(feature = "crc32c")]
+#[derive(Clone, Copy, Debug)]
+pub struct Crc32c(u32);
+
+#[cfg(feature = "crc32c")]
+impl Default for Crc32c {
+    fn default() -> Self {
+        Crc32c(0xFFFF_FFFF)
+    }
+}
+
+#[cfg(feature = "crc32c")]
+impl Checksum for Crc32c {
+    type Output = [u8; 4];
+
+    fn update(&mut self, data: &[u8]) {
+        for &octet in data {
+            self.0 ^= u32::from(octet);
+            for _ in 0..8 {
+                self.0 = if self.0 & 1 == 1 {
+                    (self.0 >> 1) ^ 0x82F6_3B78
+                }
+                else {
+                    self.0 >> 1
+                }
+            }
+        }
+    }
+
+    fn finalize(self) -> Self::Output {
+        (!self.0).to_be_bytes()
+    }
+}
+
+
+//------------ Adler32 -------------------------------------------------------
+
+/// The Adler-32 checksum used by zlib in big-endian order.
+#[cfg(feature = "adler32")]
+#[derive(Clone, Copy, Debug)]
+pub struct Adler32 {
+    a: u32,
+    b: u32,
+}
+
+#[cfg(feature = "adler32")]
+impl Default for Adler32 {
+    fn default() -> Self {
+        Adler32 { a: 1, b: 0 }
+    }
+}
+
+#[cfg(feature = "adler32")]
+impl Checksum for Adler32 {
+    type Output = [u8; 4];
+
+    fn update(&mut self, data: &[u8]) {
+        for &octet in data {
+            self.a = (self.a + u32::from(octet)) % 65521;
+            self.b = (self.b + self.a) % 65521;
+        }
+    }
+
+    fn finalize(self) -> Self::Output {
+        ((self.b << 16) | self.a).to_be_bytes()
+    }
+}
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::recipe::core::{flip_endian, literal};
+
+    #[test]
+    fn crc32_placement() {
+        assert_eq!(
+            crc32(literal(b"123456789")).to_fragment(),
+            b"123456789\xcb\xf4\x39\x26"
+        );
+        assert_eq!(
+            (literal(b"x"), crc32(literal(b"123456789")).prepend())
+                .to_fragment(),
+            b"x\xcb\xf4\x39\x26123456789"
+        );
+        assert_eq!(
+            crc32(literal(b"123456789")).endian(Endian::Little)
+                .to_fragment(),
+            b"123456789\x26\x39\xf4\xcb"
+        );
+        assert_eq!(
+            flip_endian(crc32(literal(b"123456789"))).to_fragment(),
+            b"123456789\x26\x39\xf4\xcb"
+        );
+        assert_eq!(
+            crc32(literal(b"123456789")).offset(1).to_fragment(),
+            b"123456789\xcb\xf4\x39\x27"
+        );
+    }
+
+    #[test]
+    fn algorithms() {
+        fn check<C: Checksum + Default>(expected: &[u8]) {
+            assert_eq!(
+                checksummed(C::default(), literal(b"123456789"))
+                    .to_fragment().as_slice()[9..],
+                *expected
+            );
+        }
+
+        check::<Crc32Ieee>(b"\xcb\xf4\x39\x26");
+        #[cfg(feature = "crc16")]
+        check::<Crc16Modbus>(b"\x37\x4b");
+        #[cfg(feature = "crc16")]
+        check::<Crc16CcittFalse>(b"\x29\xb1");
+        #[cfg(feature = "crc32c")]
+        check::<Crc32c>(b"\xe3\x06\x92\x83");
+        #[cfg(feature = "adler32")]
+        check::<Adler32>(b"\x09\x1e\x01\xde");
+
+        assert_eq!(
+            (
+                literal(b"x"),
+                checksummed(Crc32Ieee::default(), literal(b"123456789"))
+                    .prepend()
+            ).to_fragment(),
+            b"x\xcb\xf4\x39\x26123456789"
+        );
+    }
+}
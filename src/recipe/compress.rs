@@ -0,0 +1,227 @@
+//! Compressed data formats.
+//!
+//! The [`compressed`] recipe wraps the data of another recipe in the
+//! deflate format of RFC 1951 or one of its containers, gzip as defined
+//! in RFC 1952 and zlib as defined in RFC 1950.
+//!
+//! With the `flate2` feature, the data is compressed using the [flate2]
+//! crate at its default level. Without it, the data uses stored deflate
+//! blocks. That is, it isn’t actually compressed but the result is valid
+//! data that any decoder accepts and that is slightly longer than the
+//! original. In this case, the zlib format needs the `adler32` feature
+//! for its checksum.
+//!
+//! [flate2]: https://docs.rs/flate2/
+
+#[cfg(feature = "flate2")]
+use std::io::Write;
+#[cfg(not(feature = "flate2"))]
+use crate::crc::Crc32;
+use super::core::{Fragment, Recipe};
+#[cfg(all(feature = "adler32", not(feature = "flate2")))]
+use super::checksum::{Adler32, Checksum};
+
+
+//------------ Codec ---------------------------------------------------------
+
+/// The format of compressed data.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Codec {
+    /// Raw deflate data without any header or trailer.
+    Deflate,
+
+    /// Deflate data in gzip format.
+    ///
+    /// The header has no file name or modification time and an unknown
+    /// operating system.
+    Gzip,
+
+    /// Deflate data in zlib format.
+    ///
+    /// The header announces a 32K window and default compression.
+    ///
+    /// Without the `flate2` feature, encoding data in this format needs
+    /// the `adler32` feature.
+    Zlib,
+}
+
+impl Codec {
+    /// Appends _data_ in this format to _target._
+    ///
+    /// # Panics
+    ///
+    /// The method panics if the format is [`Codec::Zlib`] and neither the
+    /// `flate2` nor the `adler32` feature are enabled.
+    #[cfg(feature = "flate2")]
+    pub fn encode(self, data: &[u8], target: &mut Fragment) {
+        use flate2::Compression;
+        use flate2::write::{DeflateEncoder, GzEncoder, ZlibEncoder};
+
+        let level = Compression::default();
+        let res = match self {
+            Codec::Deflate => {
+                let mut enc = DeflateEncoder::new(Vec::new(), level);
+                enc.write_all(data).and_then(|_| enc.finish())
+            }
+            Codec::Gzip => {
+                let mut enc = GzEncoder::new(Vec::new(), level);
+                enc.write_all(data).and_then(|_| enc.finish())
+            }
+            Codec::Zlib => {
+                let mut enc = ZlibEncoder::new(Vec::new(), level);
+                enc.write_all(data).and_then(|_| enc.finish())
+            }
+        };
+        // Writing to a vec doesn’t fail.
+        target.extend_from_slice(&res.expect("compression failed"))
+    }
+
+    /// Appends _data_ in this format to _target._
+    ///
+    /// # Panics
+    ///
+    /// The method panics if the format is [`Codec::Zlib`] and neither the
+    /// `flate2` nor the `adler32` feature are enabled.
+    #[cfg(not(feature = "flate2"))]
+    pub fn encode(self, data: &[u8], target: &mut Fragment) {
+        match self {
+            Codec::Deflate => deflate_stored(data, target),
+            Codec::Gzip => {
+                target.extend_from_slice(
+                    &[0x1f, 0x8b, 8, 0, 0, 0, 0, 0, 0, 0xff]
+                );
+                deflate_stored(data, target);
+                target.extend_from_slice(&Crc32::checksum(data).to_le_bytes());
+                target.extend_from_slice(&(data.len() as u32).to_le_bytes());
+            }
+            #[cfg(feature = "adler32")]
+            Codec::Zlib => {
+                target.extend_from_slice(&[0x78, 0x9c]);
+                deflate_stored(data, target);
+                let mut adler = Adler32::default();
+                adler.update(data);
+                target.extend_from_slice(&adler.finalize());
+            }
+            #[cfg(not(feature = "adler32"))]
+            Codec::Zlib => {
+                panic!(
+                    "Codec::Zlib needs the flate2 or adler32 feature"
+                )
+            }
+        }
+    }
+}
+
+
+//------------ compressed ----------------------------------------------------
+
+/// Returns a recipe producing the compressed data of another recipe.
+///
+/// The data of _inner_ is encoded in the format given by _codec._
+pub fn compressed<R: Recipe>(codec: Codec, inner: R) -> Compressed<R> {
+    Compressed { codec, inner }
+}
+
+pub struct Compressed<R> {
+    codec: Codec,
+    inner: R,
+}
+
+impl<R: Recipe> Recipe for Compressed<R> {
+    fn assemble(&self, target: &mut Fragment) {
+        let start = target.len();
+        self.inner.assemble(target);
+        let data = target[start..].to_vec();
+        target.truncate(start);
+        self.codec.encode(&data, target)
+    }
+}
+
+
+//------------ Helpers -------------------------------------------------------
+
+/// Appends the data as a sequence of stored deflate blocks.
+#[cfg(not(feature = "flate2"))]
+fn deflate_stored(data: &[u8], target: &mut Fragment) {
+    let mut blocks = data.chunks(0xFFFF).peekable();
+    if blocks.peek().is_none() {
+        target.extend_from_slice(&[1, 0, 0, 0xff, 0xff]);
+    }
+    while let Some(block) = blocks.next() {
+        let len = block.len() as u16;
+        target.push(u8::from(blocks.peek().is_none()));
+        target.extend_from_slice(&len.to_le_bytes());
+        target.extend_from_slice(&(!len).to_le_bytes());
+        target.extend_from_slice(block);
+    }
+}
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::recipe::core::{literal, repeat};
+
+    #[test]
+    #[cfg(not(feature = "flate2"))]
+    fn deflate() {
+        assert_eq!(
+            compressed(Codec::Deflate, literal("")).to_fragment(),
+            b"\x01\0\0\xff\xff"
+        );
+        let data = compressed(
+            Codec::Deflate, repeat(0x10000, literal("a"))
+        ).to_fragment();
+        assert_eq!(data.len(), 0x10000 + 10);
+        assert_eq!(&data[..5], b"\0\xff\xff\0\0");
+        assert_eq!(&data[0x10004..], b"\x01\x01\0\xfe\xffa");
+    }
+
+    #[test]
+    #[cfg(not(feature = "flate2"))]
+    fn gzip() {
+        assert_eq!(
+            compressed(Codec::Gzip, literal("a")).to_fragment(),
+            b"\x1f\x8b\x08\0\0\0\0\0\0\xff\
+              \x01\x01\x00\xfe\xff\x61\
+              \x43\xbe\xb7\xe8\x01\0\0\0"
+        );
+    }
+
+    #[test]
+    #[cfg(all(feature = "adler32", not(feature = "flate2")))]
+    fn zlib() {
+        assert_eq!(
+            compressed(Codec::Zlib, literal("a")).to_fragment(),
+            b"\x78\x9c\x01\x01\x00\xfe\xff\x61\0\x62\0\x62"
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "flate2")]
+    fn flate2() {
+        use std::io::Read;
+        use flate2::read::{DeflateDecoder, GzDecoder, ZlibDecoder};
+
+        fn decode(codec: Codec, data: &[u8]) -> Vec<u8> {
+            let mut res = Vec::new();
+            match codec {
+                Codec::Deflate => {
+                    DeflateDecoder::new(data).read_to_end(&mut res)
+                }
+                Codec::Gzip => GzDecoder::new(data).read_to_end(&mut res),
+                Codec::Zlib => ZlibDecoder::new(data).read_to_end(&mut res),
+            }.unwrap();
+            res
+        }
+
+        let inner = repeat(0x10000, literal("a"));
+        for codec in [Codec::Deflate, Codec::Gzip, Codec::Zlib] {
+            let data = compressed(codec, &inner).to_fragment();
+            assert!(data.len() < 0x1000);
+            assert_eq!(decode(codec, &data), inner.to_fragment().as_slice());
+        }
+    }
+}
@@ -0,0 +1,353 @@
+//! Conformance suites with expected outcomes.
+//!
+//! Knowing that an implementation rejects broken input is often not
+//! enough – it should also reject it for the right reason. A [`Suite`]
+//! keeps a [`RecipeSet`] together with the [`Outcome`] expected for each
+//! of its recipes: either that the data is accepted or that it is
+//! rejected with an error of a certain category. Running the suite with a
+//! closure that classifies how the implementation under test reacted to
+//! each recipe produces a [`Report`] comparing expected and actual
+//! outcomes.
+//!
+//! The categories are plain strings, so they can be chosen to match
+//! whatever error reporting the implementation under test has.
+
+use std::fmt;
+use serde::{Deserialize, Serialize};
+use super::core::{Fragment, Recipe};
+use super::set::RecipeSet;
+
+
+//------------ Outcome -------------------------------------------------------
+
+/// The outcome of processing the data of a recipe.
+#[derive(Clone, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+pub enum Outcome {
+    /// The data was accepted.
+    Accept,
+
+    /// The data was rejected with an error of the given category.
+    Reject(String),
+}
+
+impl Outcome {
+    /// Creates a reject outcome with the given category.
+    pub fn reject(category: impl Into<String>) -> Self {
+        Outcome::Reject(category.into())
+    }
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Outcome::Accept => f.write_str("accept"),
+            Outcome::Reject(ref category) => {
+                write!(f, "reject({})", category)
+            }
+        }
+    }
+}
+
+
+//------------ Suite ---------------------------------------------------------
+
+/// A set of recipes with their expected outcomes.
+#[derive(Debug, Default)]
+pub struct Suite {
+    recipes: RecipeSet,
+    expected: Vec<(String, Outcome)>,
+}
+
+impl Suite {
+    /// Creates a new, empty suite.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a suite from a recipe set.
+    ///
+    /// The closure _expected_ is called with the name of each recipe and
+    /// returns the outcome expected for it.
+    pub fn from_set(
+        recipes: RecipeSet, mut expected: impl FnMut(&str) -> Outcome
+    ) -> Self {
+        let expected = recipes.names().map(|name| {
+            (name.into(), expected(name))
+        }).collect();
+        Suite { recipes, expected }
+    }
+
+    /// Adds a recipe that is expected to be accepted.
+    pub fn accept(
+        self, name: impl Into<String>, recipe: impl Recipe + 'static
+    ) -> Self {
+        self.expect(name, Outcome::Accept, recipe)
+    }
+
+    /// Adds a recipe that is expected to be rejected.
+    pub fn reject(
+        self, name: impl Into<String>, category: impl Into<String>,
+        recipe: impl Recipe + 'static
+    ) -> Self {
+        self.expect(name, Outcome::reject(category), recipe)
+    }
+
+    /// Adds a recipe with the given expected outcome.
+    ///
+    /// As with [`RecipeSet`], adding a recipe under an existing name
+    /// replaces the earlier recipe and its expected outcome.
+    pub fn expect(
+        mut self, name: impl Into<String>, outcome: Outcome,
+        recipe: impl Recipe + 'static
+    ) -> Self {
+        let name = name.into();
+        match self.expected.iter_mut().find(|item| item.0 == name) {
+            Some(item) => item.1 = outcome,
+            None => self.expected.push((name.clone(), outcome)),
+        }
+        self.recipes.insert(name, recipe);
+        self
+    }
+
+    /// Returns the recipes of the suite.
+    pub fn recipes(&self) -> &RecipeSet {
+        &self.recipes
+    }
+
+    /// Returns the outcome expected for the recipe with the given name.
+    pub fn expected(&self, name: &str) -> Option<&Outcome> {
+        self.expected.iter().find(|item| item.0 == name).map(|item| {
+            &item.1
+        })
+    }
+
+    /// Runs the suite.
+    ///
+    /// The closure _classify_ is called with the name and assembled data
+    /// of each recipe and returns how the implementation under test
+    /// reacted to the data.
+    pub fn run(
+        &self, mut classify: impl FnMut(&str, &Fragment) -> Outcome
+    ) -> Report {
+        let mut target = Fragment::new();
+        let rows = self.recipes.iter().zip(&self.expected).map(|item| {
+            let ((name, recipe), (_, expected)) = item;
+            target.clear();
+            recipe.assemble(&mut target);
+            ReportRow {
+                name: name.into(),
+                expected: expected.clone(),
+                actual: classify(name, &target),
+            }
+        }).collect();
+        Report { rows }
+    }
+}
+
+
+//------------ Report --------------------------------------------------------
+
+/// The result of running a conformance suite.
+///
+/// When displayed, the report is a table with a row for each recipe
+/// followed by a summary line.
+#[derive(Clone, Debug)]
+pub struct Report {
+    rows: Vec<ReportRow>,
+}
+
+impl Report {
+    /// Returns the rows of the report.
+    pub fn rows(&self) -> &[ReportRow] {
+        &self.rows
+    }
+
+    /// Returns whether all recipes had the expected outcome.
+    pub fn is_conformant(&self) -> bool {
+        self.rows.iter().all(|row| row.verdict() == Verdict::Pass)
+    }
+
+    /// Returns the number of rows with the given verdict.
+    pub fn count(&self, verdict: Verdict) -> usize {
+        self.rows.iter().filter(|row| row.verdict() == verdict).count()
+    }
+
+    /// Returns the matrix of expected versus actual outcomes.
+    ///
+    /// Each item contains an expected outcome, an actual outcome, and the
+    /// number of recipes with that combination. Items are in the order
+    /// the combination first appears in.
+    pub fn matrix(&self) -> Vec<(&Outcome, &Outcome, usize)> {
+        let mut res: Vec<(&Outcome, &Outcome, usize)> = Vec::new();
+        for row in &self.rows {
+            match res.iter_mut().find(|item| {
+                *item.0 == row.expected && *item.1 == row.actual
+            }) {
+                Some(item) => item.2 += 1,
+                None => res.push((&row.expected, &row.actual, 1)),
+            }
+        }
+        res
+    }
+
+    /// Panics if any recipe did not have the expected outcome.
+    ///
+    /// The panic message contains the complete report.
+    pub fn assert_conformant(&self) {
+        if !self.is_conformant() {
+            panic!("conformance suite failed:\n{}", self)
+        }
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let width = self.rows.iter().map(|row| {
+            row.name.len()
+        }).max().unwrap_or(0);
+        for row in &self.rows {
+            writeln!(
+                f, "{:width$}  {:14}  expected {}, got {}",
+                row.name, row.verdict().to_string(), row.expected,
+                row.actual, width = width
+            )?;
+        }
+        write!(
+            f, "{} passed, {} wrong category, {} false accept, \
+                {} false reject",
+            self.count(Verdict::Pass), self.count(Verdict::WrongCategory),
+            self.count(Verdict::FalseAccept),
+            self.count(Verdict::FalseReject),
+        )
+    }
+}
+
+
+//------------ ReportRow -----------------------------------------------------
+
+/// The result for a single recipe.
+#[derive(Clone, Debug)]
+pub struct ReportRow {
+    /// The name of the recipe.
+    pub name: String,
+
+    /// The expected outcome.
+    pub expected: Outcome,
+
+    /// The actual outcome.
+    pub actual: Outcome,
+}
+
+impl ReportRow {
+    /// Returns the verdict for the row.
+    pub fn verdict(&self) -> Verdict {
+        match (&self.expected, &self.actual) {
+            (Outcome::Accept, Outcome::Accept) => Verdict::Pass,
+            (Outcome::Accept, Outcome::Reject(_)) => Verdict::FalseReject,
+            (Outcome::Reject(_), Outcome::Accept) => Verdict::FalseAccept,
+            (Outcome::Reject(left), Outcome::Reject(right)) => {
+                if left == right { Verdict::Pass }
+                else { Verdict::WrongCategory }
+            }
+        }
+    }
+}
+
+
+//------------ Verdict -------------------------------------------------------
+
+/// How the actual outcome compares to the expected outcome.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Verdict {
+    /// The outcome was as expected.
+    Pass,
+
+    /// The data was rejected as expected but with the wrong category.
+    WrongCategory,
+
+    /// The data was accepted but should have been rejected.
+    FalseAccept,
+
+    /// The data was rejected but should have been accepted.
+    FalseReject,
+}
+
+impl fmt::Display for Verdict {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            Verdict::Pass => "pass",
+            Verdict::WrongCategory => "wrong category",
+            Verdict::FalseAccept => "false accept",
+            Verdict::FalseReject => "false reject",
+        })
+    }
+}
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::recipe::core::{be, literal};
+
+    fn suite() -> Suite {
+        Suite::new()
+            .accept("valid", be(1u16))
+            .reject("short", "length", literal(b"\x01"))
+            .reject("long", "length", be(1u32))
+            .reject("zero", "value", be(0u16))
+    }
+
+    /// A classifier that checks the length but not the value.
+    fn classify(_: &str, data: &Fragment) -> Outcome {
+        if data.len() != 2 {
+            Outcome::reject("length")
+        }
+        else {
+            Outcome::Accept
+        }
+    }
+
+    #[test]
+    fn run() {
+        let report = suite().run(classify);
+        assert!(!report.is_conformant());
+        assert_eq!(report.count(Verdict::Pass), 3);
+        assert_eq!(report.rows()[3].verdict(), Verdict::FalseAccept);
+        assert_eq!(
+            report.matrix(),
+            [
+                (&Outcome::Accept, &Outcome::Accept, 1),
+                (&Outcome::reject("length"), &Outcome::reject("length"), 2),
+                (&Outcome::reject("value"), &Outcome::Accept, 1),
+            ]
+        );
+        assert_eq!(
+            report.to_string(),
+            "valid  pass            expected accept, got accept\n\
+             short  pass            expected reject(length), \
+             got reject(length)\n\
+             long   pass            expected reject(length), \
+             got reject(length)\n\
+             zero   false accept    expected reject(value), got accept\n\
+             3 passed, 0 wrong category, 1 false accept, 0 false reject"
+        );
+    }
+
+    #[test]
+    fn from_set() {
+        let suite = Suite::from_set(
+            RecipeSet::new().baseline(be(1u16)).variant("zero", be(0u16)),
+            |name| {
+                if name == RecipeSet::BASELINE { Outcome::Accept }
+                else { Outcome::reject(name) }
+            }
+        );
+        assert_eq!(suite.expected("zero"), Some(&Outcome::reject("zero")));
+        suite.run(|name, _| {
+            if name == "zero" { Outcome::reject("zero") }
+            else { Outcome::Accept }
+        }).assert_conformant();
+    }
+}
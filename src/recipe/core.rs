@@ -1,12 +1,27 @@
 //! Fundamentals for recipes.
 
-use std::{borrow, io, ops};
+use std::{borrow, cmp, fmt, hash, io, mem, ops};
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use rand::{Rng, RngCore, SeedableRng};
+use rand_chacha::ChaCha8Rng;
 
 
 //------------ Recipe --------------------------------------------------------
 
 
 /// A type that knows how to assemble some data and add it to a fragment.
+///
+/// Besides the recipe types of this crate, the trait is implemented for
+/// references to recipes and boxes, `Rc`s, and `Arc`s of recipes, for
+/// tuples of up to six recipes as well as slices, arrays, and vecs of
+/// recipes which assemble their elements in order, and for an `Option<_>`
+/// of a recipe which assembles nothing if it is `None`. Recipes of
+/// different types can be collected by converting them via
+/// [`Recipe::boxed`]. Finally, strings and octet sequences are recipes
+/// that produce their content just like [`literal`].
 pub trait Recipe {
     /// Assembles the data andd appends it a fragment.
     fn assemble(&self, target: &mut Fragment);
@@ -17,17 +32,180 @@ pub trait Recipe {
         self.assemble(&mut frag);
         frag
     }
+
+    /// Assembles the data into an existing fragment replacing its content.
+    ///
+    /// Because the fragment’s buffer is reused, this avoids allocations
+    /// when assembling many payloads in a loop. The fragment is emptied
+    /// via [`Fragment::clear`], so it keeps its mode, limit, and context.
+    fn reassemble(&self, target: &mut Fragment) {
+        target.clear();
+        self.assemble(target)
+    }
+
+    /// Assembles the data with parameters from a context.
+    ///
+    /// The parameters of _context_ are available to the [`param`] recipes
+    /// assembled as part of this recipe. Any context of _target_ is
+    /// restored afterwards.
+    fn assemble_with(&self, context: &Context, target: &mut Fragment) {
+        let saved = mem::replace(&mut target.context, context.clone());
+        self.assemble(target);
+        target.context = saved;
+    }
+
+    /// Assembles the data with parameters from a context into a new vec.
+    fn to_fragment_with(&self, context: &Context) -> Fragment {
+        let mut frag = Fragment::new();
+        self.assemble_with(context, &mut frag);
+        frag
+    }
+
+    /// Assembles the data in strict mode into a new fragment.
+    ///
+    /// In strict mode, recipes that normally happily produce invalid data
+    /// check their input and report any problems. If there were any, they
+    /// are returned as an error. See [`Fragment::strict`] for details.
+    fn to_strict_fragment(&self) -> Result<Fragment, ValidationError> {
+        let mut frag = Fragment::strict();
+        self.assemble(&mut frag);
+        for item in mem::take(&mut frag.offsets) {
+            item.validate(&mut frag)
+        }
+        match frag.errors.take() {
+            Some(errors) if !errors.is_empty() => {
+                Err(ValidationError { errors })
+            }
+            _ => Ok(frag)
+        }
+    }
+
+    /// Assembles the data into a new fragment and measures its parts.
+    ///
+    /// Parts of the data are marked for measuring by wrapping their recipe
+    /// in [`labelled`]. Next to the fragment, the method returns the
+    /// offset and length of each labelled part, arranged as a tree that
+    /// follows the nesting of the labelled recipes.
+    fn measured(&self) -> (Fragment, Measurement) {
+        let mut frag = Fragment::measuring();
+        self.assemble(&mut frag);
+        let spans = frag.spans.take().unwrap_or_default();
+        let measurement = Measurement::from_spans(&spans);
+        (frag, measurement)
+    }
+
+    /// Assembles the data into a new fragment of at most _max_ octets.
+    ///
+    /// As soon as the data would grow beyond _max_ octets, any further
+    /// data is dropped and an error is returned once assembly has
+    /// finished. This guards against accidentally producing huge amounts
+    /// of data because of a wrong repeat count or length. Recipes that
+    /// assemble part of their data into a temporary fragment use a
+    /// [child][Fragment::child] that is subject to the same limit.
+    fn to_fragment_limited(
+        &self, max: usize
+    ) -> Result<Fragment, LimitError> {
+        let mut frag = Fragment::new();
+        frag.limit = Some(max);
+        self.assemble(&mut frag);
+        if frag.exceeded {
+            Err(LimitError { limit: max })
+        }
+        else {
+            frag.limit = None;
+            Ok(frag)
+        }
+    }
+
+    /// Converts the recipe into a boxed trait object.
+    ///
+    /// Since boxed recipes are recipes, too, this allows collecting
+    /// recipes of different types in a slice, array, or vec.
+    fn boxed(self) -> Box<dyn Recipe>
+    where Self: Sized + 'static {
+        Box::new(self)
+    }
 }
 
-impl<'a, T: Recipe> Recipe for &'a T {
+impl<T: Recipe + ?Sized> Recipe for &T {
     fn assemble(&self, target: &mut Fragment) {
         (*self).assemble(target)
     }
 }
 
-impl<T: Recipe + 'static> From<T> for Box<dyn Recipe> {
-    fn from(src: T) -> Self {
-        Box::new(src)
+impl<T: Recipe + ?Sized> Recipe for Box<T> {
+    fn assemble(&self, target: &mut Fragment) {
+        self.as_ref().assemble(target)
+    }
+}
+
+impl<T: Recipe + ?Sized> Recipe for Rc<T> {
+    fn assemble(&self, target: &mut Fragment) {
+        self.as_ref().assemble(target)
+    }
+}
+
+impl<T: Recipe + ?Sized> Recipe for Arc<T> {
+    fn assemble(&self, target: &mut Fragment) {
+        self.as_ref().assemble(target)
+    }
+}
+
+impl<T: Recipe> Recipe for Option<T> {
+    fn assemble(&self, target: &mut Fragment) {
+        if let Some(inner) = self {
+            inner.assemble(target)
+        }
+    }
+}
+
+impl<T: Recipe> Recipe for [T] {
+    fn assemble(&self, target: &mut Fragment) {
+        for item in self {
+            item.assemble(target)
+        }
+    }
+}
+
+impl<T: Recipe, const N: usize> Recipe for [T; N] {
+    fn assemble(&self, target: &mut Fragment) {
+        self.as_slice().assemble(target)
+    }
+}
+
+impl<T: Recipe> Recipe for Vec<T> {
+    fn assemble(&self, target: &mut Fragment) {
+        self.as_slice().assemble(target)
+    }
+}
+
+impl Recipe for [u8] {
+    fn assemble(&self, target: &mut Fragment) {
+        target.extend_from_slice(self)
+    }
+}
+
+impl<const N: usize> Recipe for [u8; N] {
+    fn assemble(&self, target: &mut Fragment) {
+        target.extend_from_slice(self)
+    }
+}
+
+impl Recipe for Vec<u8> {
+    fn assemble(&self, target: &mut Fragment) {
+        target.extend_from_slice(self)
+    }
+}
+
+impl Recipe for str {
+    fn assemble(&self, target: &mut Fragment) {
+        target.extend_from_slice(self.as_bytes())
+    }
+}
+
+impl Recipe for String {
+    fn assemble(&self, target: &mut Fragment) {
+        target.extend_from_slice(self.as_bytes())
     }
 }
 
@@ -102,12 +280,75 @@ impl<
 }
 
 
+//------------ Presentation --------------------------------------------------
+
+/// A recipe that also has a textual presentation form.
+///
+/// Many protocols define a text format for their data next to the wire
+/// format, such as the master file format for DNS records or the dotted
+/// notation for object identifiers. Types implementing this trait can
+/// produce both from the same value, so a single test vector can feed a
+/// wire format decoder as well as a text parser and the results can be
+/// cross-checked.
+///
+/// Any recipe can be given a presentation form via [`presented`].
+pub trait Presentation: Recipe {
+    /// Appends the presentation form to a string.
+    fn present(&self, target: &mut String);
+
+    /// Returns the presentation form as a new string.
+    fn to_presentation(&self) -> String {
+        let mut res = String::new();
+        self.present(&mut res);
+        res
+    }
+}
+
+impl<T: Presentation> Presentation for &T {
+    fn present(&self, target: &mut String) {
+        (*self).present(target)
+    }
+}
+
+
 //------------ Fragment ------------------------------------------------------
 
 /// A fragment of data produced by executing a recipt.
-#[derive(Clone, Debug, Default, Hash, Ord, PartialOrd)]
+#[derive(Clone, Debug, Default)]
 pub struct Fragment {
     data: Vec<u8>,
+
+    /// The problems found in strict mode or `None` if not in strict mode.
+    errors: Option<Vec<String>>,
+
+    /// Are integers to be written in flipped byte order?
+    flip_endian: bool,
+
+    /// The labelled parts or `None` if the fragment isn’t measuring.
+    spans: Option<Vec<Span>>,
+
+    /// The number of labelled recipes currently being assembled.
+    depth: usize,
+
+    /// The maximum length of the data if limited.
+    limit: Option<usize>,
+
+    /// Has data been dropped because it exceeded the limit?
+    exceeded: bool,
+
+    /// The names and positions of the anchors assembled so far.
+    ///
+    /// The position is `None` if the anchor’s data has been removed.
+    anchors: Vec<(String, Option<usize>)>,
+
+    /// The offset fields referring to anchors.
+    offsets: Vec<OffsetField>,
+
+    /// The identifiers and positions of the reserved placeholders.
+    reserved: Vec<(usize, ops::Range<usize>)>,
+
+    /// The parameters available to recipes.
+    context: Context,
 }
 
 impl Fragment {
@@ -116,19 +357,395 @@ impl Fragment {
         Default::default()
     }
 
+    /// Creates a new, empty fragment with at least the given capacity.
+    pub fn with_capacity(capacity: usize) -> Self {
+        Fragment {
+            data: Vec::with_capacity(capacity),
+            errors: None,
+            flip_endian: false,
+            spans: None,
+            depth: 0,
+            limit: None,
+            exceeded: false,
+            anchors: Vec::new(),
+            offsets: Vec::new(),
+            reserved: Vec::new(),
+            context: Context::new(),
+        }
+    }
+
+    /// Creates a new, empty fragment in strict mode.
+    ///
+    /// Most recipes don’t check their input so that they can be used to
+    /// create broken data. When assembled into a fragment in strict mode,
+    /// recipes that document it check their input after all and record
+    /// any problems via [`validate`][Self::validate]. This allows using the
+    /// same recipes both for generating valid and invalid data.
+    ///
+    /// Normally, you will want to use [`Recipe::to_strict_fragment`]
+    /// instead which returns the recorded problems as an error.
+    pub fn strict() -> Self {
+        Fragment {
+            data: Vec::new(),
+            errors: Some(Vec::new()),
+            flip_endian: false,
+            spans: None,
+            depth: 0,
+            limit: None,
+            exceeded: false,
+            anchors: Vec::new(),
+            offsets: Vec::new(),
+            reserved: Vec::new(),
+            context: Context::new(),
+        }
+    }
+
+    /// Creates a new, empty fragment that measures labelled parts.
+    ///
+    /// When assembled into such a fragment, the recipes created by
+    /// [`labelled`] record where their data starts and ends. Normally,
+    /// you will want to use [`Recipe::measured`] instead which returns
+    /// the result as a [`Measurement`].
+    pub fn measuring() -> Self {
+        Fragment {
+            data: Vec::new(),
+            errors: None,
+            flip_endian: false,
+            spans: Some(Vec::new()),
+            depth: 0,
+            limit: None,
+            exceeded: false,
+            anchors: Vec::new(),
+            offsets: Vec::new(),
+            reserved: Vec::new(),
+            context: Context::new(),
+        }
+    }
+
+    /// Creates a new, empty fragment for assembling part of this one’s data.
+    ///
+    /// Some recipes need to assemble data into a temporary fragment of
+    /// their own before adding it to the fragment in some transformed
+    /// way. The child is in strict mode if this fragment is, uses the
+    /// same byte order and context, and is limited to the space left in
+    /// this fragment. It doesn’t measure labelled parts since their
+    /// positions within temporary data are meaningless.
+    ///
+    /// Normally, you will want to use
+    /// [`assemble_child`][Self::assemble_child] which also takes care of
+    /// passing any problems on to this fragment.
+    pub fn child(&self) -> Self {
+        Fragment {
+            data: Vec::new(),
+            errors: self.errors.as_ref().map(|_| Vec::new()),
+            flip_endian: self.flip_endian,
+            spans: None,
+            depth: 0,
+            limit: self.limit.map(|limit| {
+                limit.saturating_sub(self.data.len())
+            }),
+            exceeded: self.exceeded,
+            anchors: Vec::new(),
+            offsets: Vec::new(),
+            reserved: Vec::new(),
+            context: self.context.clone(),
+        }
+    }
+
+    /// Assembles a recipe into a child fragment and returns it.
+    ///
+    /// The child is created via [`child`][Self::child]. Any problems it
+    /// records in strict mode are recorded in this fragment, too. If it
+    /// exceeds its limit, so has this fragment.
+    pub fn assemble_child(
+        &mut self, recipe: &(impl Recipe + ?Sized)
+    ) -> Self {
+        let mut res = self.child();
+        recipe.assemble(&mut res);
+        self.adopt(&res);
+        res
+    }
+
+    /// Appends the data of another fragment.
+    ///
+    /// Any problems recorded in _other_ in strict mode are recorded in
+    /// this fragment, too.
+    pub fn append(&mut self, other: &Fragment) {
+        self.extend_from_slice(other);
+        self.adopt(other)
+    }
+
+    /// Takes over problems and the exceeded limit from another fragment.
+    fn adopt(&mut self, other: &Fragment) {
+        if let (Some(errors), Some(other)) = (
+            self.errors.as_mut(), other.errors.as_ref()
+        ) {
+            errors.extend_from_slice(other)
+        }
+        self.exceeded |= other.exceeded;
+    }
+
+    /// Returns whether data has been dropped because of the limit.
+    ///
+    /// Recipes that assemble data in a loop should stop once this
+    /// happens. See [`Recipe::to_fragment_limited`] for more.
+    pub fn is_exceeded(&self) -> bool {
+        self.exceeded
+    }
+
+    /// Returns whether the fragment measures labelled parts.
+    pub fn is_measuring(&self) -> bool {
+        self.spans.is_some()
+    }
+
+    /// Returns whether the fragment is in strict mode.
+    pub fn is_strict(&self) -> bool {
+        self.errors.is_some()
+    }
+
+    /// Records a problem if in strict mode and a condition doesn’t hold.
+    ///
+    /// If the fragment is in strict mode and _valid_ is `false`, calls
+    /// _problem_ to get a description of the problem and records it.
+    /// Otherwise does nothing.
+    pub fn validate<F: FnOnce() -> String>(
+        &mut self, valid: bool, problem: F
+    ) {
+        if !valid {
+            if let Some(errors) = self.errors.as_mut() {
+                errors.push(problem())
+            }
+        }
+    }
+
+    /// Returns the problems recorded so far in strict mode.
+    pub fn problems(&self) -> &[String] {
+        self.errors.as_deref().unwrap_or_default()
+    }
+
+    /// Returns the context providing parameters.
+    pub fn context(&self) -> &Context {
+        &self.context
+    }
+
+    /// Returns whether integers are written in flipped byte order.
+    ///
+    /// If this is `true`, recipes for integers in a specific byte order,
+    /// such as [`be`], write them in the opposite order instead. This
+    /// allows quickly creating a variant of a message with the wrong byte
+    /// order throughout. Normally, the mode is enabled for part of the
+    /// data via [`flip_endian`].
+    pub fn is_endian_flipped(&self) -> bool {
+        self.flip_endian
+    }
+
+    /// Sets whether integers are written in flipped byte order.
+    pub fn set_endian_flipped(&mut self, flip: bool) {
+        self.flip_endian = flip
+    }
+
+    /// Returns the number of octets the fragment can hold without
+    /// reallocating.
+    pub fn capacity(&self) -> usize {
+        self.data.capacity()
+    }
+
+    /// Removes all content from the fragment while keeping its buffer.
+    ///
+    /// Besides the data, this drops recorded problems, measured parts,
+    /// anchors, and whether the limit was exceeded. The fragment stays in
+    /// strict or measuring mode and keeps its limit, context, and byte
+    /// order. Use [`reset`][Self::reset] to drop those, too.
+    pub fn clear(&mut self) {
+        self.data.clear();
+        if let Some(errors) = self.errors.as_mut() {
+            errors.clear()
+        }
+        if let Some(spans) = self.spans.as_mut() {
+            spans.clear()
+        }
+        self.depth = 0;
+        self.exceeded = false;
+        self.anchors.clear();
+        self.offsets.clear();
+        self.reserved.clear();
+    }
+
+    /// Returns the fragment to its initial state while keeping its buffer.
+    ///
+    /// Apart from the buffer, the fragment is the same as one created via
+    /// [`Fragment::new`]. In particular, it leaves strict and measuring
+    /// mode and loses its limit, context, and flipped byte order.
+    pub fn reset(&mut self) {
+        let mut data = mem::take(&mut self.data);
+        data.clear();
+        *self = Fragment { data, ..Fragment::new() }
+    }
+
+    /// Shortens the fragment to the given length.
+    ///
+    /// If the fragment is already shorter, nothing happens. Positions
+    /// recorded by recipes such as [`anchor`] beyond the new end are
+    /// forgotten.
+    pub fn truncate(&mut self, len: usize) {
+        self.data.truncate(len);
+        for item in &mut self.anchors {
+            if item.1.map(|pos| pos > len).unwrap_or(false) {
+                item.1 = None
+            }
+        }
+        self.offsets.retain(|item| item.start + item.width.octets() <= len);
+        self.reserved.retain(|item| item.1.end <= len);
+        if let Some(spans) = self.spans.as_mut() {
+            spans.retain(|item| item.start <= len);
+            for item in spans {
+                item.end = cmp::min(item.end, len)
+            }
+        }
+    }
+
+    /// Resizes the fragment to the given length.
+    ///
+    /// If the fragment is shorter, it is extended with octets of the
+    /// value _fill._ If it is longer, it is truncated.
+    pub fn resize(&mut self, len: usize, fill: u8) {
+        if len < self.data.len() {
+            self.truncate(len)
+        }
+        else if self.check_limit(len - self.data.len()) {
+            self.data.resize(len, fill)
+        }
+    }
+
+    /// Returns a mark for the current end of the fragment.
+    ///
+    /// See [`Mark`] for what to do with it.
+    pub fn mark(&self) -> Mark {
+        Mark {
+            start: self.data.len(),
+            anchors: self.anchors.len(),
+            offsets: self.offsets.len(),
+            reserved: self.reserved.len(),
+            spans: self.spans.as_ref().map(Vec::len).unwrap_or(0),
+        }
+    }
+
+    /// Moves the data starting at _from_ in front of the data since _mark._
+    ///
+    /// This is used to insert a header, such as a length, in front of
+    /// data that has been assembled first. The header is appended to the
+    /// fragment and then moved to its place. Positions recorded since
+    /// _mark_ move along with the data.
+    pub fn move_before(&mut self, mark: &Mark, from: usize) {
+        let start = mark.start;
+        let len = self.data.len() - from;
+        self.data[start..].rotate_right(len);
+        self.move_positions(mark, |pos| {
+            if pos <= from { pos + len } else { pos - from + start }
+        })
+    }
+
+    /// Removes the data within _range_ from the fragment.
+    ///
+    /// Later data moves forward. Recorded positions within the range
+    /// move to its start, offset fields and placeholders within it are
+    /// forgotten.
+    pub fn remove_range(&mut self, range: ops::Range<usize>) {
+        self.data.drain(range.clone());
+        self.offsets.retain(|item| {
+            item.start + item.width.octets() <= range.start
+                || item.start >= range.end
+        });
+        self.reserved.retain(|item| {
+            item.1.end <= range.start || item.1.start >= range.end
+        });
+        self.move_positions(&Mark::default(), |pos| {
+            if pos < range.start { pos }
+            else if pos < range.end { range.start }
+            else { pos - range.len() }
+        })
+    }
+
+    /// Moves the positions recorded since _mark_ using _op._
+    ///
+    /// Offset fields are updated afterwards.
+    fn move_positions(&mut self, mark: &Mark, op: impl Fn(usize) -> usize) {
+        for item in &mut self.anchors[mark.anchors..] {
+            item.1 = item.1.map(&op)
+        }
+        for item in &mut self.offsets[mark.offsets..] {
+            item.start = op(item.start)
+        }
+        for item in &mut self.reserved[mark.reserved..] {
+            let start = op(item.1.start);
+            item.1 = start..start + item.1.len();
+        }
+        if let Some(spans) = self.spans.as_mut() {
+            for item in &mut spans[mark.spans..] {
+                item.start = op(item.start);
+                item.end = op(item.end);
+            }
+        }
+        self.update_offsets()
+    }
+
+    /// Fills in all offset fields whose anchors are known.
+    fn update_offsets(&mut self) {
+        let mut offsets = mem::take(&mut self.offsets);
+        for item in &mut offsets {
+            item.update(self)
+        }
+        self.offsets = offsets;
+    }
+
+    /// Returns the index of the most recent anchor called _name._
+    fn find_anchor(&self, name: &str) -> Option<usize> {
+        self.anchors.iter().rposition(|item| {
+            item.0 == name && item.1.is_some()
+        })
+    }
+
+    /// Converts the fragment into its underlying vec.
+    pub fn into_vec(self) -> Vec<u8> {
+        self.data
+    }
+
     /// Returns the content of the fragment as a slice.
     pub fn as_slice(&self) -> &[u8] {
         self.data.as_ref()
     }
 
+    /// Returns the content of the fragment as a mutable slice.
+    pub fn as_mut_slice(&mut self) -> &mut [u8] {
+        self.data.as_mut()
+    }
+
     /// Appends a single octet the the fragment.
     pub fn push(&mut self, octet: u8) {
-        self.data.push(octet)
+        if self.check_limit(1) {
+            self.data.push(octet)
+        }
     }
 
     /// Appends a the content of a slice of octets to the fragment.
     pub fn extend_from_slice(&mut self, slice: &[u8]) {
-        self.data.extend_from_slice(slice)
+        if self.check_limit(slice.len()) {
+            self.data.extend_from_slice(slice)
+        }
+    }
+
+    /// Returns whether _additional_ octets can be added.
+    ///
+    /// If adding them would exceed the limit, marks the fragment as
+    /// having exceeded it. Once that has happened, no more octets can be
+    /// added.
+    fn check_limit(&mut self, additional: usize) -> bool {
+        if let Some(limit) = self.limit {
+            if self.data.len().saturating_add(additional) > limit {
+                self.exceeded = true
+            }
+        }
+        !self.exceeded
     }
 }
 
@@ -144,6 +761,27 @@ impl<T: AsRef<[u8]>> PartialEq<T> for Fragment {
 impl Eq for Fragment { }
 
 
+//--- PartialOrd, Ord, and Hash
+
+impl PartialOrd for Fragment {
+    fn partial_cmp(&self, other: &Self) -> Option<cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Fragment {
+    fn cmp(&self, other: &Self) -> cmp::Ordering {
+        self.data.cmp(&other.data)
+    }
+}
+
+impl hash::Hash for Fragment {
+    fn hash<H: hash::Hasher>(&self, state: &mut H) {
+        self.data.hash(state)
+    }
+}
+
+
 //--- Deref, AsRef, Borrow
 
 impl ops::Deref for Fragment {
@@ -154,12 +792,24 @@ impl ops::Deref for Fragment {
     }
 }
 
+impl ops::DerefMut for Fragment {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.as_mut_slice()
+    }
+}
+
 impl AsRef<[u8]> for Fragment {
     fn as_ref(&self) -> &[u8] {
         self.as_slice()
     }
 }
 
+impl AsMut<[u8]> for Fragment {
+    fn as_mut(&mut self) -> &mut [u8] {
+        self.as_mut_slice()
+    }
+}
+
 impl borrow::Borrow<[u8]> for Fragment {
     fn borrow(&self) -> &[u8] {
         self.as_slice()
@@ -171,7 +821,7 @@ impl borrow::Borrow<[u8]> for Fragment {
 
 impl io::Write for Fragment {
     fn write(&mut self, buf: &[u8]) -> Result<usize, io::Error> {
-        self.data.extend_from_slice(buf);
+        self.extend_from_slice(buf);
         Ok(buf.len())
     }
 
@@ -181,59 +831,209 @@ impl io::Write for Fragment {
 }
 
 
-//------------ iter ----------------------------------------------------------
+//------------ Mark ----------------------------------------------------------
 
-/// Returns a recipe iterating over and assembling the items of an iterator.
-pub fn iter<T>(iter: T) -> Iter<T> {
-    Iter(iter)
+/// The state of a fragment before some data was assembled.
+///
+/// Recipes that move their data around after assembling it, such as to
+/// insert a header in front of it, take a mark via [`Fragment::mark`]
+/// before assembling and pass it to [`Fragment::move_before`] afterwards.
+/// This tells the fragment which of the positions recorded by recipes
+/// such as [`anchor`] belong to the data and need to move with it.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Mark {
+    /// The length of the fragment.
+    start: usize,
+
+    /// The number of anchors.
+    anchors: usize,
+
+    /// The number of offset fields.
+    offsets: usize,
+
+    /// The number of reserved placeholders.
+    reserved: usize,
+
+    /// The number of labelled parts.
+    spans: usize,
 }
 
-pub struct Iter<T>(T);
-
-impl<T> Recipe for Iter<T>
-where
-    for<'a> &'a T: IntoIterator,
-    for<'a> <&'a T as IntoIterator>::Item: Recipe,
-{
-    fn assemble(&self, target: &mut Fragment) {
-        for item in &self.0 {
-            item.assemble(target)
-        }
+impl Mark {
+    /// Returns the length of the fragment when the mark was taken.
+    ///
+    /// This is where the data assembled since the mark starts.
+    pub fn start(&self) -> usize {
+        self.start
     }
 }
 
 
-//------------ empty ---------------------------------------------------------
+//------------ FragmentPool --------------------------------------------------
 
-/// Returns an empty recipe.
-pub fn empty() -> Empty {
-    Empty
+/// A pool of fragments for reuse.
+///
+/// When generating large numbers of payloads, allocating a new fragment
+/// for each of them can become expensive. A pool keeps the buffers of
+/// fragments that aren’t needed anymore and hands them out again.
+///
+/// Fragments are taken from the pool via [`get`][Self::get] or
+/// [`assemble`][Self::assemble]. They are returned as a
+/// [`PooledFragment`] which gives the buffer back to the pool when
+/// dropped.
+#[derive(Debug, Default)]
+pub struct FragmentPool {
+    /// The currently unused fragments.
+    fragments: RefCell<Vec<Fragment>>,
 }
 
-pub struct Empty;
+impl FragmentPool {
+    /// Creates a new, empty pool.
+    pub fn new() -> Self {
+        Default::default()
+    }
 
-impl Recipe for Empty {
-    fn assemble(&self, _: &mut Fragment) {
+    /// Returns an empty fragment from the pool.
+    ///
+    /// If the pool doesn’t have an unused fragment, a new one is created.
+    /// A reused fragment is [reset][Fragment::reset] first, so it doesn’t
+    /// keep the mode or context of its previous use.
+    pub fn get(&self) -> PooledFragment<'_> {
+        let mut fragment = self.fragments.borrow_mut().pop().unwrap_or_default(
+        );
+        fragment.reset();
+        PooledFragment { pool: self, fragment }
     }
-}
 
+    /// Assembles a recipe into a fragment from the pool.
+    pub fn assemble(&self, recipe: &impl Recipe) -> PooledFragment<'_> {
+        let mut res = self.get();
+        recipe.assemble(&mut res);
+        res
+    }
 
-//------------ literal -------------------------------------------------------
+    /// Returns the number of unused fragments currently in the pool.
+    pub fn len(&self) -> usize {
+        self.fragments.borrow().len()
+    }
+
+    /// Returns whether there are no unused fragments in the pool.
+    pub fn is_empty(&self) -> bool {
+        self.fragments.borrow().is_empty()
+    }
+}
+
+
+//------------ PooledFragment ------------------------------------------------
+
+/// A fragment taken from a [`FragmentPool`].
+///
+/// The type dereferences to [`Fragment`]. When dropped, the fragment is
+/// returned to the pool.
+#[derive(Debug)]
+pub struct PooledFragment<'a> {
+    pool: &'a FragmentPool,
+    fragment: Fragment,
+}
+
+impl<'a> PooledFragment<'a> {
+    /// Takes the fragment out of the pool for good.
+    pub fn detach(mut self) -> Fragment {
+        mem::take(&mut self.fragment)
+    }
+}
+
+impl<'a> ops::Deref for PooledFragment<'a> {
+    type Target = Fragment;
+
+    fn deref(&self) -> &Fragment {
+        &self.fragment
+    }
+}
+
+impl<'a> ops::DerefMut for PooledFragment<'a> {
+    fn deref_mut(&mut self) -> &mut Fragment {
+        &mut self.fragment
+    }
+}
+
+impl<'a> Drop for PooledFragment<'a> {
+    fn drop(&mut self) {
+        if self.fragment.capacity() > 0 {
+            self.pool.fragments.borrow_mut().push(
+                mem::take(&mut self.fragment)
+            )
+        }
+    }
+}
+
+
+//------------ iter ----------------------------------------------------------
+
+/// Returns a recipe iterating over and assembling the items of an iterator.
+pub fn iter<T>(iter: T) -> Iter<T> {
+    Iter(iter)
+}
+
+pub struct Iter<T>(T);
+
+impl<T> Recipe for Iter<T>
+where
+    for<'a> &'a T: IntoIterator,
+    for<'a> <&'a T as IntoIterator>::Item: Recipe,
+{
+    fn assemble(&self, target: &mut Fragment) {
+        for item in &self.0 {
+            item.assemble(target)
+        }
+    }
+}
+
+
+//------------ empty ---------------------------------------------------------
+
+/// Returns an empty recipe.
+pub fn empty() -> Empty {
+    Empty
+}
+
+pub struct Empty;
+
+impl Recipe for Empty {
+    fn assemble(&self, _: &mut Fragment) {
+    }
+}
+
+
+//------------ literal -------------------------------------------------------
 
 /// Constructs a recipe adding a literal.
 ///
 /// The function accepts any static object that implements `AsRef<[u8]>`.
 /// Appart from actual string and bytes literals, these are also `u8` arrays,
 /// which comes in handy when describing actual binary data.
-pub fn literal<T: AsRef<[u8]> + 'static>(literal: T) -> Literal<T> {
-    Literal(literal)
+pub const fn literal<T: AsRef<[u8]> + 'static>(
+    literal: T
+) -> Literal<T> {
+    Literal { data: literal, ordered: false }
 }
 
-pub struct Literal<T>(T);
+pub struct Literal<T> {
+    data: T,
+
+    /// Is the data an integer affected by flipped byte order?
+    ordered: bool,
+}
 
 impl<T: AsRef<[u8]> + 'static> Recipe for Literal<T> {
     fn assemble(&self, target: &mut Fragment) {
-        target.extend_from_slice(self.0.as_ref())
+        if self.ordered && target.is_endian_flipped() {
+            for octet in self.data.as_ref().iter().rev() {
+                target.push(*octet)
+            }
+        }
+        else {
+            target.extend_from_slice(self.data.as_ref())
+        }
     }
 }
 
@@ -261,7 +1061,7 @@ impl<T: AsRef<str>> Hex<T> {
         let mut count = 0;
         for ch in self.0.as_ref().chars() {
             if ch.is_ascii_whitespace() { }
-            else if ch.is_digit(16) {
+            else if ch.is_ascii_hexdigit() {
                 count += 1
             }
             else {
@@ -289,11 +1089,43 @@ impl<T: AsRef<str>> Recipe for Hex<T> {
 }
 
 
+//------------ cstring -------------------------------------------------------
+
+/// Returns a recipe for a NUL-terminated string.
+///
+/// The recipe assembles _content_ and appends a single zero octet. In
+/// strict mode, content that itself contains a zero octet is recorded as
+/// a problem.
+pub fn cstring<R: Recipe>(content: R) -> CString<R> {
+    CString(content)
+}
+
+pub struct CString<R>(R);
+
+impl<R: Recipe> Recipe for CString<R> {
+    fn assemble(&self, target: &mut Fragment) {
+        let start = target.len();
+        self.0.assemble(target);
+        if target.is_strict() {
+            if let Some(pos) = target[start..].iter().position(|&x| x == 0) {
+                target.validate(false, || {
+                    format!("embedded NUL at offset {} of C string", pos)
+                });
+            }
+        }
+        target.push(0);
+    }
+}
+
 //------------ be ------------------------------------------------------------
 
 /// Returns a recipe writing the given integer in big-endian encoding.
+///
+/// Floating point numbers are written in their IEEE 754 binary format.
+/// If the fragment has flipped byte order enabled, the number is written
+/// in little-endian encoding instead.
 pub fn be<T: IntoBigEndian>(int: T) -> Literal<T::Literal> {
-    Literal(int.into_be())
+    Literal { data: int.into_be(), ordered: true }
 }
 
 pub trait IntoBigEndian {
@@ -340,12 +1172,262 @@ impl IntoBigEndian for i8 {
     }
 }
 
+impl IntoBigEndian for f32 {
+    type Literal = [u8; 4];
+
+    fn into_be(self) -> Self::Literal {
+        self.to_be_bytes()
+    }
+}
+
+impl IntoBigEndian for f64 {
+    type Literal = [u8; 8];
+
+    fn into_be(self) -> Self::Literal {
+        self.to_be_bytes()
+    }
+}
+
+
+//------------ le ------------------------------------------------------------
+
+/// Returns a recipe writing the given integer in little-endian encoding.
+///
+/// Floating point numbers are written in their IEEE 754 binary format.
+/// If the fragment has flipped byte order enabled, the number is written
+/// in big-endian encoding instead.
+pub fn le<T: IntoLittleEndian>(int: T) -> Literal<T::Literal> {
+    Literal { data: int.into_le(), ordered: true }
+}
+
+pub trait IntoLittleEndian {
+    type Literal: AsRef<[u8]> + 'static;
+
+    fn into_le(self) -> Self::Literal;
+}
+
+macro_rules! into_le {
+    ( $type:ident) => {
+        impl IntoLittleEndian for $type {
+            type Literal = [u8; ($type::BITS as usize) >> 3];
+
+            fn into_le(self) -> Self::Literal {
+                self.to_le_bytes()
+            }
+        }
+    }
+}
+
+into_le!(u8);
+into_le!(u16);
+into_le!(u32);
+into_le!(u64);
+into_le!(u128);
+into_le!(i8);
+into_le!(i16);
+into_le!(i32);
+into_le!(i64);
+into_le!(i128);
+
+impl IntoLittleEndian for f32 {
+    type Literal = [u8; 4];
+
+    fn into_le(self) -> Self::Literal {
+        self.to_le_bytes()
+    }
+}
+
+impl IntoLittleEndian for f64 {
+    type Literal = [u8; 8];
+
+    fn into_le(self) -> Self::Literal {
+        self.to_le_bytes()
+    }
+}
+
+
+//------------ be_hex --------------------------------------------------------
+
+/// Returns a recipe writing a big-endian integer given in hex.
+///
+/// The octets are taken from the hex string exactly as written, including
+/// any leading zeros, as with [`hex`]. If the fragment has flipped byte
+/// order enabled, they are written in reverse order instead, as with
+/// [`be`].
+pub fn be_hex<T: AsRef<str>>(hex: T) -> BigEndianHex<T> {
+    BigEndianHex(self::hex(hex))
+}
+
+pub struct BigEndianHex<T>(Hex<T>);
+
+impl<T: AsRef<str>> Recipe for BigEndianHex<T> {
+    fn assemble(&self, target: &mut Fragment) {
+        let start = target.len();
+        self.0.assemble(target);
+        if target.is_endian_flipped() {
+            target[start..].reverse()
+        }
+    }
+}
+
+
+//------------ varint, signed_varint, and zigzag -----------------------------
+
+/// Returns a recipe writing an unsigned integer in LEB128 encoding.
+///
+/// The integer is split into groups of seven bits starting with the
+/// least significant group. Each group is written in an octet that has
+/// its top bit set unless it is the last one. By default, the minimal
+/// number of octets is used. Use [`Varint::non_minimal`] for additional
+/// redundant octets.
+pub fn varint(value: u64) -> Varint {
+    Varint { value: value.into(), signed: false, extra: 0 }
+}
+
+/// Returns a recipe writing a signed integer in LEB128 encoding.
+///
+/// This is the same as [`varint`] for the two’s complement of the
+/// integer. The last group has to repeat the sign bit, so positive
+/// integers with the top bit of the last group set need an extra octet.
+pub fn signed_varint(value: i64) -> Varint {
+    Varint { value: value.into(), signed: true, extra: 0 }
+}
+
+/// Maps a signed integer to its ZigZag encoding.
+///
+/// The encoding used by Protocol Buffers for `sint32` and `sint64` fields
+/// interleaves negative and positive integers so that integers with a
+/// small absolute value are small: 0 becomes 0, -1 becomes 1, 1 becomes
+/// 2, and so on. The result is normally written via [`varint`].
+pub fn zigzag(value: i64) -> u64 {
+    ((value << 1) ^ (value >> 63)) as u64
+}
+
+pub struct Varint {
+    value: i128,
+    signed: bool,
+    extra: usize,
+}
+
+impl Varint {
+    /// Adds _extra_ redundant octets to the encoding.
+    ///
+    /// The additional groups contain only the sign extension, i.e., zero
+    /// for positive and all ones for negative integers. Many parsers
+    /// reject such encodings or limit the number of octets.
+    pub fn non_minimal(mut self, extra: usize) -> Self {
+        self.extra = extra;
+        self
+    }
+}
+
+impl Recipe for Varint {
+    fn assemble(&self, target: &mut Fragment) {
+        let mut value = self.value;
+        loop {
+            let group = (value & 0x7F) as u8;
+            value >>= 7;
+            let done = if self.signed {
+                (value == 0 && group & 0x40 == 0)
+                    || (value == -1 && group & 0x40 != 0)
+            }
+            else {
+                value == 0
+            };
+            if done {
+                let fill = if self.value < 0 { 0x7F } else { 0 };
+                if self.extra == 0 {
+                    target.push(group);
+                }
+                else {
+                    target.push(group | 0x80);
+                    for _ in 1..self.extra {
+                        target.push(fill | 0x80);
+                    }
+                    target.push(fill);
+                }
+                break
+            }
+            target.push(group | 0x80);
+        }
+    }
+}
+
+
+//------------ quic_varint ---------------------------------------------------
+
+/// Returns a recipe writing an integer in QUIC variable-length encoding.
+///
+/// The encoding defined in RFC 9000 uses one, two, four, or eight octets
+/// with the two most significant bits of the first octet giving the
+/// length. By default, the shortest encoding that fits the value is used.
+/// A longer one can be chosen via [`QuicVarint::width`].
+///
+/// Values that don’t fit into 62 bits or the chosen width are truncated.
+/// In strict mode, this is recorded as a problem.
+pub fn quic_varint(value: u64) -> QuicVarint {
+    let width = if value < 1 << 6 { Width::U8 }
+        else if value < 1 << 14 { Width::U16 }
+        else if value < 1 << 30 { Width::U32 }
+        else { Width::U64 };
+    QuicVarint { value, width }
+}
+
+pub struct QuicVarint {
+    value: u64,
+    width: Width,
+}
+
+impl QuicVarint {
+    /// Sets the width of the encoding.
+    ///
+    /// Any width larger than necessary results in a valid but
+    /// non-minimal encoding which QUIC receivers have to accept for most
+    /// fields.
+    ///
+    /// # Panics
+    ///
+    /// The method panics if _width_ is [`Width::U24`] which the encoding
+    /// doesn’t have.
+    pub fn width(mut self, width: Width) -> Self {
+        assert!(
+            width != Width::U24,
+            "QUIC variable-length integers can’t have three octets"
+        );
+        self.width = width;
+        self
+    }
+}
+
+impl Recipe for QuicVarint {
+    fn assemble(&self, target: &mut Fragment) {
+        let octets = self.width.octets();
+        let max = self.width.max() >> 2;
+        target.validate(self.value <= max, || {
+            format!(
+                "value {} too large for {} octet QUIC variable-length \
+                 integer",
+                self.value, octets
+            )
+        });
+        let prefix = match self.width {
+            Width::U8 => 0,
+            Width::U16 => 1,
+            Width::U32 => 2,
+            Width::U64 => 3,
+            Width::U24 => unreachable!(),
+        };
+        let value = (self.value & max) | (prefix << (octets * 8 - 2));
+        target.extend_from_slice(&value.to_be_bytes()[8 - octets..]);
+    }
+}
+
 
 //------------ exec ----------------------------------------------------------
 
 /// Returns a recipe executing the given closure whenever data is assembled.
 pub fn exec<Op: Fn(&mut Fragment) + 'static>(op: Op) -> Exec<Op> {
-    Exec(op).into()
+    Exec(op)
 }
 
 pub struct Exec<Op>(Op);
@@ -357,3 +1439,2072 @@ impl<Op: Fn(&mut Fragment) + 'static> Recipe for Exec<Op> {
 }
 
 
+//------------ SharedRecipe --------------------------------------------------
+
+/// A recipe of any type that can be cloned cheaply.
+///
+/// Builders use this to keep recipes given to them, such as replacements
+/// for fields, until they are assembled as part of the builder’s data.
+#[derive(Clone)]
+pub(crate) struct SharedRecipe(Arc<dyn Recipe + Send + Sync>);
+
+impl SharedRecipe {
+    pub(crate) fn new(recipe: impl Recipe + Send + Sync + 'static) -> Self {
+        SharedRecipe(Arc::new(recipe))
+    }
+}
+
+impl Recipe for SharedRecipe {
+    fn assemble(&self, target: &mut Fragment) {
+        self.0.assemble(target)
+    }
+}
+
+impl fmt::Debug for SharedRecipe {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("SharedRecipe(..)")
+    }
+}
+
+
+//------------ sign ----------------------------------------------------------
+
+/// Returns a recipe producing a signature over another recipe.
+///
+/// The data of _inner_ is assembled and handed to the closure _signer_
+/// whose return value replaces the data. Since the signed data usually
+/// appears next to its signature, _inner_ will normally be a reference
+/// to a recipe that is used a second time elsewhere. For instance, a
+/// self-signed certificate can be described as a sequence of `&tbs`, the
+/// signature algorithm, and a bit string containing `sign(&tbs, signer)`.
+pub fn sign<R, F, T>(inner: R, signer: F) -> Sign<R, F>
+where R: Recipe, F: Fn(&[u8]) -> T, T: AsRef<[u8]> {
+    Sign { inner, signer }
+}
+
+pub struct Sign<R, F> {
+    inner: R,
+    signer: F,
+}
+
+impl<R, F, T> Recipe for Sign<R, F>
+where R: Recipe, F: Fn(&[u8]) -> T, T: AsRef<[u8]> {
+    fn assemble(&self, target: &mut Fragment) {
+        let start = target.len();
+        self.inner.assemble(target);
+        let signature = (self.signer)(&target[start..]);
+        target.truncate(start);
+        target.extend_from_slice(signature.as_ref())
+    }
+}
+
+
+//------------ map -----------------------------------------------------------
+
+/// Returns a recipe rewriting the data of another recipe.
+///
+/// The data of _inner_ is assembled, handed to the closure _op,_ and
+/// replaced by whatever the closure returns. This allows for any
+/// transformation not provided by a dedicated recipe.
+pub fn map<R, F>(inner: R, op: F) -> Map<R, F>
+where R: Recipe, F: Fn(Vec<u8>) -> Vec<u8> {
+    Map { inner, op }
+}
+
+pub struct Map<R, F> {
+    inner: R,
+    op: F,
+}
+
+impl<R, F> Recipe for Map<R, F>
+where R: Recipe, F: Fn(Vec<u8>) -> Vec<u8> {
+    fn assemble(&self, target: &mut Fragment) {
+        let start = target.len();
+        self.inner.assemble(target);
+        let data = (self.op)(target[start..].to_vec());
+        target.truncate(start);
+        target.extend_from_slice(&data)
+    }
+}
+
+
+//------------ lazy ----------------------------------------------------------
+
+/// Returns a recipe created anew each time it is assembled.
+///
+/// The closure _op_ is called every time the recipe is assembled and the
+/// recipe it returns is assembled in its place. This allows for values
+/// such as time stamps or nonces that need to be determined when the data
+/// is produced rather than when the recipe is constructed.
+pub fn lazy<R, F>(op: F) -> Lazy<F>
+where R: Recipe, F: Fn() -> R {
+    Lazy(op)
+}
+
+pub struct Lazy<F>(F);
+
+impl<R, F> Recipe for Lazy<F>
+where R: Recipe, F: Fn() -> R {
+    fn assemble(&self, target: &mut Fragment) {
+        (self.0)().assemble(target)
+    }
+}
+
+//------------ select --------------------------------------------------------
+
+/// Returns a recipe choosing one of several recipes by a key.
+///
+/// The recipes are added together with the key they are chosen for via
+/// [`Select::case`]. When assembled, the recipe of the first case whose
+/// key equals _key_ is used. If there is none, the recipe given via
+/// [`Select::fallback`] is used. This allows building parameterized
+/// templates where, e.g., a test parameter picks the type of a message.
+///
+/// # Panics
+///
+/// Assembling panics if no case matches and there is no fallback.
+pub fn select<K: PartialEq + fmt::Debug>(key: K) -> Select<K> {
+    Select { key, cases: Vec::new(), fallback: None }
+}
+
+pub struct Select<K> {
+    key: K,
+    cases: Vec<(K, Box<dyn Recipe>)>,
+    fallback: Option<Box<dyn Recipe>>,
+}
+
+impl<K> Select<K> {
+    /// Adds the recipe to use if the key equals _key._
+    pub fn case(mut self, key: K, recipe: impl Recipe + 'static) -> Self {
+        self.cases.push((key, Box::new(recipe)));
+        self
+    }
+
+    /// Sets the recipe to use if no case matches.
+    pub fn fallback(mut self, recipe: impl Recipe + 'static) -> Self {
+        self.fallback = Some(Box::new(recipe));
+        self
+    }
+}
+
+impl<K: PartialEq + fmt::Debug> Recipe for Select<K> {
+    fn assemble(&self, target: &mut Fragment) {
+        let recipe = self.cases.iter().find_map(|(key, recipe)| {
+            (*key == self.key).then_some(recipe)
+        }).or(self.fallback.as_ref());
+        match recipe {
+            Some(recipe) => recipe.assemble(target),
+            None => panic!("no recipe selected for key {:?}", self.key),
+        }
+    }
+}
+
+
+//------------ flip_endian ---------------------------------------------------
+
+/// Returns a recipe assembling another recipe with flipped byte order.
+///
+/// While assembling _inner,_ all integers produced by byte order aware
+/// recipes such as [`be`] are written in the opposite byte order. Using
+/// this recipe inside another one flips the byte order back. See
+/// [`Fragment::is_endian_flipped`] for more.
+pub fn flip_endian<R: Recipe>(inner: R) -> FlipEndian<R> {
+    FlipEndian(inner)
+}
+
+pub struct FlipEndian<R>(R);
+
+impl<R: Recipe> Recipe for FlipEndian<R> {
+    fn assemble(&self, target: &mut Fragment) {
+        let flip = target.is_endian_flipped();
+        target.set_endian_flipped(!flip);
+        self.0.assemble(target);
+        target.set_endian_flipped(flip);
+    }
+}
+
+
+//------------ labelled ------------------------------------------------------
+
+/// Returns a recipe marking the data of another recipe for measuring.
+///
+/// The recipe assembles _inner_ unchanged. If the fragment is measuring,
+/// it records the offset and length of the data under _label._ See
+/// [`Recipe::measured`] for how to get at the result.
+///
+/// If a recipe later moves the data, such as a DER value inserting its
+/// header in front of its content, the part moves along. If the data is
+/// removed or replaced, such as by [`slice()`] or [`map`], the part is cut
+/// short or forgotten.
+pub fn labelled<R: Recipe>(
+    label: impl Into<String>, inner: R
+) -> Labelled<R> {
+    Labelled { label: label.into(), inner }
+}
+
+pub struct Labelled<R> {
+    label: String,
+    inner: R,
+}
+
+impl<R: Recipe> Recipe for Labelled<R> {
+    fn assemble(&self, target: &mut Fragment) {
+        let start = target.len();
+        let index = match target.spans.as_mut() {
+            Some(spans) => {
+                spans.push(Span {
+                    label: self.label.clone(),
+                    depth: target.depth,
+                    start,
+                    end: start,
+                });
+                spans.len() - 1
+            }
+            None => {
+                self.inner.assemble(target);
+                return
+            }
+        };
+        target.depth += 1;
+        self.inner.assemble(target);
+        target.depth -= 1;
+        let end = target.len();
+        if let Some(span) = target.spans.as_mut().and_then(|spans| {
+            spans.get_mut(index)
+        }) {
+            span.end = end
+        }
+    }
+}
+
+/// A labelled part recorded by a measuring fragment.
+#[derive(Clone, Debug)]
+struct Span {
+    label: String,
+    depth: usize,
+    start: usize,
+    end: usize,
+}
+
+
+//------------ Measurement ---------------------------------------------------
+
+/// The labelled parts of assembled data.
+///
+/// A measurement is returned by [`Recipe::measured`]. It contains a
+/// [`Part`] for each [`labelled`] recipe that was assembled. Parts
+/// assembled within another labelled recipe are that part’s children.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Measurement {
+    parts: Vec<Part>,
+}
+
+impl Measurement {
+    /// Builds the tree from spans in the order they were started.
+    fn from_spans(spans: &[Span]) -> Self {
+        Measurement { parts: Part::from_spans(spans, 0).0 }
+    }
+
+    /// Returns the top-level parts in the order they were assembled.
+    pub fn parts(&self) -> &[Part] {
+        &self.parts
+    }
+
+    /// Returns the first part matching a path of labels.
+    ///
+    /// The _path_ consists of labels separated by slashes, starting with
+    /// a top-level part. At each level, the first part with a matching
+    /// label is used.
+    pub fn get(&self, path: &str) -> Option<&Part> {
+        let mut labels = path.split('/');
+        let mut part = Part::find(&self.parts, labels.next()?)?;
+        for label in labels {
+            part = Part::find(&part.children, label)?;
+        }
+        Some(part)
+    }
+
+    /// Returns the length of the first part matching a path of labels.
+    pub fn len_of(&self, path: &str) -> Option<usize> {
+        self.get(path).map(|part| part.len)
+    }
+}
+
+impl fmt::Display for Measurement {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fn write_parts(
+            f: &mut fmt::Formatter, parts: &[Part], indent: usize
+        ) -> fmt::Result {
+            for part in parts {
+                writeln!(
+                    f, "{:indent$}{}: {} octets at {}",
+                    "", part.label, part.len, part.offset,
+                    indent = indent
+                )?;
+                write_parts(f, &part.children, indent + 2)?;
+            }
+            Ok(())
+        }
+
+        write_parts(f, &self.parts, 0)
+    }
+}
+
+
+//------------ Part ----------------------------------------------------------
+
+/// A single labelled part of assembled data.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Part {
+    /// The label given to the recipe.
+    pub label: String,
+
+    /// The offset of the part’s data from the start of the fragment.
+    pub offset: usize,
+
+    /// The length of the part’s data in octets.
+    pub len: usize,
+
+    /// The labelled parts assembled within this part.
+    pub children: Vec<Part>,
+}
+
+impl Part {
+    /// Returns the first part with the given label.
+    fn find<'a>(parts: &'a [Part], label: &str) -> Option<&'a Part> {
+        parts.iter().find(|part| part.label == label)
+    }
+
+    /// Builds parts from spans at the given depth.
+    ///
+    /// Stops at the first span of a lower depth and returns the parts and
+    /// the number of spans consumed.
+    fn from_spans(spans: &[Span], depth: usize) -> (Vec<Part>, usize) {
+        let mut res = Vec::new();
+        let mut pos = 0;
+        while let Some(span) = spans.get(pos) {
+            if span.depth < depth {
+                break
+            }
+            let (children, used) = Part::from_spans(
+                &spans[pos + 1..], depth + 1
+            );
+            res.push(Part {
+                label: span.label.clone(),
+                offset: span.start,
+                len: span.end - span.start,
+                children,
+            });
+            pos += used + 1;
+        }
+        (res, pos)
+    }
+}
+
+
+//------------ slice, truncate, and skip -------------------------------------
+
+/// Returns a recipe producing only part of the data of another recipe.
+///
+/// The recipe assembles _inner_ and keeps only the octets within _range._
+/// The range is relative to the start of the inner data and is cut short
+/// if it extends beyond its end. This makes it easy to split a single
+/// canonical payload into pieces sent at different times.
+pub fn slice<R: Recipe>(
+    range: impl ops::RangeBounds<usize>, inner: R
+) -> Slice<R> {
+    Slice {
+        start: range.start_bound().cloned(),
+        end: range.end_bound().cloned(),
+        inner
+    }
+}
+
+pub struct Slice<R> {
+    start: ops::Bound<usize>,
+    end: ops::Bound<usize>,
+    inner: R,
+}
+
+impl<R: Recipe> Recipe for Slice<R> {
+    fn assemble(&self, target: &mut Fragment) {
+        let base = target.len();
+        self.inner.assemble(target);
+        let len = target.len() - base;
+        let start = match self.start {
+            ops::Bound::Included(start) => start,
+            ops::Bound::Excluded(start) => start.saturating_add(1),
+            ops::Bound::Unbounded => 0,
+        };
+        let end = match self.end {
+            ops::Bound::Included(end) => end.saturating_add(1),
+            ops::Bound::Excluded(end) => end,
+            ops::Bound::Unbounded => len,
+        };
+        let end = cmp::min(end, len);
+        let start = cmp::min(start, end);
+        target.truncate(base + end);
+        target.remove_range(base..base + start);
+    }
+}
+
+/// Returns a recipe producing the first _len_ octets of another recipe.
+///
+/// This is the same as `slice(..len, inner)`. If _inner_ produces fewer
+/// octets, all of them are kept.
+pub fn truncate<R: Recipe>(len: usize, inner: R) -> Slice<R> {
+    slice(..len, inner)
+}
+
+/// Returns a recipe leaving out the first _len_ octets of another recipe.
+///
+/// This is the same as `slice(len.., inner)`. If _inner_ produces fewer
+/// octets, nothing is left.
+pub fn skip<R: Recipe>(len: usize, inner: R) -> Slice<R> {
+    slice(len.., inner)
+}
+
+
+//------------ repeat and repeat_with ----------------------------------------
+
+/// Returns a recipe assembling another recipe a number of times.
+pub fn repeat<R: Recipe>(count: usize, inner: R) -> Repeat<R> {
+    Repeat { count, inner }
+}
+
+pub struct Repeat<R> {
+    count: usize,
+    inner: R,
+}
+
+impl<R: Recipe> Recipe for Repeat<R> {
+    fn assemble(&self, target: &mut Fragment) {
+        for _ in 0..self.count {
+            if target.is_exceeded() {
+                break
+            }
+            self.inner.assemble(target)
+        }
+    }
+}
+
+/// Returns a recipe assembling the recipes returned by a closure.
+///
+/// The closure _op_ is called _count_ times with the index of the
+/// repetition, starting at zero. The recipes it returns are assembled in
+/// order.
+pub fn repeat_with<F, R>(count: usize, op: F) -> RepeatWith<F>
+where F: Fn(usize) -> R, R: Recipe {
+    RepeatWith { count, op }
+}
+
+pub struct RepeatWith<F> {
+    count: usize,
+    op: F,
+}
+
+impl<F, R> Recipe for RepeatWith<F>
+where F: Fn(usize) -> R, R: Recipe {
+    fn assemble(&self, target: &mut Fragment) {
+        for idx in 0..self.count {
+            if target.is_exceeded() {
+                break
+            }
+            (self.op)(idx).assemble(target)
+        }
+    }
+}
+
+
+//------------ pad_to --------------------------------------------------------
+
+/// Returns a recipe padding another recipe to a fixed length.
+///
+/// The recipe assembles _inner_ and appends octets of value _fill_ until
+/// the inner data is _len_ octets long. What happens if the inner data is
+/// already longer is determined by the [`Overflow`] policy which can be
+/// changed via [`PadTo::overflow`]. By default, assembling panics.
+pub fn pad_to<R: Recipe>(len: usize, fill: u8, inner: R) -> PadTo<R> {
+    PadTo { len, fill, overflow: Overflow::Panic, inner }
+}
+
+pub struct PadTo<R> {
+    len: usize,
+    fill: u8,
+    overflow: Overflow,
+    inner: R,
+}
+
+impl<R> PadTo<R> {
+    /// Sets what happens if the inner data is too long.
+    pub fn overflow(mut self, overflow: Overflow) -> Self {
+        self.overflow = overflow;
+        self
+    }
+}
+
+impl<R: Recipe> Recipe for PadTo<R> {
+    fn assemble(&self, target: &mut Fragment) {
+        let base = target.len();
+        self.inner.assemble(target);
+        let len = target.len() - base;
+        if len <= self.len {
+            target.resize(base + self.len, self.fill);
+            return
+        }
+        match self.overflow {
+            Overflow::Panic => {
+                panic!(
+                    "pad_to: inner data is {} octets, limit is {}",
+                    len, self.len
+                )
+            }
+            Overflow::Truncate => target.truncate(base + self.len),
+            Overflow::Leave => { }
+        }
+    }
+}
+
+
+//------------ align_to ------------------------------------------------------
+
+/// Returns a recipe aligning the fragment to a boundary.
+///
+/// The recipe appends octets of value _fill_ until the length of the
+/// fragment it is assembled into is a multiple of _boundary._ Because the
+/// length is taken at assembly time, the alignment is relative to the
+/// start of the fragment. To align relative to the start of some part of
+/// the data, assemble that part into a fragment of its own.
+///
+/// A _boundary_ of zero or one never adds any padding.
+pub fn align_to(boundary: usize, fill: u8) -> AlignTo {
+    AlignTo { boundary, fill }
+}
+
+pub struct AlignTo {
+    boundary: usize,
+    fill: u8,
+}
+
+impl Recipe for AlignTo {
+    fn assemble(&self, target: &mut Fragment) {
+        if self.boundary > 1 {
+            let len = target.len().next_multiple_of(self.boundary);
+            target.resize(len, self.fill)
+        }
+    }
+}
+
+
+//------------ Overflow ------------------------------------------------------
+
+/// What to do if data exceeds a fixed length.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Overflow {
+    /// Panic while assembling.
+    Panic,
+
+    /// Cut the data short at the fixed length.
+    Truncate,
+
+    /// Keep the data as it is.
+    Leave,
+}
+
+
+//------------ length_prefix and lp_string -----------------------------------
+
+/// Returns a recipe prefixing another recipe with its length.
+///
+/// The recipe assembles _inner_ and writes the number of octets it
+/// produced as an unsigned integer of the given _width_ and byte order
+/// before it. If the fragment has flipped byte order enabled, the
+/// opposite byte order is used.
+///
+/// If the length doesn’t fit into the prefix, only its lower bits are
+/// written. In strict mode, this is recorded as a problem.
+pub fn length_prefix<R: Recipe>(
+    width: Width, endian: Endian, inner: R
+) -> LengthPrefix<R> {
+    LengthPrefix { width, endian, inner }
+}
+
+/// Returns a recipe for a string prefixed with its length.
+///
+/// This is [`length_prefix`] with the length in big-endian byte order as
+/// used by most protocols, e.g., with a width of [`Width::U32`] for SSH
+/// strings or [`Width::U8`] for DNS character strings.
+pub fn lp_string<R: Recipe>(width: Width, content: R) -> LengthPrefix<R> {
+    length_prefix(width, Endian::Big, content)
+}
+
+pub struct LengthPrefix<R> {
+    width: Width,
+    endian: Endian,
+    inner: R,
+}
+
+impl<R: Recipe> Recipe for LengthPrefix<R> {
+    fn assemble(&self, target: &mut Fragment) {
+        let start = target.len();
+        let width = self.width.octets();
+        target.resize(start + width, 0);
+        self.inner.assemble(target);
+        if target.is_exceeded() {
+            return
+        }
+        let len = target.len() - start - width;
+        target.validate(len as u64 <= self.width.max(), || {
+            format!(
+                "length {} exceeds {} octet length prefix", len, width
+            )
+        });
+        let len = (len as u64).to_be_bytes();
+        let prefix = &mut target[start..start + width];
+        prefix.copy_from_slice(&len[8 - width..]);
+        if (self.endian == Endian::Little) != target.is_endian_flipped() {
+            target[start..start + width].reverse()
+        }
+    }
+}
+
+
+//------------ placeholder ---------------------------------------------------
+
+/// Returns a placeholder for an integer field filled in later.
+///
+/// Some fields, such as the length of a whole message or the number of
+/// items that follow, depend on data assembled after them. The returned
+/// handle provides two recipes for such a field: the one returned by
+/// [`Placeholder::reserve`] writes an unsigned integer of the given
+/// _width_ and byte order with the value zero and remembers where it put
+/// it, the one returned by [`Placeholder::fill`] or
+/// [`Placeholder::fill_len`] assembles to nothing but overwrites the
+/// reserved integer with a value calculated from the data assembled so
+/// far. If the fragment has flipped byte order enabled, the opposite
+/// byte order is used.
+///
+/// Both recipes need to be assembled into the same fragment, i.e., they
+/// mustn’t be separated by a recipe that assembles part of its data into
+/// a temporary fragment of its own or replaces it, such as [`map`].
+/// Filling in a placeholder that wasn’t reserved in the fragment or whose
+/// field has since been removed panics. Recipes that merely move data
+/// around, such as those inserting a header, move the field along.
+///
+/// If the value doesn’t fit into the field, only its lower bits are
+/// written. In strict mode, this is recorded as a problem.
+pub fn placeholder(width: Width, endian: Endian) -> Placeholder {
+    static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+    Placeholder {
+        width, endian, id: NEXT_ID.fetch_add(1, Ordering::Relaxed)
+    }
+}
+
+#[derive(Clone)]
+pub struct Placeholder {
+    width: Width,
+    endian: Endian,
+
+    /// The identifier for finding the field in a fragment.
+    id: usize,
+}
+
+impl Placeholder {
+    /// Returns a recipe reserving space for the field.
+    ///
+    /// If the recipe is assembled more than once, later fills use the
+    /// most recently reserved space.
+    pub fn reserve(&self) -> Reserve {
+        Reserve(self.clone())
+    }
+
+    /// Returns a recipe filling in the field.
+    ///
+    /// The closure _op_ receives the data assembled so far and the offset
+    /// of the field within it and returns the value of the field.
+    ///
+    /// # Panics
+    ///
+    /// Assembling the recipe panics if space for the field hasn’t been
+    /// reserved in the fragment yet or has been removed again.
+    pub fn fill<F: Fn(&[u8], usize) -> u64>(&self, op: F) -> Fill<F> {
+        Fill { placeholder: self.clone(), op }
+    }
+
+    /// Returns a recipe filling in the field with a length.
+    ///
+    /// The length is the number of octets assembled after the field.
+    pub fn fill_len(&self) -> Fill<impl Fn(&[u8], usize) -> u64> {
+        let width = self.width.octets();
+        self.fill(move |data, offset| (data.len() - offset - width) as u64)
+    }
+}
+
+pub struct Reserve(Placeholder);
+
+impl Recipe for Reserve {
+    fn assemble(&self, target: &mut Fragment) {
+        let start = target.len();
+        let end = start + self.0.width.octets();
+        target.resize(end, 0);
+        target.reserved.push((self.0.id, start..end));
+    }
+}
+
+pub struct Fill<F> {
+    placeholder: Placeholder,
+    op: F,
+}
+
+impl<F: Fn(&[u8], usize) -> u64> Recipe for Fill<F> {
+    fn assemble(&self, target: &mut Fragment) {
+        if target.is_exceeded() {
+            return
+        }
+        let width = self.placeholder.width.octets();
+        let start = match target.reserved.iter().rev().find(|item| {
+            item.0 == self.placeholder.id
+        }) {
+            Some(item) => item.1.start,
+            None => panic!("placeholder filled before it was reserved"),
+        };
+        let value = (self.op)(target, start);
+        target.validate(value <= self.placeholder.width.max(), || {
+            format!(
+                "value {} too large for {} octet placeholder", value, width
+            )
+        });
+        let value = value.to_be_bytes();
+        let field = &mut target[start..start + width];
+        field.copy_from_slice(&value[8 - width..]);
+        if (self.placeholder.endian == Endian::Little)
+            != target.is_endian_flipped()
+        {
+            target[start..start + width].reverse()
+        }
+    }
+}
+
+
+//------------ anchor and offset_to ------------------------------------------
+
+/// Returns a recipe marking a position in the data.
+///
+/// The recipe assembles to nothing but records the current position under
+/// _name,_ so that recipes created by [`offset_to`] can refer to it. If
+/// an anchor is assembled more than once, later references use the most
+/// recent position.
+///
+/// As with [`placeholder`], the anchor and the recipes referring to it
+/// need to be assembled into the same fragment. Recipes that move data
+/// around, such as those inserting a header in front of their content,
+/// move the anchors along with it and any offsets referring to them are
+/// updated.
+pub fn anchor(name: impl Into<String>) -> Anchor {
+    Anchor(name.into())
+}
+
+pub struct Anchor(String);
+
+impl Recipe for Anchor {
+    fn assemble(&self, target: &mut Fragment) {
+        target.anchors.push((self.0.clone(), Some(target.data.len())));
+        target.update_offsets();
+    }
+}
+
+/// Returns a recipe writing the offset of an anchor.
+///
+/// The recipe writes the position of the [`anchor`] called _name_
+/// relative to _base_ as an unsigned integer of the given _width_ in
+/// big-endian byte order. The anchor may be assembled before or after
+/// the offset. In the latter case, the offset is filled in once the
+/// anchor has been assembled. If the anchor is never assembled, the
+/// offset stays zero. In strict mode, this is recorded as a problem.
+///
+/// The byte order can be changed via [`OffsetTo::endian`]. If the
+/// fragment has flipped byte order enabled, the opposite byte order is
+/// used. Bits that mark the field, such as the two top bits of a DNS
+/// compression pointer, can be added via [`OffsetTo::flags`].
+///
+/// Negative offsets are written in two’s complement. If the offset
+/// doesn’t fit into the field, only its lower bits are written. In strict
+/// mode, this is recorded as a problem once assembly has finished.
+pub fn offset_to(
+    name: impl Into<String>, width: Width, base: Base
+) -> OffsetTo {
+    OffsetTo {
+        name: name.into(), width, base, endian: Endian::Big, flags: 0
+    }
+}
+
+pub struct OffsetTo {
+    name: String,
+    width: Width,
+    base: Base,
+    endian: Endian,
+    flags: u64,
+}
+
+impl OffsetTo {
+    /// Sets the byte order of the offset.
+    pub fn endian(mut self, endian: Endian) -> Self {
+        self.endian = endian;
+        self
+    }
+
+    /// Sets bits to be added to the offset.
+    ///
+    /// The _flags_ are combined with the offset via bitwise or.
+    pub fn flags(mut self, flags: u64) -> Self {
+        self.flags = flags;
+        self
+    }
+}
+
+impl Recipe for OffsetTo {
+    fn assemble(&self, target: &mut Fragment) {
+        let start = target.len();
+        target.resize(start + self.width.octets(), 0);
+        let mut item = OffsetField {
+            name: self.name.clone(),
+            base: self.base.clone(),
+            start,
+            width: self.width,
+            little: (self.endian == Endian::Little)
+                != target.is_endian_flipped(),
+            flags: self.flags,
+            anchor: None,
+            base_anchor: None,
+        };
+        item.update(target);
+        target.offsets.push(item)
+    }
+}
+
+
+//------------ Base ----------------------------------------------------------
+
+/// The position an offset is relative to.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub enum Base {
+    /// The start of the fragment.
+    Start,
+
+    /// The start of the offset field itself.
+    Field,
+
+    /// The end of the offset field.
+    FieldEnd,
+
+    /// The anchor of the given name.
+    Anchor(String),
+}
+
+
+//------------ OffsetField ---------------------------------------------------
+
+/// An offset field recorded by a fragment.
+#[derive(Clone, Debug)]
+struct OffsetField {
+    /// The name of the anchor the offset points to.
+    name: String,
+
+    /// What the offset is relative to.
+    base: Base,
+
+    /// The position of the field.
+    start: usize,
+
+    /// The width of the field.
+    width: Width,
+
+    /// Is the field in little-endian byte order?
+    little: bool,
+
+    /// The bits to add to the offset.
+    flags: u64,
+
+    /// The index of the anchor once it is known.
+    anchor: Option<usize>,
+
+    /// The index of the base anchor once it is known.
+    base_anchor: Option<usize>,
+}
+
+impl OffsetField {
+    /// Looks for missing anchors and fills in the field if possible.
+    ///
+    /// Once found, an anchor stays with the field even if another anchor
+    /// of the same name is assembled later.
+    fn update(&mut self, target: &mut Fragment) {
+        if self.anchor.is_none() {
+            self.anchor = target.find_anchor(&self.name)
+        }
+        if let Base::Anchor(ref name) = self.base {
+            if self.base_anchor.is_none() {
+                self.base_anchor = target.find_anchor(name)
+            }
+        }
+        if target.is_exceeded() {
+            return
+        }
+        let value = match self.value(target) {
+            Some(value) => value,
+            None => return
+        };
+        let width = self.width.octets();
+        let value = (value as u64 | self.flags).to_be_bytes();
+        let field = &mut target[self.start..self.start + width];
+        field.copy_from_slice(&value[8 - width..]);
+        if self.little {
+            field.reverse()
+        }
+    }
+
+    /// Returns the offset if all its anchors are known.
+    fn value(&self, target: &Fragment) -> Option<i128> {
+        let position = |index: Option<usize>| {
+            index.and_then(|index| target.anchors[index].1)
+        };
+        let base = match self.base {
+            Base::Start => 0,
+            Base::Field => self.start,
+            Base::FieldEnd => self.start + self.width.octets(),
+            Base::Anchor(_) => position(self.base_anchor)?,
+        };
+        Some(position(self.anchor)? as i128 - base as i128)
+    }
+
+    /// Records a problem with the final offset in strict mode.
+    fn validate(&self, target: &mut Fragment) {
+        let value = match self.value(target) {
+            Some(value) => value,
+            None => {
+                target.validate(false, || {
+                    format!("offset to unknown anchor '{}'", self.name)
+                });
+                return
+            }
+        };
+        let max = i128::from(self.width.max());
+        target.validate(value <= max && -value <= max / 2 + 1, || {
+            format!(
+                "offset {} to anchor '{}' too large for {} octet field",
+                value, self.name, self.width.octets()
+            )
+        });
+    }
+}
+
+
+//------------ Context -------------------------------------------------------
+
+/// Named parameters supplied when assembling a recipe.
+///
+/// A context allows assembling the same recipe several times with
+/// different values for some of its fields, such as serial numbers,
+/// names, or addresses. The fields are marked by [`param`] recipes and
+/// the values provided via [`Recipe::assemble_with`] or
+/// [`Recipe::to_fragment_with`].
+///
+/// The values are recipes assembled in place of the `param` recipes, so
+/// they behave like any other part of the data, e.g., in strict mode.
+/// Cloning a context is cheap.
+///
+/// The parameters are also available to recipes assembled into a
+/// [child fragment][Fragment::child] by another recipe.
+#[derive(Clone, Debug, Default)]
+pub struct Context {
+    values: Arc<Vec<(String, SharedRecipe)>>,
+}
+
+impl Context {
+    /// Creates an empty context.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds _recipe_ as the value of the parameter _name._
+    pub fn with(
+        mut self, name: &str, recipe: impl Recipe + Send + Sync + 'static
+    ) -> Self {
+        self.insert(name, recipe);
+        self
+    }
+
+    /// Adds _recipe_ as the value of the parameter _name_ to an existing
+    /// context.
+    ///
+    /// Any earlier value for the same parameter is dropped.
+    pub fn insert(
+        &mut self, name: &str, recipe: impl Recipe + Send + Sync + 'static
+    ) {
+        let recipe = SharedRecipe::new(recipe);
+        let values = Arc::make_mut(&mut self.values);
+        match values.iter_mut().find(|item| item.0 == name) {
+            Some(item) => item.1 = recipe,
+            None => values.push((name.into(), recipe)),
+        }
+    }
+
+    /// Returns the value of the parameter _name_ if there is one.
+    pub fn get(&self, name: &str) -> Option<&dyn Recipe> {
+        self.shared(name).map(|recipe| recipe as &dyn Recipe)
+    }
+
+    /// Returns the shared recipe for the parameter _name._
+    fn shared(&self, name: &str) -> Option<&SharedRecipe> {
+        self.values.iter().find(|item| item.0 == name).map(|item| &item.1)
+    }
+}
+
+
+//------------ param and param_or --------------------------------------------
+
+/// Returns a recipe producing the value of a parameter.
+///
+/// When assembled, the recipe writes the value of the parameter _name_
+/// from the fragment’s [`Context`].
+///
+/// # Panics
+///
+/// Assembling panics if the context has no value for the parameter.
+pub fn param(name: impl Into<String>) -> Param<Empty> {
+    Param { name: name.into(), default: None }
+}
+
+/// Returns a recipe producing the value of a parameter or a default.
+///
+/// If the fragment’s [`Context`] has no value for the parameter _name,_
+/// the recipe assembles _default_ instead.
+pub fn param_or<R: Recipe>(name: impl Into<String>, default: R) -> Param<R> {
+    Param { name: name.into(), default: Some(default) }
+}
+
+pub struct Param<R> {
+    name: String,
+    default: Option<R>,
+}
+
+impl<R: Recipe> Recipe for Param<R> {
+    fn assemble(&self, target: &mut Fragment) {
+        if let Some(value) = target.context.shared(&self.name).cloned() {
+            value.assemble(target);
+            return
+        }
+        match self.default {
+            Some(ref default) => default.assemble(target),
+            None => panic!("no value for parameter '{}'", self.name),
+        }
+    }
+}
+
+//------------ Width ---------------------------------------------------------
+
+/// The width of an integer field.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Width {
+    U8,
+    U16,
+    U24,
+    U32,
+    U64,
+}
+
+impl Width {
+    /// Returns the number of octets of the field.
+    pub fn octets(self) -> usize {
+        match self {
+            Width::U8 => 1,
+            Width::U16 => 2,
+            Width::U24 => 3,
+            Width::U32 => 4,
+            Width::U64 => 8,
+        }
+    }
+
+    /// Returns the largest value the field can hold.
+    pub fn max(self) -> u64 {
+        match self {
+            Width::U8 => u8::MAX.into(),
+            Width::U16 => u16::MAX.into(),
+            Width::U24 => 0xFF_FFFF,
+            Width::U32 => u32::MAX.into(),
+            Width::U64 => u64::MAX,
+        }
+    }
+}
+
+
+//------------ Endian --------------------------------------------------------
+
+/// The byte order of an integer field.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Endian {
+    Big,
+    Little,
+}
+
+
+//------------ random --------------------------------------------------------
+
+/// Returns a recipe producing pseudo-random data.
+///
+/// The recipe produces _len_ octets from a random number generator
+/// seeded with _seed._ The same seed always results in the same data,
+/// so large payloads can be reproduced without storing them. The
+/// generator is ChaCha8, whose output doesn’t change with new versions
+/// of the `rand` crate or between platforms.
+pub fn random(len: usize, seed: u64) -> Random {
+    Random { len, seed }
+}
+
+pub struct Random {
+    len: usize,
+    seed: u64,
+}
+
+impl Recipe for Random {
+    fn assemble(&self, target: &mut Fragment) {
+        let start = target.len();
+        target.resize(start + self.len, 0);
+        ChaCha8Rng::seed_from_u64(self.seed).fill_bytes(&mut target[start..]);
+    }
+}
+
+
+//------------ choice --------------------------------------------------------
+
+/// Returns a recipe assembling one of several recipes picked at random.
+///
+/// Every time the recipe is assembled, one of _items_ is picked using a
+/// random number generator seeded with _seed._ The generator keeps its
+/// state between assemblies, so assembling the recipe repeatedly – e.g.,
+/// inside [`repeat`] – picks a sequence of items. A new recipe with the
+/// same seed picks the same sequence again.
+///
+/// If _items_ is empty, the recipe produces no data.
+pub fn choice<R: Recipe>(
+    seed: u64, items: impl IntoIterator<Item = R>
+) -> Choice<R> {
+    Choice {
+        items: items.into_iter().collect(),
+        rng: RefCell::new(ChaCha8Rng::seed_from_u64(seed)),
+    }
+}
+
+pub struct Choice<R> {
+    items: Vec<R>,
+    rng: RefCell<ChaCha8Rng>,
+}
+
+impl<R: Recipe> Recipe for Choice<R> {
+    fn assemble(&self, target: &mut Fragment) {
+        if self.items.is_empty() {
+            return
+        }
+        let idx = self.rng.borrow_mut().gen_range(0..self.items.len());
+        self.items[idx].assemble(target)
+    }
+}
+
+
+//------------ counter -------------------------------------------------------
+
+/// Returns a recipe producing a different integer each time.
+///
+/// The first time the recipe is assembled, it writes _start_ as an
+/// unsigned integer of the given _width_ and byte order. Each further
+/// time, _step_ is added first. The value wraps around when it exceeds
+/// the range of the width, as sequence numbers and serials do. If the
+/// fragment has flipped byte order enabled, the opposite byte order is
+/// used.
+///
+/// Assembling the recipe repeatedly, e.g., in a loop or inside
+/// [`repeat`], produces a sequence of increasing numbers.
+pub fn counter(
+    start: u64, step: i64, width: Width, endian: Endian
+) -> Counter {
+    Counter {
+        next: Cell::new(start & width.max()), step, width, endian
+    }
+}
+
+pub struct Counter {
+    next: Cell<u64>,
+    step: i64,
+    width: Width,
+    endian: Endian,
+}
+
+impl Counter {
+    /// Returns the value the recipe writes next.
+    pub fn peek(&self) -> u64 {
+        self.next.get()
+    }
+}
+
+impl Recipe for Counter {
+    fn assemble(&self, target: &mut Fragment) {
+        let value = self.next.get();
+        self.next.set(
+            value.wrapping_add_signed(self.step) & self.width.max()
+        );
+        let width = self.width.octets();
+        let mut value = value.to_be_bytes();
+        let value = &mut value[8 - width..];
+        if (self.endian == Endian::Little) != target.is_endian_flipped() {
+            value.reverse()
+        }
+        target.extend_from_slice(value)
+    }
+}
+
+
+//------------ presented -----------------------------------------------------
+
+/// Returns a recipe with the given presentation form.
+///
+/// The recipe assembles whatever _recipe_ assembles and presents as
+/// _text._ This allows pairing data with its text form where the two have
+/// to be given separately – or to deliberately have them disagree.
+pub fn presented<R: Recipe>(
+    recipe: R, text: impl Into<String>
+) -> Presented<R> {
+    Presented { recipe, text: text.into() }
+}
+
+pub struct Presented<R> {
+    recipe: R,
+    text: String,
+}
+
+impl<R: Recipe> Recipe for Presented<R> {
+    fn assemble(&self, target: &mut Fragment) {
+        self.recipe.assemble(target)
+    }
+}
+
+impl<R: Recipe> Presentation for Presented<R> {
+    fn present(&self, target: &mut String) {
+        target.push_str(&self.text)
+    }
+}
+
+
+//------------ Overrides -----------------------------------------------------
+
+/// A set of replacements for individual fields of a builder.
+///
+/// Builders for structured data keep a value of this type and consult it
+/// for every field they assemble. If a replacement has been registered
+/// for the field’s path, its data is used instead of the field’s regular
+/// encoding. Length fields that are calculated by the builder are left
+/// alone if replaced. This allows corrupting a single field of otherwise
+/// correct data.
+///
+/// Paths are the field names listed in the documentation of the builder.
+/// The replacement recipes are kept and assembled together with the
+/// builder’s data, so they behave like any other part of it, e.g., in
+/// strict mode.
+#[derive(Clone, Debug, Default)]
+pub struct Overrides {
+    fields: Vec<(String, SharedRecipe)>,
+}
+
+impl Overrides {
+    /// Creates an empty set of overrides.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers _recipe_ as the replacement for the field at _path._
+    ///
+    /// Any earlier replacement for the same path is dropped.
+    pub fn insert(
+        &mut self, path: &str, recipe: impl Recipe + Send + Sync + 'static
+    ) {
+        let recipe = SharedRecipe::new(recipe);
+        match self.fields.iter_mut().find(|item| item.0 == path) {
+            Some(item) => item.1 = recipe,
+            None => self.fields.push((path.into(), recipe)),
+        }
+    }
+
+    /// Returns the replacement for the field at _path_ if there is one.
+    pub fn get(&self, path: &str) -> Option<&dyn Recipe> {
+        self.fields.iter().find(|item| item.0 == path).map(|item| {
+            &item.1 as &dyn Recipe
+        })
+    }
+
+    /// Returns whether the field at _path_ has been replaced.
+    pub fn contains(&self, path: &str) -> bool {
+        self.get(path).is_some()
+    }
+
+    /// Returns whether there are no replacements.
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty()
+    }
+
+    /// Assembles the field at _path._
+    ///
+    /// Uses the replacement if there is one or _default_ otherwise.
+    pub fn assemble_field(
+        &self, path: &str, default: impl Recipe, target: &mut Fragment
+    ) {
+        match self.get(path) {
+            Some(recipe) => recipe.assemble(target),
+            None => default.assemble(target),
+        }
+    }
+}
+
+
+//------------ OverrideField -------------------------------------------------
+
+/// A builder that allows replacing individual fields.
+pub trait OverrideField: Sized {
+    /// Replaces the field at _path_ with whatever _recipe_ assembles.
+    fn override_field(
+        self, path: &str, recipe: impl Recipe + Send + Sync + 'static
+    ) -> Self;
+}
+
+
+//------------ equivalent and diff -------------------------------------------
+
+/// Returns whether two recipes assemble to the same data.
+pub fn equivalent(left: &impl Recipe, right: &impl Recipe) -> bool {
+    left.to_fragment() == right.to_fragment()
+}
+
+/// Compares the data assembled by two recipes.
+///
+/// Returns `None` if both recipes assemble to the same data or information
+/// on where the data starts to differ otherwise.
+///
+/// This compares the data octet by octet. For DER encoded data,
+/// [`der::diff`][super::der::diff] provides a comparison that is aware of
+/// the structure of the data.
+pub fn diff(left: &impl Recipe, right: &impl Recipe) -> Option<Difference> {
+    Difference::compare(&left.to_fragment(), &right.to_fragment())
+}
+
+/// Information on where two pieces of assembled data differ.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Difference {
+    /// The offset of the first octet that differs.
+    ///
+    /// If one piece of data is a prefix of the other, this is the length
+    /// of the shorter one.
+    pub offset: usize,
+
+    /// The length of the left data.
+    pub left_len: usize,
+
+    /// The length of the right data.
+    pub right_len: usize,
+}
+
+impl Difference {
+    /// Compares two slices.
+    pub fn compare(left: &[u8], right: &[u8]) -> Option<Self> {
+        if left == right {
+            return None
+        }
+        Some(Difference {
+            offset: left.iter().zip(right).take_while(|(l, r)| l == r).count(),
+            left_len: left.len(),
+            right_len: right.len(),
+        })
+    }
+}
+
+impl fmt::Display for Difference {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "data differs at offset {}", self.offset)?;
+        if self.left_len != self.right_len {
+            write!(
+                f, " (lengths {} and {})", self.left_len, self.right_len
+            )?;
+        }
+        Ok(())
+    }
+}
+
+
+//------------ ValidationError -----------------------------------------------
+
+/// Assembling a recipe in strict mode found problems.
+#[derive(Clone, Debug)]
+pub struct ValidationError {
+    errors: Vec<String>,
+}
+
+impl ValidationError {
+    /// Returns the descriptions of the individual problems.
+    pub fn problems(&self) -> &[String] {
+        &self.errors
+    }
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("invalid data")?;
+        for (idx, item) in self.errors.iter().enumerate() {
+            if idx == 0 {
+                f.write_str(": ")?;
+            }
+            else {
+                f.write_str("; ")?;
+            }
+            f.write_str(item)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ValidationError { }
+
+
+//------------ LimitError ----------------------------------------------------
+
+/// Assembling a recipe exceeded the maximum size of the data.
+#[derive(Clone, Copy, Debug)]
+pub struct LimitError {
+    limit: usize,
+}
+
+impl LimitError {
+    /// Returns the limit that was exceeded.
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+}
+
+impl fmt::Display for LimitError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "assembled data exceeds limit of {} octets", self.limit)
+    }
+}
+
+impl std::error::Error for LimitError { }
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn fragment_pool() {
+        let pool = FragmentPool::new();
+        let ptr = {
+            let frag = pool.assemble(&hex("01020304"));
+            assert_eq!(frag.as_slice(), b"\x01\x02\x03\x04");
+            frag.as_ptr()
+        };
+        assert_eq!(pool.len(), 1);
+        let frag = pool.assemble(&hex("0506"));
+        assert_eq!(frag.as_slice(), b"\x05\x06");
+        assert_eq!(frag.as_ptr(), ptr);
+        assert!(pool.is_empty());
+        let frag = frag.detach();
+        assert_eq!(frag, b"\x05\x06");
+        assert!(pool.is_empty());
+
+        *pool.get() = Fragment::strict();
+        assert!(!pool.get().is_strict());
+
+        {
+            let mut frag = pool.get();
+            frag.set_endian_flipped(true);
+            be(1u16).assemble(&mut frag);
+        }
+        assert_eq!(pool.assemble(&be(1u16)).as_slice(), b"\0\x01");
+    }
+
+    #[test]
+    fn clear() {
+        let mut frag = Fragment::strict();
+        frag.set_endian_flipped(true);
+        frag.push(1);
+        frag.clear();
+        assert!(frag.is_empty());
+        assert!(frag.is_strict());
+        assert!(frag.is_endian_flipped());
+        frag.push(1);
+        frag.reset();
+        assert!(frag.is_empty());
+        assert!(!frag.is_strict());
+        assert!(!frag.is_endian_flipped());
+
+        let mut frag = Fragment::strict();
+        let recipe = lp_string(Width::U8, repeat(256, hex("00")));
+        recipe.reassemble(&mut frag);
+        recipe.reassemble(&mut frag);
+        assert_eq!(frag.problems().len(), 1);
+    }
+
+    #[test]
+    fn difference() {
+        assert!(equivalent(&hex("0102"), &(be(1u8), be(2u8))));
+        assert_eq!(diff(&hex("0102"), &be(0x0102u16)), None);
+        assert_eq!(
+            diff(&hex("010203"), &hex("0102")),
+            Some(Difference { offset: 2, left_len: 3, right_len: 2 })
+        );
+        assert_eq!(
+            diff(&hex("010203"), &hex("010303")).unwrap().to_string(),
+            "data differs at offset 1"
+        );
+    }
+
+    #[test]
+    fn flipped() {
+        let msg = (be(0x0102u16), literal(b"ab"), be(0x03040506u32));
+        assert_eq!(
+            flip_endian(&msg).to_fragment(), b"\x02\x01ab\x06\x05\x04\x03"
+        );
+        assert_eq!(
+            flip_endian((be(1u16), flip_endian(be(2u16)))).to_fragment(),
+            b"\x01\x00\x00\x02"
+        );
+    }
+
+    #[test]
+    fn measured() {
+        let recipe = (
+            hex("00"),
+            labelled("msg", (
+                labelled("head", hex("0102")),
+                labelled("body", (hex("03"), labelled("ext", hex("0405")))),
+            )),
+            labelled("trailer", empty()),
+        );
+        let (frag, measurement) = recipe.measured();
+        assert_eq!(frag, b"\0\x01\x02\x03\x04\x05");
+        assert_eq!(measurement.len_of("msg"), Some(5));
+        assert_eq!(measurement.get("msg/body/ext").unwrap().offset, 4);
+        assert_eq!(measurement.len_of("msg/ext"), None);
+        assert_eq!(measurement.parts().len(), 2);
+        assert_eq!(
+            measurement.to_string(),
+            "msg: 5 octets at 1\n\
+             \x20 head: 2 octets at 1\n\
+             \x20 body: 3 octets at 3\n\
+             \x20   ext: 2 octets at 4\n\
+             trailer: 0 octets at 6\n"
+        );
+        assert_eq!(recipe.to_fragment(), frag);
+    }
+
+    #[test]
+    fn limited() {
+        assert_eq!(
+            repeat(4, hex("01")).to_fragment_limited(4).unwrap(),
+            b"\x01\x01\x01\x01"
+        );
+        let err = repeat(usize::MAX, hex("0102"))
+            .to_fragment_limited(1000).unwrap_err();
+        assert_eq!(err.limit(), 1000);
+        assert_eq!(
+            err.to_string(), "assembled data exceeds limit of 1000 octets"
+        );
+        assert!(pad_to(5, 0, empty()).to_fragment_limited(4).is_err());
+        assert!(
+            lp_string(Width::U16, repeat(usize::MAX, hex("01")))
+                .to_fragment_limited(10).is_err()
+        );
+    }
+
+    #[test]
+    fn limited_reassemble() {
+        // Reassembles into the same child twice and appends the result.
+        struct Twice<R>(R);
+
+        impl<R: Recipe> Recipe for Twice<R> {
+            fn assemble(&self, target: &mut Fragment) {
+                let mut child = target.child();
+                self.0.reassemble(&mut child);
+                assert!(child.len() <= 4);
+                self.0.reassemble(&mut child);
+                assert!(child.len() <= 4);
+                target.append(&child)
+            }
+        }
+
+        assert!(Twice(repeat(8, hex("01"))).to_fragment_limited(4).is_err());
+        assert_eq!(
+            Twice(repeat(3, hex("01"))).to_fragment_limited(4).unwrap(),
+            b"\x01\x01\x01"
+        );
+        let context = Context::new().with("x", hex("02"));
+        assert_eq!(Twice(param("x")).to_fragment_with(&context), b"\x02");
+    }
+
+    #[test]
+    #[should_panic(expected = "pad_to")]
+    fn limited_other_panic() {
+        let _ = pad_to(1, 0, hex("0102")).to_fragment_limited(4);
+    }
+
+    #[test]
+    fn varints() {
+        assert_eq!(varint(0).to_fragment(), b"\0");
+        assert_eq!(varint(64).to_fragment(), b"\x40");
+        assert_eq!(varint(624485).to_fragment(), b"\xe5\x8e\x26");
+        assert_eq!(varint(u64::MAX).to_fragment().len(), 10);
+        assert_eq!(signed_varint(64).to_fragment(), b"\xc0\0");
+        assert_eq!(signed_varint(-1).to_fragment(), b"\x7f");
+        assert_eq!(signed_varint(-123456).to_fragment(), b"\xc0\xbb\x78");
+        assert_eq!(varint(1).non_minimal(2).to_fragment(), b"\x81\x80\0");
+        assert_eq!(
+            signed_varint(-1).non_minimal(1).to_fragment(), b"\xff\x7f"
+        );
+    }
+
+    #[test]
+    fn lp_strings() {
+        assert_eq!(
+            lp_string(Width::U32, literal("ssh")).to_fragment(),
+            b"\0\0\0\x03ssh"
+        );
+        assert_eq!(lp_string(Width::U8, empty()).to_fragment(), b"\0");
+        assert!(
+            lp_string(Width::U8, repeat(256, hex("00")))
+                .to_strict_fragment().is_err()
+        );
+    }
+
+    #[test]
+    fn cstrings() {
+        assert_eq!(cstring(literal("abc")).to_fragment(), b"abc\0");
+        assert_eq!(cstring(hex("6100")).to_fragment(), b"a\0\0");
+        assert_eq!(
+            cstring(hex("6100")).to_strict_fragment().unwrap_err()
+                .to_string(),
+            "invalid data: embedded NUL at offset 1 of C string"
+        );
+        assert!(cstring(empty()).to_strict_fragment().is_ok());
+    }
+
+    #[test]
+    fn zigzags() {
+        assert_eq!(zigzag(0), 0);
+        assert_eq!(zigzag(-1), 1);
+        assert_eq!(zigzag(1), 2);
+        assert_eq!(zigzag(i64::MAX), u64::MAX - 1);
+        assert_eq!(zigzag(i64::MIN), u64::MAX);
+        assert_eq!(varint(zigzag(-64)).to_fragment(), b"\x7f");
+    }
+
+    #[test]
+    fn quic_varints() {
+        assert_eq!(quic_varint(37).to_fragment(), b"\x25");
+        assert_eq!(quic_varint(15293).to_fragment(), b"\x7b\xbd");
+        assert_eq!(
+            quic_varint(494878333).to_fragment(), b"\x9d\x7f\x3e\x7d"
+        );
+        assert_eq!(
+            quic_varint(151288809941952652).to_fragment(),
+            b"\xc2\x19\x7c\x5e\xff\x14\xe8\x8c"
+        );
+        assert_eq!(
+            quic_varint(37).width(Width::U16).to_fragment(), b"\x40\x25"
+        );
+        assert_eq!(
+            quic_varint(300).width(Width::U8).to_strict_fragment()
+                .unwrap_err().to_string(),
+            "invalid data: value 300 too large for 1 octet QUIC \
+             variable-length integer"
+        );
+    }
+
+    #[test]
+    fn floats() {
+        assert_eq!(be(1.0f32).to_fragment(), b"\x3f\x80\0\0");
+        assert_eq!(le(1.0f32).to_fragment(), b"\0\0\x80\x3f");
+        assert_eq!(be(-2.0f64).to_fragment(), b"\xc0\0\0\0\0\0\0\0");
+        assert_eq!(le(-2.0f64).to_fragment(), b"\0\0\0\0\0\0\0\xc0");
+        assert_eq!(
+            flip_endian(be(1.0f32)).to_fragment(), b"\0\0\x80\x3f"
+        );
+    }
+
+    #[test]
+    fn little_endian() {
+        assert_eq!(le(0x0102u16).to_fragment(), b"\x02\x01");
+        assert_eq!(le(-2i8).to_fragment(), b"\xfe");
+        assert_eq!(le(1u128).to_fragment().len(), 16);
+        assert_eq!(
+            flip_endian((le(0x0102u16), be(0x0304u16))).to_fragment(),
+            b"\x01\x02\x04\x03"
+        );
+    }
+
+    #[test]
+    fn hex_integers() {
+        assert_eq!(be_hex("00 ff01").to_fragment(), b"\0\xff\x01");
+        assert_eq!(
+            (hex("aa"), flip_endian(be_hex("00ff01"))).to_fragment(),
+            b"\xaa\x01\xff\0"
+        );
+    }
+
+    #[test]
+    fn signing() {
+        let data = hex("0102");
+        let signed = (
+            &data,
+            sign(&data, |data: &[u8]| {
+                data.iter().map(|x| x ^ 0xFF).collect::<Vec<_>>()
+            })
+        );
+        assert_eq!(signed.to_fragment(), b"\x01\x02\xfe\xfd");
+    }
+
+    #[test]
+    fn maps() {
+        assert_eq!(
+            (hex("01"), map(hex("0203"), |mut data| {
+                data.reverse();
+                data
+            })).to_fragment(),
+            b"\x01\x03\x02"
+        );
+    }
+
+    #[test]
+    fn lazies() {
+        let value = Cell::new(0u8);
+        let recipe = lazy(|| {
+            value.set(value.get() + 1);
+            be(value.get())
+        });
+        assert_eq!(value.get(), 0);
+        assert_eq!(recipe.to_fragment(), b"\x01");
+        assert_eq!(recipe.to_fragment(), b"\x02");
+    }
+
+    #[test]
+    fn selects() {
+        let choose = |key| {
+            select(key).case(1, hex("01")).case(2, be(2u16))
+        };
+        assert_eq!(choose(1).to_fragment(), b"\x01");
+        assert_eq!(choose(2).to_fragment(), b"\0\x02");
+        assert_eq!(choose(3).fallback(empty()).to_fragment(), b"");
+        assert!(std::panic::catch_unwind(|| choose(3).to_fragment()).is_err());
+    }
+
+    #[test]
+    fn options() {
+        let field = |present: bool| present.then(|| hex("02"));
+        assert_eq!((hex("01"), field(true)).to_fragment(), b"\x01\x02");
+        assert_eq!((hex("01"), field(false)).to_fragment(), b"\x01");
+    }
+
+    #[test]
+    fn sequences() {
+        let items = [be(1u8), be(2u8)];
+        assert_eq!(items.to_fragment(), b"\x01\x02");
+        assert_eq!(items[..1].to_fragment(), b"\x01");
+        assert_eq!(
+            (1..4).map(|x| be(x as u16)).collect::<Vec<_>>().to_fragment(),
+            b"\0\x01\0\x02\0\x03"
+        );
+        assert_eq!(Vec::<Empty>::new().to_fragment(), b"");
+    }
+
+    #[test]
+    fn boxes() {
+        let items = [be(1u8).boxed(), hex("02").boxed()];
+        assert_eq!(items.to_fragment(), b"\x01\x02");
+        assert_eq!(
+            (Box::new(be(3u8)), items).to_fragment(), b"\x03\x01\x02"
+        );
+    }
+
+    #[test]
+    fn shared() {
+        let header = Rc::new(hex("0102"));
+        let first = (header.clone(), be(3u8));
+        let second = (header, be(4u8));
+        assert_eq!(first.to_fragment(), b"\x01\x02\x03");
+        assert_eq!(second.to_fragment(), b"\x01\x02\x04");
+
+        let header: Arc<dyn Recipe + Send + Sync> = Arc::new(be(1u8));
+        let other = header.clone();
+        std::thread::spawn(move || {
+            assert_eq!(other.to_fragment(), b"\x01")
+        }).join().unwrap();
+        assert_eq!(header.to_fragment(), b"\x01");
+    }
+
+    #[test]
+    fn plain_data() {
+        assert_eq!(("ab", String::from("c")).to_fragment(), b"abc");
+        assert_eq!(
+            (b"a", &b"bc"[..], vec![0u8, 1], [2u8]).to_fragment(),
+            b"abc\0\x01\x02"
+        );
+    }
+
+    #[test]
+    fn counters() {
+        let seq = counter(0xFE, 1, Width::U8, Endian::Big);
+        assert_eq!(repeat(3, &seq).to_fragment(), b"\xfe\xff\x00");
+        assert_eq!(seq.peek(), 1);
+        let seq = counter(1, -2, Width::U16, Endian::Little);
+        assert_eq!(seq.to_fragment(), b"\x01\x00");
+        assert_eq!(seq.to_fragment(), b"\xff\xff");
+        assert_eq!(flip_endian(&seq).to_fragment(), b"\xff\xfd");
+    }
+
+    #[test]
+    fn placeholders() {
+        let len = placeholder(Width::U16, Endian::Big);
+        let total = placeholder(Width::U8, Endian::Big);
+        let msg = (
+            total.reserve(), len.reserve(), hex("0102 03"),
+            len.fill_len(), hex("04"), total.fill(|data, _| data.len() as u64)
+        );
+        assert_eq!(msg.to_fragment(), b"\x07\x00\x03\x01\x02\x03\x04");
+        assert_eq!(
+            (hex("ff"), &msg).to_fragment(),
+            b"\xff\x08\x00\x03\x01\x02\x03\x04"
+        );
+        assert!(
+            (total.reserve(), repeat(256, hex("00")), total.fill_len())
+                .to_strict_fragment().is_err()
+        );
+        let fresh = placeholder(Width::U8, Endian::Little);
+        assert!(std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            fresh.fill_len().to_fragment()
+        })).is_err());
+    }
+
+    #[test]
+    fn anchors() {
+        let msg = (
+            (anchor("start"), hex("aa")),
+            offset_to("name", Width::U16, Base::Start).flags(0xC000),
+            offset_to("name", Width::U8, Base::FieldEnd),
+            (anchor("name"), hex("bb")),
+            offset_to("start", Width::U8, Base::Field),
+            offset_to("name", Width::U16, Base::Anchor("start".into()))
+                .endian(Endian::Little),
+        );
+        assert_eq!(
+            msg.to_fragment(),
+            b"\xaa\xc0\x04\x00\xbb\xfb\x04\x00"
+        );
+        assert_eq!(
+            (
+                skip(1, (hex("0102"), anchor("s"))),
+                offset_to("s", Width::U8, Base::Start)
+            ).to_fragment(),
+            b"\x02\x01"
+        );
+        assert!(
+            offset_to("missing", Width::U8, Base::Start)
+                .to_strict_fragment().is_err()
+        );
+        assert!(
+            (
+                offset_to("far", Width::U8, Base::Start),
+                repeat(255, hex("00")), anchor("far")
+            ).to_strict_fragment().is_err()
+        );
+    }
+
+    #[test]
+    fn contexts() {
+        let msg = (param("serial"), hex("00"), param_or("name", hex("ff")));
+        let context = Context::new().with("serial", be(7u16));
+        assert_eq!(msg.to_fragment_with(&context), b"\0\x07\0\xff");
+        let context = context.with("name", "a").with("serial", be(8u8));
+        assert_eq!(msg.to_fragment_with(&context), b"\x08\0a");
+        assert!(std::panic::catch_unwind(|| msg.to_fragment()).is_err());
+
+        // Values are assembled in place.
+        let context = Context::new().with(
+            "serial", lp_string(Width::U8, repeat(256, hex("00")))
+        );
+        let mut target = Fragment::strict();
+        param("serial").assemble_with(&context, &mut target);
+        assert_eq!(target.len(), 257);
+        assert_eq!(target.problems().len(), 1);
+    }
+
+    #[test]
+    fn slices() {
+        let data = hex("0001020304");
+        assert_eq!(slice(1..3, &data).to_fragment(), b"\x01\x02");
+        assert_eq!(slice(3.., &data).to_fragment(), b"\x03\x04");
+        assert_eq!(slice(..=0, &data).to_fragment(), b"\x00");
+        assert_eq!(slice(4..9, &data).to_fragment(), b"\x04");
+        assert_eq!(slice(7.., &data).to_fragment(), b"");
+        assert_eq!(
+            (be(0xFFu8), slice(..1, &data)).to_fragment(), b"\xff\x00"
+        );
+        assert_eq!(truncate(2, &data).to_fragment(), b"\x00\x01");
+        assert_eq!(truncate(9, &data).to_fragment(), b"\0\x01\x02\x03\x04");
+        assert_eq!(skip(3, &data).to_fragment(), b"\x03\x04");
+        assert_eq!(skip(9, &data).to_fragment(), b"");
+    }
+
+    #[test]
+    fn repeats() {
+        assert_eq!(
+            repeat(3, hex("ab01")).to_fragment(), b"\xab\x01\xab\x01\xab\x01"
+        );
+        assert_eq!(repeat(0, hex("ab")).to_fragment(), b"");
+        assert_eq!(
+            repeat_with(3, |idx| be(idx as u16)).to_fragment(),
+            b"\x00\x00\x00\x01\x00\x02"
+        );
+    }
+
+    #[test]
+    fn padding() {
+        assert_eq!(pad_to(4, 0, hex("0102")).to_fragment(), b"\x01\x02\0\0");
+        assert_eq!(pad_to(2, 0xff, hex("0102")).to_fragment(), b"\x01\x02");
+        assert_eq!(
+            (hex("aa"), pad_to(2, 0, hex("010203")).overflow(
+                Overflow::Truncate
+            )).to_fragment(),
+            b"\xaa\x01\x02"
+        );
+        assert_eq!(
+            pad_to(2, 0, hex("010203")).overflow(Overflow::Leave)
+                .to_fragment(),
+            b"\x01\x02\x03"
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "inner data is 3 octets, limit is 2")]
+    fn padding_overflow() {
+        pad_to(2, 0, hex("010203")).to_fragment();
+    }
+
+    #[test]
+    fn alignment() {
+        assert_eq!(
+            (hex("010203"), align_to(4, 0)).to_fragment(), b"\x01\x02\x03\0"
+        );
+        assert_eq!(
+            (hex("01020304"), align_to(4, 0)).to_fragment(),
+            b"\x01\x02\x03\x04"
+        );
+        assert_eq!(
+            (hex("01"), align_to(8, 0xff), hex("02")).to_fragment(),
+            b"\x01\xff\xff\xff\xff\xff\xff\xff\x02"
+        );
+        assert_eq!((hex("01"), align_to(0, 0)).to_fragment(), b"\x01");
+    }
+
+    #[test]
+    fn length_prefixes() {
+        assert_eq!(
+            length_prefix(Width::U16, Endian::Big, hex("0102")).to_fragment(),
+            b"\0\x02\x01\x02"
+        );
+        assert_eq!(
+            (hex("ff"), length_prefix(Width::U32, Endian::Little, hex("01")))
+                .to_fragment(),
+            b"\xff\x01\0\0\0\x01"
+        );
+        assert_eq!(
+            flip_endian(length_prefix(Width::U16, Endian::Big, empty()))
+                .to_fragment(),
+            b"\0\0"
+        );
+        assert_eq!(
+            flip_endian(length_prefix(Width::U16, Endian::Big, hex("01")))
+                .to_fragment(),
+            b"\x01\0\x01"
+        );
+        let long = length_prefix(
+            Width::U8, Endian::Big, repeat(256, hex("aa"))
+        );
+        assert_eq!(long.to_fragment()[0], 0);
+        assert_eq!(
+            long.to_strict_fragment().unwrap_err().to_string(),
+            "invalid data: length 256 exceeds 1 octet length prefix"
+        );
+    }
+
+    #[test]
+    fn randoms() {
+        let data = random(1000, 7).to_fragment();
+        assert_eq!(data.len(), 1000);
+        assert_eq!(data, random(1000, 7).to_fragment());
+        assert_ne!(data, random(1000, 8).to_fragment());
+        assert_eq!(
+            (hex("ff"), random(10, 7)).to_fragment()[1..], data[..10]
+        );
+        // The generator must not change between versions.
+        assert_eq!(data[..8], *b"\xbb\x43\xd7\x23\x34\x53\x65\x28");
+    }
+
+    #[test]
+    fn choices() {
+        let items = [hex("01"), hex("02"), hex("03")];
+        let data = repeat(100, choice(5, items.iter())).to_fragment();
+        assert_eq!(data, repeat(100, choice(5, items.iter())).to_fragment());
+        assert!(data.iter().all(|octet| (1..=3).contains(octet)));
+        assert!((1..=3).all(|octet| data.contains(&octet)));
+        assert_eq!(choice(5, Vec::<Empty>::new()).to_fragment(), b"");
+    }
+
+    #[test]
+    fn presentation() {
+        let item = presented(be(0x0102u16), "258");
+        assert_eq!(item.to_fragment(), b"\x01\x02");
+        assert_eq!(item.to_presentation(), "258");
+    }
+
+    #[test]
+    fn overrides() {
+        let mut overrides = Overrides::new();
+        overrides.insert("a", hex("01"));
+        overrides.insert("a", hex("0203"));
+        let mut target = Fragment::new();
+        overrides.assemble_field("a", hex("ff"), &mut target);
+        overrides.assemble_field("b", hex("ff"), &mut target);
+        assert_eq!(target, b"\x02\x03\xff");
+
+        // The replacement is assembled in place and in strict mode.
+        overrides.insert("a", exec(|target: &mut Fragment| {
+            target.validate(false, || "bad".into());
+            target.push(target.len() as u8)
+        }));
+        let mut target = Fragment::strict();
+        target.push(0);
+        overrides.assemble_field("a", hex("ff"), &mut target);
+        assert_eq!(target, b"\x00\x01");
+        assert_eq!(target.problems(), ["bad"]);
+    }
+
+    #[test]
+    fn strict_mode() {
+        let check = exec(|target: &mut Fragment| {
+            target.validate(false, || "bad".into())
+        });
+        assert_eq!(check.to_fragment(), b"");
+        assert_eq!(
+            (hex("01"), &check, &check).to_strict_fragment()
+                .unwrap_err().to_string(),
+            "invalid data: bad; bad"
+        );
+        assert_eq!(hex("01").to_strict_fragment().unwrap(), b"\x01");
+    }
+}
@@ -0,0 +1,191 @@
+//! Certificate Transparency structures.
+//!
+//! This module provides recipes for the signed certificate timestamps
+//! (SCTs) defined in RFC 6962 and their embedding into TLS handshakes and
+//! X.509 certificates.
+//!
+//! Log IDs and signatures are given as recipes. They are not checked in
+//! any way, so invalid log IDs – including ones of the wrong length – and
+//! bad signatures can be created easily.
+
+use super::core::{Recipe, be, literal};
+use super::{der, tls};
+
+
+//------------ Constants -----------------------------------------------------
+
+/// The SCT version v1.
+pub const V1: u8 = 0;
+
+/// The hash algorithm value for SHA-256.
+pub const HASH_SHA256: u8 = 4;
+
+/// The signature algorithm value for RSA.
+pub const SIGNATURE_RSA: u8 = 1;
+
+/// The signature algorithm value for ECDSA.
+pub const SIGNATURE_ECDSA: u8 = 3;
+
+/// The TLS extension type of the signed_certificate_timestamp extension.
+pub const TLS_EXTENSION_TYPE: u16 = 18;
+
+
+//------------ sct -----------------------------------------------------------
+
+/// Returns a recipe for a signed certificate timestamp.
+///
+/// The _log_id_ should be the 32 octet SHA-256 hash of the log’s public
+/// key. The _extensions_ recipe provides the content of the extensions
+/// field which should normally be empty. The _signature_ should be a
+/// [`digitally_signed`] recipe.
+pub fn sct<L: Recipe, E: Recipe, S: Recipe>(
+    version: u8, log_id: L, timestamp: u64, extensions: E, signature: S
+) -> impl Recipe {
+    (
+        literal([version]),
+        log_id,
+        be(timestamp),
+        tls::vec16(extensions),
+        signature
+    )
+}
+
+
+//------------ digitally_signed ----------------------------------------------
+
+/// Returns a recipe for a digitally-signed element.
+///
+/// The element consists of the hash and signature algorithms followed by
+/// the signature provided by _signature_ as a vector with a two octet
+/// length.
+pub fn digitally_signed<S: Recipe>(
+    hash: u8, signature_algorithm: u8, signature: S
+) -> impl Recipe {
+    (literal([hash, signature_algorithm]), tls::vec16(signature))
+}
+
+
+//------------ sct_list ------------------------------------------------------
+
+/// Returns a recipe for a list of serialized SCTs.
+///
+/// The recipe _items_ should produce a sequence of [`serialized_sct`]s.
+pub fn sct_list<R: Recipe>(items: R) -> impl Recipe {
+    tls::vec16(items)
+}
+
+/// Returns a recipe for a serialized SCT as an element of an SCT list.
+pub fn serialized_sct<R: Recipe>(sct: R) -> impl Recipe {
+    tls::vec16(sct)
+}
+
+
+//------------ Embeddings ----------------------------------------------------
+
+/// Returns a recipe for the signed_certificate_timestamp TLS extension.
+///
+/// The _list_ should be an [`sct_list`].
+pub fn tls_extension<R: Recipe>(list: R) -> impl Recipe {
+    tls::extension(TLS_EXTENSION_TYPE, list)
+}
+
+/// Returns a recipe for the X.509 extension carrying an SCT list.
+///
+/// The extension is the non-critical extension with the OID
+/// 1.3.6.1.4.1.11129.2.4.2 as used in certificates. The _list_, which
+/// should be an [`sct_list`], is wrapped into an octet string.
+pub fn x509_extension<R: Recipe>(list: R) -> impl Recipe {
+    der::sequence((
+        der::oid([1, 3, 6, 1, 4, 1, 11129, 2, 4, 2]),
+        der::octetstring(der::octetstring(list)),
+    ))
+}
+
+/// Returns a recipe for the precertificate poison X.509 extension.
+///
+/// This is the critical extension with the OID 1.3.6.1.4.1.11129.2.4.3
+/// and a null value which marks a precertificate.
+pub fn precert_poison() -> impl Recipe {
+    der::sequence((
+        der::oid([1, 3, 6, 1, 4, 1, 11129, 2, 4, 3]),
+        der::boolean(true),
+        der::octetstring(der::null()),
+    ))
+}
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::recipe::core::{empty, hex, repeat};
+
+    #[test]
+    fn sct_structure() {
+        let ext = tls_extension(sct_list(serialized_sct(sct(
+            V1, literal([0xAA; 32]), 0x0102, empty(),
+            digitally_signed(HASH_SHA256, SIGNATURE_ECDSA, hex("5151"))
+        )))).to_fragment();
+        assert_eq!(&ext[..8], b"\x00\x12\x00\x35\x00\x33\x00\x31");
+        assert_eq!(ext[8], V1);
+        assert_eq!(&ext[9..41], &[0xAA; 32]);
+        assert_eq!(
+            &ext[41..],
+            b"\x00\x00\x00\x00\x00\x00\x01\x02\x00\x00\x04\x03\x00\x02\x51\x51"
+        );
+    }
+
+    #[test]
+    fn broken_lengths() {
+        // Log IDs aren’t checked, so a short one simply shifts the rest.
+        let short = sct(
+            V1, literal([0xAA; 31]), 1, empty(),
+            digitally_signed(HASH_SHA256, SIGNATURE_RSA, empty())
+        ).to_fragment();
+        assert_eq!(short.len(), 1 + 31 + 8 + 2 + 4);
+        assert_eq!(&short[32..40], b"\0\0\0\0\0\0\0\x01");
+
+        // An oversized signature wraps the length around.
+        let signed = digitally_signed(
+            HASH_SHA256, SIGNATURE_RSA, literal(vec![0u8; 0x10001])
+        );
+        assert_eq!(&signed.to_fragment()[..4], b"\x04\x01\x00\x01");
+        let list = sct_list(serialized_sct(sct(
+            V1, empty(), 0, empty(), signed
+        ))).to_fragment();
+        assert_eq!(&list[..4], b"\x00\x12\x00\x10");
+    }
+
+    #[test]
+    fn strict_lengths() {
+        // Both the signature and the SCT around it are too long.
+        let signed = digitally_signed(
+            HASH_SHA256, SIGNATURE_RSA, repeat(0x10001, hex("00"))
+        );
+        assert_eq!(
+            sct_list(serialized_sct(sct(
+                V1, empty(), 0, empty(), signed
+            ))).to_strict_fragment().unwrap_err().problems(),
+            [
+                "length 65537 exceeds 2 octet length prefix",
+                "length 65552 exceeds 2 octet length prefix",
+                "length 65554 exceeds 2 octet length prefix",
+            ]
+        );
+    }
+
+    #[test]
+    fn x509_extensions() {
+        assert_eq!(
+            x509_extension(sct_list(empty())).to_fragment(),
+            b"\x30\x12\x06\x0a\x2b\x06\x01\x04\x01\xd6\x79\x02\x04\x02\
+              \x04\x04\x04\x02\x00\x00"
+        );
+        assert_eq!(
+            precert_poison().to_fragment(),
+            b"\x30\x13\x06\x0a\x2b\x06\x01\x04\x01\xd6\x79\x02\x04\x03\
+              \x01\x01\xff\x04\x02\x05\x00"
+        );
+    }
+}
@@ -1,6 +1,9 @@
 //! Encoding data using DER.
 
-use super::core::{Fragment, Recipe, literal};
+use std::{fmt, ops};
+use super::core::{
+    Fragment, Hex, Measurement, Part, Presentation, Recipe, hex
+};
 
 
 //============ Basic Machinery ===============================================
@@ -14,7 +17,7 @@ pub trait DerContent {
     fn assemble_content(&self, target: &mut Fragment);
 }
 
-impl<'a, C: DerContent> DerContent for &'a C {
+impl<C: DerContent> DerContent for &C {
     fn is_constructed(&self) -> bool {
         (*self).is_constructed()
     }
@@ -92,6 +95,17 @@ pub fn private<C>(number: u128, content: C) -> Value<C> {
     value(Tag::new(Class::Private, number), content)
 }
 
+/// Returns a recipe for an explicitly tagged value in the context class.
+///
+/// The recipe given via _content_ is wrapped in a constructed value with
+/// the given context-specific tag. It is most useful with recipes producing
+/// complete DER values.
+pub fn explicit<R>(
+    number: u128, content: R
+) -> Value<ConstructedDerContent<R>> {
+    context(number, constructed(content))
+}
+
 
 pub struct Value<C> {
     tag: Tag,
@@ -111,7 +125,7 @@ impl<C: DerContent> Value<C> {
             // subsequent octets with bit 8 forced to 1.
             let usize_octets = (usize::BITS >> 3) as usize;
             let octets = (
-                ((usize::BITS - length.leading_zeros()) >> 3) + 1
+                (usize::BITS - length.leading_zeros()).div_ceil(8)
             ) as usize;
             target.push((octets as u8) | 0x80);
             target.extend_from_slice(
@@ -124,11 +138,18 @@ impl<C: DerContent> Value<C> {
 
 impl<C: DerContent> Recipe for Value<C> {
     fn assemble(&self, target: &mut Fragment) {
-        let mut content = Fragment::new();
-        self.content.assemble_content(&mut content);
+        // We assemble the content straight into the target, then append
+        // tag and length and finally move those two to the front. This
+        // avoids allocating a temporary fragment for every value.
+        let mark = target.mark();
+        self.content.assemble_content(target);
+        if target.is_exceeded() {
+            return
+        }
+        let content_end = target.len();
         self.tag.assemble(self.content.is_constructed(), target);
-        Self::assemble_length(content.len(), target);
-        target.extend_from_slice(content.as_ref());
+        Self::assemble_length(content_end - mark.start(), target);
+        target.move_before(&mark, content_end);
     }
 }
 
@@ -206,13 +227,13 @@ impl<C: IntegerContent> DerContent for Integer<C> {
 /// Assembles an unsigned integer from an slice in network byte order.
 fn assemble_unsigned_slice(mut slice: &[u8], target: &mut Fragment) {
     // Skip over empty octets.
-    while slice.get(0).copied() == Some(0) {
+    while slice.first().copied() == Some(0) {
         slice = &slice[1..];
     }
 
     // If the left-most bit is set, we need to add another octet to signal
     // that we have a positive integer.
-    if slice.get(0).copied().unwrap_or(0xFF) & 0x80 != 0 {
+    if slice.first().copied().unwrap_or(0xFF) & 0x80 != 0 {
         target.push(0);
     }
 
@@ -224,13 +245,13 @@ fn assemble_unsigned_slice(mut slice: &[u8], target: &mut Fragment) {
 fn assemble_signed_slice(mut slice: &[u8], target: &mut Fragment) {
     // Check the left-most bit. If it is not set, the number is positive
     // and we can fall back to an unsigned slice.
-    if slice.get(0).copied().unwrap_or(0xFF) & 0x80 == 0 {
+    if slice.first().copied().unwrap_or(0xFF) & 0x80 == 0 {
         return assemble_unsigned_slice(slice, target)
     }
 
     // We have a non-empty negative number. Because of the two’s complement
     // thing, ununsed left octets are 0xFF.
-    while slice.get(0).copied() == Some(0xFF) {
+    while slice.first().copied() == Some(0xFF) {
         slice = &slice[1..];
     }
 
@@ -318,7 +339,7 @@ impl IntegerContent for isize {
     }
 }
 
-impl<'a> IntegerContent for &'a [u8] {
+impl IntegerContent for &[u8] {
     fn assemble_integer(&self, target: &mut Fragment) {
         assemble_signed_slice(self, target);
     }
@@ -357,6 +378,21 @@ impl<C: AsRef<[u8]>> DerContent for IntegerSlice<C> {
 }
 
 
+//------------ integer_hex ---------------------------------------------------
+
+/// Returns a recipe for writing a DER-encoded integer given in hex.
+///
+/// The content octets are taken from the hex string exactly as written,
+/// including any leading zeros. Thus, the integer is only encoded
+/// correctly if the string is. The string is checked as for
+/// [`hex`].
+pub fn integer_hex<T: AsRef<str>>(
+    content: T
+) -> Value<SimpleDerContent<Hex<T>>> {
+    universal(2, simple(hex(content)))
+}
+
+
 //------------ bitstring -----------------------------------------------------
 
 /// Returns a recipe for writing a DER-encoded bitstring.
@@ -370,11 +406,54 @@ impl<C: AsRef<[u8]>> DerContent for IntegerSlice<C> {
 /// Naturally, _unused_ cannot be larger than 7. However, in order to make
 /// it possible to create broken values, the recipe does not check this
 /// either.
-pub fn bitstring<R: Recipe>(unused: u8, content: R) -> impl Recipe + DerContent {
-    StringValue::new(
-        Tag::universal(3),
-        sequence((literal([unused]), content))
-    )
+///
+/// In strict mode, both conditions as well as that there are no unused bits
+/// if the content is empty are checked.
+pub fn bitstring<R: Recipe>(
+    unused: u8, content: R
+) -> impl Recipe + DerContent {
+    BitString { unused, content }
+}
+
+struct BitString<R> {
+    unused: u8,
+    content: R,
+}
+
+impl<R: Recipe> DerContent for BitString<R> {
+    fn is_constructed(&self) -> bool {
+        false
+    }
+
+    fn assemble_content(&self, target: &mut Fragment) {
+        target.push(self.unused);
+        let start = target.len();
+        self.content.assemble(target);
+        if !target.is_strict() {
+            return
+        }
+        let unused = self.unused;
+        target.validate(unused < 8, || {
+            format!("bit string with {} unused bits", unused)
+        });
+        let last = target[start..].last().copied();
+        target.validate(last.is_some() || unused == 0, || {
+            "empty bit string with unused bits".into()
+        });
+        if let Some(last) = last {
+            let mask = 0xFFu8.checked_shr(8 - u32::from(unused.min(8)));
+            let mask = mask.unwrap_or(0);
+            target.validate(last & mask == 0, || {
+                "unused bits in bit string not zero".into()
+            });
+        }
+    }
+}
+
+impl<R: Recipe> Recipe for BitString<R> {
+    fn assemble(&self, target: &mut Fragment) {
+        universal(3, self).assemble(target)
+    }
 }
 
 
@@ -414,11 +493,69 @@ impl DerContent for Null {
 //------------ oid -----------------------------------------------------------
 
 /// Returns a recipe for writing an object identifier.
+///
+/// The arcs of the identifier are not checked except in strict mode.
+/// The encoding of individual subidentifiers can be broken on purpose via
+/// the methods of [`Oid`].
 pub fn oid<const N: usize>(items: [u128; N]) -> Oid<N> {
-    Oid(items)
+    Oid {
+        arcs: items,
+        first: None,
+        padding: Vec::new(),
+        big_arcs: Vec::new(),
+    }
 }
 
-pub struct Oid<const N: usize>([u128; N]);
+/// An object identifier.
+///
+/// The knobs of this type refer to the encoded subidentifiers rather than
+/// the arcs: subidentifier 0 is the combination of the first two arcs,
+/// subidentifier _n_ is arc _n + 1._ An identifier with a single arc is
+/// encoded as that arc alone and one without arcs has no content unless
+/// a first subidentifier is given. The presentation format always shows
+/// the original arcs.
+pub struct Oid<const N: usize> {
+    arcs: [u128; N],
+
+    /// A value replacing the first subidentifier.
+    first: Option<u128>,
+
+    /// Subidentifiers and the number of 0x80 octets to put before them.
+    padding: Vec<(usize, usize)>,
+
+    /// Subidentifiers replaced by big-endian octets of any length.
+    big_arcs: Vec<(usize, Vec<u8>)>,
+}
+
+impl<const N: usize> Oid<N> {
+    /// Uses _value_ as the first subidentifier.
+    ///
+    /// Normally, the first subidentifier is calculated from the first two
+    /// arcs. This allows, e.g., a value of 80 or more – which implies a
+    /// first arc of 2 – while giving a different first arc for the
+    /// presentation format.
+    pub fn first_subidentifier(mut self, value: u128) -> Self {
+        self.first = Some(value);
+        self
+    }
+
+    /// Puts _count_ leading 0x80 octets before a subidentifier.
+    ///
+    /// This results in a non-minimal encoding of subidentifier _index._
+    pub fn non_minimal(mut self, index: usize, count: usize) -> Self {
+        self.padding.push((index, count));
+        self
+    }
+
+    /// Replaces a subidentifier with a number of arbitrary size.
+    ///
+    /// The number is given in _value_ as big-endian octets and can thus be
+    /// larger than 128 bits. Leading zero octets are ignored.
+    pub fn big_arc(mut self, index: usize, value: impl Into<Vec<u8>>) -> Self {
+        self.big_arcs.push((index, value.into()));
+        self
+    }
+}
 
 impl<const N: usize> DerContent for Oid<N> {
     fn is_constructed(&self) -> bool {
@@ -426,9 +563,46 @@ impl<const N: usize> DerContent for Oid<N> {
     }
 
     fn assemble_content(&self, target: &mut Fragment) {
-        assemble_base_7((self.0[0] * 40) + self.0[1], target);
-        for value in &self.0[2..] {
-            assemble_base_7(*value, target)
+        if target.is_strict() {
+            let arcs = self.arcs;
+            target.validate(N >= 2, || {
+                format!("object identifier with {} arcs", N)
+            });
+            if N < 2 {
+                return
+            }
+            target.validate(arcs[0] < 3, || {
+                format!("object identifier with first arc {}", arcs[0])
+            });
+            target.validate(arcs[0] == 2 || arcs[1] < 40, || {
+                format!(
+                    "object identifier with second arc {} under {}",
+                    arcs[1], arcs[0]
+                )
+            });
+        }
+        // With less than two arcs there is nothing to combine, so the
+        // single arc if any becomes the first subidentifier.
+        let (first, rest) = if N >= 2 {
+            let first = self.first.unwrap_or_else(|| {
+                (self.arcs[0] * 40) + self.arcs[1]
+            });
+            (Some(first), &self.arcs[2..])
+        }
+        else {
+            (self.first.or_else(|| self.arcs.first().copied()), &[][..])
+        };
+        let subids = first.into_iter().chain(rest.iter().copied());
+        for (index, value) in subids.enumerate() {
+            for (_, count) in self.padding.iter().filter(|item| {
+                item.0 == index
+            }) {
+                target.resize(target.len().saturating_add(*count), 0x80)
+            }
+            match self.big_arcs.iter().find(|item| item.0 == index) {
+                Some((_, big)) => assemble_big_base_7(big, target),
+                None => assemble_base_7(value, target),
+            }
         }
     }
 }
@@ -439,6 +613,17 @@ impl<const N: usize> Recipe for Oid<N> {
     }
 }
 
+impl<const N: usize> Presentation for Oid<N> {
+    fn present(&self, target: &mut String) {
+        for (idx, arc) in self.arcs.iter().enumerate() {
+            if idx > 0 {
+                target.push('.')
+            }
+            target.push_str(&arc.to_string())
+        }
+    }
+}
+
 
 //------------ sequence ------------------------------------------------------
 
@@ -460,7 +645,7 @@ pub fn set(items: impl Recipe) -> impl Recipe {
 
 /// Returns a recipe for writing the given content as PrintableString.
 ///
-/// Does not check if the content is a valid printable string.
+/// Only checks if the content is a valid printable string in strict mode.
 pub fn printable_string<R>(content: R) -> StringValue<R> {
     StringValue::new(Tag::universal(19), content)
 }
@@ -470,12 +655,22 @@ pub fn printable_string<R>(content: R) -> StringValue<R> {
 
 /// Returns a recipe for writing the given content as IA5String.
 ///
-/// Does not check if the content is a valid printable string.
+/// Only checks if the content is a valid IA5 string in strict mode.
 pub fn ia5_string<R>(content: R) -> StringValue<R> {
     StringValue::new(Tag::universal(22), content)
 }
 
 
+//------------ general_string ------------------------------------------------
+
+/// Returns a recipe for writing the given content as GeneralString.
+///
+/// Does not check if the content is a valid general string.
+pub fn general_string<R>(content: R) -> StringValue<R> {
+    StringValue::new(Tag::universal(27), content)
+}
+
+
 //------------ utc_time and generalized_time ---------------------------------
 
 /// Returns a recipe for writing a time as a UTCTime value.
@@ -530,7 +725,7 @@ pub struct GeneralizedTime<T>(T);
 
 impl<T: TimeContent> Recipe for GeneralizedTime<T> {
     fn assemble(&self, target: &mut Fragment) {
-        universal(23, self).assemble(target)
+        universal(24, self).assemble(target)
     }
 }
 
@@ -545,11 +740,291 @@ impl<T: TimeContent> DerContent for GeneralizedTime<T> {
 }
 
 
+//------------ x509_time -----------------------------------------------------
+
+/// Returns a recipe for writing a time as an X.509 Time value.
+///
+/// As required by section 4.1.2.5 of RFC 5280, times before the year 2050
+/// are written as UTCTime and times from 2050 onwards as GeneralizedTime.
+/// The year is taken from the first four digits of the GeneralizedTime
+/// form of _time._
+pub fn x509_time<T: TimeContent>(time: T) -> impl Recipe + DerContent {
+    let mut generalized = Fragment::new();
+    time.assemble_generalized_time(&mut generalized);
+    let year = generalized.get(..4).and_then(|year| {
+        std::str::from_utf8(year).ok()?.parse::<u16>().ok()
+    });
+    X509Time(year.map(|year| year < 2050).unwrap_or(false), time)
+}
+
+struct X509Time<T>(bool, T);
+
+impl<T: TimeContent> Recipe for X509Time<T> {
+    fn assemble(&self, target: &mut Fragment) {
+        universal(if self.0 { 23 } else { 24 }, self).assemble(target)
+    }
+}
+
+impl<T: TimeContent> DerContent for X509Time<T> {
+    fn is_constructed(&self) -> bool {
+        false
+    }
+
+    fn assemble_content(&self, target: &mut Fragment) {
+        if self.0 {
+            self.1.assemble_utc_time(target)
+        }
+        else {
+            self.1.assemble_generalized_time(target)
+        }
+    }
+}
+
+
+//============ Comparing Values ==============================================
+
+//------------ diff ----------------------------------------------------------
+
+/// Compares the DER encoded data assembled by two recipes.
+///
+/// Returns `None` if both recipes assemble to the same data. Otherwise,
+/// returns information on the innermost DER value that differs between
+/// the two as well as where exactly inside it the data starts to differ.
+///
+/// If the data cannot be parsed as DER at some point, the difference will
+/// be reported relative to the last value that could be parsed.
+///
+/// If a value on the path was assembled by a [`labelled`] recipe on
+/// either side, its label is reported alongside its index. The label of
+/// the left side is preferred.
+///
+/// [`labelled`]: super::core::labelled
+pub fn diff(
+    left: &impl Recipe, right: &impl Recipe
+) -> Option<StructuralDifference> {
+    let (left, left_parts) = left.measured();
+    let (right, right_parts) = right.measured();
+    if left == right {
+        return None
+    }
+    let mut values = Vec::new();
+    let (offset, content_offset) = diff_values(
+        &left, &right, 0, &mut values
+    );
+    let labels = values.iter().map(|(_, range)| {
+        find_label(&left_parts, range).or_else(|| {
+            find_label(&right_parts, range)
+        })
+    }).collect();
+    Some(StructuralDifference {
+        path: values.into_iter().map(|(index, _)| index).collect(),
+        labels,
+        offset,
+        content_offset
+    })
+}
+
+/// Information on where two pieces of DER encoded data differ.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct StructuralDifference {
+    /// The path to the innermost value that differs.
+    ///
+    /// Each element is the index of a value inside its parent value – or
+    /// the top-level data for the first element. An empty path means that
+    /// the top-level data could not be parsed.
+    pub path: Vec<usize>,
+
+    /// The labels of the values along the path.
+    ///
+    /// There is one element for each element of
+    /// [`path`][Self::path]. It is the label of the labelled part that
+    /// spans exactly the value if there is one.
+    pub labels: Vec<Option<String>>,
+
+    /// The absolute offset of the first octet that differs.
+    pub offset: usize,
+
+    /// The offset of the first differing octet within the value’s content.
+    ///
+    /// This is `None` if the value’s tag or length differ.
+    pub content_offset: Option<usize>,
+}
+
+impl fmt::Display for StructuralDifference {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("data differs")?;
+        if !self.path.is_empty() {
+            f.write_str(" inside ")?;
+            for (pos, index) in self.path.iter().enumerate() {
+                match self.labels.get(pos) {
+                    Some(Some(label)) if pos == 0 => f.write_str(label)?,
+                    Some(Some(label)) => write!(f, ".{}", label)?,
+                    _ => write!(f, "[{}]", index)?,
+                }
+            }
+        }
+        match self.content_offset {
+            Some(offset) => write!(f, " at content offset {}", offset)?,
+            None if !self.path.is_empty() => {
+                f.write_str(" in tag or length")?
+            }
+            None => { }
+        }
+        write!(f, " (offset {})", self.offset)
+    }
+}
+
+/// Compares two sequences of values.
+///
+/// The values start at absolute offset _base._ Pushes the index and the
+/// absolute range of differing values to _path_ and returns the absolute
+/// offset of the first differing octet and, if applicable, the content
+/// offset.
+fn diff_values(
+    mut left: &[u8], mut right: &[u8], mut base: usize,
+    path: &mut Vec<(usize, ops::Range<usize>)>,
+) -> (usize, Option<usize>) {
+    let mut index = 0;
+    loop {
+        let (left_value, right_value) = match (
+            RawValue::parse(left), RawValue::parse(right)
+        ) {
+            (Some(left_value), Some(right_value)) => {
+                (left_value, right_value)
+            }
+            _ => {
+                // Either we reached the end on one side or something
+                // doesn’t parse. Report the octet offset at this level.
+                let offset = first_difference(left, right);
+                return (base + offset, None)
+            }
+        };
+        if left_value.raw == right_value.raw {
+            left = &left[left_value.raw.len()..];
+            right = &right[right_value.raw.len()..];
+            base += left_value.raw.len();
+            index += 1;
+            continue;
+        }
+        path.push((index, base..base + left_value.raw.len()));
+        let content_base = base + left_value.header_len;
+        if left_value.header() != right_value.header() {
+            return (base + first_difference(left, right), None)
+        }
+        if left_value.constructed {
+            let path_len = path.len();
+            let res = diff_values(
+                left_value.content, right_value.content,
+                content_base, path
+            );
+            if path.len() > path_len {
+                return res
+            }
+        }
+        let offset = first_difference(
+            left_value.content, right_value.content
+        );
+        return (content_base + offset, Some(offset))
+    }
+}
+
+/// Returns the label of a part spanning exactly _range._
+///
+/// If labelled parts are nested, the outermost one is used.
+fn find_label(
+    measurement: &Measurement, range: &ops::Range<usize>
+) -> Option<String> {
+    fn find(parts: &[Part], range: &ops::Range<usize>) -> Option<String> {
+        parts.iter().find_map(|part| {
+            if part.offset == range.start
+                && part.offset + part.len == range.end
+            {
+                Some(part.label.clone())
+            }
+            else {
+                find(&part.children, range)
+            }
+        })
+    }
+
+    find(measurement.parts(), range)
+}
+
+/// Returns the offset of the first octet that differs.
+fn first_difference(left: &[u8], right: &[u8]) -> usize {
+    left.iter().zip(right).take_while(|(l, r)| l == r).count()
+}
+
+
+//------------ RawValue ------------------------------------------------------
+
+/// A parsed DER value.
+struct RawValue<'a> {
+    /// The complete encoded value.
+    raw: &'a [u8],
+
+    /// The length of the tag and length octets.
+    header_len: usize,
+
+    /// Whether the value is constructed.
+    constructed: bool,
+
+    /// The content octets.
+    content: &'a [u8],
+}
+
+impl<'a> RawValue<'a> {
+    /// Parses the first value from the start of the data.
+    ///
+    /// Returns `None` if the data is empty or the value is broken.
+    fn parse(data: &'a [u8]) -> Option<Self> {
+        let first = *data.first()?;
+        let constructed = first & 0b0010_0000 != 0;
+        let mut pos = 1;
+        if first & 0b0001_1111 == 0b0001_1111 {
+            while *data.get(pos)? & 0x80 != 0 {
+                pos += 1;
+            }
+            pos += 1;
+        }
+        let len_octet = *data.get(pos)?;
+        pos += 1;
+        let len = if len_octet < 0x80 {
+            len_octet as usize
+        }
+        else {
+            let octets = (len_octet & 0x7F) as usize;
+            if octets == 0 || octets > (usize::BITS >> 3) as usize {
+                return None
+            }
+            let mut len = 0usize;
+            for &octet in data.get(pos..pos + octets)? {
+                len = (len << 8) | octet as usize;
+            }
+            pos += octets;
+            len
+        };
+        let end = pos.checked_add(len)?;
+        Some(RawValue {
+            raw: data.get(..end)?,
+            header_len: pos,
+            constructed,
+            content: &data[pos..end],
+        })
+    }
+
+    /// Returns the tag and length octets.
+    fn header(&self) -> &'a [u8] {
+        &self.raw[..self.header_len]
+    }
+}
+
+
 //============ Helper Types ==================================================
 
 //------------ Tag -----------------------------------------------------------
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
 struct Tag {
     class: Class,
     number: u128,
@@ -572,7 +1047,7 @@ impl Tag {
             Class::Private => 0b1100_0000,
         };
         if constructed {
-            first = first | 0b0010_0000;
+            first |= 0b0010_0000;
         }
         if self.number < 31 {
             target.push(first | self.number as u8);
@@ -592,7 +1067,7 @@ fn assemble_base_7(mut number: u128, target: &mut Fragment) {
     let mut digits = [0u8; 19];
     for i in (0..19_usize).rev() {
         digits[i] = (number as u8) & 0b0111_1111;
-        number = number >> 7;
+        number >>= 7;
     }
 
     // Now skip over empty octets and then add the remaining ones. All
@@ -608,11 +1083,37 @@ fn assemble_base_7(mut number: u128, target: &mut Fragment) {
     target.push(digits[18]);
 }
 
+/// Assembles a big-endian number of any size in base 7.
+fn assemble_big_base_7(number: &[u8], target: &mut Fragment) {
+    // Collect the digits starting with the least significant one.
+    let mut digits = Vec::new();
+    let mut acc = 0u16;
+    let mut bits = 0;
+    for &octet in number.iter().rev() {
+        acc |= u16::from(octet) << bits;
+        bits += 8;
+        while bits >= 7 {
+            digits.push((acc & 0x7F) as u8);
+            acc >>= 7;
+            bits -= 7;
+        }
+    }
+    digits.push(acc as u8);
+    while digits.len() > 1 && digits.last() == Some(&0) {
+        digits.pop();
+    }
+    let last = digits.remove(0);
+    for digit in digits.iter().rev() {
+        target.push(digit | 0b1000_0000)
+    }
+    target.push(last)
+}
+
 
 //------------ Class ---------------------------------------------------------
 
 /// The class portion of a DER tag.
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
 enum Class {
     Universal,
     Application,
@@ -641,10 +1142,34 @@ impl<R: Recipe> DerContent for StringValue<R> {
     }
 
     fn assemble_content(&self, target: &mut Fragment) {
-        self.content.assemble(target)
+        let start = target.len();
+        self.content.assemble(target);
+        if !target.is_strict() {
+            return
+        }
+        let (name, valid): (_, fn(u8) -> bool) = match self.tag {
+            tag if tag == Tag::universal(19) => {
+                ("printable string", is_printable)
+            }
+            tag if tag == Tag::universal(22) => {
+                ("IA5 string", |ch: u8| ch.is_ascii())
+            }
+            _ => return
+        };
+        if let Some(pos) = target[start..].iter().position(|&ch| !valid(ch)) {
+            let ch = target[start + pos];
+            target.validate(false, || {
+                format!("invalid octet 0x{:02x} in {}", ch, name)
+            })
+        }
     }
 }
 
+/// Returns whether an octet is allowed in a PrintableString.
+fn is_printable(ch: u8) -> bool {
+    ch.is_ascii_alphanumeric() || b" '()+,-./:=?".contains(&ch)
+}
+
 impl<R: Recipe> Recipe for StringValue<R> {
     fn assemble(&self, target: &mut Fragment) {
         value(self.tag, self).assemble(target)
@@ -657,6 +1182,7 @@ impl<R: Recipe> Recipe for StringValue<R> {
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::recipe::core::literal;
 
     #[test]
     fn der_boolean() {
@@ -668,6 +1194,17 @@ mod test {
         );
     }
 
+    #[test]
+    fn der_lengths() {
+        let len = |n| octetstring(literal(vec![0u8; n])).to_fragment();
+        assert_eq!(&len(127)[..2], b"\x04\x7f");
+        assert_eq!(&len(128)[..3], b"\x04\x81\x80");
+        assert_eq!(&len(255)[..3], b"\x04\x81\xff");
+        assert_eq!(&len(256)[..4], b"\x04\x82\x01\x00");
+        assert_eq!(&len(65535)[..4], b"\x04\x82\xff\xff");
+        assert_eq!(&len(65536)[..5], b"\x04\x83\x01\x00\x00");
+    }
+
     #[test]
     fn der_integer() {
         assert_eq!(integer(0u8).to_fragment(), b"\x02\x01\x00");
@@ -681,5 +1218,221 @@ mod test {
         assert_eq!(integer(-2i32).to_fragment(), b"\x02\x01\xFE");
         assert_eq!(integer(-2i128).to_fragment(), b"\x02\x01\xFE");
     }
-}
 
+    #[test]
+    fn der_diff() {
+        let left = sequence((
+            integer(1u8),
+            sequence((octetstring(literal(b"abc")), null())),
+        ));
+        let right = sequence((
+            integer(1u8),
+            sequence((octetstring(literal(b"abd")), null())),
+        ));
+        assert_eq!(diff(&left, &left), None);
+        let res = diff(&left, &right).unwrap();
+        assert_eq!(res.path, [0, 1, 0]);
+        assert_eq!(res.content_offset, Some(2));
+        assert_eq!(res.offset, 11);
+        assert_eq!(
+            res.to_string(),
+            "data differs inside [0][1][0] at content offset 2 (offset 11)"
+        );
+
+        assert_eq!(res.labels, [None, None, None]);
+
+        let res = diff(&integer(1u8), &integer(256u16)).unwrap();
+        assert_eq!(res.path, [0]);
+        assert_eq!(res.content_offset, None);
+    }
+
+    #[test]
+    fn der_diff_labels() {
+        use crate::recipe::core::labelled;
+
+        let left = labelled("cert", sequence((
+            integer(1u8),
+            labelled("name", sequence((
+                octetstring(literal(b"abc")), null()
+            ))),
+        )));
+        let right = sequence((
+            integer(1u8),
+            sequence((
+                labelled("value", octetstring(literal(b"abd"))), null()
+            )),
+        ));
+        let res = diff(&left, &right).unwrap();
+        assert_eq!(res.path, [0, 1, 0]);
+        assert_eq!(
+            res.labels,
+            [Some("cert".into()), Some("name".into()), Some("value".into())]
+        );
+        assert_eq!(
+            res.to_string(),
+            "data differs inside cert.name.value at content offset 2 \
+             (offset 11)"
+        );
+
+        let res = diff(
+            &sequence((integer(1u8), labelled("b", integer(2u8)))),
+            &sequence((integer(1u8), integer(3u8))),
+        ).unwrap();
+        assert_eq!(
+            res.to_string(),
+            "data differs inside [0].b at content offset 0 (offset 7)"
+        );
+    }
+
+    #[test]
+    fn der_measured() {
+        use crate::recipe::core::{hex, labelled};
+
+        let (_, measurement) = sequence((
+            hex("01"), labelled("x", hex("0203"))
+        )).measured();
+        assert_eq!(measurement.to_string(), "x: 2 octets at 3\n");
+    }
+
+    #[test]
+    fn der_anchors() {
+        use crate::recipe::core::{Base, Width, anchor, hex, offset_to};
+
+        assert_eq!(
+            (
+                sequence((hex("01"), anchor("a"), hex("02"))),
+                offset_to("a", Width::U8, Base::Start)
+            ).to_fragment(),
+            b"\x30\x02\x01\x02\x03"
+        );
+        assert_eq!(
+            (
+                anchor("a"),
+                sequence((
+                    offset_to("a", Width::U8, Base::Field),
+                    octetstring((hex("01"), anchor("b"))),
+                    offset_to("b", Width::U8, Base::Start),
+                ))
+            ).to_fragment(),
+            b"\x30\x05\xfe\x04\x01\x01\x06"
+        );
+    }
+
+    #[test]
+    fn der_placeholders() {
+        use crate::recipe::core::{Endian, Width, hex, map, placeholder};
+
+        let field = placeholder(Width::U8, Endian::Big);
+        assert_eq!(
+            (
+                sequence((hex("01"), field.reserve(), hex("02"))),
+                field.fill(|_, _| 0xaa)
+            ).to_fragment(),
+            b"\x30\x03\x01\xaa\x02"
+        );
+        assert!(std::panic::catch_unwind(|| {
+            (
+                map(sequence(field.reserve()), |data| data),
+                field.fill(|_, _| 0xaa)
+            ).to_fragment()
+        }).is_err());
+    }
+
+    #[test]
+    fn der_oid_presentation() {
+        assert_eq!(
+            oid([1, 2, 840, 113549]).to_presentation(), "1.2.840.113549"
+        );
+    }
+
+    #[test]
+    fn der_integer_hex() {
+        assert_eq!(
+            integer_hex("00ff ee").to_fragment(), b"\x02\x03\x00\xff\xee"
+        );
+        assert_eq!(integer_hex("0001").to_fragment(), b"\x02\x02\x00\x01");
+    }
+
+    #[test]
+    fn der_oid_knobs() {
+        assert_eq!(
+            oid([1, 2, 840]).non_minimal(1, 2).to_fragment(),
+            b"\x06\x05\x2a\x80\x80\x86\x48"
+        );
+        assert_eq!(
+            oid([1, 2, 840]).first_subidentifier(85).to_fragment(),
+            b"\x06\x03\x55\x86\x48"
+        );
+        // 2^128 needs 19 base 7 digits, the first one being 4.
+        let mut big = [0u8; 17];
+        big[0] = 1;
+        assert_eq!(
+            oid([1, 2, 3]).big_arc(1, big).to_fragment().as_slice()[..5],
+            *b"\x06\x14\x2a\x84\x80"
+        );
+        assert_eq!(
+            oid([1, 2, 3]).big_arc(1, [0, 0x03, 0xe8]).to_fragment(),
+            oid([1, 2, 1000]).to_fragment()
+        );
+        assert_eq!(
+            oid([1, 2, 3]).big_arc(1, []).to_fragment(),
+            oid([1, 2, 0]).to_fragment()
+        );
+    }
+
+    #[test]
+    fn der_oid_short() {
+        assert_eq!(oid([]).to_fragment(), b"\x06\x00");
+        assert_eq!(
+            oid([]).first_subidentifier(42).to_fragment(), b"\x06\x01\x2a"
+        );
+        assert_eq!(oid([1]).to_fragment(), b"\x06\x01\x01");
+        assert_eq!(
+            oid([1]).first_subidentifier(200).non_minimal(0, 1).to_fragment(),
+            b"\x06\x03\x80\x81\x48"
+        );
+        assert_eq!(
+            oid([1]).big_arc(0, [0x01, 0x00]).to_fragment(),
+            b"\x06\x02\x82\x00"
+        );
+        assert_eq!(
+            oid([1]).to_strict_fragment().unwrap_err().problems(),
+            ["object identifier with 1 arcs"]
+        );
+    }
+
+    #[test]
+    fn der_strict() {
+        let valid = (
+            printable_string(literal("Foo (1)")),
+            bitstring(3, literal([0xA8])),
+            bitstring(0, literal([])),
+            oid([2, 999, 3]),
+        );
+        assert!(valid.to_strict_fragment().is_ok());
+
+        let invalid = (
+            printable_string(literal("f@o")),
+            ia5_string(literal(b"\xff")),
+            bitstring(3, literal([0xA9])),
+            bitstring(8, literal([0x00])),
+            bitstring(1, literal([])),
+            (oid([1, 40]), oid([3, 1])),
+        );
+        assert_eq!(
+            invalid.to_strict_fragment().unwrap_err().problems(),
+            [
+                "invalid octet 0x40 in printable string",
+                "invalid octet 0xff in IA5 string",
+                "unused bits in bit string not zero",
+                "bit string with 8 unused bits",
+                "empty bit string with unused bits",
+                "object identifier with second arc 40 under 1",
+                "object identifier with first arc 3",
+            ]
+        );
+
+        // Without strict mode, the same recipes happily assemble.
+        assert_eq!(invalid.to_fragment().len(), 25);
+    }
+}
@@ -0,0 +1,605 @@
+//! DNS resource records.
+//!
+//! This module provides recipes for domain names and resource records in
+//! DNS wire format as defined in RFC 1035 and the records’ representation
+//! in master files, i.e., zone files.
+//!
+//! Records are described by [`Record`] values. These assemble into wire
+//! format when used as a recipe while a [`Zone`] renders a list of them
+//! into master file text. This way, wire format and zone file parsers can
+//! be fed from the same test data. Names, record data, and records also
+//! implement [`Presentation`] for their individual presentation form.
+//!
+//! Names are never compressed and, as usual, nothing is checked. Names may
+//! contain labels that are too long and record data doesn’t need to make
+//! any sense.
+
+use std::fmt::Write;
+use std::net::{Ipv4Addr, Ipv6Addr};
+use super::core::{
+    Fragment, OverrideField, Overrides, Presentation, Recipe, be
+};
+
+
+//------------ Constants -----------------------------------------------------
+
+/// The Internet class.
+pub const CLASS_IN: u16 = 1;
+
+/// The Chaos class.
+pub const CLASS_CH: u16 = 3;
+
+/// The record type for an IPv4 host address.
+pub const TYPE_A: u16 = 1;
+
+/// The record type for an authoritative name server.
+pub const TYPE_NS: u16 = 2;
+
+/// The record type for a canonical name.
+pub const TYPE_CNAME: u16 = 5;
+
+/// The record type for the start of a zone of authority.
+pub const TYPE_SOA: u16 = 6;
+
+/// The record type for a mail exchange.
+pub const TYPE_MX: u16 = 15;
+
+/// The record type for text strings.
+pub const TYPE_TXT: u16 = 16;
+
+/// The record type for an IPv6 host address.
+pub const TYPE_AAAA: u16 = 28;
+
+
+//------------ Name ----------------------------------------------------------
+
+/// An absolute domain name.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Name {
+    /// The labels of the name without the root label.
+    labels: Vec<Vec<u8>>,
+}
+
+/// Creates a name from its textual representation.
+///
+/// The name is always treated as absolute, i.e., a final dot is optional.
+/// Labels are separated by dots. A backslash escapes the following
+/// character or, if followed by three decimal digits, the octet with that
+/// value. Panics if an escape sequence is broken.
+pub fn name(name: &str) -> Name {
+    let mut labels = Vec::new();
+    let mut label = Vec::new();
+    let mut chars = name.bytes();
+    while let Some(ch) = chars.next() {
+        match ch {
+            b'.' => labels.push(std::mem::take(&mut label)),
+            b'\\' => {
+                let ch = chars.next().expect("broken escape sequence");
+                if ch.is_ascii_digit() {
+                    let mut value = u32::from(ch - b'0');
+                    for _ in 0..2 {
+                        let ch = chars.next().filter(u8::is_ascii_digit)
+                            .expect("broken escape sequence");
+                        value = value * 10 + u32::from(ch - b'0');
+                    }
+                    label.push(value as u8)
+                }
+                else {
+                    label.push(ch)
+                }
+            }
+            _ => label.push(ch)
+        }
+    }
+    if !label.is_empty() {
+        labels.push(label)
+    }
+    Name { labels }
+}
+
+impl Name {
+    /// Returns the root name.
+    pub fn root() -> Self {
+        Name { labels: Vec::new() }
+    }
+
+    /// Returns whether the name ends with _base._
+    pub fn ends_with(&self, base: &Name) -> bool {
+        self.labels.ends_with(&base.labels)
+    }
+
+    /// Writes the name relative to _origin_ if it is under it.
+    ///
+    /// The origin itself is written as `@`.
+    fn write_relative(&self, origin: Option<&Name>, target: &mut String) {
+        if let Some(origin) = origin.filter(|origin| self.ends_with(origin)) {
+            let len = self.labels.len() - origin.labels.len();
+            if len == 0 {
+                target.push('@');
+                return
+            }
+            for (idx, label) in self.labels[..len].iter().enumerate() {
+                if idx > 0 {
+                    target.push('.')
+                }
+                write_escaped(label, NAME_SPECIAL, target);
+            }
+            return
+        }
+        self.write(target)
+    }
+
+    /// Writes the absolute name.
+    fn write(&self, target: &mut String) {
+        if self.labels.is_empty() {
+            target.push('.');
+        }
+        for label in &self.labels {
+            write_escaped(label, NAME_SPECIAL, target);
+            target.push('.');
+        }
+    }
+}
+
+impl Presentation for Name {
+    fn present(&self, target: &mut String) {
+        self.write(target)
+    }
+}
+
+impl Recipe for Name {
+    fn assemble(&self, target: &mut Fragment) {
+        for label in &self.labels {
+            target.push(label.len() as u8);
+            target.extend_from_slice(label);
+        }
+        target.push(0)
+    }
+}
+
+
+//------------ Rdata ---------------------------------------------------------
+
+/// The data of a record.
+#[derive(Clone, Debug)]
+pub enum Rdata {
+    A(Ipv4Addr),
+    Aaaa(Ipv6Addr),
+    Ns(Name),
+    Cname(Name),
+    Mx(u16, Name),
+    Txt(Vec<Vec<u8>>),
+    Soa(Soa),
+
+    /// Data of any type given as raw octets.
+    ///
+    /// This is presented in the generic format of RFC 3597.
+    Unknown(u16, Vec<u8>),
+}
+
+/// The data of a SOA record.
+#[derive(Clone, Debug)]
+pub struct Soa {
+    pub mname: Name,
+    pub rname: Name,
+    pub serial: u32,
+    pub refresh: u32,
+    pub retry: u32,
+    pub expire: u32,
+    pub minimum: u32,
+}
+
+impl Rdata {
+    /// Returns the record type of the data.
+    pub fn rtype(&self) -> u16 {
+        match *self {
+            Rdata::A(_) => TYPE_A,
+            Rdata::Aaaa(_) => TYPE_AAAA,
+            Rdata::Ns(_) => TYPE_NS,
+            Rdata::Cname(_) => TYPE_CNAME,
+            Rdata::Mx(..) => TYPE_MX,
+            Rdata::Txt(_) => TYPE_TXT,
+            Rdata::Soa(_) => TYPE_SOA,
+            Rdata::Unknown(rtype, _) => rtype,
+        }
+    }
+
+    /// Writes the presentation format of the data.
+    ///
+    /// If _multiline_ is `true`, SOA data is spread over several lines
+    /// using parentheses.
+    fn write(
+        &self, origin: Option<&Name>, multiline: bool, target: &mut String
+    ) {
+        match *self {
+            Rdata::A(addr) => write!(target, "{}", addr).unwrap(),
+            Rdata::Aaaa(addr) => write!(target, "{}", addr).unwrap(),
+            Rdata::Ns(ref name) | Rdata::Cname(ref name) => {
+                name.write_relative(origin, target)
+            }
+            Rdata::Mx(pref, ref name) => {
+                write!(target, "{} ", pref).unwrap();
+                name.write_relative(origin, target)
+            }
+            Rdata::Txt(ref strings) => {
+                for (idx, item) in strings.iter().enumerate() {
+                    if idx > 0 {
+                        target.push(' ')
+                    }
+                    target.push('"');
+                    write_escaped(item, b"\"", target);
+                    target.push('"');
+                }
+            }
+            Rdata::Soa(ref soa) => {
+                soa.mname.write_relative(origin, target);
+                target.push(' ');
+                soa.rname.write_relative(origin, target);
+                let values = [
+                    (soa.serial, "serial"), (soa.refresh, "refresh"),
+                    (soa.retry, "retry"), (soa.expire, "expire"),
+                    (soa.minimum, "minimum"),
+                ];
+                if multiline {
+                    target.push_str(" (\n");
+                    for (value, label) in values {
+                        writeln!(target, "\t\t{} ; {}", value, label).unwrap();
+                    }
+                    target.push_str("\t\t)");
+                }
+                else {
+                    for (value, _) in values {
+                        write!(target, " {}", value).unwrap();
+                    }
+                }
+            }
+            Rdata::Unknown(_, ref data) => {
+                write!(target, "\\# {}", data.len()).unwrap();
+                if !data.is_empty() {
+                    target.push(' ');
+                    for octet in data {
+                        write!(target, "{:02x}", octet).unwrap();
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Presentation for Rdata {
+    fn present(&self, target: &mut String) {
+        self.write(None, false, target)
+    }
+}
+
+impl Recipe for Rdata {
+    fn assemble(&self, target: &mut Fragment) {
+        match *self {
+            Rdata::A(addr) => target.extend_from_slice(&addr.octets()),
+            Rdata::Aaaa(addr) => target.extend_from_slice(&addr.octets()),
+            Rdata::Ns(ref name) | Rdata::Cname(ref name) => {
+                name.assemble(target)
+            }
+            Rdata::Mx(pref, ref name) => {
+                target.extend_from_slice(&pref.to_be_bytes());
+                name.assemble(target)
+            }
+            Rdata::Txt(ref strings) => {
+                for item in strings {
+                    target.push(item.len() as u8);
+                    target.extend_from_slice(item);
+                }
+            }
+            Rdata::Soa(ref soa) => {
+                soa.mname.assemble(target);
+                soa.rname.assemble(target);
+                for value in [
+                    soa.serial, soa.refresh, soa.retry, soa.expire,
+                    soa.minimum
+                ] {
+                    target.extend_from_slice(&value.to_be_bytes())
+                }
+            }
+            Rdata::Unknown(_, ref data) => target.extend_from_slice(data),
+        }
+    }
+}
+
+
+//------------ Record --------------------------------------------------------
+
+/// A resource record.
+///
+/// Used as a recipe, the record assembles into wire format. The fields of
+/// the wire format can be replaced via [`OverrideField`] using the paths
+/// `owner`, `type`, `class`, `ttl`, `rdlength`, and `rdata`. Overrides are
+/// ignored in the presentation format.
+#[derive(Clone, Debug)]
+pub struct Record {
+    pub owner: Name,
+    pub class: u16,
+    pub ttl: u32,
+    pub data: Rdata,
+    pub overrides: Overrides,
+}
+
+/// Returns a record of class IN.
+pub fn record(owner: Name, ttl: u32, data: Rdata) -> Record {
+    Record {
+        owner, class: CLASS_IN, ttl, data, overrides: Overrides::new()
+    }
+}
+
+impl Record {
+    /// Writes the record as a line of a master file.
+    ///
+    /// Any parts equal to the ones given in _zone_ are omitted. No line
+    /// feed is added.
+    fn write(&self, zone: &Zone, omit_owner: bool, target: &mut String) {
+        if !omit_owner {
+            self.owner.write_relative(zone.origin.as_ref(), target);
+        }
+        if zone.default_ttl != Some(self.ttl) {
+            write!(target, "\t{}", self.ttl).unwrap();
+        }
+        if !zone.omit_class {
+            target.push('\t');
+            write_class(self.class, target);
+        }
+        target.push('\t');
+        write_rtype(self.data.rtype(), target);
+        target.push('\t');
+        self.data.write(zone.origin.as_ref(), zone.multiline, target);
+    }
+}
+
+impl Presentation for Record {
+    fn present(&self, target: &mut String) {
+        self.write(&Zone::new(), false, target)
+    }
+}
+
+impl OverrideField for Record {
+    fn override_field(
+        mut self, path: &str, recipe: impl Recipe + Send + Sync + 'static
+    ) -> Self {
+        self.overrides.insert(path, recipe);
+        self
+    }
+}
+
+impl Recipe for Record {
+    fn assemble(&self, target: &mut Fragment) {
+        let fields = &self.overrides;
+        fields.assemble_field("owner", &self.owner, target);
+        fields.assemble_field("type", be(self.data.rtype()), target);
+        fields.assemble_field("class", be(self.class), target);
+        fields.assemble_field("ttl", be(self.ttl), target);
+        if let Some(rdlength) = fields.get("rdlength") {
+            rdlength.assemble(target);
+            fields.assemble_field("rdata", &self.data, target);
+            return
+        }
+        let start = target.len();
+        target.extend_from_slice(&[0; 2]);
+        fields.assemble_field("rdata", &self.data, target);
+        if target.is_exceeded() {
+            return
+        }
+        let len = (target.len() - start - 2) as u16;
+        target[start..start + 2].copy_from_slice(&len.to_be_bytes());
+    }
+}
+
+
+//------------ Zone ----------------------------------------------------------
+
+/// A renderer for master files.
+///
+/// The renderer has a number of options that influence how records are
+/// presented. By default, each record is rendered on its own line with
+/// its absolute owner name, TTL, class, type, and data.
+#[derive(Clone, Debug, Default)]
+pub struct Zone {
+    /// The origin to emit via `$ORIGIN` and to make names relative to.
+    origin: Option<Name>,
+
+    /// The default TTL to emit via `$TTL`.
+    default_ttl: Option<u32>,
+
+    /// Omit the class of records?
+    omit_class: bool,
+
+    /// Omit the owner if it is the same as that of the previous record?
+    omit_repeated_owner: bool,
+
+    /// Use parentheses to spread SOA data over multiple lines?
+    multiline: bool,
+}
+
+impl Zone {
+    /// Creates a new renderer with default options.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Emits an `$ORIGIN` directive and makes names relative to it.
+    pub fn origin(mut self, origin: Name) -> Self {
+        self.origin = Some(origin);
+        self
+    }
+
+    /// Emits a `$TTL` directive.
+    ///
+    /// The TTL of records that have this TTL is omitted.
+    pub fn default_ttl(mut self, ttl: u32) -> Self {
+        self.default_ttl = Some(ttl);
+        self
+    }
+
+    /// Omits the class of all records.
+    pub fn omit_class(mut self) -> Self {
+        self.omit_class = true;
+        self
+    }
+
+    /// Leaves the owner blank if it is the same as that of the previous
+    /// record.
+    pub fn omit_repeated_owner(mut self) -> Self {
+        self.omit_repeated_owner = true;
+        self
+    }
+
+    /// Spreads the data of SOA records over several lines.
+    pub fn multiline(mut self) -> Self {
+        self.multiline = true;
+        self
+    }
+
+    /// Renders the records into master file text.
+    pub fn render<'a>(
+        &self, records: impl IntoIterator<Item = &'a Record>
+    ) -> String {
+        let mut res = String::new();
+        if let Some(ref origin) = self.origin {
+            res.push_str("$ORIGIN ");
+            origin.write(&mut res);
+            res.push('\n');
+        }
+        if let Some(ttl) = self.default_ttl {
+            writeln!(res, "$TTL {}", ttl).unwrap();
+        }
+        let mut prev_owner: Option<&Name> = None;
+        for record in records {
+            record.write(
+                self,
+                self.omit_repeated_owner && prev_owner == Some(&record.owner),
+                &mut res
+            );
+            prev_owner = Some(&record.owner);
+            res.push('\n');
+        }
+        res
+    }
+}
+
+
+//------------ Helpers -------------------------------------------------------
+
+/// Writes the mnemonic of a class.
+fn write_class(class: u16, target: &mut String) {
+    match class {
+        CLASS_IN => target.push_str("IN"),
+        CLASS_CH => target.push_str("CH"),
+        _ => write!(target, "CLASS{}", class).unwrap(),
+    }
+}
+
+/// Writes the mnemonic of a record type.
+fn write_rtype(rtype: u16, target: &mut String) {
+    match rtype {
+        TYPE_A => target.push('A'),
+        TYPE_NS => target.push_str("NS"),
+        TYPE_CNAME => target.push_str("CNAME"),
+        TYPE_SOA => target.push_str("SOA"),
+        TYPE_MX => target.push_str("MX"),
+        TYPE_TXT => target.push_str("TXT"),
+        TYPE_AAAA => target.push_str("AAAA"),
+        _ => write!(target, "TYPE{}", rtype).unwrap(),
+    }
+}
+
+/// The characters that need escaping in names.
+const NAME_SPECIAL: &[u8] = b". ;()\"";
+
+/// Writes octets escaping everything but printable ASCII.
+///
+/// The characters in _special_ as well as backslashes are escaped with a
+/// backslash, everything else that isn’t printable ASCII as a decimal
+/// escape.
+fn write_escaped(data: &[u8], special: &[u8], target: &mut String) {
+    for &ch in data {
+        if ch == b'\\' || special.contains(&ch) {
+            target.push('\\');
+            target.push(ch as char);
+        }
+        else if ch.is_ascii_graphic() || ch == b' ' {
+            target.push(ch as char)
+        }
+        else {
+            write!(target, "\\{:03}", ch).unwrap();
+        }
+    }
+}
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn records() -> Vec<Record> {
+        vec![
+            record(name("example.com"), 3600, Rdata::Soa(Soa {
+                mname: name("ns.example.com."), rname: name("a\\.b.example"),
+                serial: 1, refresh: 2, retry: 3, expire: 4, minimum: 5,
+            })),
+            record(name("example.com"), 300, Rdata::Mx(
+                10, name("mail.example.com")
+            )),
+            record(name("w\\032x.example.com"), 3600, Rdata::Txt(vec![
+                b"a \"b\"".to_vec(), b"\x00".to_vec()
+            ])),
+            record(name("example.com"), 3600, Rdata::Unknown(99, vec![1, 2])),
+        ]
+    }
+
+    #[test]
+    fn wire() {
+        assert_eq!(
+            record(name("a.b"), 1, Rdata::A(Ipv4Addr::new(192, 0, 2, 1)))
+                .to_fragment(),
+            b"\x01a\x01b\x00\x00\x01\x00\x01\x00\x00\x00\x01\x00\x04\
+              \xc0\x00\x02\x01"
+        );
+        assert_eq!(name("a\\.b").to_fragment(), b"\x03a.b\x00");
+        assert_eq!(
+            record(Name::root(), 1, Rdata::Txt(vec![b"a".to_vec()]))
+                .override_field("class", be(0xFFFFu16))
+                .override_field("rdlength", be(7u16))
+                .to_fragment(),
+            b"\x00\x00\x10\xff\xff\x00\x00\x00\x01\x00\x07\x01a"
+        );
+    }
+
+    #[test]
+    fn presentation() {
+        let item = record(name("a.b"), 1, Rdata::Cname(name("c")));
+        assert_eq!(item.to_presentation(), "a.b.\t1\tIN\tCNAME\tc.");
+        assert_eq!(item.data.to_presentation(), "c.");
+    }
+
+    #[test]
+    fn zone_file() {
+        assert_eq!(
+            Zone::new().render(&records()),
+            "example.com.\t3600\tIN\tSOA\tns.example.com. a\\.b.example. \
+             1 2 3 4 5\n\
+             example.com.\t300\tIN\tMX\t10 mail.example.com.\n\
+             w\\ x.example.com.\t3600\tIN\tTXT\t\"a \\\"b\\\"\" \"\\000\"\n\
+             example.com.\t3600\tIN\tTYPE99\t\\# 2 0102\n"
+        );
+        assert_eq!(
+            Zone::new().origin(name("example.com")).default_ttl(3600)
+                .omit_class().omit_repeated_owner().multiline()
+                .render(&records()[..2]),
+            "$ORIGIN example.com.\n\
+             $TTL 3600\n\
+             @\tSOA\tns a\\.b.example. (\n\
+             \t\t1 ; serial\n\t\t2 ; refresh\n\t\t3 ; retry\n\
+             \t\t4 ; expire\n\t\t5 ; minimum\n\t\t)\n\
+             \t300\tMX\t10 mail\n"
+        );
+    }
+}
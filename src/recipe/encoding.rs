@@ -0,0 +1,286 @@
+//! Text encodings of binary data.
+//!
+//! The recipes in this module assemble an inner recipe and produce a text
+//! representation of its data, such as the [`base64()`] used in HTTP
+//! authorization headers, SASL exchanges, and the body of PEM files, the
+//! [`base32`] used for NSEC3 hashes and TOTP secrets, or plain hexadecimal
+//! text via [`hex_text`].
+//!
+//! For testing parsers of text fields, [`invalid_utf8`] provides a corpus
+//! of malformed UTF-8 sequences.
+
+use super::core::{Fragment, Recipe, literal};
+use super::set::RecipeSet;
+
+
+//------------ base64 --------------------------------------------------------
+
+/// Returns a recipe producing the Base64 encoding of another recipe.
+///
+/// By default, the standard alphabet with padding as defined in section 4
+/// of RFC 4648 is used. The URL-safe alphabet of section 5 can be chosen
+/// via [`Base64::url_safe`] and padding can be left out via
+/// [`Base64::no_padding`]. No line breaks are added.
+pub fn base64<R: Recipe>(inner: R) -> Base64<R> {
+    Base64 { inner, url_safe: false, padding: true }
+}
+
+pub struct Base64<R> {
+    inner: R,
+    url_safe: bool,
+    padding: bool,
+}
+
+impl<R> Base64<R> {
+    /// Uses the URL and filename safe alphabet.
+    pub fn url_safe(mut self) -> Self {
+        self.url_safe = true;
+        self
+    }
+
+    /// Leaves out the padding at the end.
+    pub fn no_padding(mut self) -> Self {
+        self.padding = false;
+        self
+    }
+}
+
+impl<R: Recipe> Recipe for Base64<R> {
+    fn assemble(&self, target: &mut Fragment) {
+        let start = target.len();
+        self.inner.assemble(target);
+        let config = match (self.url_safe, self.padding) {
+            (false, true) => base64::STANDARD,
+            (false, false) => base64::STANDARD_NO_PAD,
+            (true, true) => base64::URL_SAFE,
+            (true, false) => base64::URL_SAFE_NO_PAD,
+        };
+        let text = base64::encode_config(&target[start..], config);
+        target.truncate(start);
+        target.extend_from_slice(text.as_bytes())
+    }
+}
+
+
+//------------ base32 --------------------------------------------------------
+
+/// Returns a recipe producing the Base32 encoding of another recipe.
+///
+/// By default, the upper case standard alphabet with padding as defined
+/// in section 6 of RFC 4648 is used. The extended hex alphabet of section
+/// 7 used by NSEC3 can be chosen via [`Base32::extended_hex`]. Lower case
+/// letters and no padding can be chosen via [`Base32::lowercase`] and
+/// [`Base32::no_padding`].
+pub fn base32<R: Recipe>(inner: R) -> Base32<R> {
+    Base32 { inner, hex: false, lowercase: false, padding: true }
+}
+
+pub struct Base32<R> {
+    inner: R,
+    hex: bool,
+    lowercase: bool,
+    padding: bool,
+}
+
+impl<R> Base32<R> {
+    /// Uses the extended hex alphabet.
+    pub fn extended_hex(mut self) -> Self {
+        self.hex = true;
+        self
+    }
+
+    /// Uses lower case letters.
+    pub fn lowercase(mut self) -> Self {
+        self.lowercase = true;
+        self
+    }
+
+    /// Leaves out the padding at the end.
+    pub fn no_padding(mut self) -> Self {
+        self.padding = false;
+        self
+    }
+}
+
+impl<R: Recipe> Recipe for Base32<R> {
+    fn assemble(&self, target: &mut Fragment) {
+        let alphabet: &[u8; 32] = if self.hex {
+            b"0123456789ABCDEFGHIJKLMNOPQRSTUV"
+        }
+        else {
+            b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567"
+        };
+        let start = target.len();
+        self.inner.assemble(target);
+        let data = target[start..].to_vec();
+        target.truncate(start);
+        for chunk in data.chunks(5) {
+            let mut block = [0u8; 8];
+            block[..chunk.len()].copy_from_slice(chunk);
+            let bits = u64::from_be_bytes(block) >> 24;
+            let chars = (chunk.len() * 8).div_ceil(5);
+            for idx in 0..8 {
+                if idx < chars {
+                    let ch = alphabet[
+                        (bits >> (35 - idx * 5)) as usize & 0x1F
+                    ];
+                    target.push(
+                        if self.lowercase { ch.to_ascii_lowercase() }
+                        else { ch }
+                    )
+                }
+                else if self.padding {
+                    target.push(b'=')
+                }
+            }
+        }
+    }
+}
+
+
+//------------ hex_text ------------------------------------------------------
+
+/// Returns a recipe producing the hexadecimal text of another recipe.
+///
+/// Each octet becomes two lower case hex digits without any separators.
+/// Upper case digits can be chosen via [`HexText::uppercase`].
+pub fn hex_text<R: Recipe>(inner: R) -> HexText<R> {
+    HexText { inner, uppercase: false }
+}
+
+pub struct HexText<R> {
+    inner: R,
+    uppercase: bool,
+}
+
+impl<R> HexText<R> {
+    /// Uses upper case digits.
+    pub fn uppercase(mut self) -> Self {
+        self.uppercase = true;
+        self
+    }
+}
+
+impl<R: Recipe> Recipe for HexText<R> {
+    fn assemble(&self, target: &mut Fragment) {
+        let digits = if self.uppercase {
+            b"0123456789ABCDEF"
+        }
+        else {
+            b"0123456789abcdef"
+        };
+        let start = target.len();
+        self.inner.assemble(target);
+        let data = target[start..].to_vec();
+        target.truncate(start);
+        for octet in data {
+            target.push(digits[usize::from(octet >> 4)]);
+            target.push(digits[usize::from(octet & 0x0F)]);
+        }
+    }
+}
+
+
+//------------ invalid_utf8 --------------------------------------------------
+
+/// Returns a set of invalid UTF-8 sequences.
+///
+/// The baseline is a valid sequence containing characters encoded in one
+/// to four octets. The variants each contain one malformed sequence
+/// without any surrounding text, so they can be embedded into any string
+/// field. They cover overlong encodings, encoded UTF-16 surrogates, code
+/// points beyond U+10FFFF, octets that never appear in UTF-8, unexpected
+/// continuation octets, and truncated sequences.
+pub fn invalid_utf8() -> RecipeSet {
+    let mut res = RecipeSet::new().baseline(
+        literal("a\u{e4}\u{20ac}\u{1f600}")
+    );
+    for &(name, data) in INVALID_UTF8 {
+        res.insert(name, literal(data));
+    }
+    res
+}
+
+/// The variants of the invalid UTF-8 set.
+const INVALID_UTF8: &[(&str, &[u8])] = &[
+    ("overlong-2-slash", b"\xc0\xaf"),
+    ("overlong-3-slash", b"\xe0\x80\xaf"),
+    ("overlong-4-slash", b"\xf0\x80\x80\xaf"),
+    ("overlong-2-nul", b"\xc0\x80"),
+    ("overlong-2-max", b"\xc1\xbf"),
+    ("overlong-3-max", b"\xe0\x9f\xbf"),
+    ("overlong-4-max", b"\xf0\x8f\xbf\xbf"),
+    ("surrogate-high-first", b"\xed\xa0\x80"),
+    ("surrogate-high-last", b"\xed\xaf\xbf"),
+    ("surrogate-low-first", b"\xed\xb0\x80"),
+    ("surrogate-low-last", b"\xed\xbf\xbf"),
+    ("surrogate-pair", b"\xed\xa0\xbd\xed\xb8\x80"),
+    ("beyond-max", b"\xf4\x90\x80\x80"),
+    ("lead-f5", b"\xf5\x80\x80\x80"),
+    ("five-octets", b"\xf8\x88\x80\x80\x80"),
+    ("six-octets", b"\xfc\x84\x80\x80\x80\x80"),
+    ("octet-fe", b"\xfe"),
+    ("octet-ff", b"\xff"),
+    ("lone-continuation", b"\x80"),
+    ("extra-continuation", b"\xc3\xa4\xbf"),
+    ("truncated-2", b"\xc3"),
+    ("truncated-3", b"\xe2\x82"),
+    ("truncated-4", b"\xf0\x9f\x98"),
+    ("truncated-before-ascii", b"\xe2\x82a"),
+];
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::recipe::core::{hex, literal};
+
+    #[test]
+    fn base64_text() {
+        assert_eq!(base64(literal("foob")).to_fragment(), b"Zm9vYg==");
+        assert_eq!(
+            base64(literal("foob")).no_padding().to_fragment(), b"Zm9vYg"
+        );
+        assert_eq!(base64(hex("fbff")).to_fragment(), b"+/8=");
+        assert_eq!(base64(hex("fbff")).url_safe().to_fragment(), b"-_8=");
+    }
+
+    #[test]
+    fn base32_text() {
+        assert_eq!(base32(literal("")).to_fragment(), b"");
+        assert_eq!(base32(literal("f")).to_fragment(), b"MY======");
+        assert_eq!(base32(literal("foob")).to_fragment(), b"MZXW6YQ=");
+        assert_eq!(base32(literal("fooba")).to_fragment(), b"MZXW6YTB");
+        assert_eq!(
+            base32(literal("foobar")).to_fragment(), b"MZXW6YTBOI======"
+        );
+        assert_eq!(
+            base32(literal("foobar")).extended_hex().lowercase().no_padding()
+                .to_fragment(),
+            b"cpnmuoj1e8"
+        );
+    }
+
+    #[test]
+    fn hex_digits() {
+        assert_eq!(hex_text(hex("00a9ff")).to_fragment(), b"00a9ff");
+        assert_eq!(
+            hex_text(hex("00a9ff")).uppercase().to_fragment(), b"00A9FF"
+        );
+    }
+
+    #[test]
+    fn utf8_corpus() {
+        let set = invalid_utf8();
+        assert_eq!(set.len(), INVALID_UTF8.len() + 1);
+        for (name, data) in set.assemble_all() {
+            assert_eq!(
+                std::str::from_utf8(&data).is_ok(),
+                name == RecipeSet::BASELINE,
+                "{}", name
+            );
+        }
+    }
+}
@@ -0,0 +1,87 @@
+//! GSS-API tokens.
+//!
+//! This module provides recipes for the mechanism-independent token framing
+//! defined in section 3.1 of RFC 2743. The mechanism-specific content of
+//! the tokens is provided by other modules, e.g., [`kerberos`].
+//!
+//! [`kerberos`]: super::kerberos
+
+use super::core::Recipe;
+use super::der;
+
+
+//------------ initial_context_token -----------------------------------------
+
+/// Returns a recipe for a GSS-API initial context token.
+///
+/// The token is an application class, constructed value with tag number 0
+/// – hence starting with the octet 0x60 – which contains the mechanism
+/// identifier given via _mech_ followed by the mechanism-specific
+/// _inner_ token.
+///
+/// The mechanism is a recipe, too, so that it can be replaced by something
+/// other than an object identifier. Normally, it should be one of the
+/// functions below or [`der::oid`].
+pub fn initial_context_token<M: Recipe, R: Recipe>(
+    mech: M, inner: R
+) -> impl Recipe {
+    der::application(0, der::constructed((mech, inner)))
+}
+
+
+//------------ Mechanism Identifiers -----------------------------------------
+
+/// Returns a recipe for the Kerberos V5 mechanism OID.
+///
+/// This is 1.2.840.113554.1.2.2 as defined in RFC 1964.
+pub fn krb5_mech() -> der::Oid<7> {
+    der::oid([1, 2, 840, 113554, 1, 2, 2])
+}
+
+/// Returns a recipe for the SPNEGO mechanism OID.
+///
+/// This is 1.3.6.1.5.5.2 as defined in RFC 4178.
+pub fn spnego_mech() -> der::Oid<7> {
+    der::oid([1, 3, 6, 1, 5, 5, 2])
+}
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::recipe::core::literal;
+
+    #[test]
+    fn framing() {
+        assert_eq!(
+            initial_context_token(krb5_mech(), literal(b"\x01\x00"))
+                .to_fragment(),
+            b"\x60\x0d\x06\x09\x2a\x86\x48\x86\xf7\x12\x01\x02\x02\x01\x00"
+        );
+    }
+
+    #[test]
+    fn long_and_broken_tokens() {
+        // A large inner token needs the long form of the DER length.
+        let token = initial_context_token(
+            spnego_mech(), literal(vec![0u8; 200])
+        ).to_fragment();
+        assert_eq!(&token[..3], b"\x60\x81\xd0");
+        assert_eq!(&token[3..11], b"\x06\x06\x2b\x06\x01\x05\x05\x02");
+        assert_eq!(token.len(), 3 + 8 + 200);
+
+        // The mechanism can be something other than an OID.
+        assert_eq!(
+            initial_context_token(literal(b"\x05\x00"), literal(b""))
+                .to_fragment(),
+            b"\x60\x02\x05\x00"
+        );
+        assert_eq!(
+            initial_context_token(der::oid([3, 50]), literal(b""))
+                .to_fragment(),
+            b"\x60\x04\x06\x02\x81\x2a"
+        );
+    }
+}
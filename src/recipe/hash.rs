@@ -0,0 +1,269 @@
+//! Cryptographic hashes over the data of a recipe.
+//!
+//! The [`digest`] recipe assembles an inner recipe and produces the hash
+//! of its data instead of the data itself. This allows describing fields
+//! such as the digest of a DNS DS record or the message digest attribute
+//! of CMS signed data as part of a single recipe.
+//!
+//! Similarly, [`hmac`] produces a keyed-hash message authentication code
+//! as used by TSIG in DNS, session tickets, and many authentication
+//! schemes of HTTP APIs.
+//!
+//! The algorithms are only available with the features of the crates
+//! implementing them: SHA-1 needs `sha1`, SHA-256 and SHA-512 need
+//! `sha2`. The module is missing if neither feature is enabled.
+
+use super::core::{Fragment, Recipe};
+
+
+//------------ HashAlgorithm -------------------------------------------------
+
+/// A cryptographic hash algorithm.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum HashAlgorithm {
+    /// SHA-1.
+    #[cfg(feature = "sha1")]
+    Sha1,
+
+    /// SHA-256.
+    #[cfg(feature = "sha2")]
+    Sha256,
+
+    /// SHA-512.
+    #[cfg(feature = "sha2")]
+    Sha512,
+}
+
+impl HashAlgorithm {
+    /// Returns the length of a hash in octets.
+    pub fn output_len(self) -> usize {
+        match self {
+            #[cfg(feature = "sha1")]
+            HashAlgorithm::Sha1 => 20,
+            #[cfg(feature = "sha2")]
+            HashAlgorithm::Sha256 => 32,
+            #[cfg(feature = "sha2")]
+            HashAlgorithm::Sha512 => 64,
+        }
+    }
+
+    /// Returns the length of the blocks the hash function operates on.
+    fn block_len(self) -> usize {
+        match self {
+            #[cfg(feature = "sha1")]
+            HashAlgorithm::Sha1 => 64,
+            #[cfg(feature = "sha2")]
+            HashAlgorithm::Sha256 => 64,
+            #[cfg(feature = "sha2")]
+            HashAlgorithm::Sha512 => 128,
+        }
+    }
+
+    /// Returns the hash of _data._
+    pub fn hash(self, data: &[u8]) -> Vec<u8> {
+        match self {
+            #[cfg(feature = "sha1")]
+            HashAlgorithm::Sha1 => {
+                <sha1::Sha1 as sha1::Digest>::digest(data).to_vec()
+            }
+            #[cfg(feature = "sha2")]
+            HashAlgorithm::Sha256 => {
+                <sha2::Sha256 as sha2::Digest>::digest(data).to_vec()
+            }
+            #[cfg(feature = "sha2")]
+            HashAlgorithm::Sha512 => {
+                <sha2::Sha512 as sha2::Digest>::digest(data).to_vec()
+            }
+        }
+    }
+}
+
+
+//------------ digest --------------------------------------------------------
+
+/// Returns a recipe producing the hash of the data of another recipe.
+///
+/// The data of _inner_ is hashed with _algo_ and replaced by the hash.
+/// For negative tests, [`Digest::corrupt`] produces a wrong hash.
+pub fn digest<R: Recipe>(algo: HashAlgorithm, inner: R) -> Digest<R> {
+    Digest { algo, inner, corrupt: false }
+}
+
+pub struct Digest<R> {
+    algo: HashAlgorithm,
+    inner: R,
+    corrupt: bool,
+}
+
+impl<R> Digest<R> {
+    /// Flips the least significant bit of the last octet of the hash.
+    pub fn corrupt(mut self) -> Self {
+        self.corrupt = true;
+        self
+    }
+}
+
+impl<R: Recipe> Recipe for Digest<R> {
+    fn assemble(&self, target: &mut Fragment) {
+        let start = target.len();
+        self.inner.assemble(target);
+        let mut hash = self.algo.hash(&target[start..]);
+        if self.corrupt {
+            if let Some(last) = hash.last_mut() {
+                *last ^= 1
+            }
+        }
+        target.truncate(start);
+        target.extend_from_slice(&hash)
+    }
+}
+
+
+//------------ hmac ----------------------------------------------------------
+
+/// Returns a recipe producing the HMAC of the data of another recipe.
+///
+/// The data of _inner_ is authenticated using HMAC as defined in RFC 2104
+/// with the hash function _algo_ and the given _key_ and replaced by the
+/// MAC. The MAC can be cut short via [`Hmac::truncate`] for algorithms
+/// such as hmac-sha1-96. For negative tests, [`Hmac::corrupt`] produces
+/// a wrong MAC.
+pub fn hmac<R: Recipe>(
+    algo: HashAlgorithm, key: impl AsRef<[u8]>, inner: R
+) -> Hmac<R> {
+    Hmac {
+        algo, key: key.as_ref().into(), inner, truncate: None, corrupt: false
+    }
+}
+
+pub struct Hmac<R> {
+    algo: HashAlgorithm,
+    key: Vec<u8>,
+    inner: R,
+    truncate: Option<usize>,
+    corrupt: bool,
+}
+
+impl<R> Hmac<R> {
+    /// Only uses the first _len_ octets of the MAC.
+    pub fn truncate(mut self, len: usize) -> Self {
+        self.truncate = Some(len);
+        self
+    }
+
+    /// Flips the least significant bit of the last octet of the MAC.
+    ///
+    /// If the MAC is truncated, the last octet of the truncated MAC is
+    /// changed.
+    pub fn corrupt(mut self) -> Self {
+        self.corrupt = true;
+        self
+    }
+}
+
+impl<R: Recipe> Recipe for Hmac<R> {
+    fn assemble(&self, target: &mut Fragment) {
+        let block_len = self.algo.block_len();
+        let mut key = if self.key.len() > block_len {
+            self.algo.hash(&self.key)
+        }
+        else {
+            self.key.clone()
+        };
+        key.resize(block_len, 0);
+
+        let start = target.len();
+        let mut inner: Vec<_> = key.iter().map(|x| x ^ 0x36).collect();
+        self.inner.assemble(target);
+        inner.extend_from_slice(&target[start..]);
+        let mut outer: Vec<_> = key.iter().map(|x| x ^ 0x5c).collect();
+        outer.extend_from_slice(&self.algo.hash(&inner));
+        let mut mac = self.algo.hash(&outer);
+
+        if let Some(len) = self.truncate {
+            mac.truncate(len)
+        }
+        if self.corrupt {
+            if let Some(last) = mac.last_mut() {
+                *last ^= 1
+            }
+        }
+        target.truncate(start);
+        target.extend_from_slice(&mac)
+    }
+}
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::recipe::core::{hex, literal};
+
+    #[test]
+    #[cfg(feature = "sha1")]
+    fn sha1() {
+        assert_eq!(
+            digest(HashAlgorithm::Sha1, literal("abc")).to_fragment(),
+            hex("a9993e364706816aba3e25717850c26c9cd0d89d").to_fragment()
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "sha2")]
+    fn sha2() {
+        let data = digest(HashAlgorithm::Sha256, literal("abc"));
+        assert_eq!(
+            data.to_fragment(),
+            hex(
+                "ba7816bf8f01cfea414140de5dae2223\
+                 b00361a396177a9cb410ff61f20015ad"
+            ).to_fragment()
+        );
+        assert_eq!(data.corrupt().to_fragment()[31], 0xac);
+        let data = digest(HashAlgorithm::Sha512, literal(""));
+        assert_eq!(
+            data.to_fragment().len(), HashAlgorithm::Sha512.output_len()
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "sha1")]
+    fn hmac_sha1() {
+        let data = "what do ya want for nothing?";
+        let mac = hmac(HashAlgorithm::Sha1, "Jefe", literal(data));
+        assert_eq!(
+            mac.to_fragment(),
+            hex("effcdf6ae5eb2fa2d27416d5f184df9c259a7c79").to_fragment()
+        );
+        assert_eq!(
+            mac.truncate(12).corrupt().to_fragment(),
+            hex("effcdf6ae5eb2fa2d27416d4").to_fragment()
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "sha2")]
+    fn hmac_sha2() {
+        let data = "what do ya want for nothing?";
+        assert_eq!(
+            hmac(HashAlgorithm::Sha256, "Jefe", literal(data)).to_fragment(),
+            hex(
+                "5bdcc146bf60754e6a042426089575c7\
+                 5a003f089d2739839dec58b964ec3843"
+            ).to_fragment()
+        );
+        let data = "Test Using Larger Than Block-Size Key - Hash Key First";
+        assert_eq!(
+            hmac(HashAlgorithm::Sha512, [0xAA; 131], literal(data))
+                .to_fragment(),
+            hex(
+                "80b24263c7c1a3ebb71493c1dd7be8b4\
+                 9b46d1f41b4aeec1121b013783f8f352\
+                 6b56d037e05f2598bd0fd2215d6a1e52\
+                 95e64f73f63f0aec8b915a985d786598"
+            ).to_fragment()
+        );
+    }
+}
@@ -0,0 +1,227 @@
+//! HTTP/1.1 messages.
+//!
+//! This module provides recipes for HTTP/1.1 messages as defined in RFC
+//! 9112 with a focus on message framing. Next to the start line and
+//! header recipes, [`chunked`] produces a body in chunked transfer coding
+//! and [`length_mismatches`] produces a set of responses in which the
+//! Content-Length and Transfer-Encoding headers disagree with the actual
+//! body in the various ways that make request smuggling possible.
+//!
+//! Compressed bodies use the gzip format with stored deflate blocks as
+//! produced by [`compressed`][super::compress::compressed].
+//!
+//! None of the recipes check their input.
+
+use super::compress::Codec;
+use super::core::{Fragment, Recipe, literal};
+use super::set::RecipeSet;
+
+
+//------------ Constants -----------------------------------------------------
+
+/// The protocol version in request and status lines.
+pub const VERSION: &str = "HTTP/1.1";
+
+/// The line separator.
+pub const CRLF: &str = "\r\n";
+
+
+//------------ request_line and status_line ----------------------------------
+
+/// Returns a recipe for a request line.
+pub fn request_line(method: &str, target: &str) -> impl Recipe {
+    literal(format!("{} {} {}{}", method, target, VERSION, CRLF))
+}
+
+/// Returns a recipe for a status line.
+pub fn status_line(code: u16, reason: &str) -> impl Recipe {
+    literal(format!("{} {} {}{}", VERSION, code, reason, CRLF))
+}
+
+
+//------------ header --------------------------------------------------------
+
+/// Returns a recipe for a header line.
+///
+/// The line consists of the _name,_ a colon and a space, whatever _value_
+/// produces, and the line separator.
+pub fn header<R: Recipe>(name: &str, value: R) -> impl Recipe {
+    (literal(format!("{}: ", name)), value, literal(CRLF))
+}
+
+
+//------------ chunked -------------------------------------------------------
+
+/// Returns a recipe for a body in chunked transfer coding.
+///
+/// The data of _inner_ is split into chunks of _size_ octets, the last
+/// one possibly shorter, followed by the last chunk and an empty trailer
+/// section.
+///
+/// # Panics
+///
+/// Assembling panics if _size_ is zero.
+pub fn chunked<R: Recipe>(size: usize, inner: R) -> Chunked<R> {
+    Chunked { size, inner }
+}
+
+pub struct Chunked<R> {
+    size: usize,
+    inner: R,
+}
+
+impl<R: Recipe> Recipe for Chunked<R> {
+    fn assemble(&self, target: &mut Fragment) {
+        let data = target.assemble_child(&self.inner);
+        for chunk in data.chunks(self.size) {
+            let line = format!("{:x}{}", chunk.len(), CRLF);
+            target.extend_from_slice(line.as_bytes());
+            target.extend_from_slice(chunk);
+            target.extend_from_slice(CRLF.as_bytes());
+        }
+        target.extend_from_slice(b"0\r\n\r\n");
+    }
+}
+
+
+//------------ length_mismatches ---------------------------------------------
+
+/// Returns responses whose framing headers disagree with the body.
+///
+/// All responses carry _body_ gzip-compressed with a Content-Encoding
+/// header. The baseline has a Content-Length matching the compressed
+/// body. The variants are:
+///
+/// * `cl-decoded-length`: Content-Length is the length of the
+///   uncompressed body,
+/// * `cl-short` and `cl-long`: Content-Length is one less or one more
+///   than the length of the body,
+/// * `cl-duplicate`: two Content-Length headers with the length of the
+///   compressed and uncompressed body,
+/// * `cl-and-chunked`: both a correct Content-Length and
+///   `Transfer-Encoding: chunked` with a chunked body,
+/// * `cl-wire-length-and-chunked`: as before but the Content-Length is
+///   the length of the chunked body,
+/// * `te-gzip-not-chunked`: `Transfer-Encoding: gzip` without chunked as
+///   the final coding and without Content-Length,
+/// * `te-chunked-not-last`: `Transfer-Encoding: chunked, gzip` with a
+///   chunked body,
+/// * `cl-and-te-gzip`: a correct Content-Length together with
+///   `Transfer-Encoding: gzip`,
+/// * `no-length`: neither header, i.e., the body ends when the
+///   connection is closed.
+pub fn length_mismatches(body: &[u8]) -> RecipeSet {
+    let encoded = gzip(body);
+    let len = encoded.len();
+    let chunked_len = chunked(CHUNK_SIZE, literal(encoded.clone()))
+        .to_fragment().len();
+
+    let response = |headers: &[(&str, String)], chunk: bool| {
+        let mut res = format!("{} 200 OK{}", VERSION, CRLF);
+        res.push_str("Content-Encoding: gzip\r\n");
+        for (name, value) in headers {
+            res.push_str(&format!("{}: {}{}", name, value, CRLF));
+        }
+        res.push_str(CRLF);
+        let mut res = literal(res).to_fragment();
+        if chunk {
+            chunked(CHUNK_SIZE, literal(encoded.clone())).assemble(&mut res);
+        }
+        else {
+            res.extend_from_slice(&encoded);
+        }
+        literal(res.into_vec())
+    };
+    let cl = |len: usize| ("Content-Length", len.to_string());
+    let te = |value: &str| ("Transfer-Encoding", value.to_string());
+
+    RecipeSet::new()
+        .baseline(response(&[cl(len)], false))
+        .variant("cl-decoded-length", response(&[cl(body.len())], false))
+        .variant("cl-short", response(&[cl(len - 1)], false))
+        .variant("cl-long", response(&[cl(len + 1)], false))
+        .variant(
+            "cl-duplicate", response(&[cl(len), cl(body.len())], false)
+        )
+        .variant(
+            "cl-and-chunked", response(&[cl(len), te("chunked")], true)
+        )
+        .variant(
+            "cl-wire-length-and-chunked",
+            response(&[cl(chunked_len), te("chunked")], true)
+        )
+        .variant("te-gzip-not-chunked", response(&[te("gzip")], false))
+        .variant(
+            "te-chunked-not-last", response(&[te("chunked, gzip")], true)
+        )
+        .variant("cl-and-te-gzip", response(&[cl(len), te("gzip")], false))
+        .variant("no-length", response(&[], false))
+}
+
+/// The chunk size used for chunked bodies.
+const CHUNK_SIZE: usize = 16;
+
+
+//------------ Helpers -------------------------------------------------------
+
+/// Returns the data in gzip format.
+fn gzip(data: &[u8]) -> Vec<u8> {
+    let mut res = Fragment::new();
+    Codec::Gzip.encode(data, &mut res);
+    res.into_vec()
+}
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::recipe::core::{Width, lp_string, repeat};
+
+    #[test]
+    fn chunks() {
+        assert_eq!(
+            chunked(2, literal("abcde")).to_fragment(),
+            "2\r\nab\r\n2\r\ncd\r\n1\r\ne\r\n0\r\n\r\n"
+        );
+        assert_eq!(chunked(2, literal("")).to_fragment(), "0\r\n\r\n");
+    }
+
+    #[test]
+    fn strict_chunks() {
+        let err = chunked(
+            16, lp_string(Width::U8, repeat(256, literal("a")))
+        ).to_strict_fragment().unwrap_err();
+        assert_eq!(
+            err.problems(), ["length 256 exceeds 1 octet length prefix"]
+        );
+    }
+
+    #[test]
+    fn mismatches() {
+        let body = gzip(b"a");
+        let head = format!(
+            "HTTP/1.1 200 OK\r\n\
+             Content-Encoding: gzip\r\n\
+             Content-Length: {}\r\n\r\n",
+            body.len()
+        );
+        let set = length_mismatches(b"a");
+        assert_eq!(set.len(), 11);
+        let baseline = set.get(RecipeSet::BASELINE).unwrap().to_fragment();
+        assert_eq!(&baseline[..head.len()], head.as_bytes());
+        assert_eq!(&baseline[head.len()..], body);
+        let last = match body.len() % CHUNK_SIZE {
+            0 => CHUNK_SIZE,
+            len => len,
+        };
+        let mut tail = format!("\r\n{:x}\r\n", last).into_bytes();
+        tail.extend_from_slice(&body[body.len() - last..]);
+        tail.extend_from_slice(b"\r\n0\r\n\r\n");
+        assert!(
+            set.get("te-chunked-not-last").unwrap().to_fragment()
+                .ends_with(&tail)
+        );
+    }
+}
@@ -0,0 +1,515 @@
+//! IKEv2 messages.
+//!
+//! This module provides recipes for the messages of the Internet Key
+//! Exchange protocol version 2 as defined in RFC 7296.
+//!
+//! An IKEv2 message consists of a header followed by a chain of payloads
+//! where each payload header and the message header name the type of the
+//! payload that follows. The [`message`] and [`chain`] recipes link up a
+//! list of [`Payload`]s and calculate all lengths. Individual payloads can
+//! override their next payload type and length to create broken chains and
+//! length fields that don’t match the content. Any other field of the
+//! header and the payloads can be replaced via [`OverrideField`].
+
+use super::core::{
+    Fragment, OverrideField, Overrides, Recipe, SharedRecipe, be, iter,
+    literal
+};
+
+
+//------------ Constants -----------------------------------------------------
+
+/// The version field value for IKEv2.
+pub const VERSION: u8 = 0x20;
+
+/// The next payload value signaling the end of the chain.
+pub const NO_NEXT_PAYLOAD: u8 = 0;
+
+/// The payload type of a Security Association payload.
+pub const PAYLOAD_SA: u8 = 33;
+
+/// The payload type of a Key Exchange payload.
+pub const PAYLOAD_KE: u8 = 34;
+
+/// The payload type of an Identification payload of the initiator.
+pub const PAYLOAD_IDI: u8 = 35;
+
+/// The payload type of an Identification payload of the responder.
+pub const PAYLOAD_IDR: u8 = 36;
+
+/// The payload type of an Authentication payload.
+pub const PAYLOAD_AUTH: u8 = 39;
+
+/// The payload type of a Nonce payload.
+pub const PAYLOAD_NONCE: u8 = 40;
+
+/// The payload type of a Notify payload.
+pub const PAYLOAD_NOTIFY: u8 = 41;
+
+/// The payload type of a Vendor ID payload.
+pub const PAYLOAD_VENDOR_ID: u8 = 43;
+
+/// The payload type of an Encrypted payload.
+pub const PAYLOAD_SK: u8 = 46;
+
+/// The exchange type of the IKE_SA_INIT exchange.
+pub const IKE_SA_INIT: u8 = 34;
+
+/// The exchange type of the IKE_AUTH exchange.
+pub const IKE_AUTH: u8 = 35;
+
+/// The exchange type of the CREATE_CHILD_SA exchange.
+pub const CREATE_CHILD_SA: u8 = 36;
+
+/// The exchange type of the INFORMATIONAL exchange.
+pub const INFORMATIONAL: u8 = 37;
+
+/// The header flag set by the original initiator.
+pub const FLAG_INITIATOR: u8 = 0x08;
+
+/// The header flag signaling support for a higher version.
+pub const FLAG_VERSION: u8 = 0x10;
+
+/// The header flag marking a response.
+pub const FLAG_RESPONSE: u8 = 0x20;
+
+/// The protocol ID of IKE.
+pub const PROTOCOL_IKE: u8 = 1;
+
+/// The protocol ID of ESP.
+pub const PROTOCOL_ESP: u8 = 3;
+
+/// The transform type for encryption algorithms.
+pub const TRANSFORM_ENCR: u8 = 1;
+
+/// The transform type for pseudo-random functions.
+pub const TRANSFORM_PRF: u8 = 2;
+
+/// The transform type for integrity algorithms.
+pub const TRANSFORM_INTEG: u8 = 3;
+
+/// The transform type for Diffie-Hellman groups.
+pub const TRANSFORM_DH: u8 = 4;
+
+
+//------------ Header --------------------------------------------------------
+
+/// An IKEv2 message header.
+///
+/// The header implements [`Recipe`] and all its fields are used as given.
+/// When used with [`message`], the next payload and length are replaced
+/// with the correct values unless they have been overridden.
+///
+/// The paths for [`OverrideField`] are the names of the fields. The
+/// version field is called `version`.
+#[derive(Clone, Debug)]
+pub struct Header {
+    pub initiator_spi: u64,
+    pub responder_spi: u64,
+    pub next_payload: u8,
+    pub version: u8,
+    pub exchange_type: u8,
+    pub flags: u8,
+    pub message_id: u32,
+    pub length: u32,
+    pub overrides: Overrides,
+}
+
+impl Header {
+    /// Creates a new header with the given exchange type and flags.
+    ///
+    /// The version is set to [`VERSION`], all other fields are zero.
+    pub fn new(exchange_type: u8, flags: u8) -> Self {
+        Header {
+            initiator_spi: 0,
+            responder_spi: 0,
+            next_payload: NO_NEXT_PAYLOAD,
+            version: VERSION,
+            exchange_type,
+            flags,
+            message_id: 0,
+            length: 0,
+            overrides: Overrides::new(),
+        }
+    }
+}
+
+impl OverrideField for Header {
+    fn override_field(
+        mut self, path: &str, recipe: impl Recipe + Send + Sync + 'static
+    ) -> Self {
+        self.overrides.insert(path, recipe);
+        self
+    }
+}
+
+impl Recipe for Header {
+    fn assemble(&self, target: &mut Fragment) {
+        let fields = &self.overrides;
+        fields.assemble_field(
+            "initiator_spi", be(self.initiator_spi), target
+        );
+        fields.assemble_field(
+            "responder_spi", be(self.responder_spi), target
+        );
+        fields.assemble_field("next_payload", be(self.next_payload), target);
+        fields.assemble_field("version", be(self.version), target);
+        fields.assemble_field(
+            "exchange_type", be(self.exchange_type), target
+        );
+        fields.assemble_field("flags", be(self.flags), target);
+        fields.assemble_field("message_id", be(self.message_id), target);
+        fields.assemble_field("length", be(self.length), target);
+    }
+}
+
+
+//------------ message -------------------------------------------------------
+
+/// Returns a recipe for a complete message.
+///
+/// The next payload field of the _header_ is set to the type of the first
+/// payload and the length to the length of the complete message. The
+/// payloads are linked up via [`chain`].
+pub fn message(mut header: Header, payloads: Vec<Payload>) -> impl Recipe {
+    header.next_payload = payloads.first().map(|item| {
+        item.payload_type
+    }).unwrap_or(NO_NEXT_PAYLOAD);
+    Message { header, payloads: chain(payloads) }
+}
+
+struct Message<P> {
+    header: Header,
+    payloads: P,
+}
+
+impl<P: Recipe> Recipe for Message<P> {
+    fn assemble(&self, target: &mut Fragment) {
+        let start = target.len();
+        self.header.assemble(target);
+        let end = target.len();
+        self.payloads.assemble(target);
+        if target.is_exceeded() {
+            return
+        }
+        if !self.header.overrides.contains("length") {
+            let len = (target.len() - start) as u32;
+            target[end - 4..end].copy_from_slice(&len.to_be_bytes());
+        }
+    }
+}
+
+
+//------------ chain ---------------------------------------------------------
+
+/// Returns a recipe for a chain of payloads.
+///
+/// The next payload field of each payload is set to the type of the
+/// following payload or [`NO_NEXT_PAYLOAD`] for the last one unless
+/// overridden by the payload.
+pub fn chain(payloads: Vec<Payload>) -> impl Recipe {
+    let mut payloads = payloads;
+    let types: Vec<_> = payloads.iter().skip(1).map(|item| {
+        item.payload_type
+    }).collect();
+    for (item, next) in payloads.iter_mut().zip(
+        types.into_iter().chain(Some(NO_NEXT_PAYLOAD))
+    ) {
+        item.next_payload.get_or_insert(next);
+    }
+    iter(payloads)
+}
+
+
+//------------ Payload -------------------------------------------------------
+
+/// A payload in a chain of payloads.
+///
+/// The paths for [`OverrideField`] are `next_payload`, `flags` for the
+/// octet holding the critical bit, `length`, and `body`.
+#[derive(Clone, Debug)]
+pub struct Payload {
+    payload_type: u8,
+    critical: bool,
+    next_payload: Option<u8>,
+    length: Option<u16>,
+    body: SharedRecipe,
+    overrides: Overrides,
+}
+
+impl Payload {
+    /// Creates a new payload from its type and body.
+    ///
+    /// The _body_ is assembled each time the payload is assembled.
+    pub fn new(
+        payload_type: u8, body: impl Recipe + Send + Sync + 'static
+    ) -> Self {
+        Payload {
+            payload_type,
+            critical: false,
+            next_payload: None,
+            length: None,
+            body: SharedRecipe::new(body),
+            overrides: Overrides::new(),
+        }
+    }
+
+    /// Sets the critical bit of the payload.
+    pub fn critical(mut self) -> Self {
+        self.critical = true;
+        self
+    }
+
+    /// Sets the next payload field to a fixed value.
+    pub fn with_next_payload(mut self, next_payload: u8) -> Self {
+        self.next_payload = Some(next_payload);
+        self
+    }
+
+    /// Sets the payload length to a fixed value.
+    pub fn with_length(mut self, length: u16) -> Self {
+        self.length = Some(length);
+        self
+    }
+
+    /// Assembles the body or its override.
+    fn assemble_body(&self, target: &mut Fragment) {
+        match self.overrides.get("body") {
+            Some(body) => body.assemble(target),
+            None => self.body.assemble(target),
+        }
+    }
+}
+
+impl OverrideField for Payload {
+    fn override_field(
+        mut self, path: &str, recipe: impl Recipe + Send + Sync + 'static
+    ) -> Self {
+        self.overrides.insert(path, recipe);
+        self
+    }
+}
+
+impl Recipe for Payload {
+    fn assemble(&self, target: &mut Fragment) {
+        let fields = &self.overrides;
+        let start = target.len();
+        fields.assemble_field(
+            "next_payload",
+            be(self.next_payload.unwrap_or(NO_NEXT_PAYLOAD)),
+            target
+        );
+        fields.assemble_field(
+            "flags", be(if self.critical { 0x80u8 } else { 0 }), target
+        );
+        if let Some(length) = fields.get("length") {
+            length.assemble(target);
+            self.assemble_body(target);
+            return
+        }
+        let pos = target.len();
+        target.extend_from_slice(&[0; 2]);
+        self.assemble_body(target);
+        if target.is_exceeded() {
+            return
+        }
+        let len = self.length.unwrap_or((target.len() - start) as u16);
+        target[pos..pos + 2].copy_from_slice(&len.to_be_bytes());
+    }
+}
+
+
+//------------ sa ------------------------------------------------------------
+
+/// Returns a Security Association payload.
+///
+/// The “last substructure” fields of the proposals are set according to
+/// their position.
+pub fn sa(proposals: Vec<Proposal>) -> Payload {
+    let count = proposals.len();
+    Payload::new(PAYLOAD_SA, iter(
+        proposals.into_iter().enumerate().map(|(idx, proposal)| {
+            Substructure { last: idx + 1 == count, more: 2, body: proposal }
+        }).collect::<Vec<_>>()
+    ))
+}
+
+/// A proposal within a Security Association payload.
+#[derive(Clone, Debug)]
+pub struct Proposal {
+    pub number: u8,
+    pub protocol_id: u8,
+    pub spi: Vec<u8>,
+    pub transforms: Vec<Transform>,
+}
+
+impl Proposal {
+    /// Creates a new proposal without an SPI.
+    pub fn new(
+        number: u8, protocol_id: u8, transforms: Vec<Transform>
+    ) -> Self {
+        Proposal { number, protocol_id, spi: Vec::new(), transforms }
+    }
+}
+
+impl Recipe for Proposal {
+    fn assemble(&self, target: &mut Fragment) {
+        target.extend_from_slice(&[
+            self.number, self.protocol_id, self.spi.len() as u8,
+            self.transforms.len() as u8
+        ]);
+        target.extend_from_slice(&self.spi);
+        let count = self.transforms.len();
+        for (idx, transform) in self.transforms.iter().enumerate() {
+            Substructure {
+                last: idx + 1 == count, more: 3, body: transform
+            }.assemble(target)
+        }
+    }
+}
+
+/// A transform within a proposal.
+#[derive(Clone, Copy, Debug)]
+pub struct Transform {
+    pub transform_type: u8,
+    pub transform_id: u16,
+
+    /// The value of the key length attribute if present.
+    pub key_length: Option<u16>,
+}
+
+impl Transform {
+    /// Creates a new transform without attributes.
+    pub fn new(transform_type: u8, transform_id: u16) -> Self {
+        Transform { transform_type, transform_id, key_length: None }
+    }
+}
+
+impl Recipe for Transform {
+    fn assemble(&self, target: &mut Fragment) {
+        target.extend_from_slice(&[self.transform_type, 0]);
+        target.extend_from_slice(&self.transform_id.to_be_bytes());
+        if let Some(key_length) = self.key_length {
+            target.extend_from_slice(&[0x80, 0x0E]);
+            target.extend_from_slice(&key_length.to_be_bytes());
+        }
+    }
+}
+
+/// A proposal or transform with its substructure header.
+struct Substructure<R> {
+    last: bool,
+    more: u8,
+    body: R,
+}
+
+impl<R: Recipe> Recipe for Substructure<R> {
+    fn assemble(&self, target: &mut Fragment) {
+        let start = target.len();
+        target.extend_from_slice(&[
+            if self.last { 0 } else { self.more }, 0, 0, 0
+        ]);
+        self.body.assemble(target);
+        if target.is_exceeded() {
+            return
+        }
+        let len = (target.len() - start) as u16;
+        target[start + 2..start + 4].copy_from_slice(&len.to_be_bytes());
+    }
+}
+
+
+//------------ ke, nonce, notify ---------------------------------------------
+
+/// Returns a Key Exchange payload.
+pub fn ke<R: Recipe + Send + Sync + 'static>(
+    dh_group: u16, data: R
+) -> Payload {
+    Payload::new(PAYLOAD_KE, (be(dh_group), be(0u16), data))
+}
+
+/// Returns a Nonce payload.
+pub fn nonce<R: Recipe + Send + Sync + 'static>(data: R) -> Payload {
+    Payload::new(PAYLOAD_NONCE, data)
+}
+
+/// Returns a Notify payload.
+pub fn notify<R: Recipe + Send + Sync + 'static>(
+    protocol_id: u8, spi: &[u8], notify_type: u16, data: R
+) -> Payload {
+    Payload::new(PAYLOAD_NOTIFY, (
+        literal([protocol_id, spi.len() as u8]), be(notify_type),
+        literal(spi.to_vec()), data
+    ))
+}
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::recipe::core::{exec, hex};
+
+    #[test]
+    fn message_chain() {
+        let msg = message(
+            Header::new(IKE_SA_INIT, FLAG_INITIATOR),
+            vec![
+                sa(vec![Proposal::new(1, PROTOCOL_IKE, vec![
+                    Transform {
+                        key_length: Some(128),
+                        ..Transform::new(TRANSFORM_ENCR, 12)
+                    },
+                    Transform::new(TRANSFORM_DH, 19),
+                ])]),
+                nonce(hex("0102")).with_next_payload(PAYLOAD_KE),
+            ]
+        ).to_fragment();
+        assert_eq!(msg[16..20], [PAYLOAD_SA, VERSION, IKE_SA_INIT, 0x08]);
+        assert_eq!(&msg[24..28], &(msg.len() as u32).to_be_bytes());
+        assert_eq!(
+            msg[28..],
+            *hex(
+                "28 00 0020
+                   00 00 001c 01 01 00 02
+                     03 00 000c 01 00 000c 800e 0080
+                     00 00 0008 04 00 0013
+                 22 00 0006 0102"
+            ).to_fragment()
+        );
+    }
+
+    #[test]
+    fn overrides() {
+        let msg = message(
+            Header::new(INFORMATIONAL, 0).override_field("version", hex("")),
+            vec![nonce(hex("01")).override_field("flags", hex("ff"))]
+        ).to_fragment();
+        assert_eq!(msg[16..19], [PAYLOAD_NONCE, INFORMATIONAL, 0]);
+        assert_eq!(msg[23..], *hex("00000020 00 ff 0005 01").to_fragment());
+    }
+
+    #[test]
+    fn body_assembled_in_place() {
+        fn position(target: &mut Fragment) {
+            target.validate(false, || "bad".into());
+            target.push(target.len() as u8)
+        }
+
+        let msg = message(
+            Header::new(INFORMATIONAL, 0),
+            vec![
+                nonce(exec(position)),
+                nonce(hex("00")).override_field("body", exec(position)),
+            ]
+        );
+        assert_eq!(
+            msg.to_fragment()[28..],
+            *hex("28 00 0005 20 00 00 0005 25").to_fragment()
+        );
+        assert_eq!(
+            msg.to_strict_fragment().unwrap_err().problems(), ["bad", "bad"]
+        );
+    }
+}
@@ -0,0 +1,407 @@
+//! Splitting data into datagrams and IP fragments.
+//!
+//! This module helps with testing code that deals with data split across
+//! multiple packets. The function [`datagrams`] splits the data of a
+//! recipe into pieces that fit a given MTU.
+//!
+//! For IPv4, an [`Ipv4Header`] can split a payload into [`Ipv4Fragment`]s
+//! either the way a well-behaved sender would via
+//! [`fragment`][Ipv4Header::fragment] or along arbitrary ranges via
+//! [`fragments_at`][Ipv4Header::fragments_at]. The latter allows creating
+//! overlapping fragments or fragment sets with holes to test reassembly
+//! logic. Fragments can also simply be dropped or reordered since they
+//! are returned as a vec.
+//!
+//! Finally, [`internet_checksum`] calculates the checksum used by IPv4,
+//! ICMP, UDP, and TCP over the data of a recipe.
+
+use std::net::Ipv4Addr;
+use std::ops::Range;
+use super::core::{Empty, Fragment, Recipe, be};
+
+
+//------------ datagrams -----------------------------------------------------
+
+/// Splits the data of a recipe into datagrams of at most _mtu_ octets.
+///
+/// All datagrams but the last one are exactly _mtu_ octets long. If the
+/// recipe produces no data, a single empty datagram is returned.
+///
+/// The recipe is assembled in strict mode. Any problems are recorded in
+/// the first datagram.
+///
+/// # Panics
+///
+/// The function panics if _mtu_ is zero.
+pub fn datagrams(mtu: usize, recipe: impl Recipe) -> Vec<Fragment> {
+    assert!(mtu > 0, "MTU must not be zero");
+    let data = assemble_payload(recipe);
+    if data.is_empty() {
+        return vec![data]
+    }
+    data.chunks(mtu).enumerate().map(|(idx, chunk)| {
+        let mut res = data.child();
+        res.extend_from_slice(chunk);
+        if idx == 0 {
+            copy_problems(&data, &mut res)
+        }
+        res
+    }).collect()
+}
+
+
+//------------ Ipv4Header ----------------------------------------------------
+
+/// The fields of an IPv4 header shared by all fragments of a packet.
+///
+/// The header never has options. Total length, fragment offset, the
+/// more-fragments flag, and the checksum are determined for each
+/// fragment.
+#[derive(Clone, Debug)]
+pub struct Ipv4Header {
+    pub tos: u8,
+    pub identification: u16,
+
+    /// Whether the don’t-fragment flag is set.
+    pub dont_fragment: bool,
+    pub ttl: u8,
+    pub protocol: u8,
+    pub source: Ipv4Addr,
+    pub destination: Ipv4Addr,
+}
+
+impl Ipv4Header {
+    /// The length of the header in octets.
+    pub const LEN: usize = 20;
+
+    /// Creates a header with the given protocol and addresses.
+    ///
+    /// The TTL is set to 64, everything else to zero.
+    pub fn new(
+        protocol: u8, source: Ipv4Addr, destination: Ipv4Addr
+    ) -> Self {
+        Ipv4Header {
+            tos: 0,
+            identification: 0,
+            dont_fragment: false,
+            ttl: 64,
+            protocol,
+            source,
+            destination,
+        }
+    }
+
+    /// Splits a payload into fragments that fit the given MTU.
+    ///
+    /// The MTU includes the IPv4 header. The payload of each fragment but
+    /// the last is the largest multiple of eight octets that fits.
+    ///
+    /// The payload is assembled in strict mode. Any problems are passed
+    /// on when the first fragment is assembled into a strict fragment.
+    ///
+    /// # Panics
+    ///
+    /// The function panics if the MTU leaves less than eight octets for
+    /// the payload.
+    pub fn fragment(
+        &self, mtu: usize, payload: impl Recipe
+    ) -> Vec<Ipv4Fragment> {
+        let size = mtu.saturating_sub(Self::LEN) & !7;
+        assert!(size > 0, "MTU too small for fragmentation");
+        let payload = assemble_payload(payload);
+        let ranges = (0..payload.len().max(1)).step_by(size).map(|start| {
+            start..payload.len().min(start + size)
+        }).collect::<Vec<_>>();
+        self.split(&payload, ranges)
+    }
+
+    /// Creates fragments for the given ranges of a payload.
+    ///
+    /// A fragment is created for each range in the order given. Ranges
+    /// may overlap or leave gaps. The more-fragments flag is set unless
+    /// the range ends at the end of the payload. Ranges are cut short at
+    /// the end of the payload.
+    ///
+    /// The fragment offset of the header is in units of eight octets. If
+    /// the start of a range isn’t a multiple of eight, the offset is
+    /// rounded down while the payload still starts at the given octet.
+    ///
+    /// As with [`fragment`][Self::fragment], problems with the payload
+    /// are passed on by the first fragment.
+    pub fn fragments_at(
+        &self, payload: impl Recipe,
+        ranges: impl IntoIterator<Item = Range<usize>>
+    ) -> Vec<Ipv4Fragment> {
+        self.split(&assemble_payload(payload), ranges)
+    }
+
+    /// Creates fragments for the given ranges of assembled payload.
+    fn split(
+        &self, payload: &Fragment,
+        ranges: impl IntoIterator<Item = Range<usize>>
+    ) -> Vec<Ipv4Fragment> {
+        ranges.into_iter().enumerate().map(|(idx, range)| {
+            let end = range.end.min(payload.len());
+            let start = range.start.min(end);
+            let mut data = payload.child();
+            data.extend_from_slice(&payload[start..end]);
+            if idx == 0 {
+                copy_problems(payload, &mut data)
+            }
+            Ipv4Fragment {
+                header: self.clone(),
+                offset: (start / 8) as u16,
+                more_fragments: end < payload.len(),
+                payload: data,
+            }
+        }).collect()
+    }
+}
+
+
+//------------ Ipv4Fragment --------------------------------------------------
+
+/// A single IPv4 packet carrying a fragment of a payload.
+///
+/// When assembled, the total length and header checksum are calculated.
+#[derive(Clone, Debug)]
+pub struct Ipv4Fragment {
+    pub header: Ipv4Header,
+
+    /// The fragment offset in units of eight octets.
+    ///
+    /// Only the lower 13 bits are used.
+    pub offset: u16,
+    pub more_fragments: bool,
+    pub payload: Fragment,
+}
+
+impl Recipe for Ipv4Fragment {
+    fn assemble(&self, target: &mut Fragment) {
+        let start = target.len();
+        let flags = (u16::from(self.header.dont_fragment) << 14)
+            | (u16::from(self.more_fragments) << 13);
+        target.extend_from_slice(&[0x45, self.header.tos]);
+        (
+            be((Ipv4Header::LEN + self.payload.len()) as u16),
+            be(self.header.identification),
+            be(flags | (self.offset & 0x1FFF)),
+        ).assemble(target);
+        target.extend_from_slice(&[
+            self.header.ttl, self.header.protocol, 0, 0
+        ]);
+        target.extend_from_slice(&self.header.source.octets());
+        target.extend_from_slice(&self.header.destination.octets());
+        if target.is_exceeded() {
+            return
+        }
+        let checksum = checksum(&target[start..]);
+        target[start + 10..start + 12].copy_from_slice(
+            &checksum.to_be_bytes()
+        );
+        target.append(&self.payload);
+    }
+}
+
+
+//------------ internet_checksum ---------------------------------------------
+
+/// Returns a recipe inserting the Internet checksum into another recipe.
+///
+/// The recipe assembles _inner_ and calculates the ones’ complement
+/// checksum defined in RFC 1071 over its data. The checksum is written
+/// in network byte order into the two octets starting at _position_
+/// relative to the start of the inner data. Whatever is in these octets
+/// is overwritten and treated as zero for the calculation.
+///
+/// UDP and TCP include a pseudo header in the checksum that isn’t part
+/// of the data. It can be given via [`InternetChecksum::pseudo_header`]
+/// and is assembled along with the inner data.
+///
+/// # Panics
+///
+/// Assembling panics if the inner data is too short for the position.
+pub fn internet_checksum<R: Recipe>(
+    position: usize, inner: R
+) -> InternetChecksum<R> {
+    InternetChecksum { position, pseudo: Empty, offset: 0, inner }
+}
+
+pub struct InternetChecksum<R, P = Empty> {
+    position: usize,
+    pseudo: P,
+    offset: u16,
+    inner: R,
+}
+
+impl<R, P> InternetChecksum<R, P> {
+    /// Includes the data of a pseudo header in the checksum.
+    pub fn pseudo_header<H: Recipe>(
+        self, header: H
+    ) -> InternetChecksum<R, H> {
+        InternetChecksum {
+            position: self.position,
+            pseudo: header,
+            offset: self.offset,
+            inner: self.inner,
+        }
+    }
+
+    /// Adds _offset_ to the checksum, wrapping around on overflow.
+    ///
+    /// Any offset other than zero results in a wrong checksum.
+    pub fn offset(mut self, offset: u16) -> Self {
+        self.offset = offset;
+        self
+    }
+}
+
+impl<R: Recipe, P: Recipe> Recipe for InternetChecksum<R, P> {
+    fn assemble(&self, target: &mut Fragment) {
+        let pseudo = target.assemble_child(&self.pseudo);
+        let start = target.len();
+        self.inner.assemble(target);
+        if target.is_exceeded() {
+            return
+        }
+        let pos = start + self.position;
+        assert!(
+            pos + 2 <= target.len(),
+            "checksum position {} beyond end of data", self.position
+        );
+        target[pos..pos + 2].copy_from_slice(&[0, 0]);
+        let value = checksum_parts(&[&pseudo, &target[start..]])
+            .wrapping_add(self.offset);
+        target[pos..pos + 2].copy_from_slice(&value.to_be_bytes());
+    }
+}
+
+
+//------------ Helpers -------------------------------------------------------
+
+/// Calculates the Internet checksum of some data.
+fn checksum(data: &[u8]) -> u16 {
+    checksum_parts(&[data])
+}
+
+/// Calculates the Internet checksum over the concatenation of the parts.
+fn checksum_parts(parts: &[&[u8]]) -> u16 {
+    let mut sum = 0u64;
+    let mut high = true;
+    for &octet in parts.iter().flat_map(|part| part.iter()) {
+        sum += if high { u64::from(octet) << 8 } else { u64::from(octet) };
+        high = !high;
+    }
+    while sum > 0xFFFF {
+        sum = (sum & 0xFFFF) + (sum >> 16)
+    }
+    !(sum as u16)
+}
+
+/// Assembles a payload in strict mode so its problems can be passed on.
+fn assemble_payload(recipe: impl Recipe) -> Fragment {
+    let mut res = Fragment::strict();
+    recipe.assemble(&mut res);
+    res
+}
+
+/// Records the problems of one fragment in another.
+fn copy_problems(from: &Fragment, to: &mut Fragment) {
+    for problem in from.problems() {
+        to.validate(false, || problem.clone())
+    }
+}
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::recipe::core::{
+        Context, Width, hex, lp_string, param, repeat
+    };
+
+    fn header() -> Ipv4Header {
+        Ipv4Header {
+            identification: 0x1234,
+            ..Ipv4Header::new(17, [192, 0, 2, 1].into(), [192, 0, 2, 2].into())
+        }
+    }
+
+    #[test]
+    fn split() {
+        let res = datagrams(4, hex("0102030405"));
+        assert_eq!(res.len(), 2);
+        assert_eq!(res[0], b"\x01\x02\x03\x04");
+        assert_eq!(res[1], b"\x05");
+        assert_eq!(datagrams(4, hex("")), [Fragment::new()]);
+    }
+
+    #[test]
+    fn ipv4() {
+        let frags = header().fragment(36, repeat(20, hex("aa")));
+        assert_eq!(frags.len(), 2);
+        assert_eq!(
+            frags[0].to_fragment(),
+            hex(
+                "4500 0024 1234 2000 4011 c491 c0000201 c0000202
+                 aaaaaaaa aaaaaaaa aaaaaaaa aaaaaaaa"
+            ).to_fragment()
+        );
+        assert_eq!(
+            frags[1].to_fragment(),
+            hex("4500 0018 1234 0002 4011 e49b c0000201 c0000202 aaaaaaaa")
+                .to_fragment()
+        );
+    }
+
+    #[test]
+    fn checksums() {
+        // The example from RFC 1071, section 3, with a checksum field.
+        assert_eq!(
+            internet_checksum(8, hex("0001 f203 f4f5 f6f7 ffff"))
+                .to_fragment(),
+            hex("0001 f203 f4f5 f6f7 220d").to_fragment()
+        );
+        assert_eq!(
+            internet_checksum(0, hex("0000 01")).to_fragment(),
+            b"\xfe\xff\x01"
+        );
+        assert_eq!(
+            internet_checksum(0, hex("0000 01")).pseudo_header(hex("00"))
+                .to_fragment(),
+            b"\xff\xfe\x01"
+        );
+        // The pseudo header is assembled along with the data.
+        assert_eq!(
+            internet_checksum(0, hex("0000 01")).pseudo_header(param("p"))
+                .to_fragment_with(&Context::new().with("p", hex("00"))),
+            b"\xff\xfe\x01"
+        );
+        assert_eq!(
+            internet_checksum(0, hex("0000"))
+                .pseudo_header(lp_string(Width::U8, repeat(256, hex("00"))))
+                .to_strict_fragment().unwrap_err().problems().len(),
+            1
+        );
+        assert_eq!(
+            internet_checksum(0, hex("0000")).offset(1).to_fragment(),
+            b"\0\0"
+        );
+    }
+
+    #[test]
+    fn overlapping() {
+        let frags = header().fragments_at(hex("0001020304050607 08090a0b"), [
+            8..12, 0..16, 4..8
+        ]);
+        assert_eq!(frags[0].offset, 1);
+        assert!(!frags[0].more_fragments);
+        assert_eq!(frags[1].payload.len(), 12);
+        assert!(!frags[1].more_fragments);
+        assert_eq!(frags[2].offset, 0);
+        assert!(frags[2].more_fragments);
+        assert_eq!(frags[2].payload, b"\x04\x05\x06\x07");
+    }
+}
@@ -0,0 +1,413 @@
+//! Flow export messages: IPFIX and its NetFlow predecessors.
+//!
+//! This module provides recipes for the messages of NetFlow version 5,
+//! NetFlow version 9 as described in RFC 3954, and IPFIX as defined in
+//! RFC 7011.
+//!
+//! Versions 9 and IPFIX share the same basic structure: a message header is
+//! followed by a sequence of sets (called flow sets in version 9). Template
+//! sets describe the layout of data records via [`Field`] specifiers while
+//! data sets carry the actual records under the ID of their template. The
+//! recipes here don’t keep track of templates, so data sets can easily
+//! refer to unknown templates or disagree with their template’s layout.
+//!
+//! Length fields are calculated, but there are `_raw` variants of the
+//! recipes that allow setting them to arbitrary values.
+
+use super::core::{Fragment, Recipe, be, iter};
+
+
+//------------ Constants -----------------------------------------------------
+
+/// The version number of NetFlow version 5.
+pub const NETFLOW_V5: u16 = 5;
+
+/// The version number of NetFlow version 9.
+pub const NETFLOW_V9: u16 = 9;
+
+/// The version number of IPFIX.
+pub const IPFIX: u16 = 10;
+
+/// The flow set ID of a NetFlow version 9 template flow set.
+pub const V9_TEMPLATE_SET: u16 = 0;
+
+/// The flow set ID of a NetFlow version 9 options template flow set.
+pub const V9_OPTIONS_TEMPLATE_SET: u16 = 1;
+
+/// The set ID of an IPFIX template set.
+pub const TEMPLATE_SET: u16 = 2;
+
+/// The set ID of an IPFIX options template set.
+pub const OPTIONS_TEMPLATE_SET: u16 = 3;
+
+/// The smallest template ID and thus data set ID.
+pub const MIN_DATA_SET: u16 = 256;
+
+/// The bit marking an information element as enterprise-specific.
+pub const ENTERPRISE_BIT: u16 = 0x8000;
+
+/// The field length signaling a variable-length field.
+pub const VARIABLE_LENGTH: u16 = 0xFFFF;
+
+
+//============ NetFlow Version 5 =============================================
+
+//------------ V5Header ------------------------------------------------------
+
+/// A NetFlow version 5 header.
+///
+/// The header implements [`Recipe`] directly, so all fields including the
+/// version and record count can be set to any value.
+#[derive(Clone, Copy, Debug)]
+pub struct V5Header {
+    pub version: u16,
+    pub count: u16,
+    pub sys_uptime: u32,
+    pub unix_secs: u32,
+    pub unix_nsecs: u32,
+    pub flow_sequence: u32,
+    pub engine_type: u8,
+    pub engine_id: u8,
+    pub sampling_interval: u16,
+}
+
+impl Default for V5Header {
+    fn default() -> Self {
+        V5Header {
+            version: NETFLOW_V5,
+            count: 0,
+            sys_uptime: 0,
+            unix_secs: 0,
+            unix_nsecs: 0,
+            flow_sequence: 0,
+            engine_type: 0,
+            engine_id: 0,
+            sampling_interval: 0,
+        }
+    }
+}
+
+impl Recipe for V5Header {
+    fn assemble(&self, target: &mut Fragment) {
+        (
+            (be(self.version), be(self.count), be(self.sys_uptime)),
+            (be(self.unix_secs), be(self.unix_nsecs), be(self.flow_sequence)),
+            (
+                be(self.engine_type), be(self.engine_id),
+                be(self.sampling_interval)
+            ),
+        ).assemble(target)
+    }
+}
+
+
+//------------ V5Record ------------------------------------------------------
+
+/// A NetFlow version 5 flow record.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct V5Record {
+    pub src_addr: [u8; 4],
+    pub dst_addr: [u8; 4],
+    pub next_hop: [u8; 4],
+    pub input: u16,
+    pub output: u16,
+    pub packets: u32,
+    pub octets: u32,
+    pub first: u32,
+    pub last: u32,
+    pub src_port: u16,
+    pub dst_port: u16,
+    pub tcp_flags: u8,
+    pub protocol: u8,
+    pub tos: u8,
+    pub src_as: u16,
+    pub dst_as: u16,
+    pub src_mask: u8,
+    pub dst_mask: u8,
+}
+
+impl Recipe for V5Record {
+    fn assemble(&self, target: &mut Fragment) {
+        target.extend_from_slice(&self.src_addr);
+        target.extend_from_slice(&self.dst_addr);
+        target.extend_from_slice(&self.next_hop);
+        (
+            (be(self.input), be(self.output)),
+            (be(self.packets), be(self.octets)),
+            (be(self.first), be(self.last)),
+            (be(self.src_port), be(self.dst_port)),
+            (be(0u8), be(self.tcp_flags), be(self.protocol), be(self.tos)),
+            (be(self.src_as), be(self.dst_as)),
+        ).assemble(target);
+        (
+            be(self.src_mask), be(self.dst_mask), be(0u16)
+        ).assemble(target)
+    }
+}
+
+
+//------------ v5_message ----------------------------------------------------
+
+/// Returns a recipe for a NetFlow version 5 message.
+///
+/// The record count in _header_ is replaced with the number of records.
+/// To create a message with a wrong count, combine a header and records
+/// manually.
+pub fn v5_message(
+    mut header: V5Header, records: Vec<V5Record>
+) -> impl Recipe {
+    header.count = records.len() as u16;
+    (header, iter(records))
+}
+
+
+//============ NetFlow Version 9 and IPFIX ===================================
+
+//------------ v9_header -----------------------------------------------------
+
+/// Returns a recipe for a NetFlow version 9 header.
+///
+/// The _count_ is the total number of records in the message including
+/// template records. It is not checked against the actual content.
+pub fn v9_header(
+    count: u16, sys_uptime: u32, unix_secs: u32, sequence: u32,
+    source_id: u32,
+) -> impl Recipe {
+    (
+        be(NETFLOW_V9), be(count), be(sys_uptime), be(unix_secs),
+        be(sequence), be(source_id),
+    )
+}
+
+
+//------------ message -------------------------------------------------------
+
+/// Returns a recipe for an IPFIX message.
+///
+/// The _sets_ recipe should produce a sequence of [`set`]s. The message
+/// length is calculated.
+pub fn message<R: Recipe>(
+    export_time: u32, sequence: u32, domain_id: u32, sets: R
+) -> impl Recipe {
+    Framed {
+        id: IPFIX, len: None, padding: 0,
+        content: (be(export_time), be(sequence), be(domain_id), sets),
+    }
+}
+
+/// Returns a recipe for an IPFIX message with all header fields given.
+pub fn message_raw<R: Recipe>(
+    version: u16, length: u16, export_time: u32, sequence: u32,
+    domain_id: u32, sets: R
+) -> impl Recipe {
+    Framed {
+        id: version, len: Some(length), padding: 0,
+        content: (be(export_time), be(sequence), be(domain_id), sets),
+    }
+}
+
+
+//------------ set -----------------------------------------------------------
+
+/// Returns a recipe for a set or flow set.
+///
+/// The set consists of the set ID, a length that includes the four octet
+/// set header, and the _records._ Use [`TEMPLATE_SET`] or
+/// [`V9_TEMPLATE_SET`] for template sets and the template ID for data sets.
+pub fn set<R: Recipe>(set_id: u16, records: R) -> impl Recipe {
+    Framed { id: set_id, len: None, padding: 0, content: records }
+}
+
+/// Returns a recipe for a set followed by padding.
+///
+/// The set is followed by _padding_ zero octets which are included in the
+/// set length.
+pub fn padded_set<R: Recipe>(
+    set_id: u16, padding: usize, records: R
+) -> impl Recipe {
+    Framed { id: set_id, len: None, padding, content: records }
+}
+
+/// Returns a recipe for a set with an explicit length.
+pub fn set_raw<R: Recipe>(
+    set_id: u16, length: u16, records: R
+) -> impl Recipe {
+    Framed { id: set_id, len: Some(length), padding: 0, content: records }
+}
+
+
+//------------ Field ---------------------------------------------------------
+
+/// A field specifier in a template record.
+#[derive(Clone, Copy, Debug)]
+pub struct Field {
+    /// The information element ID without the enterprise bit.
+    pub id: u16,
+
+    /// The length of the field in data records.
+    ///
+    /// Use [`VARIABLE_LENGTH`] for variable-length fields.
+    pub length: u16,
+
+    /// The private enterprise number for enterprise-specific elements.
+    pub enterprise: Option<u32>,
+}
+
+impl Field {
+    /// Creates a field for an IANA-assigned information element.
+    pub fn new(id: u16, length: u16) -> Self {
+        Field { id, length, enterprise: None }
+    }
+
+    /// Creates a field for an enterprise-specific information element.
+    ///
+    /// This is only available in IPFIX.
+    pub fn enterprise(id: u16, length: u16, enterprise: u32) -> Self {
+        Field { id, length, enterprise: Some(enterprise) }
+    }
+}
+
+impl Recipe for Field {
+    fn assemble(&self, target: &mut Fragment) {
+        match self.enterprise {
+            Some(enterprise) => {
+                (
+                    be(self.id | ENTERPRISE_BIT), be(self.length),
+                    be(enterprise)
+                ).assemble(target)
+            }
+            None => (be(self.id), be(self.length)).assemble(target)
+        }
+    }
+}
+
+
+//------------ template_record -----------------------------------------------
+
+/// Returns a recipe for a template record.
+///
+/// The field count is taken from the number of _fields._
+pub fn template_record(template_id: u16, fields: Vec<Field>) -> impl Recipe {
+    template_record_raw(template_id, fields.len() as u16, iter(fields))
+}
+
+/// Returns a recipe for a template record with an explicit field count.
+///
+/// The _fields_ recipe should produce a sequence of [`Field`]s.
+pub fn template_record_raw<R: Recipe>(
+    template_id: u16, field_count: u16, fields: R
+) -> impl Recipe {
+    (be(template_id), be(field_count), fields)
+}
+
+/// Returns a recipe for an IPFIX options template record.
+///
+/// The first _scope_field_count_ of the _fields_ are the scope fields. The
+/// field count is taken from the number of _fields._
+pub fn options_template_record(
+    template_id: u16, scope_field_count: u16, fields: Vec<Field>
+) -> impl Recipe {
+    (
+        be(template_id), be(fields.len() as u16), be(scope_field_count),
+        iter(fields)
+    )
+}
+
+
+//------------ variable_length -----------------------------------------------
+
+/// Returns a recipe for the value of a variable-length field.
+///
+/// Values shorter than 255 octets get a one octet length, longer values
+/// use the three octet form.
+pub fn variable_length<R: Recipe>(value: R) -> impl Recipe {
+    VariableLength(value)
+}
+
+struct VariableLength<R>(R);
+
+impl<R: Recipe> Recipe for VariableLength<R> {
+    fn assemble(&self, target: &mut Fragment) {
+        let mark = target.mark();
+        self.0.assemble(target);
+        let end = target.len();
+        let len = end - mark.start();
+        let header = if len < 255 {
+            vec![len as u8]
+        }
+        else {
+            let mut header = vec![255];
+            header.extend_from_slice(&(len as u16).to_be_bytes());
+            header
+        };
+        target.extend_from_slice(&header);
+        target.move_before(&mark, end);
+    }
+}
+
+
+//------------ Framed --------------------------------------------------------
+
+/// Content preceded by an ID and a length that includes this header.
+struct Framed<R> {
+    id: u16,
+    len: Option<u16>,
+    padding: usize,
+    content: R,
+}
+
+impl<R: Recipe> Recipe for Framed<R> {
+    fn assemble(&self, target: &mut Fragment) {
+        let start = target.len();
+        target.extend_from_slice(&self.id.to_be_bytes());
+        target.extend_from_slice(&[0; 2]);
+        self.content.assemble(target);
+        target.extend_from_slice(&vec![0; self.padding]);
+        if target.is_exceeded() {
+            return
+        }
+        let len = self.len.unwrap_or((target.len() - start) as u16);
+        target[start + 2..start + 4].copy_from_slice(&len.to_be_bytes());
+    }
+}
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::recipe::core::hex;
+
+    #[test]
+    fn v5() {
+        let msg = v5_message(
+            V5Header { sys_uptime: 1, ..Default::default() },
+            vec![V5Record { protocol: 6, ..Default::default() }; 2]
+        ).to_fragment();
+        assert_eq!(msg.len(), 24 + 2 * 48);
+        assert_eq!(&msg[..8], b"\x00\x05\x00\x02\x00\x00\x00\x01");
+        assert_eq!(msg[24 + 38], 6);
+    }
+
+    #[test]
+    fn ipfix() {
+        assert_eq!(
+            message(1, 2, 3, (
+                set(TEMPLATE_SET, template_record(256, vec![
+                    Field::new(8, 4), Field::enterprise(1, 2, 0x0a0b0c0d)
+                ])),
+                padded_set(256, 2, hex("c0000201 0102")),
+            )).to_fragment(),
+            hex(
+                "000a 0030 00000001 00000002 00000003
+                 0002 0014 0100 0002 0008 0004 8001 0002 0a0b0c0d
+                 0100 000c c0000201 0102 0000"
+            ).to_fragment()
+        );
+        assert_eq!(
+            set_raw(256, 100, variable_length(hex("aabb"))).to_fragment(),
+            b"\x01\x00\x00\x64\x02\xaa\xbb"
+        );
+    }
+}
@@ -0,0 +1,226 @@
+//! Kerberos V5 messages.
+//!
+//! This module provides recipes for the Kerberos messages defined in
+//! RFC 4120 that are exchanged during GSS-API or SASL negotiation, most
+//! importantly the AP-REQ message, as well as for wrapping them into
+//! GSS-API tokens per RFC 1964.
+//!
+//! All structures in the Kerberos ASN.1 module use explicit tagging. The
+//! recipes here take care of that. Where a function accepts a recipe rather
+//! than a plain value, arbitrary content – including deliberately broken
+//! content – can be inserted into the message.
+
+use super::core::{Recipe, iter, literal};
+use super::{der, gssapi};
+
+
+//------------ Constants -----------------------------------------------------
+
+/// The protocol version number for Kerberos V5.
+pub const PVNO: u8 = 5;
+
+/// The message type of an AP-REQ message.
+pub const KRB_AP_REQ: u8 = 14;
+
+/// The message type of an AP-REP message.
+pub const KRB_AP_REP: u8 = 15;
+
+/// The name type for the name of a principal.
+pub const NT_PRINCIPAL: i32 = 1;
+
+/// The name type for a service and other unique instance.
+pub const NT_SRV_INST: i32 = 2;
+
+/// The name type for a service with host name as instance.
+pub const NT_SRV_HST: i32 = 3;
+
+/// The encryption type for aes128-cts-hmac-sha1-96.
+pub const AES128_CTS_HMAC_SHA1_96: i32 = 17;
+
+/// The encryption type for aes256-cts-hmac-sha1-96.
+pub const AES256_CTS_HMAC_SHA1_96: i32 = 18;
+
+/// The AP option requesting use of the session key.
+///
+/// The AP options are a bit string of 32 bits in network byte order. The
+/// constants can thus be or-ed together and given to [`ap_options`].
+pub const AP_OPT_USE_SESSION_KEY: u32 = 0x4000_0000;
+
+/// The AP option requesting mutual authentication.
+pub const AP_OPT_MUTUAL_REQUIRED: u32 = 0x2000_0000;
+
+/// The token ID of an AP-REQ inside a GSS-API token.
+pub const TOK_ID_AP_REQ: [u8; 2] = [0x01, 0x00];
+
+/// The token ID of an AP-REP inside a GSS-API token.
+pub const TOK_ID_AP_REP: [u8; 2] = [0x02, 0x00];
+
+
+//------------ gss_token -----------------------------------------------------
+
+/// Returns a recipe for a Kerberos GSS-API initial context token.
+///
+/// The token wraps the Kerberos message produced by _message_ prefixed
+/// with the token ID _tok_id_ into the GSS-API framing with the Kerberos
+/// V5 mechanism OID as described in section 1.1 of RFC 1964.
+pub fn gss_token<R: Recipe>(tok_id: [u8; 2], message: R) -> impl Recipe {
+    gssapi::initial_context_token(
+        gssapi::krb5_mech(), (literal(tok_id), message)
+    )
+}
+
+
+//------------ ap_req --------------------------------------------------------
+
+/// Returns a recipe for an AP-REQ message.
+///
+/// The protocol version and message type are set to their correct values.
+/// Use [`ap_req_raw`] to create messages with incorrect values.
+pub fn ap_req<O: Recipe, T: Recipe, A: Recipe>(
+    ap_options: O, ticket: T, authenticator: A,
+) -> impl Recipe {
+    ap_req_raw(
+        der::integer(PVNO), der::integer(KRB_AP_REQ),
+        ap_options, ticket, authenticator
+    )
+}
+
+/// Returns a recipe for an AP-REQ message with all fields given.
+pub fn ap_req_raw<
+    P: Recipe, M: Recipe, O: Recipe, T: Recipe, A: Recipe
+>(
+    pvno: P, msg_type: M, ap_options: O, ticket: T, authenticator: A,
+) -> impl Recipe {
+    der::application(KRB_AP_REQ.into(), der::constructed(
+        der::sequence((
+            der::explicit(0, pvno),
+            der::explicit(1, msg_type),
+            der::explicit(2, ap_options),
+            der::explicit(3, ticket),
+            der::explicit(4, authenticator),
+        ))
+    ))
+}
+
+
+//------------ ap_options ----------------------------------------------------
+
+/// Returns a recipe for the AP options bit string.
+///
+/// The options are given as a 32 bit value in network byte order, i.e.,
+/// the first bit of the bit string is the most significant bit.
+pub fn ap_options(options: u32) -> impl Recipe {
+    der::bitstring(0, literal(options.to_be_bytes()))
+}
+
+
+//------------ ticket --------------------------------------------------------
+
+/// Returns a recipe for a ticket.
+///
+/// The ticket version number is set to 5.
+pub fn ticket<R: Recipe, N: Recipe, E: Recipe>(
+    realm: R, sname: N, enc_part: E
+) -> impl Recipe {
+    der::application(1, der::constructed(
+        der::sequence((
+            der::explicit(0, der::integer(PVNO)),
+            der::explicit(1, realm),
+            der::explicit(2, sname),
+            der::explicit(3, enc_part),
+        ))
+    ))
+}
+
+
+//------------ string --------------------------------------------------------
+
+/// Returns a recipe for a KerberosString.
+///
+/// Kerberos strings, which include realms, are encoded as GeneralString.
+pub fn string<T: AsRef<[u8]> + 'static>(s: T) -> impl Recipe {
+    der::general_string(literal(s))
+}
+
+
+//------------ principal_name ------------------------------------------------
+
+/// Returns a recipe for a principal name.
+///
+/// The name components are given via _components_ which should produce a
+/// sequence of Kerberos strings, e.g., a tuple of [`string`]s.
+pub fn principal_name<R: Recipe>(
+    name_type: i32, components: R
+) -> impl Recipe {
+    der::sequence((
+        der::explicit(0, der::integer(name_type)),
+        der::explicit(1, der::sequence(components)),
+    ))
+}
+
+
+//------------ encrypted_data ------------------------------------------------
+
+/// Returns a recipe for encrypted data.
+///
+/// The key version number _kvno_ is optional and left out if `None`. The
+/// _cipher_ recipe provides the content of the cipher octet string.
+pub fn encrypted_data<R: Recipe>(
+    etype: i32, kvno: Option<u32>, cipher: R
+) -> impl Recipe {
+    der::sequence((
+        der::explicit(0, der::integer(etype)),
+        iter(kvno.map(|kvno| der::explicit(1, der::integer(kvno)))),
+        der::explicit(2, der::octetstring(cipher)),
+    ))
+}
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::recipe::core::hex;
+
+    #[test]
+    fn ap_req_structure() {
+        let req = ap_req(
+            ap_options(AP_OPT_MUTUAL_REQUIRED),
+            ticket(
+                string("EXAMPLE.COM"),
+                principal_name(NT_SRV_HST, (string("host"), string("a"))),
+                encrypted_data(AES256_CTS_HMAC_SHA1_96, Some(2), hex("aa")),
+            ),
+            encrypted_data(AES256_CTS_HMAC_SHA1_96, None, hex("bb")),
+        ).to_fragment();
+
+        // Application 14, constructed, followed by the sequence.
+        assert_eq!(req[0], 0x6e);
+        assert_eq!(req[2], 0x30);
+        // pvno and msg-type.
+        assert_eq!(
+            &req[4..14],
+            b"\xa0\x03\x02\x01\x05\xa1\x03\x02\x01\x0e"
+        );
+        // ap-options.
+        assert_eq!(
+            &req[14..23],
+            b"\xa2\x07\x03\x05\x00\x20\x00\x00\x00"
+        );
+        // The authenticator comes last and lacks the kvno.
+        assert!(req.ends_with(
+            b"\xa4\x0c\x30\x0a\xa0\x03\x02\x01\x12\xa2\x03\x04\x01\xbb"
+        ));
+    }
+
+    #[test]
+    fn gss_wrapping() {
+        let token = gss_token(TOK_ID_AP_REQ, hex("6e00")).to_fragment();
+        assert_eq!(
+            token,
+            b"\x60\x0f\x06\x09\x2a\x86\x48\x86\xf7\x12\x01\x02\x02\
+              \x01\x00\x6e\x00"
+        );
+    }
+}
@@ -9,7 +9,39 @@
 //! When a recipe is _assembled,_ it is written into a [`Fragment`], which
 //! is mostly a `Vec<u8>` with some convenience functions added.
 
-pub use self::core::{Recipe, Fragment};
+pub use self::core::{
+    Recipe, Context, Fragment, FragmentPool, LimitError, Mark, Measurement,
+    OverrideField, Overrides, Presentation, ValidationError
+};
 
+pub mod boundary;
+pub mod checksum;
+pub mod compress;
+pub mod conformance;
 pub mod core;
+pub mod ct;
 pub mod der;
+pub mod dns;
+pub mod encoding;
+pub mod gssapi;
+#[cfg(any(feature = "sha1", feature = "sha2"))]
+pub mod hash;
+pub mod http1;
+pub mod ikev2;
+pub mod inet;
+pub mod ipfix;
+pub mod kerberos;
+pub mod modbus;
+pub mod mux;
+pub mod ocsp;
+#[cfg(feature = "rayon")]
+pub mod parallel;
+pub mod rtp;
+pub mod set;
+pub mod sip;
+pub mod stuffing;
+pub mod tacacs;
+pub mod time;
+pub mod tls;
+pub mod vectors;
+pub mod x509;
@@ -0,0 +1,283 @@
+//! Modbus messages.
+//!
+//! This module provides recipes for Modbus protocol data units (PDUs) as
+//! defined in the Modbus Application Protocol Specification V1.1b3 and for
+//! their framing for Modbus/TCP via the MBAP header as well as for serial
+//! lines in RTU mode.
+//!
+//! Lengths, byte counts, and quantities are calculated from the data, but
+//! the `_raw` variants of the recipes allow giving arbitrary values, so
+//! requests and responses that disagree with their function code or their
+//! framing can be created.
+
+use super::core::{Fragment, Recipe, be, literal};
+use crate::crc::Crc16Modbus;
+
+
+//------------ Constants -----------------------------------------------------
+
+/// The protocol identifier of Modbus in the MBAP header.
+pub const PROTOCOL_ID: u16 = 0;
+
+/// The function code for reading coils.
+pub const READ_COILS: u8 = 1;
+
+/// The function code for reading discrete inputs.
+pub const READ_DISCRETE_INPUTS: u8 = 2;
+
+/// The function code for reading holding registers.
+pub const READ_HOLDING_REGISTERS: u8 = 3;
+
+/// The function code for reading input registers.
+pub const READ_INPUT_REGISTERS: u8 = 4;
+
+/// The function code for writing a single coil.
+pub const WRITE_SINGLE_COIL: u8 = 5;
+
+/// The function code for writing a single register.
+pub const WRITE_SINGLE_REGISTER: u8 = 6;
+
+/// The function code for writing multiple coils.
+pub const WRITE_MULTIPLE_COILS: u8 = 15;
+
+/// The function code for writing multiple registers.
+pub const WRITE_MULTIPLE_REGISTERS: u8 = 16;
+
+/// The bit added to the function code of an exception response.
+pub const EXCEPTION_BIT: u8 = 0x80;
+
+/// The exception code for an unsupported function.
+pub const ILLEGAL_FUNCTION: u8 = 1;
+
+/// The exception code for an invalid data address.
+pub const ILLEGAL_DATA_ADDRESS: u8 = 2;
+
+/// The exception code for an invalid data value.
+pub const ILLEGAL_DATA_VALUE: u8 = 3;
+
+/// The value for switching a coil on.
+pub const COIL_ON: u16 = 0xFF00;
+
+/// The value for switching a coil off.
+pub const COIL_OFF: u16 = 0x0000;
+
+
+//============ Framing =======================================================
+
+//------------ adu -----------------------------------------------------------
+
+/// Returns a recipe for a Modbus/TCP application data unit.
+///
+/// The unit consists of the MBAP header followed by the _pdu._ The length
+/// field is calculated from the PDU.
+pub fn adu<P: Recipe>(
+    transaction_id: u16, unit_id: u8, pdu: P
+) -> impl Recipe {
+    Adu {
+        transaction_id, protocol_id: PROTOCOL_ID, length: None, unit_id, pdu
+    }
+}
+
+/// Returns a recipe for a Modbus/TCP data unit with all fields given.
+pub fn adu_raw<P: Recipe>(
+    transaction_id: u16, protocol_id: u16, length: u16, unit_id: u8, pdu: P
+) -> impl Recipe {
+    Adu { transaction_id, protocol_id, length: Some(length), unit_id, pdu }
+}
+
+struct Adu<P> {
+    transaction_id: u16,
+    protocol_id: u16,
+    length: Option<u16>,
+    unit_id: u8,
+    pdu: P,
+}
+
+impl<P: Recipe> Recipe for Adu<P> {
+    fn assemble(&self, target: &mut Fragment) {
+        (be(self.transaction_id), be(self.protocol_id)).assemble(target);
+        let start = target.len();
+        target.extend_from_slice(&[0, 0, self.unit_id]);
+        self.pdu.assemble(target);
+        if target.is_exceeded() {
+            return
+        }
+        let len = self.length.unwrap_or((target.len() - start - 2) as u16);
+        target[start..start + 2].copy_from_slice(&len.to_be_bytes());
+    }
+}
+
+
+//------------ rtu_frame -----------------------------------------------------
+
+/// Returns a recipe for a serial line frame in RTU mode.
+///
+/// The frame consists of the _address,_ the _pdu,_ and the CRC over both
+/// in little-endian byte order.
+pub fn rtu_frame<P: Recipe>(address: u8, pdu: P) -> impl Recipe {
+    RtuFrame { address, pdu }
+}
+
+struct RtuFrame<P> {
+    address: u8,
+    pdu: P,
+}
+
+impl<P: Recipe> Recipe for RtuFrame<P> {
+    fn assemble(&self, target: &mut Fragment) {
+        let start = target.len();
+        target.push(self.address);
+        self.pdu.assemble(target);
+        let crc = Crc16Modbus::checksum(&target[start..]);
+        target.extend_from_slice(&crc.to_le_bytes());
+    }
+}
+
+
+//============ Protocol Data Units ===========================================
+
+//------------ pdu -----------------------------------------------------------
+
+/// Returns a recipe for a PDU with an arbitrary function code and data.
+pub fn pdu<D: Recipe>(function: u8, data: D) -> impl Recipe {
+    (be(function), data)
+}
+
+/// Returns a recipe for an exception response.
+///
+/// The [`EXCEPTION_BIT`] is added to the _function_ code.
+pub fn exception(function: u8, code: u8) -> impl Recipe {
+    literal([function | EXCEPTION_BIT, code])
+}
+
+
+//------------ Requests ------------------------------------------------------
+
+/// Returns a recipe for a read request.
+///
+/// This is the request format for all four read functions.
+pub fn read_request(function: u8, address: u16, quantity: u16) -> impl Recipe {
+    (be(function), be(address), be(quantity))
+}
+
+/// Returns a recipe for a request to write a single coil or register.
+///
+/// This is also the format of the response to such a request.
+pub fn write_single(function: u8, address: u16, value: u16) -> impl Recipe {
+    (be(function), be(address), be(value))
+}
+
+/// Returns a recipe for a request to write multiple registers.
+pub fn write_multiple_registers(address: u16, values: &[u16]) -> impl Recipe {
+    let data = registers(values);
+    write_multiple_raw(
+        WRITE_MULTIPLE_REGISTERS, address, values.len() as u16,
+        data.len() as u8, literal(data)
+    )
+}
+
+/// Returns a recipe for a request to write multiple coils.
+pub fn write_multiple_coils(address: u16, values: &[bool]) -> impl Recipe {
+    let data = bits(values);
+    write_multiple_raw(
+        WRITE_MULTIPLE_COILS, address, values.len() as u16,
+        data.len() as u8, literal(data)
+    )
+}
+
+/// Returns a recipe for a request to write multiple values.
+///
+/// The quantity and byte count are used as given regardless of _data._
+pub fn write_multiple_raw<D: Recipe>(
+    function: u8, address: u16, quantity: u16, byte_count: u8, data: D
+) -> impl Recipe {
+    (be(function), be(address), be(quantity), be(byte_count), data)
+}
+
+
+//------------ Responses -----------------------------------------------------
+
+/// Returns a recipe for a response to a register read request.
+pub fn read_registers_response(function: u8, values: &[u16]) -> impl Recipe {
+    let data = registers(values);
+    read_response_raw(function, data.len() as u8, literal(data))
+}
+
+/// Returns a recipe for a response to a coil or discrete input read request.
+pub fn read_bits_response(function: u8, values: &[bool]) -> impl Recipe {
+    let data = bits(values);
+    read_response_raw(function, data.len() as u8, literal(data))
+}
+
+/// Returns a recipe for a read response with the byte count given.
+pub fn read_response_raw<D: Recipe>(
+    function: u8, byte_count: u8, data: D
+) -> impl Recipe {
+    (be(function), be(byte_count), data)
+}
+
+/// Returns a recipe for a response to a write multiple request.
+pub fn write_multiple_response(
+    function: u8, address: u16, quantity: u16
+) -> impl Recipe {
+    (be(function), be(address), be(quantity))
+}
+
+
+//------------ Helpers -------------------------------------------------------
+
+/// Encodes register values.
+fn registers(values: &[u16]) -> Vec<u8> {
+    values.iter().flat_map(|value| value.to_be_bytes()).collect()
+}
+
+/// Encodes bit values with the first bit in the least significant bit.
+fn bits(values: &[bool]) -> Vec<u8> {
+    let mut res = vec![0; values.len().div_ceil(8)];
+    for (idx, value) in values.iter().enumerate() {
+        if *value {
+            res[idx / 8] |= 1 << (idx % 8)
+        }
+    }
+    res
+}
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn framing() {
+        assert_eq!(
+            adu(1, 0x11, read_request(READ_HOLDING_REGISTERS, 0x6B, 3))
+                .to_fragment(),
+            b"\x00\x01\x00\x00\x00\x06\x11\x03\x00\x6b\x00\x03"
+        );
+        assert_eq!(
+            rtu_frame(0x11, read_request(READ_HOLDING_REGISTERS, 0x6B, 3))
+                .to_fragment(),
+            b"\x11\x03\x00\x6b\x00\x03\x76\x87"
+        );
+    }
+
+    #[test]
+    fn pdus() {
+        assert_eq!(
+            write_multiple_coils(0x13, &[true, false, true, true, false,
+                false, true, true, true, false]).to_fragment(),
+            b"\x0f\x00\x13\x00\x0a\x02\xcd\x01"
+        );
+        assert_eq!(
+            read_registers_response(READ_HOLDING_REGISTERS, &[0x022B])
+                .to_fragment(),
+            b"\x03\x02\x02\x2b"
+        );
+        assert_eq!(
+            exception(READ_COILS, ILLEGAL_FUNCTION).to_fragment(),
+            b"\x81\x01"
+        );
+    }
+}
@@ -0,0 +1,221 @@
+//! Interleaving data of multiplexed streams.
+//!
+//! Protocols such as HTTP/2, QUIC, or SSH carry several logical streams
+//! over a single connection by cutting the data of each stream into
+//! chunks, wrapping each chunk into a frame that names its stream, and
+//! interleaving the frames. The recipes in this module do the cutting and
+//! interleaving: the data of each stream is given as a recipe and a
+//! [`Schedule`] determines the order and size of the chunks. The framing
+//! is left to a closure, so any protocol can be modelled.
+
+use std::cmp;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use super::core::{Fragment, Recipe};
+
+
+//------------ Schedule ------------------------------------------------------
+
+/// The order in which chunks of the streams are emitted.
+#[derive(Clone, Debug)]
+pub enum Schedule {
+    /// Chunks of at most the given size are taken from each stream in turn.
+    ///
+    /// Streams that have run out of data are skipped.
+    RoundRobin(usize),
+
+    /// Chunks are taken as listed.
+    ///
+    /// Each item gives the index of a stream and the size of the chunk.
+    /// If the stream has less data left, the chunk is shorter. Chunks for
+    /// streams without any data left are skipped. Any data left once the
+    /// list has been processed is emitted as one final chunk per stream in
+    /// the order of the streams.
+    Explicit(Vec<(usize, usize)>),
+
+    /// Chunks are taken in a random order determined by a seed.
+    ///
+    /// Each chunk is taken from a randomly picked stream that still has
+    /// data left and has a random size of at least one and at most
+    /// `max_chunk` octets. The same seed always results in the same
+    /// order, so running over a range of seeds explores different
+    /// interleavings reproducibly. The random numbers come from ChaCha8,
+    /// whose output doesn’t change with new versions of the `rand`
+    /// crate or between platforms.
+    Random {
+        /// The seed for the random number generator.
+        seed: u64,
+
+        /// The maximum size of a chunk.
+        max_chunk: usize,
+    },
+}
+
+
+//------------ interleave ----------------------------------------------------
+
+/// Returns a recipe interleaving chunks of data without framing.
+///
+/// The result is the chunks of the _streams_ concatenated in the order
+/// given by _schedule._
+pub fn interleave(
+    streams: Vec<Box<dyn Recipe>>, schedule: Schedule
+) -> impl Recipe {
+    interleave_framed(streams, schedule, |_, chunk, _, target| {
+        target.extend_from_slice(chunk)
+    })
+}
+
+/// Returns a recipe interleaving chunks of data wrapped in frames.
+///
+/// The data of the _streams_ is cut into chunks according to _schedule._
+/// For each chunk, _frame_ is called with the index of the stream, the
+/// chunk, whether this is the last chunk of the stream, and the target to
+/// assemble the frame into.
+pub fn interleave_framed<F>(
+    streams: Vec<Box<dyn Recipe>>, schedule: Schedule, frame: F
+) -> impl Recipe
+where F: Fn(usize, &[u8], bool, &mut Fragment) {
+    Interleave { streams, schedule, frame }
+}
+
+struct Interleave<F> {
+    streams: Vec<Box<dyn Recipe>>,
+    schedule: Schedule,
+    frame: F,
+}
+
+impl<F> Recipe for Interleave<F>
+where F: Fn(usize, &[u8], bool, &mut Fragment) {
+    fn assemble(&self, target: &mut Fragment) {
+        let data: Vec<_> = self.streams.iter().map(|stream| {
+            target.assemble_child(stream)
+        }).collect();
+        let mut pos = vec![0; data.len()];
+        let mut emit = |idx: usize, len: usize, target: &mut Fragment| {
+            let start = pos[idx];
+            let end = start + len.min(data[idx].len() - start);
+            if end > start {
+                pos[idx] = end;
+                (self.frame)(
+                    idx, &data[idx][start..end], end == data[idx].len(),
+                    target
+                )
+            }
+        };
+        match self.schedule {
+            Schedule::RoundRobin(size) => {
+                let size = size.max(1);
+                let rounds = data.iter().map(|item| {
+                    item.len().div_ceil(size)
+                }).max().unwrap_or(0);
+                for _ in 0..rounds {
+                    for idx in 0..data.len() {
+                        emit(idx, size, target)
+                    }
+                }
+            }
+            Schedule::Explicit(ref items) => {
+                for &(idx, len) in items {
+                    emit(idx, len, target)
+                }
+                for idx in 0..data.len() {
+                    emit(idx, usize::MAX, target)
+                }
+            }
+            Schedule::Random { seed, max_chunk } => {
+                let mut rng = ChaCha8Rng::seed_from_u64(seed);
+                let mut left: Vec<_> = data.iter().map(|item| {
+                    item.len()
+                }).collect();
+                loop {
+                    let active: Vec<_> = (0..left.len()).filter(|&idx| {
+                        left[idx] > 0
+                    }).collect();
+                    if active.is_empty() {
+                        break
+                    }
+                    let idx = active[rng.gen_range(0..active.len())];
+                    let len = cmp::min(
+                        rng.gen_range(1..=max_chunk.max(1)), left[idx]
+                    );
+                    left[idx] -= len;
+                    emit(idx, len, target);
+                }
+            }
+        }
+    }
+}
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::recipe::core::{literal, repeat};
+
+    fn streams() -> Vec<Box<dyn Recipe>> {
+        vec![literal("aaaaa").boxed(), literal("bb").boxed()]
+    }
+
+    #[test]
+    fn round_robin() {
+        assert_eq!(
+            interleave(streams(), Schedule::RoundRobin(2)).to_fragment(),
+            b"aabbaaa"
+        );
+        assert_eq!(
+            interleave_framed(
+                streams(), Schedule::RoundRobin(3),
+                |idx, chunk, last, target| {
+                    target.push(idx as u8);
+                    target.push(
+                        chunk.len() as u8 | if last { 0x80 } else { 0 }
+                    );
+                    target.extend_from_slice(chunk);
+                }
+            ).to_fragment(),
+            b"\x00\x03aaa\x01\x82bb\x00\x82aa"
+        );
+    }
+
+    #[test]
+    fn random() {
+        let run = |seed| {
+            interleave(
+                streams(), Schedule::Random { seed, max_chunk: 2 }
+            ).to_fragment().into_vec()
+        };
+        assert_eq!(run(1), run(1));
+        // The generator must not change between versions.
+        assert_eq!(run(4), b"babaaaa");
+        for seed in 0..20 {
+            let data = run(seed);
+            assert_eq!(data.len(), 7);
+            assert_eq!(data.iter().filter(|&&ch| ch == b'b').count(), 2);
+        }
+        assert!((0..20).any(|seed| run(seed) != run(0)));
+    }
+
+    #[test]
+    fn explicit() {
+        assert_eq!(
+            interleave(
+                streams(), Schedule::Explicit(vec![(1, 1), (0, 4), (1, 5)])
+            ).to_fragment(),
+            b"baaaaba"
+        );
+    }
+
+    #[test]
+    fn limited() {
+        let err = interleave(
+            vec![
+                streams().remove(0), repeat(usize::MAX, literal("x")).boxed()
+            ],
+            Schedule::RoundRobin(2)
+        ).to_fragment_limited(100).unwrap_err();
+        assert_eq!(err.limit(), 100);
+    }
+}
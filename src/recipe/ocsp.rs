@@ -0,0 +1,271 @@
+//! OCSP responses and their stapling in TLS.
+//!
+//! This module provides recipes for OCSP responses as defined in RFC 6960
+//! as well as for the TLS structures used for requesting and stapling
+//! them: the status_request extension and the CertificateStatus message
+//! defined in RFC 6066 and their TLS 1.3 counterparts from RFC 8446.
+//!
+//! As usual, most parts are given as recipes, so the structures can be
+//! filled with arbitrary and broken content. In particular, signatures
+//! are never checked.
+
+use super::core::{Recipe, empty, iter, literal};
+use super::{der, tls};
+
+
+//------------ Constants -----------------------------------------------------
+
+/// The response status for a successful response.
+pub const SUCCESSFUL: u8 = 0;
+
+/// The response status for an illegal confirmation request.
+pub const MALFORMED_REQUEST: u8 = 1;
+
+/// The response status for an internal error in the issuer.
+pub const INTERNAL_ERROR: u8 = 2;
+
+/// The response status asking to try again later.
+pub const TRY_LATER: u8 = 3;
+
+/// The response status signaling that the request must be signed.
+pub const SIG_REQUIRED: u8 = 5;
+
+/// The response status for an unauthorized request.
+pub const UNAUTHORIZED: u8 = 6;
+
+/// The TLS extension type of the status_request extension.
+pub const STATUS_REQUEST: u16 = 5;
+
+/// The certificate status type for OCSP.
+pub const STATUS_TYPE_OCSP: u8 = 1;
+
+
+//============ OCSP Responses ================================================
+
+//------------ response ------------------------------------------------------
+
+/// Returns a recipe for a successful OCSP response.
+///
+/// The _basic_ response should be a [`basic_response`]. It is wrapped in
+/// response bytes of type id-pkix-ocsp-basic.
+pub fn response<R: Recipe>(basic: R) -> impl Recipe {
+    der::sequence((
+        der::universal(10, der::integer(SUCCESSFUL)),
+        der::explicit(0, der::sequence((
+            der::oid([1, 3, 6, 1, 5, 5, 7, 48, 1, 1]),
+            der::octetstring(basic),
+        ))),
+    ))
+}
+
+/// Returns a recipe for an OCSP response with only a status.
+///
+/// This is the form used for all response statuses other than
+/// [`SUCCESSFUL`].
+pub fn status_response(status: u8) -> impl Recipe {
+    der::sequence(der::universal(10, der::integer(status)))
+}
+
+
+//------------ basic_response ------------------------------------------------
+
+/// Returns a recipe for a basic OCSP response.
+///
+/// The _tbs_ should be a [`response_data`]. The _signature_ provides the
+/// content of the signature bit string. If _certs_ is given, it should
+/// produce a sequence of certificates which is added as the optional
+/// certs field.
+pub fn basic_response<T: Recipe, A: Recipe, S: Recipe, C: Recipe>(
+    tbs: T, signature_algorithm: A, signature: S, certs: Option<C>
+) -> impl Recipe {
+    der::sequence((
+        tbs,
+        signature_algorithm,
+        der::bitstring(0, signature),
+        iter(certs.map(|certs| der::explicit(0, der::sequence(certs)))),
+    ))
+}
+
+
+//------------ response_data -------------------------------------------------
+
+/// Returns a recipe for the response data of a basic response.
+///
+/// The version is left at its default. The _responder_id_ should be one of
+/// [`responder_by_name`] or [`responder_by_key`], _produced_at_ should
+/// be a GeneralizedTime value, and _responses_ should produce a sequence of
+/// [`single_response`]s.
+pub fn response_data<I: Recipe, P: Recipe, R: Recipe>(
+    responder_id: I, produced_at: P, responses: R
+) -> impl Recipe {
+    der::sequence((
+        responder_id,
+        produced_at,
+        der::sequence(responses),
+    ))
+}
+
+/// Returns a recipe for a responder ID given by name.
+///
+/// The _name_ should produce a complete DER encoded Name.
+pub fn responder_by_name<R: Recipe>(name: R) -> impl Recipe {
+    der::explicit(1, name)
+}
+
+/// Returns a recipe for a responder ID given by key hash.
+///
+/// The _key_hash_ provides the content of the octet string and should be
+/// the SHA-1 hash of the responder’s public key.
+pub fn responder_by_key<R: Recipe>(key_hash: R) -> impl Recipe {
+    der::explicit(2, der::octetstring(key_hash))
+}
+
+
+//------------ single_response -----------------------------------------------
+
+/// Returns a recipe for a single response.
+///
+/// The _cert_id_ should be a [`cert_id`], _status_ one of [`good`],
+/// [`revoked`], or [`unknown`], and the update times GeneralizedTime
+/// values.
+pub fn single_response<I: Recipe, S: Recipe, T: Recipe, N: Recipe>(
+    cert_id: I, status: S, this_update: T, next_update: Option<N>
+) -> impl Recipe {
+    der::sequence((
+        cert_id,
+        status,
+        this_update,
+        iter(next_update.map(|next| der::explicit(0, next))),
+    ))
+}
+
+/// Returns a recipe for a certificate ID.
+///
+/// The _hash_algorithm_ should be an algorithm identifier, such as
+/// [`sha1_algorithm`]. The hashes provide the content of their respective
+/// octet strings.
+pub fn cert_id<A: Recipe, N: Recipe, K: Recipe, S: Recipe>(
+    hash_algorithm: A, issuer_name_hash: N, issuer_key_hash: K, serial: S
+) -> impl Recipe {
+    der::sequence((
+        hash_algorithm,
+        der::octetstring(issuer_name_hash),
+        der::octetstring(issuer_key_hash),
+        serial,
+    ))
+}
+
+/// Returns a recipe for the SHA-1 algorithm identifier.
+pub fn sha1_algorithm() -> impl Recipe {
+    der::sequence((der::oid([1, 3, 14, 3, 2, 26]), der::null()))
+}
+
+/// Returns a recipe for the good certificate status.
+pub fn good() -> impl Recipe {
+    der::context(0, der::simple(empty()))
+}
+
+/// Returns a recipe for the revoked certificate status.
+///
+/// The _time_ should be a GeneralizedTime value. If _reason_ is given, it
+/// is added as the revocation reason.
+pub fn revoked<T: Recipe>(time: T, reason: Option<u8>) -> impl Recipe {
+    der::context(1, der::constructed((
+        time,
+        iter(reason.map(|reason| {
+            der::explicit(0, der::universal(10, der::integer(reason)))
+        })),
+    )))
+}
+
+/// Returns a recipe for the unknown certificate status.
+pub fn unknown() -> impl Recipe {
+    der::context(2, der::simple(empty()))
+}
+
+
+//============ Embeddings in TLS =============================================
+
+//------------ status_request ------------------------------------------------
+
+/// Returns a recipe for a status_request extension sent by a client.
+///
+/// The _responder_ids_ and _request_extensions_ provide the content of
+/// the respective vectors and will normally be empty.
+pub fn status_request<I: Recipe, E: Recipe>(
+    responder_ids: I, request_extensions: E
+) -> impl Recipe {
+    tls::extension(STATUS_REQUEST, (
+        literal([STATUS_TYPE_OCSP]),
+        tls::vec16(responder_ids),
+        tls::vec16(request_extensions),
+    ))
+}
+
+/// Returns a recipe for an empty status_request extension.
+///
+/// This is sent by a server in its hello to indicate that it will staple a
+/// response.
+pub fn status_request_ack() -> impl Recipe {
+    tls::extension(STATUS_REQUEST, empty())
+}
+
+
+//------------ certificate_status --------------------------------------------
+
+/// Returns a recipe for a TLS 1.2 CertificateStatus handshake message.
+///
+/// The _response_ should be an OCSP [`response`].
+pub fn certificate_status<R: Recipe>(response: R) -> impl Recipe {
+    tls::handshake(
+        tls::HANDSHAKE_CERTIFICATE_STATUS, certificate_status_body(response)
+    )
+}
+
+/// Returns a recipe for a TLS 1.3 status_request certificate extension.
+///
+/// This extension is included in the extensions of a certificate entry in
+/// the Certificate message. The _response_ should be an OCSP
+/// [`response`].
+pub fn certificate_entry_status<R: Recipe>(response: R) -> impl Recipe {
+    tls::extension(STATUS_REQUEST, certificate_status_body(response))
+}
+
+/// Returns a recipe for the CertificateStatus structure.
+pub fn certificate_status_body<R: Recipe>(response: R) -> impl Recipe {
+    (literal([STATUS_TYPE_OCSP]), tls::vec24(response))
+}
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::recipe::core::hex;
+
+    #[test]
+    fn status_responses() {
+        assert_eq!(
+            status_response(TRY_LATER).to_fragment(),
+            b"\x30\x03\x0a\x01\x03"
+        );
+        assert_eq!(good().to_fragment(), b"\x80\x00");
+        assert_eq!(
+            revoked(hex("18 00"), Some(1)).to_fragment(),
+            b"\xa1\x07\x18\x00\xa0\x03\x0a\x01\x01"
+        );
+    }
+
+    #[test]
+    fn tls_embedding() {
+        assert_eq!(
+            status_request(empty(), empty()).to_fragment(),
+            b"\x00\x05\x00\x05\x01\x00\x00\x00\x00"
+        );
+        assert_eq!(
+            certificate_status(hex("3000")).to_fragment(),
+            b"\x16\x00\x00\x06\x01\x00\x00\x02\x30\x00"
+        );
+    }
+}
@@ -0,0 +1,115 @@
+//! Assembling many recipes in parallel.
+//!
+//! Recipes are immutable descriptions of data, so assembling a large batch
+//! of them – such as a corpus of test vectors – can be spread across
+//! threads. This module provides functions that do this using [rayon].
+//!
+//! This module is only available with the `rayon` feature.
+//!
+//! [rayon]: https://docs.rs/rayon/
+
+use rayon::prelude::*;
+use super::core::{Fragment, Recipe};
+
+
+//------------ assemble_all --------------------------------------------------
+
+/// Assembles all recipes in a slice in parallel.
+///
+/// Returns the fragments in the order of the recipes.
+pub fn assemble_all<R: Recipe + Sync>(recipes: &[R]) -> Vec<Fragment> {
+    recipes.par_iter().map(Recipe::to_fragment).collect()
+}
+
+
+//------------ assemble_iter -------------------------------------------------
+
+/// Assembles all recipes produced by a parallel iterator.
+///
+/// This is useful if the recipes themselves are generated on the fly,
+/// e.g., by mapping over a range of indexes. Returns the fragments in
+/// the order of the iterator.
+pub fn assemble_iter<I>(recipes: I) -> Vec<Fragment>
+where
+    I: IntoParallelIterator,
+    I::Item: Recipe,
+    I::Iter: IndexedParallelIterator,
+{
+    recipes.into_par_iter().map(|recipe| recipe.to_fragment()).collect()
+}
+
+
+//------------ for_each_assembled --------------------------------------------
+
+/// Assembles all recipes in a slice in parallel and processes the results.
+///
+/// The closure _op_ is called with the index of each recipe in the slice
+/// and the assembled data. Each thread reuses a single fragment, so unlike
+/// with [`assemble_all`] the data doesn’t need to be kept in memory all at
+/// once. This makes it suitable for writing large corpora to disk.
+///
+/// The closure is called in no particular order.
+pub fn for_each_assembled<R, F>(recipes: &[R], op: F)
+where
+    R: Recipe + Sync,
+    F: Fn(usize, &Fragment) + Sync + Send,
+{
+    recipes.par_iter().enumerate().for_each_init(
+        Fragment::new,
+        |fragment, (index, recipe)| {
+            recipe.reassemble(fragment);
+            op(index, fragment)
+        }
+    )
+}
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::Mutex;
+    use crate::recipe::core::{Literal, be, literal};
+
+    #[test]
+    fn parallel_assembly() {
+        let recipes: Vec<_> = (0..1000u16).map(be).collect();
+        let fragments = assemble_all(&recipes);
+        for (idx, frag) in fragments.iter().enumerate() {
+            assert_eq!(frag, &(idx as u16).to_be_bytes());
+        }
+        assert_eq!(
+            assemble_iter((0..1000u16).into_par_iter().map(be)),
+            fragments
+        );
+
+        let seen = Mutex::new(vec![false; recipes.len()]);
+        for_each_assembled(&recipes, |idx, frag| {
+            assert_eq!(frag, &(idx as u16).to_be_bytes());
+            seen.lock().unwrap()[idx] = true;
+        });
+        assert!(seen.into_inner().unwrap().into_iter().all(|x| x));
+    }
+
+    #[test]
+    fn reused_fragments() {
+        // Longer data assembled earlier must not leak into shorter data.
+        let recipes: Vec<_> = (0..500usize).map(|idx| {
+            literal(vec![idx as u8; idx % 7])
+        }).collect();
+        for_each_assembled(&recipes, |idx, frag| {
+            assert_eq!(frag.as_slice(), vec![idx as u8; idx % 7]);
+        });
+    }
+
+    #[test]
+    fn empty_input() {
+        let recipes: Vec<Literal<[u8; 1]>> = Vec::new();
+        assert!(assemble_all(&recipes).is_empty());
+        assert!(assemble_iter(recipes.into_par_iter()).is_empty());
+        for_each_assembled(&[] as &[Literal<[u8; 1]>], |_, _| {
+            panic!("called for empty input")
+        });
+    }
+}
@@ -0,0 +1,364 @@
+//! RTP and RTCP packets.
+//!
+//! This module provides recipes for the packets of the Real-time Transport
+//! Protocol and its control protocol as defined in RFC 3550.
+//!
+//! RTP packets are described by a [`Header`] whose fields are used as is,
+//! so the CSRC count or the flags can disagree with the rest of the packet.
+//! The [`packet`] function sets the flags to match. RTCP packets can be
+//! combined into compound packets simply by using a tuple. The length
+//! fields of RTCP packets are calculated, but [`rtcp_packet_raw`] allows
+//! giving any value.
+
+use super::core::{Fragment, Recipe, be, iter, literal};
+
+
+//------------ Constants -----------------------------------------------------
+
+/// The current protocol version.
+pub const VERSION: u8 = 2;
+
+/// The RTCP packet type of a sender report.
+pub const RTCP_SR: u8 = 200;
+
+/// The RTCP packet type of a receiver report.
+pub const RTCP_RR: u8 = 201;
+
+/// The RTCP packet type of source description items.
+pub const RTCP_SDES: u8 = 202;
+
+/// The RTCP packet type of a goodbye packet.
+pub const RTCP_BYE: u8 = 203;
+
+/// The RTCP packet type of application-defined packets.
+pub const RTCP_APP: u8 = 204;
+
+/// The SDES item type for the canonical name.
+pub const SDES_CNAME: u8 = 1;
+
+/// The SDES item type for the user name.
+pub const SDES_NAME: u8 = 2;
+
+/// The SDES item type for the tool name.
+pub const SDES_TOOL: u8 = 6;
+
+
+//============ RTP ===========================================================
+
+//------------ Header --------------------------------------------------------
+
+/// An RTP header.
+#[derive(Clone, Debug)]
+pub struct Header {
+    pub version: u8,
+    pub padding: bool,
+    pub extension: bool,
+    pub marker: bool,
+    pub payload_type: u8,
+    pub sequence: u16,
+    pub timestamp: u32,
+    pub ssrc: u32,
+    pub csrcs: Vec<u32>,
+
+    /// The CSRC count if it should differ from the number of CSRCs.
+    pub csrc_count: Option<u8>,
+}
+
+impl Default for Header {
+    fn default() -> Self {
+        Header {
+            version: VERSION,
+            padding: false,
+            extension: false,
+            marker: false,
+            payload_type: 0,
+            sequence: 0,
+            timestamp: 0,
+            ssrc: 0,
+            csrcs: Vec::new(),
+            csrc_count: None,
+        }
+    }
+}
+
+impl Recipe for Header {
+    fn assemble(&self, target: &mut Fragment) {
+        let count = self.csrc_count.unwrap_or(self.csrcs.len() as u8);
+        target.push(
+            (self.version << 6) | (u8::from(self.padding) << 5)
+            | (u8::from(self.extension) << 4) | (count & 0x0F)
+        );
+        target.push(
+            (u8::from(self.marker) << 7) | (self.payload_type & 0x7F)
+        );
+        (
+            be(self.sequence), be(self.timestamp), be(self.ssrc)
+        ).assemble(target);
+        for csrc in &self.csrcs {
+            target.extend_from_slice(&csrc.to_be_bytes())
+        }
+    }
+}
+
+
+//------------ packet --------------------------------------------------------
+
+/// Returns a recipe for an RTP packet.
+///
+/// The packet consists of the _header,_ the optional header _extension,_
+/// which should be an [`extension`], the _payload,_ and _padding_ octets of
+/// [`padding`]. The extension and padding flags of the header are set
+/// according to the presence of the extension and padding.
+pub fn packet<E: Recipe, P: Recipe>(
+    mut header: Header, extension: Option<E>, payload: P, padding: u8
+) -> impl Recipe {
+    header.extension = extension.is_some();
+    header.padding = padding > 0;
+    (header, iter(extension), payload, self::padding(padding))
+}
+
+
+//------------ extension -----------------------------------------------------
+
+/// Returns a recipe for a header extension.
+///
+/// The extension consists of the profile-defined value, the length of
+/// _data_ in 32 bit words, and the data itself. The data should be a
+/// multiple of four octets long. If it isn’t, the length is rounded down.
+pub fn extension<R: Recipe>(profile: u16, data: R) -> impl Recipe {
+    Extension { profile, length: None, data }
+}
+
+/// Returns a recipe for a header extension with the length given.
+pub fn extension_raw<R: Recipe>(
+    profile: u16, length: u16, data: R
+) -> impl Recipe {
+    Extension { profile, length: Some(length), data }
+}
+
+struct Extension<R> {
+    profile: u16,
+    length: Option<u16>,
+    data: R,
+}
+
+impl<R: Recipe> Recipe for Extension<R> {
+    fn assemble(&self, target: &mut Fragment) {
+        be(self.profile).assemble(target);
+        let start = target.len();
+        target.extend_from_slice(&[0; 2]);
+        self.data.assemble(target);
+        if target.is_exceeded() {
+            return
+        }
+        let len = self.length.unwrap_or(
+            ((target.len() - start - 2) / 4) as u16
+        );
+        target[start..start + 2].copy_from_slice(&len.to_be_bytes());
+    }
+}
+
+
+//------------ padding -------------------------------------------------------
+
+/// Returns a recipe for _count_ octets of padding.
+///
+/// The padding consists of zero octets followed by the count itself. If
+/// _count_ is zero, the recipe produces nothing.
+pub fn padding(count: u8) -> impl Recipe {
+    let mut data = vec![0; count as usize];
+    if let Some(last) = data.last_mut() {
+        *last = count
+    }
+    literal(data)
+}
+
+
+//============ RTCP ==========================================================
+
+//------------ rtcp_packet ---------------------------------------------------
+
+/// Returns a recipe for an RTCP packet.
+///
+/// The packet consists of the common header with the given _count_ – the
+/// number of reports, chunks, or sources, or the subtype – and
+/// _packet_type_ followed by the _body._ The length is calculated from
+/// the body which should be a multiple of four octets long.
+pub fn rtcp_packet<B: Recipe>(
+    count: u8, packet_type: u8, body: B
+) -> impl Recipe {
+    RtcpPacket { count, packet_type, length: None, body }
+}
+
+/// Returns a recipe for an RTCP packet with the length field given.
+pub fn rtcp_packet_raw<B: Recipe>(
+    count: u8, packet_type: u8, length: u16, body: B
+) -> impl Recipe {
+    RtcpPacket { count, packet_type, length: Some(length), body }
+}
+
+struct RtcpPacket<B> {
+    count: u8,
+    packet_type: u8,
+    length: Option<u16>,
+    body: B,
+}
+
+impl<B: Recipe> Recipe for RtcpPacket<B> {
+    fn assemble(&self, target: &mut Fragment) {
+        let start = target.len();
+        target.extend_from_slice(&[
+            (VERSION << 6) | (self.count & 0x1F), self.packet_type, 0, 0
+        ]);
+        self.body.assemble(target);
+        if target.is_exceeded() {
+            return
+        }
+        let len = self.length.unwrap_or(
+            ((target.len() - start) / 4).saturating_sub(1) as u16
+        );
+        target[start + 2..start + 4].copy_from_slice(&len.to_be_bytes());
+    }
+}
+
+
+//------------ ReportBlock ---------------------------------------------------
+
+/// A reception report block.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ReportBlock {
+    pub ssrc: u32,
+    pub fraction_lost: u8,
+
+    /// The cumulative number of packets lost.
+    ///
+    /// Only the lower 24 bits are used.
+    pub cumulative_lost: u32,
+    pub highest_sequence: u32,
+    pub jitter: u32,
+    pub last_sr: u32,
+    pub delay_since_last_sr: u32,
+}
+
+impl Recipe for ReportBlock {
+    fn assemble(&self, target: &mut Fragment) {
+        (
+            be(self.ssrc),
+            be((u32::from(self.fraction_lost) << 24)
+                | (self.cumulative_lost & 0x00FF_FFFF)),
+            be(self.highest_sequence), be(self.jitter),
+            be(self.last_sr), be(self.delay_since_last_sr),
+        ).assemble(target)
+    }
+}
+
+
+//------------ sender_report and receiver_report -----------------------------
+
+/// Returns a recipe for a sender report packet.
+pub fn sender_report(
+    ssrc: u32, ntp_timestamp: u64, rtp_timestamp: u32, packets: u32,
+    octets: u32, blocks: Vec<ReportBlock>
+) -> impl Recipe {
+    rtcp_packet(blocks.len() as u8, RTCP_SR, (
+        be(ssrc), be(ntp_timestamp), be(rtp_timestamp), be(packets),
+        be(octets), iter(blocks)
+    ))
+}
+
+/// Returns a recipe for a receiver report packet.
+pub fn receiver_report(ssrc: u32, blocks: Vec<ReportBlock>) -> impl Recipe {
+    rtcp_packet(blocks.len() as u8, RTCP_RR, (be(ssrc), iter(blocks)))
+}
+
+
+//------------ sdes ----------------------------------------------------------
+
+/// Returns a recipe for a source description packet.
+///
+/// Each chunk consists of an SSRC and a list of items given as pairs of
+/// item type and text. The items of each chunk are terminated by a null
+/// octet and padded to a multiple of four octets.
+pub fn sdes(chunks: &[(u32, &[(u8, &str)])]) -> impl Recipe {
+    let mut body = Vec::new();
+    for (ssrc, items) in chunks {
+        body.extend_from_slice(&ssrc.to_be_bytes());
+        for (item_type, text) in items.iter() {
+            body.push(*item_type);
+            body.push(text.len() as u8);
+            body.extend_from_slice(text.as_bytes());
+        }
+        body.push(0);
+        body.resize((body.len() + 3) & !3, 0);
+    }
+    rtcp_packet(chunks.len() as u8, RTCP_SDES, literal(body))
+}
+
+
+//------------ bye -----------------------------------------------------------
+
+/// Returns a recipe for a goodbye packet.
+///
+/// If given, the _reason_ is added as a length-prefixed string padded to a
+/// multiple of four octets.
+pub fn bye(ssrcs: &[u32], reason: Option<&str>) -> impl Recipe {
+    let mut body: Vec<u8> = ssrcs.iter().flat_map(|ssrc| {
+        ssrc.to_be_bytes()
+    }).collect();
+    if let Some(reason) = reason {
+        body.push(reason.len() as u8);
+        body.extend_from_slice(reason.as_bytes());
+        body.resize((body.len() + 3) & !3, 0);
+    }
+    rtcp_packet(ssrcs.len() as u8, RTCP_BYE, literal(body))
+}
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::recipe::core::hex;
+
+    #[test]
+    fn rtp() {
+        assert_eq!(
+            packet(
+                Header {
+                    marker: true, payload_type: 96, sequence: 1,
+                    timestamp: 2, ssrc: 3, csrcs: vec![4],
+                    ..Default::default()
+                },
+                Some(extension(0xBEDE, hex("11223344"))),
+                hex("aa"), 3
+            ).to_fragment(),
+            hex(
+                "b1 e0 0001 00000002 00000003 00000004
+                 bede 0001 11223344 aa 0000 03"
+            ).to_fragment()
+        );
+    }
+
+    #[test]
+    fn rtcp() {
+        assert_eq!(
+            (
+                receiver_report(1, vec![ReportBlock {
+                    ssrc: 2, cumulative_lost: 0xFF00_0001,
+                    ..Default::default()
+                }]),
+                bye(&[1], Some("x")),
+            ).to_fragment(),
+            hex(
+                "81 c9 0007 00000001
+                 00000002 00000001 00000000 00000000 00000000 00000000
+                 81 cb 0002 00000001 01 78 0000"
+            ).to_fragment()
+        );
+        assert_eq!(
+            sdes(&[(1, &[(SDES_CNAME, "ab")])]).to_fragment(),
+            b"\x81\xca\x00\x03\x00\x00\x00\x01\x01\x02ab\0\0\0\0"
+        );
+    }
+}
@@ -0,0 +1,226 @@
+//! Named collections of recipes.
+//!
+//! Test vectors for a protocol usually come in families: a valid baseline
+//! and a number of variants that each break one thing. A [`RecipeSet`]
+//! keeps such a family together under names, can write all of it to a
+//! directory, and can run a check over every member while reporting
+//! failures by name.
+
+use std::{fmt, fs, io, panic};
+use std::path::Path;
+use super::core::{Fragment, Recipe};
+
+
+//------------ RecipeSet -----------------------------------------------------
+
+/// A collection of named recipes.
+///
+/// The recipes are kept in the order they were added in. Names are
+/// unique: adding a recipe under an existing name replaces the earlier
+/// recipe.
+#[derive(Default)]
+pub struct RecipeSet {
+    items: Vec<(String, Box<dyn Recipe>)>,
+}
+
+impl RecipeSet {
+    /// The name used for the baseline recipe.
+    pub const BASELINE: &'static str = "baseline";
+
+    /// Creates a new, empty set.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the baseline recipe under the name [`BASELINE`][Self::BASELINE].
+    pub fn baseline(self, recipe: impl Recipe + 'static) -> Self {
+        self.variant(Self::BASELINE, recipe)
+    }
+
+    /// Adds a recipe under the given name.
+    pub fn variant(
+        mut self, name: impl Into<String>, recipe: impl Recipe + 'static
+    ) -> Self {
+        self.insert(name, recipe);
+        self
+    }
+
+    /// Adds a recipe under the given name to an existing set.
+    pub fn insert(
+        &mut self, name: impl Into<String>, recipe: impl Recipe + 'static
+    ) {
+        let name = name.into();
+        let recipe = Box::new(recipe);
+        match self.items.iter_mut().find(|item| item.0 == name) {
+            Some(item) => item.1 = recipe,
+            None => self.items.push((name, recipe)),
+        }
+    }
+
+    /// Returns the recipe with the given name if it exists.
+    pub fn get(&self, name: &str) -> Option<&dyn Recipe> {
+        self.items.iter().find(|item| item.0 == name).map(|item| {
+            item.1.as_ref()
+        })
+    }
+
+    /// Returns the number of recipes in the set.
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    /// Returns whether the set is empty.
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /// Returns an iterator over the names of the recipes.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.items.iter().map(|item| item.0.as_str())
+    }
+
+    /// Returns an iterator over the names and recipes.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &dyn Recipe)> {
+        self.items.iter().map(|item| (item.0.as_str(), item.1.as_ref()))
+    }
+
+    /// Assembles all recipes.
+    ///
+    /// Returns the names and assembled data in the order of the set.
+    pub fn assemble_all(&self) -> Vec<(&str, Fragment)> {
+        self.iter().map(|(name, recipe)| {
+            let mut target = Fragment::new();
+            recipe.assemble(&mut target);
+            (name, target)
+        }).collect()
+    }
+
+    /// Writes all recipes into files in a directory.
+    ///
+    /// The directory is created if necessary. Each recipe is written into
+    /// a file named after the recipe with the given _extension_ appended.
+    pub fn write_to_dir(
+        &self, dir: impl AsRef<Path>, extension: &str
+    ) -> Result<(), io::Error> {
+        let dir = dir.as_ref();
+        fs::create_dir_all(dir)?;
+        let mut target = Fragment::new();
+        for (name, recipe) in self.iter() {
+            target.clear();
+            recipe.assemble(&mut target);
+            fs::write(dir.join(format!("{}{}", name, extension)), &target)?;
+        }
+        Ok(())
+    }
+
+    /// Runs a check for every recipe in the set.
+    ///
+    /// The closure _op_ is called with the name and assembled data of each
+    /// recipe. It should panic if the check fails, e.g., via the usual
+    /// assertion macros. All recipes are checked even if some of them fail.
+    ///
+    /// # Panics
+    ///
+    /// The method panics if the check failed for any recipe. The panic
+    /// message lists the names of all failed recipes together with the
+    /// messages of their panics.
+    pub fn check_each(&self, mut op: impl FnMut(&str, &Fragment)) {
+        let mut failed = Vec::new();
+        let mut target = Fragment::new();
+        for (name, recipe) in self.iter() {
+            target.clear();
+            recipe.assemble(&mut target);
+            let res = panic::catch_unwind(panic::AssertUnwindSafe(|| {
+                op(name, &target)
+            }));
+            if let Err(err) = res {
+                failed.push(Failure { name, message: panic_message(&err) })
+            }
+        }
+        if !failed.is_empty() {
+            let mut msg = format!(
+                "{} of {} recipes failed:", failed.len(), self.len()
+            );
+            for item in failed {
+                msg.push_str(&format!("\n  {}", item));
+            }
+            panic!("{}", msg)
+        }
+    }
+}
+
+impl fmt::Debug for RecipeSet {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list().entries(self.names()).finish()
+    }
+}
+
+
+//------------ Failure -------------------------------------------------------
+
+/// A recipe that failed its check.
+struct Failure<'a> {
+    name: &'a str,
+    message: String,
+}
+
+impl fmt::Display for Failure<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.name, self.message)
+    }
+}
+
+/// Extracts the message from a panic payload.
+fn panic_message(err: &(dyn std::any::Any + Send)) -> String {
+    if let Some(msg) = err.downcast_ref::<&str>() {
+        (*msg).into()
+    }
+    else if let Some(msg) = err.downcast_ref::<String>() {
+        msg.clone()
+    }
+    else {
+        "unknown panic".into()
+    }
+}
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::recipe::core::{be, literal};
+
+    fn set() -> RecipeSet {
+        RecipeSet::new()
+            .baseline(be(1u16))
+            .variant("short", literal(b"\x01"))
+            .variant("long", be(1u32))
+    }
+
+    #[test]
+    fn collection() {
+        let mut set = set();
+        set.insert("short", literal(b""));
+        assert_eq!(
+            set.names().collect::<Vec<_>>(), ["baseline", "short", "long"]
+        );
+        assert_eq!(set.get("short").unwrap().to_fragment(), b"");
+        assert_eq!(set.assemble_all()[2].1, b"\0\0\0\x01");
+
+        let dir = std::env::temp_dir().join(
+            format!("prototest-set-{}", std::process::id())
+        );
+        set.write_to_dir(&dir, ".bin").unwrap();
+        assert_eq!(
+            std::fs::read(dir.join("long.bin")).unwrap(), b"\0\0\0\x01"
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    #[should_panic(expected = "2 of 3 recipes failed:\n  short: ")]
+    fn check_each() {
+        set().check_each(|_, data| assert_eq!(data.len(), 2))
+    }
+}
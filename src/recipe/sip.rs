@@ -0,0 +1,243 @@
+//! SIP messages.
+//!
+//! This module provides recipes for the text-based messages of the Session
+//! Initiation Protocol defined in RFC 3261. Next to well-formed requests
+//! and responses, they make it easy to produce the constructs that SIP
+//! parsers tend to get wrong: header values folded over several lines,
+//! compact header names, Content-Length headers that disagree with the
+//! body, and multipart bodies.
+//!
+//! None of the recipes check their input. In particular, values may
+//! contain line breaks which allows injecting arbitrary headers.
+
+use super::core::{Fragment, Recipe, literal};
+
+
+//------------ Constants -----------------------------------------------------
+
+/// The protocol version in request and status lines.
+pub const VERSION: &str = "SIP/2.0";
+
+/// The line separator.
+pub const CRLF: &str = "\r\n";
+
+
+//------------ request_line and status_line ----------------------------------
+
+/// Returns a recipe for a request line.
+pub fn request_line(method: &str, uri: &str) -> impl Recipe {
+    literal(format!("{} {} {}{}", method, uri, VERSION, CRLF))
+}
+
+/// Returns a recipe for a status line.
+pub fn status_line(code: u16, reason: &str) -> impl Recipe {
+    literal(format!("{} {} {}{}", VERSION, code, reason, CRLF))
+}
+
+
+//------------ header --------------------------------------------------------
+
+/// Returns a recipe for a header line.
+///
+/// The line consists of the _name,_ a colon and a space, whatever _value_
+/// produces, and the line separator.
+pub fn header<R: Recipe>(name: &str, value: R) -> impl Recipe {
+    (literal(format!("{}: ", name)), value, literal(CRLF))
+}
+
+/// Returns a recipe for a header line using the compact form of the name.
+///
+/// If there is no compact form for _name,_ it is used as is.
+pub fn compact_header<R: Recipe>(name: &str, value: R) -> impl Recipe {
+    header(compact_name(name).unwrap_or(name), value)
+}
+
+/// Returns a recipe for a header with a value folded over several lines.
+///
+/// The first item of _lines_ is placed on the header line itself. Each
+/// following item is placed on a continuation line starting with a single
+/// space.
+pub fn folded_header(name: &str, lines: &[&str]) -> impl Recipe {
+    literal(format!("{}: {}{}", name, lines.join("\r\n "), CRLF))
+}
+
+
+//------------ compact_name --------------------------------------------------
+
+/// Returns the compact form of a header name if there is one.
+///
+/// The name is compared ignoring case.
+pub fn compact_name(name: &str) -> Option<&'static str> {
+    const NAMES: &[(&str, &str)] = &[
+        ("Accept-Contact", "a"),
+        ("Allow-Events", "u"),
+        ("Call-ID", "i"),
+        ("Contact", "m"),
+        ("Content-Encoding", "e"),
+        ("Content-Length", "l"),
+        ("Content-Type", "c"),
+        ("Event", "o"),
+        ("From", "f"),
+        ("Refer-To", "r"),
+        ("Referred-By", "b"),
+        ("Session-Expires", "x"),
+        ("Subject", "s"),
+        ("Supported", "k"),
+        ("To", "t"),
+        ("Via", "v"),
+    ];
+    NAMES.iter().find(|item| item.0.eq_ignore_ascii_case(name)).map(|item| {
+        item.1
+    })
+}
+
+
+//------------ message -------------------------------------------------------
+
+/// Returns a recipe for a message with a calculated Content-Length.
+///
+/// The message consists of the _start_line_ – one of [`request_line`] or
+/// [`status_line`] –, the _headers,_ a Content-Length header with the
+/// length of the body, an empty line, and the _body._
+///
+/// Any Content-Length headers included in _headers_ are kept, which
+/// allows creating messages with conflicting lengths.
+pub fn message<S: Recipe, H: Recipe, B: Recipe>(
+    start_line: S, headers: H, body: B
+) -> impl Recipe {
+    Message { head: (start_line, headers), body }
+}
+
+/// Returns a recipe for a message without adding a Content-Length.
+pub fn message_raw<S: Recipe, H: Recipe, B: Recipe>(
+    start_line: S, headers: H, body: B
+) -> impl Recipe {
+    (start_line, headers, literal(CRLF), body)
+}
+
+struct Message<H, B> {
+    head: H,
+    body: B,
+}
+
+impl<H: Recipe, B: Recipe> Recipe for Message<H, B> {
+    fn assemble(&self, target: &mut Fragment) {
+        self.head.assemble(target);
+        let mark = target.mark();
+        target.extend_from_slice(CRLF.as_bytes());
+        self.body.assemble(target);
+        if target.is_exceeded() {
+            return
+        }
+        let end = target.len();
+        let len = end - mark.start() - CRLF.len();
+        let header = format!("Content-Length: {}{}", len, CRLF);
+        target.extend_from_slice(header.as_bytes());
+        target.move_before(&mark, end);
+    }
+}
+
+
+//------------ Multipart Bodies ----------------------------------------------
+
+/// Returns a recipe for the value of a multipart Content-Type header.
+pub fn multipart_type(subtype: &str, boundary: &str) -> impl Recipe {
+    literal(format!("multipart/{};boundary={}", subtype, boundary))
+}
+
+/// Returns a recipe for a body part of a multipart body.
+///
+/// The part starts with the delimiter line for _boundary,_ followed by the
+/// _headers,_ an empty line, the _content,_ and a line separator.
+pub fn body_part<H: Recipe, C: Recipe>(
+    boundary: &str, headers: H, content: C
+) -> impl Recipe {
+    (
+        literal(format!("--{}{}", boundary, CRLF)),
+        headers, literal(CRLF), content, literal(CRLF)
+    )
+}
+
+/// Returns a recipe for the closing delimiter of a multipart body.
+pub fn multipart_end(boundary: &str) -> impl Recipe {
+    literal(format!("--{}--{}", boundary, CRLF))
+}
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::recipe::core::empty;
+
+    #[test]
+    fn messages() {
+        assert_eq!(
+            message(
+                request_line("INVITE", "sip:bob@example.com"),
+                (
+                    compact_header("via", literal("SIP/2.0/UDP host")),
+                    folded_header("Subject", &["a", "b"]),
+                    header("Content-Length", literal("99")),
+                ),
+                literal("xyz")
+            ).to_fragment(),
+            "INVITE sip:bob@example.com SIP/2.0\r\n\
+             v: SIP/2.0/UDP host\r\n\
+             Subject: a\r\n b\r\n\
+             Content-Length: 99\r\n\
+             Content-Length: 3\r\n\
+             \r\n\
+             xyz"
+        );
+        assert_eq!(
+            (
+                body_part("b", empty(), literal("x")),
+                multipart_end("b")
+            ).to_fragment(),
+            "--b\r\n\r\nx\r\n--b--\r\n"
+        );
+    }
+
+    #[test]
+    fn content_length() {
+        // The length counts octets, not characters.
+        assert_eq!(
+            message(status_line(200, "OK"), empty(), literal("\u{e9}"))
+                .to_fragment(),
+            "SIP/2.0 200 OK\r\nContent-Length: 2\r\n\r\n\u{e9}"
+        );
+        assert_eq!(
+            message(status_line(100, "Trying"), empty(), empty())
+                .to_fragment(),
+            "SIP/2.0 100 Trying\r\nContent-Length: 0\r\n\r\n"
+        );
+        assert_eq!(
+            message_raw(
+                request_line("ACK", "sip:a"),
+                header("Content-Length", literal("5")),
+                literal("ab")
+            ).to_fragment(),
+            "ACK sip:a SIP/2.0\r\nContent-Length: 5\r\n\r\nab"
+        );
+    }
+
+    #[test]
+    fn header_names() {
+        assert_eq!(compact_name("CALL-id"), Some("i"));
+        assert_eq!(compact_name("X-Unknown"), None);
+        assert_eq!(
+            compact_header("X-Unknown", literal("1")).to_fragment(),
+            "X-Unknown: 1\r\n"
+        );
+        // Values aren’t checked, so headers can be injected.
+        assert_eq!(
+            header("To", literal("a\r\nFrom: b")).to_fragment(),
+            "To: a\r\nFrom: b\r\n"
+        );
+        assert_eq!(
+            folded_header("Via", &["a"]).to_fragment(), "Via: a\r\n"
+        );
+    }
+}
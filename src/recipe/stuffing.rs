@@ -0,0 +1,330 @@
+//! Byte stuffing for framing on serial lines.
+//!
+//! The recipes in this module assemble an inner recipe and encode its data
+//! so that it can be delimited on a byte stream: [`slip`] as defined in
+//! RFC 1055, [`hdlc`] using the asynchronous HDLC-like framing of PPP
+//! defined in RFC 1662, and [`cobs`] using Consistent Overhead Byte
+//! Stuffing. Frame check sequences are not added; use the recipes in
+//! [`checksum`][super::checksum] on the inner data for that.
+//!
+//! Each recipe can produce a deliberately broken frame via the same
+//! [`Broken`] options.
+
+use super::core::{Fragment, Recipe};
+
+
+//------------ Broken --------------------------------------------------------
+
+/// How to break the encoding of a frame.
+///
+/// The exact meaning of each option depends on the encoding and is
+/// described with the individual recipes.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Broken {
+    /// Octets that need escaping are left alone.
+    Unescaped,
+
+    /// The frame ends in the middle of an escape sequence.
+    DanglingEscape,
+
+    /// The frame contains an escape sequence that isn’t valid.
+    InvalidEscape,
+
+    /// The delimiter at the end of the frame is missing.
+    Unterminated,
+}
+
+
+//------------ slip ----------------------------------------------------------
+
+/// The SLIP frame delimiter.
+pub const SLIP_END: u8 = 0xC0;
+
+/// The SLIP escape octet.
+pub const SLIP_ESC: u8 = 0xDB;
+
+/// Returns a recipe for a SLIP frame.
+///
+/// The data of _inner_ is escaped by replacing END octets with ESC
+/// followed by 0xDC and ESC octets with ESC followed by 0xDD. An END octet
+/// is added after the data and, if requested via [`Slip::leading_end`],
+/// also before it.
+///
+/// When broken, a dangling escape is an ESC right before the final END
+/// and an invalid escape is an ESC followed by a zero octet at the end of
+/// the data.
+pub fn slip<R: Recipe>(inner: R) -> Slip<R> {
+    Slip { inner, leading_end: false, broken: None }
+}
+
+pub struct Slip<R> {
+    inner: R,
+    leading_end: bool,
+    broken: Option<Broken>,
+}
+
+impl<R> Slip<R> {
+    /// Adds an END octet before the data, too.
+    pub fn leading_end(mut self) -> Self {
+        self.leading_end = true;
+        self
+    }
+
+    /// Produces a broken frame.
+    pub fn broken(mut self, broken: Broken) -> Self {
+        self.broken = Some(broken);
+        self
+    }
+}
+
+impl<R: Recipe> Recipe for Slip<R> {
+    fn assemble(&self, target: &mut Fragment) {
+        let data = take_data(&self.inner, target);
+        if self.leading_end {
+            target.push(SLIP_END)
+        }
+        for &octet in &data {
+            match octet {
+                _ if self.broken == Some(Broken::Unescaped) => {
+                    target.push(octet)
+                }
+                SLIP_END => target.extend_from_slice(&[SLIP_ESC, 0xDC]),
+                SLIP_ESC => target.extend_from_slice(&[SLIP_ESC, 0xDD]),
+                _ => target.push(octet),
+            }
+        }
+        match self.broken {
+            Some(Broken::DanglingEscape) => target.push(SLIP_ESC),
+            Some(Broken::InvalidEscape) => {
+                target.extend_from_slice(&[SLIP_ESC, 0])
+            }
+            _ => { }
+        }
+        if self.broken != Some(Broken::Unterminated) {
+            target.push(SLIP_END)
+        }
+    }
+}
+
+
+//------------ hdlc ----------------------------------------------------------
+
+/// The HDLC flag sequence.
+pub const HDLC_FLAG: u8 = 0x7E;
+
+/// The HDLC control escape octet.
+pub const HDLC_ESC: u8 = 0x7D;
+
+/// Returns a recipe for an HDLC-like frame.
+///
+/// The data of _inner_ is escaped by replacing flag and escape octets
+/// as well as the control characters given via [`Hdlc::accm`] with the
+/// escape octet followed by the original octet with bit 5 inverted. The
+/// frame starts and ends with a flag sequence.
+///
+/// When broken, a dangling escape is an escape octet right before the
+/// final flag sequence, which aborts the frame, and an invalid escape is
+/// an escape octet followed by another one at the end of the data.
+pub fn hdlc<R: Recipe>(inner: R) -> Hdlc<R> {
+    Hdlc { inner, accm: 0, broken: None }
+}
+
+pub struct Hdlc<R> {
+    inner: R,
+    accm: u32,
+    broken: Option<Broken>,
+}
+
+impl<R> Hdlc<R> {
+    /// Sets the async control character map.
+    ///
+    /// If bit _n_ of _accm_ is set, the octet with the value _n_ is
+    /// escaped. The default is zero, i.e., no control characters are
+    /// escaped. PPP starts with all bits set.
+    pub fn accm(mut self, accm: u32) -> Self {
+        self.accm = accm;
+        self
+    }
+
+    /// Produces a broken frame.
+    pub fn broken(mut self, broken: Broken) -> Self {
+        self.broken = Some(broken);
+        self
+    }
+}
+
+impl<R: Recipe> Recipe for Hdlc<R> {
+    fn assemble(&self, target: &mut Fragment) {
+        let data = take_data(&self.inner, target);
+        target.push(HDLC_FLAG);
+        for &octet in &data {
+            let escape = self.broken != Some(Broken::Unescaped) && (
+                octet == HDLC_FLAG || octet == HDLC_ESC
+                || (octet < 0x20 && self.accm & (1 << octet) != 0)
+            );
+            if escape {
+                target.extend_from_slice(&[HDLC_ESC, octet ^ 0x20])
+            }
+            else {
+                target.push(octet)
+            }
+        }
+        match self.broken {
+            Some(Broken::DanglingEscape) => target.push(HDLC_ESC),
+            Some(Broken::InvalidEscape) => {
+                target.extend_from_slice(&[HDLC_ESC, HDLC_ESC])
+            }
+            _ => { }
+        }
+        if self.broken != Some(Broken::Unterminated) {
+            target.push(HDLC_FLAG)
+        }
+    }
+}
+
+
+//------------ cobs ----------------------------------------------------------
+
+/// Returns a recipe for a COBS frame.
+///
+/// The data of _inner_ is encoded using Consistent Overhead Byte
+/// Stuffing so that it doesn’t contain any zero octets and a zero octet
+/// is added as the frame delimiter.
+///
+/// When broken, unescaped data is a single code octet followed by the
+/// data including its zero octets, a dangling escape is a code octet
+/// announcing four more octets right before the delimiter, and an
+/// invalid escape is a zero octet in place of the first code octet.
+pub fn cobs<R: Recipe>(inner: R) -> Cobs<R> {
+    Cobs { inner, broken: None }
+}
+
+pub struct Cobs<R> {
+    inner: R,
+    broken: Option<Broken>,
+}
+
+impl<R> Cobs<R> {
+    /// Produces a broken frame.
+    pub fn broken(mut self, broken: Broken) -> Self {
+        self.broken = Some(broken);
+        self
+    }
+}
+
+impl<R: Recipe> Recipe for Cobs<R> {
+    fn assemble(&self, target: &mut Fragment) {
+        let data = take_data(&self.inner, target);
+        let mut frame = Vec::with_capacity(data.len() + data.len() / 254 + 3);
+        if self.broken == Some(Broken::Unescaped) {
+            frame.push(data.len().saturating_add(1).min(0xFF) as u8);
+            frame.extend_from_slice(&data);
+        }
+        else {
+            let mut code_pos = 0;
+            let mut code = 1u8;
+            frame.push(0);
+            for (idx, &octet) in data.iter().enumerate() {
+                if octet != 0 {
+                    frame.push(octet);
+                    code += 1;
+                    if code < 0xFF || idx + 1 == data.len() {
+                        continue
+                    }
+                }
+                frame[code_pos] = code;
+                code_pos = frame.len();
+                code = 1;
+                frame.push(0);
+            }
+            frame[code_pos] = code;
+        }
+        match self.broken {
+            Some(Broken::DanglingEscape) => frame.push(5),
+            Some(Broken::InvalidEscape) => frame[0] = 0,
+            _ => { }
+        }
+        if self.broken != Some(Broken::Unterminated) {
+            frame.push(0)
+        }
+        target.extend_from_slice(&frame)
+    }
+}
+
+
+//------------ Helpers -------------------------------------------------------
+
+/// Assembles a recipe and removes its data from the fragment again.
+fn take_data(recipe: &impl Recipe, target: &mut Fragment) -> Vec<u8> {
+    let start = target.len();
+    recipe.assemble(target);
+    let data = target[start..].to_vec();
+    target.truncate(start);
+    data
+}
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::recipe::core::{hex, repeat};
+
+    #[test]
+    fn slip_frames() {
+        assert_eq!(
+            slip(hex("01c0 02db")).to_fragment(),
+            b"\x01\xdb\xdc\x02\xdb\xdd\xc0"
+        );
+        assert_eq!(
+            slip(hex("01")).leading_end().to_fragment(), b"\xc0\x01\xc0"
+        );
+        assert_eq!(
+            slip(hex("c0")).broken(Broken::Unescaped).to_fragment(),
+            b"\xc0\xc0"
+        );
+        assert_eq!(
+            slip(hex("01")).broken(Broken::DanglingEscape).to_fragment(),
+            b"\x01\xdb\xc0"
+        );
+        assert_eq!(
+            slip(hex("01")).broken(Broken::Unterminated).to_fragment(),
+            b"\x01"
+        );
+    }
+
+    #[test]
+    fn hdlc_frames() {
+        assert_eq!(
+            hdlc(hex("7e 7d 01 11")).accm(1 << 0x11).to_fragment(),
+            b"\x7e\x7d\x5e\x7d\x5d\x01\x7d\x31\x7e"
+        );
+        assert_eq!(
+            hdlc(hex("01")).broken(Broken::InvalidEscape).to_fragment(),
+            b"\x7e\x01\x7d\x7d\x7e"
+        );
+    }
+
+    #[test]
+    fn cobs_frames() {
+        assert_eq!(cobs(hex("")).to_fragment(), b"\x01\0");
+        assert_eq!(cobs(hex("00")).to_fragment(), b"\x01\x01\0");
+        assert_eq!(
+            cobs(hex("11 22 00 33")).to_fragment(), b"\x03\x11\x22\x02\x33\0"
+        );
+        let frame = cobs(repeat(254, hex("01"))).to_fragment();
+        assert_eq!(frame.len(), 256);
+        assert_eq!(frame[0], 0xFF);
+        let frame = cobs(repeat(255, hex("01"))).to_fragment();
+        assert_eq!(&frame[254..], b"\x01\x02\x01\0");
+        assert_eq!(
+            cobs(hex("1100")).broken(Broken::Unescaped).to_fragment(),
+            b"\x03\x11\0\0"
+        );
+        assert_eq!(
+            cobs(hex("11")).broken(Broken::InvalidEscape).to_fragment(),
+            b"\0\x11\0"
+        );
+    }
+}
@@ -0,0 +1,393 @@
+//! TACACS+ packets.
+//!
+//! This module provides recipes for the packets of the TACACS+ protocol as
+//! defined in RFC 8907: the common header as well as the bodies of the
+//! authentication, authorization, and accounting packets.
+//!
+//! The header length is calculated from the body, but [`header`] can be
+//! used to create a header with any length. The body can be obfuscated
+//! with a shared secret via [`obfuscate`] or [`obfuscated_packet`] which
+//! requires the `md-5` feature. Since the header flags are always taken as
+//! given, packets with an unobfuscated body that claim otherwise – and vice
+//! versa – are easy to create.
+
+use super::core::{Fragment, Recipe, be, literal};
+
+
+//------------ Constants -----------------------------------------------------
+
+/// The version byte with the default minor version.
+pub const VERSION_DEFAULT: u8 = 0xC0;
+
+/// The version byte with minor version one.
+pub const VERSION_ONE: u8 = 0xC1;
+
+/// The packet type of authentication packets.
+pub const TYPE_AUTHEN: u8 = 1;
+
+/// The packet type of authorization packets.
+pub const TYPE_AUTHOR: u8 = 2;
+
+/// The packet type of accounting packets.
+pub const TYPE_ACCT: u8 = 3;
+
+/// The header flag signaling an unobfuscated body.
+pub const FLAG_UNENCRYPTED: u8 = 0x01;
+
+/// The header flag signaling support for single connection mode.
+pub const FLAG_SINGLE_CONNECT: u8 = 0x04;
+
+/// The authentication action for a login.
+pub const AUTHEN_LOGIN: u8 = 1;
+
+/// The authentication type for ASCII login.
+pub const AUTHEN_TYPE_ASCII: u8 = 1;
+
+/// The authentication type for PAP.
+pub const AUTHEN_TYPE_PAP: u8 = 2;
+
+/// The authentication type for CHAP.
+pub const AUTHEN_TYPE_CHAP: u8 = 3;
+
+/// The authentication service for a login.
+pub const AUTHEN_SVC_LOGIN: u8 = 1;
+
+/// The authentication status for a successful authentication.
+pub const AUTHEN_STATUS_PASS: u8 = 1;
+
+/// The authentication status for a failed authentication.
+pub const AUTHEN_STATUS_FAIL: u8 = 2;
+
+/// The authentication status asking for the user name.
+pub const AUTHEN_STATUS_GETUSER: u8 = 4;
+
+/// The authentication status asking for the password.
+pub const AUTHEN_STATUS_GETPASS: u8 = 5;
+
+/// The authorization status for an approved request as is.
+pub const AUTHOR_STATUS_PASS_ADD: u8 = 1;
+
+/// The authorization status for a denied request.
+pub const AUTHOR_STATUS_FAIL: u8 = 0x10;
+
+/// The accounting flag for a start record.
+pub const ACCT_FLAG_START: u8 = 0x02;
+
+/// The accounting flag for a stop record.
+pub const ACCT_FLAG_STOP: u8 = 0x04;
+
+/// The accounting status for a successful request.
+pub const ACCT_STATUS_SUCCESS: u8 = 1;
+
+
+//============ Header and Packet =============================================
+
+//------------ header --------------------------------------------------------
+
+/// Returns a recipe for a packet header with all fields given.
+pub fn header(
+    version: u8, packet_type: u8, seq_no: u8, flags: u8, session_id: u32,
+    length: u32,
+) -> impl Recipe {
+    (
+        literal([version, packet_type, seq_no, flags]),
+        be(session_id), be(length),
+    )
+}
+
+
+//------------ packet --------------------------------------------------------
+
+/// Returns a recipe for a complete packet.
+///
+/// The packet consists of the header with the length taken from the
+/// assembled _body_ followed by the body. The body is included as is. If
+/// it should be obfuscated, use [`obfuscated_packet`] instead.
+pub fn packet<R: Recipe>(
+    version: u8, packet_type: u8, seq_no: u8, flags: u8, session_id: u32,
+    body: R,
+) -> impl Recipe {
+    Packet {
+        header: literal([version, packet_type, seq_no, flags]),
+        session_id, body
+    }
+}
+
+struct Packet<H, R> {
+    header: H,
+    session_id: u32,
+    body: R,
+}
+
+impl<H: Recipe, R: Recipe> Recipe for Packet<H, R> {
+    fn assemble(&self, target: &mut Fragment) {
+        self.header.assemble(target);
+        be(self.session_id).assemble(target);
+        let start = target.len();
+        target.extend_from_slice(&[0; 4]);
+        self.body.assemble(target);
+        if target.is_exceeded() {
+            return
+        }
+        let len = (target.len() - start - 4) as u32;
+        target[start..start + 4].copy_from_slice(&len.to_be_bytes());
+    }
+}
+
+
+//------------ obfuscated_packet ---------------------------------------------
+
+/// Returns a recipe for a complete packet with an obfuscated body.
+///
+/// The _body_ is obfuscated using the shared secret _key_ via
+/// [`obfuscate`] with the values from the header. The _flags_ are used as
+/// given, i.e., they should not include [`FLAG_UNENCRYPTED`].
+///
+/// This is only available with the `md-5` feature.
+#[cfg(feature = "md-5")]
+pub fn obfuscated_packet<R: Recipe>(
+    version: u8, packet_type: u8, seq_no: u8, flags: u8, session_id: u32,
+    key: &[u8], body: R,
+) -> impl Recipe {
+    packet(
+        version, packet_type, seq_no, flags, session_id,
+        obfuscate(session_id, key, version, seq_no, body)
+    )
+}
+
+
+//------------ obfuscate -----------------------------------------------------
+
+/// Returns a recipe obfuscating a packet body.
+///
+/// The assembled _body_ is XORed with the pseudo-random pad derived from
+/// the session ID, the shared secret _key_, the version, and the sequence
+/// number as described in section 4.5 of RFC 8907. Because the operation
+/// is its own inverse, this can also be used to reveal an obfuscated body.
+///
+/// This is only available with the `md-5` feature.
+#[cfg(feature = "md-5")]
+pub fn obfuscate<R: Recipe>(
+    session_id: u32, key: &[u8], version: u8, seq_no: u8, body: R
+) -> impl Recipe {
+    Obfuscate { session_id, key: key.into(), version, seq_no, body }
+}
+
+#[cfg(feature = "md-5")]
+struct Obfuscate<R> {
+    session_id: u32,
+    key: Vec<u8>,
+    version: u8,
+    seq_no: u8,
+    body: R,
+}
+
+#[cfg(feature = "md-5")]
+impl<R: Recipe> Recipe for Obfuscate<R> {
+    fn assemble(&self, target: &mut Fragment) {
+        use md5::{Digest, Md5};
+
+        let start = target.len();
+        self.body.assemble(target);
+        let mut prev: Option<[u8; 16]> = None;
+        for chunk in target[start..].chunks_mut(16) {
+            let mut hash = Md5::new();
+            hash.update(self.session_id.to_be_bytes());
+            hash.update(&self.key);
+            hash.update([self.version, self.seq_no]);
+            if let Some(prev) = prev {
+                hash.update(prev);
+            }
+            let pad: [u8; 16] = hash.finalize().into();
+            for (octet, pad) in chunk.iter_mut().zip(pad) {
+                *octet ^= pad
+            }
+            prev = Some(pad);
+        }
+    }
+}
+
+
+//============ Authentication ================================================
+
+//------------ AuthenStart ---------------------------------------------------
+
+/// The body of an authentication START packet.
+///
+/// The lengths of the variable fields are derived from the fields.
+#[derive(Clone, Debug, Default)]
+pub struct AuthenStart {
+    pub action: u8,
+    pub priv_lvl: u8,
+    pub authen_type: u8,
+    pub authen_service: u8,
+    pub user: Vec<u8>,
+    pub port: Vec<u8>,
+    pub rem_addr: Vec<u8>,
+    pub data: Vec<u8>,
+}
+
+impl Recipe for AuthenStart {
+    fn assemble(&self, target: &mut Fragment) {
+        target.extend_from_slice(&[
+            self.action, self.priv_lvl, self.authen_type, self.authen_service,
+            self.user.len() as u8, self.port.len() as u8,
+            self.rem_addr.len() as u8, self.data.len() as u8,
+        ]);
+        target.extend_from_slice(&self.user);
+        target.extend_from_slice(&self.port);
+        target.extend_from_slice(&self.rem_addr);
+        target.extend_from_slice(&self.data);
+    }
+}
+
+
+//------------ authen_reply --------------------------------------------------
+
+/// Returns a recipe for the body of an authentication REPLY packet.
+pub fn authen_reply(
+    status: u8, flags: u8, server_msg: &[u8], data: &[u8]
+) -> impl Recipe {
+    (
+        literal([status, flags]),
+        be(server_msg.len() as u16), be(data.len() as u16),
+        literal(server_msg.to_vec()), literal(data.to_vec()),
+    )
+}
+
+
+//------------ authen_continue -----------------------------------------------
+
+/// Returns a recipe for the body of an authentication CONTINUE packet.
+pub fn authen_continue(
+    flags: u8, user_msg: &[u8], data: &[u8]
+) -> impl Recipe {
+    (
+        be(user_msg.len() as u16), be(data.len() as u16), be(flags),
+        literal(user_msg.to_vec()), literal(data.to_vec()),
+    )
+}
+
+
+//============ Authorization and Accounting ==================================
+
+//------------ AuthorRequest -------------------------------------------------
+
+/// The body of an authorization REQUEST packet.
+///
+/// The lengths of the variable fields and the argument count are derived
+/// from the fields. Prefixed with flags via [`acct_request`], it also
+/// serves as the body of an accounting REQUEST packet.
+#[derive(Clone, Debug, Default)]
+pub struct AuthorRequest {
+    pub authen_method: u8,
+    pub priv_lvl: u8,
+    pub authen_type: u8,
+    pub authen_service: u8,
+    pub user: Vec<u8>,
+    pub port: Vec<u8>,
+    pub rem_addr: Vec<u8>,
+    pub args: Vec<Vec<u8>>,
+}
+
+impl Recipe for AuthorRequest {
+    fn assemble(&self, target: &mut Fragment) {
+        target.extend_from_slice(&[
+            self.authen_method, self.priv_lvl, self.authen_type,
+            self.authen_service,
+            self.user.len() as u8, self.port.len() as u8,
+            self.rem_addr.len() as u8, self.args.len() as u8,
+        ]);
+        for arg in &self.args {
+            target.push(arg.len() as u8)
+        }
+        target.extend_from_slice(&self.user);
+        target.extend_from_slice(&self.port);
+        target.extend_from_slice(&self.rem_addr);
+        for arg in &self.args {
+            target.extend_from_slice(arg)
+        }
+    }
+}
+
+
+//------------ author_reply --------------------------------------------------
+
+/// Returns a recipe for the body of an authorization REPLY packet.
+pub fn author_reply(
+    status: u8, server_msg: &[u8], data: &[u8], args: &[&[u8]]
+) -> impl Recipe {
+    (
+        literal([status, args.len() as u8]),
+        be(server_msg.len() as u16), be(data.len() as u16),
+        literal(args.iter().map(|arg| arg.len() as u8).collect::<Vec<_>>()),
+        literal([server_msg, data, &args.concat()].concat()),
+    )
+}
+
+
+//------------ acct_request --------------------------------------------------
+
+/// Returns a recipe for the body of an accounting REQUEST packet.
+pub fn acct_request(flags: u8, request: AuthorRequest) -> impl Recipe {
+    (be(flags), request)
+}
+
+
+//------------ acct_reply ----------------------------------------------------
+
+/// Returns a recipe for the body of an accounting REPLY packet.
+pub fn acct_reply(status: u8, server_msg: &[u8], data: &[u8]) -> impl Recipe {
+    (
+        be(server_msg.len() as u16), be(data.len() as u16), be(status),
+        literal([server_msg, data].concat()),
+    )
+}
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::recipe::core::hex;
+
+    #[test]
+    fn packets() {
+        assert_eq!(
+            packet(
+                VERSION_DEFAULT, TYPE_ACCT, 2, FLAG_UNENCRYPTED, 0x01020304,
+                acct_reply(ACCT_STATUS_SUCCESS, b"ok", b"")
+            ).to_fragment(),
+            hex("c0 03 02 01 01020304 00000007 0002 0000 01 6f6b")
+                .to_fragment()
+        );
+        assert_eq!(
+            AuthorRequest {
+                user: b"u".to_vec(), args: vec![b"a=1".to_vec()],
+                ..Default::default()
+            }.to_fragment(),
+            b"\x00\x00\x00\x00\x01\x00\x00\x01\x03ua=1"
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "md-5")]
+    fn obfuscation() {
+        let body = AuthenStart {
+            action: AUTHEN_LOGIN, user: vec![b'x'; 30],
+            ..Default::default()
+        };
+        let obfuscated = obfuscate(
+            1, b"secret", VERSION_DEFAULT, 1, &body
+        ).to_fragment();
+        assert_eq!(obfuscated.len(), 38);
+        assert_ne!(obfuscated, body.to_fragment());
+        assert_eq!(
+            obfuscate(
+                1, b"secret", VERSION_DEFAULT, 1, literal(obfuscated)
+            ).to_fragment(),
+            body.to_fragment()
+        );
+    }
+}
@@ -0,0 +1,424 @@
+//! Time values.
+//!
+//! This module provides [`DateTime`], a broken-down UTC time that can be
+//! encoded as DER UTCTime and GeneralizedTime values via
+//! [`der::utc_time`] and [`der::generalized_time`] as well as UNIX and NTP
+//! timestamps via the recipes of this module.
+//!
+//! Unlike the types of the `chrono` crate, `DateTime` can represent
+//! values that are not valid times but that parsers still need to deal
+//! with, such as a leap second with a seconds value of 60, the year 0,
+//! or an explicit time zone offset. It also provides named constructors
+//! for the time values at which implementations tend to break: the 2038
+//! rollover of 32 bit UNIX time, the NTP era rollover, the switch from
+//! UTCTime to GeneralizedTime in X.509, and the extremes of four digit
+//! years.
+//!
+//! With the `chrono` feature, `DateTime` can be created from a chrono
+//! UTC time and the [`now`] and [`ntp_now`] recipes produce timestamps for
+//! the time they are assembled at.
+//!
+//! [`der::utc_time`]: super::der::utc_time
+//! [`der::generalized_time`]: super::der::generalized_time
+
+use std::io::Write;
+use super::core::{Fragment, Recipe, be};
+use super::der::TimeContent;
+
+
+//------------ Constants -----------------------------------------------------
+
+/// The number of seconds between the NTP epoch and the UNIX epoch.
+pub const NTP_UNIX_OFFSET: i64 = 2_208_988_800;
+
+
+//------------ DateTime ------------------------------------------------------
+
+/// A broken-down time.
+///
+/// None of the fields are checked, so the value can be any combination,
+/// valid or not.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct DateTime {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+
+    /// The offset from UTC in minutes.
+    ///
+    /// If this is `None`, the time is in UTC and written with a `Z`
+    /// suffix. Otherwise the offset is written as `+hhmm` or `-hhmm`
+    /// which is not allowed in DER.
+    pub offset: Option<i16>,
+}
+
+impl DateTime {
+    /// Creates a new UTC time from its components.
+    pub fn new(
+        year: u16, month: u8, day: u8, hour: u8, minute: u8, second: u8
+    ) -> Self {
+        DateTime { year, month, day, hour, minute, second, offset: None }
+    }
+
+    /// Sets the time zone offset in minutes.
+    ///
+    /// The time is kept as is, so the resulting value describes a
+    /// different point in time.
+    pub fn with_offset(mut self, minutes: i16) -> Self {
+        self.offset = Some(minutes);
+        self
+    }
+
+    /// Returns the number of seconds since the UNIX epoch.
+    ///
+    /// A leap second is counted as the first second of the following day,
+    /// the same way that POSIX time does.
+    pub fn unix_seconds(&self) -> i64 {
+        days_from_civil(
+            i64::from(self.year), i64::from(self.month), i64::from(self.day)
+        ) * 86400
+            + i64::from(self.hour) * 3600
+            + i64::from(self.minute) * 60
+            + i64::from(self.second)
+            - i64::from(self.offset.unwrap_or(0)) * 60
+    }
+
+    /// Returns the number of seconds since the NTP prime epoch.
+    ///
+    /// This value is not limited to a single era.
+    pub fn ntp_seconds(&self) -> i64 {
+        self.unix_seconds() + NTP_UNIX_OFFSET
+    }
+
+    /// Writes the time zone designator.
+    fn write_offset(&self, target: &mut Fragment) {
+        match self.offset {
+            None => target.push(b'Z'),
+            Some(offset) => {
+                write!(
+                    target, "{}{:02}{:02}",
+                    if offset < 0 { '-' } else { '+' },
+                    offset.unsigned_abs() / 60, offset.unsigned_abs() % 60
+                ).unwrap()
+            }
+        }
+    }
+}
+
+/// Named constructors for well-known edge cases.
+impl DateTime {
+    /// The UNIX epoch, 1970-01-01T00:00:00Z.
+    pub fn unix_epoch() -> Self {
+        Self::new(1970, 1, 1, 0, 0, 0)
+    }
+
+    /// The last second representable in signed 32 bit UNIX time.
+    ///
+    /// This is 2038-01-19T03:14:07Z.
+    pub fn y2038_last() -> Self {
+        Self::new(2038, 1, 19, 3, 14, 7)
+    }
+
+    /// The first second not representable in signed 32 bit UNIX time.
+    ///
+    /// This is 2038-01-19T03:14:08Z.
+    pub fn y2038_rollover() -> Self {
+        Self::new(2038, 1, 19, 3, 14, 8)
+    }
+
+    /// The NTP prime epoch, 1900-01-01T00:00:00Z.
+    pub fn ntp_epoch() -> Self {
+        Self::new(1900, 1, 1, 0, 0, 0)
+    }
+
+    /// The last second of NTP era 0.
+    ///
+    /// This is 2036-02-07T06:28:15Z.
+    pub fn ntp_era_last() -> Self {
+        Self::new(2036, 2, 7, 6, 28, 15)
+    }
+
+    /// The first second of NTP era 1.
+    ///
+    /// This is 2036-02-07T06:28:16Z. Its 32 bit NTP timestamp is zero.
+    pub fn ntp_era_rollover() -> Self {
+        Self::new(2036, 2, 7, 6, 28, 16)
+    }
+
+    /// The most recent leap second, 2016-12-31T23:59:60Z.
+    pub fn leap_second() -> Self {
+        Self::new(2016, 12, 31, 23, 59, 60)
+    }
+
+    /// The last time before the leap second at 2016-12-31T23:59:59Z.
+    pub fn before_leap_second() -> Self {
+        Self::new(2016, 12, 31, 23, 59, 59)
+    }
+
+    /// A leap day of a century year, 2000-02-29T00:00:00Z.
+    pub fn leap_day() -> Self {
+        Self::new(2000, 2, 29, 0, 0, 0)
+    }
+
+    /// The last time written as UTCTime in X.509, 2049-12-31T23:59:59Z.
+    pub fn utc_time_last() -> Self {
+        Self::new(2049, 12, 31, 23, 59, 59)
+    }
+
+    /// The first time written as GeneralizedTime in X.509.
+    ///
+    /// This is 2050-01-01T00:00:00Z.
+    pub fn generalized_time_first() -> Self {
+        Self::new(2050, 1, 1, 0, 0, 0)
+    }
+
+    /// The earliest time representable as UTCTime, 1950-01-01T00:00:00Z.
+    pub fn utc_time_first() -> Self {
+        Self::new(1950, 1, 1, 0, 0, 0)
+    }
+
+    /// The earliest time with a four digit year, 0000-01-01T00:00:00Z.
+    pub fn year_zero() -> Self {
+        Self::new(0, 1, 1, 0, 0, 0)
+    }
+
+    /// The latest time with a four digit year, 9999-12-31T23:59:59Z.
+    ///
+    /// RFC 5280 uses this value for certificates without a well-defined
+    /// expiration date.
+    pub fn year_max() -> Self {
+        Self::new(9999, 12, 31, 23, 59, 59)
+    }
+}
+
+#[cfg(feature = "chrono")]
+impl From<chrono::DateTime<chrono::offset::Utc>> for DateTime {
+    /// Converts a chrono time into a `DateTime`.
+    ///
+    /// Fractions of a second are dropped. A leap second becomes a seconds
+    /// value of 60.
+    fn from(time: chrono::DateTime<chrono::offset::Utc>) -> Self {
+        use chrono::{Datelike, Timelike};
+
+        DateTime::new(
+            time.year() as u16, time.month() as u8, time.day() as u8,
+            time.hour() as u8, time.minute() as u8,
+            time.second() as u8
+                + u8::from(time.nanosecond() >= 1_000_000_000)
+        )
+    }
+}
+
+impl TimeContent for DateTime {
+    /// Writes the time as UTCTime.
+    ///
+    /// Only the last two digits of the year are written, so years outside
+    /// of 1950 to 2049 silently map to a different year.
+    fn assemble_utc_time(&self, target: &mut Fragment) {
+        write!(
+            target, "{:02}{:02}{:02}{:02}{:02}{:02}",
+            self.year % 100, self.month, self.day,
+            self.hour, self.minute, self.second
+        ).unwrap();
+        self.write_offset(target);
+    }
+
+    fn assemble_generalized_time(&self, target: &mut Fragment) {
+        write!(
+            target, "{:04}{:02}{:02}{:02}{:02}{:02}",
+            self.year, self.month, self.day,
+            self.hour, self.minute, self.second
+        ).unwrap();
+        self.write_offset(target);
+    }
+}
+
+
+//------------ unix32 and unix64 ---------------------------------------------
+
+/// Returns a recipe for a signed 32 bit UNIX timestamp.
+///
+/// The value is truncated to 32 bits, so times past
+/// [`DateTime::y2038_last`] wrap around to negative values.
+pub fn unix32(time: DateTime) -> impl Recipe {
+    be(time.unix_seconds() as i32)
+}
+
+/// Returns a recipe for an unsigned 32 bit UNIX timestamp.
+///
+/// The value is truncated to 32 bits.
+pub fn unix32_unsigned(time: DateTime) -> impl Recipe {
+    be(time.unix_seconds() as u32)
+}
+
+/// Returns a recipe for a signed 64 bit UNIX timestamp.
+pub fn unix64(time: DateTime) -> impl Recipe {
+    be(time.unix_seconds())
+}
+
+
+//------------ ntp -----------------------------------------------------------
+
+/// Returns a recipe for a 64 bit NTP timestamp.
+///
+/// The timestamp consists of the seconds since the start of the NTP era
+/// the _time_ falls into and the given _fraction_ of a second in units of
+/// 2^-32 seconds.
+pub fn ntp(time: DateTime, fraction: u32) -> impl Recipe {
+    (be(time.ntp_seconds() as u32), be(fraction))
+}
+
+/// Returns a recipe for a 32 bit NTP short format value.
+///
+/// The value consists of the lower 16 bits of the seconds since the start
+/// of the NTP era and the upper 16 bits of the _fraction._
+pub fn ntp_short(time: DateTime, fraction: u32) -> impl Recipe {
+    (be(time.ntp_seconds() as u16), be((fraction >> 16) as u16))
+}
+
+
+//------------ now and ntp_now -----------------------------------------------
+
+/// Returns a recipe for the time it is assembled at.
+///
+/// Every time the recipe is assembled, it converts the current time into
+/// a [`DateTime`] and assembles whatever recipe _op_ returns for it,
+/// e.g., `now(unix64)` for a 64 bit UNIX timestamp or
+/// `now(der::generalized_time)`.
+#[cfg(feature = "chrono")]
+pub fn now<F, R>(op: F) -> Now<F>
+where F: Fn(DateTime) -> R, R: Recipe {
+    Now(op)
+}
+
+/// Returns a recipe for a 64 bit NTP timestamp of the assembly time.
+///
+/// Unlike [`now`], the timestamp includes the fraction of the current
+/// second.
+#[cfg(feature = "chrono")]
+pub fn ntp_now() -> impl Recipe {
+    use chrono::Timelike;
+
+    Now(|_| {
+        let time = chrono::Utc::now();
+        let nanos = u64::from(time.nanosecond() % 1_000_000_000);
+        ntp(time.into(), ((nanos << 32) / 1_000_000_000) as u32)
+    })
+}
+
+/// A recipe for the time it is assembled at.
+///
+/// Values of this type are created via [`now`] and [`ntp_now`].
+#[cfg(feature = "chrono")]
+pub struct Now<F>(F);
+
+#[cfg(feature = "chrono")]
+impl<F, R> Recipe for Now<F>
+where F: Fn(DateTime) -> R, R: Recipe {
+    fn assemble(&self, target: &mut Fragment) {
+        (self.0)(chrono::Utc::now().into()).assemble(target)
+    }
+}
+
+
+//------------ Helpers -------------------------------------------------------
+
+/// Returns the number of days since 1970-01-01 in the Gregorian calendar.
+///
+/// The month and day are not checked and simply extend into the
+/// neighbouring months or years.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let doy = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::recipe::der::{generalized_time, utc_time, x509_time};
+
+    #[test]
+    fn timestamps() {
+        assert_eq!(DateTime::unix_epoch().unix_seconds(), 0);
+        assert_eq!(
+            DateTime::y2038_last().unix_seconds(), i64::from(i32::MAX)
+        );
+        assert_eq!(
+            unix32(DateTime::y2038_rollover()).to_fragment(),
+            b"\x80\x00\x00\x00"
+        );
+        assert_eq!(DateTime::ntp_epoch().ntp_seconds(), 0);
+        assert_eq!(
+            DateTime::ntp_era_last().ntp_seconds(), i64::from(u32::MAX)
+        );
+        assert_eq!(
+            ntp(DateTime::ntp_era_rollover(), 1).to_fragment(),
+            b"\0\0\0\0\0\0\0\x01"
+        );
+        assert_eq!(
+            DateTime::leap_second().unix_seconds(),
+            DateTime::new(2017, 1, 1, 0, 0, 0).unix_seconds()
+        );
+        assert_eq!(DateTime::leap_day().unix_seconds(), 951782400);
+        assert_eq!(DateTime::year_zero().unix_seconds(), -62167219200);
+        assert_eq!(DateTime::year_max().unix_seconds(), 253402300799);
+        assert_eq!(
+            DateTime::unix_epoch().with_offset(60).unix_seconds(), -3600
+        );
+    }
+
+    #[test]
+    fn der_times() {
+        assert_eq!(
+            generalized_time(DateTime::leap_second()).to_fragment(),
+            b"\x18\x0f20161231235960Z"
+        );
+        assert_eq!(
+            utc_time(DateTime::utc_time_last().with_offset(-90))
+                .to_fragment(),
+            b"\x17\x11491231235959-0130"
+        );
+        assert_eq!(
+            generalized_time(DateTime::year_zero()).to_fragment(),
+            b"\x18\x0f00000101000000Z"
+        );
+        assert_eq!(
+            x509_time(DateTime::utc_time_last()).to_fragment(),
+            b"\x17\x0d491231235959Z"
+        );
+        assert_eq!(
+            x509_time(DateTime::generalized_time_first()).to_fragment(),
+            b"\x18\x0f20500101000000Z"
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "chrono")]
+    fn current_time() {
+        let leap = chrono::NaiveDate::from_ymd_opt(2016, 12, 31).unwrap()
+            .and_hms_milli_opt(23, 59, 59, 1500).unwrap();
+        assert_eq!(
+            DateTime::from(leap.and_utc()), DateTime::leap_second()
+        );
+
+        let before = chrono::Utc::now().timestamp();
+        let time = now(unix64).to_fragment();
+        let time = i64::from_be_bytes(time.as_slice().try_into().unwrap());
+        assert!(time >= before && time <= before + 1);
+
+        let time = ntp_now().to_fragment();
+        let secs = u32::from_be_bytes(time[..4].try_into().unwrap());
+        let before = (before + NTP_UNIX_OFFSET) % (1 << 32);
+        assert!((before..=before + 1).contains(&i64::from(secs)));
+    }
+}
@@ -0,0 +1,184 @@
+//! Encoding data using the TLS presentation language.
+//!
+//! This module provides recipes for the basic building blocks of TLS
+//! messages as defined in section 3 of RFC 8446 – mostly variable-length
+//! vectors – as well as for the framing of extensions, handshake messages
+//! and records.
+//!
+//! None of the recipes check that their content fits the length field.
+//! If it doesn’t, the length is silently truncated which is handy for
+//! creating broken messages. In strict mode, this is recorded as a
+//! problem.
+
+use super::core::{LengthPrefix, Recipe, Width, be, lp_string};
+
+
+//------------ Constants -----------------------------------------------------
+
+/// The content type of a change cipher spec record.
+pub const CONTENT_CHANGE_CIPHER_SPEC: u8 = 20;
+
+/// The content type of an alert record.
+pub const CONTENT_ALERT: u8 = 21;
+
+/// The content type of a handshake record.
+pub const CONTENT_HANDSHAKE: u8 = 22;
+
+/// The content type of an application data record.
+pub const CONTENT_APPLICATION_DATA: u8 = 23;
+
+/// The handshake type of a ClientHello message.
+pub const HANDSHAKE_CLIENT_HELLO: u8 = 1;
+
+/// The handshake type of a ServerHello message.
+pub const HANDSHAKE_SERVER_HELLO: u8 = 2;
+
+/// The handshake type of a Certificate message.
+pub const HANDSHAKE_CERTIFICATE: u8 = 11;
+
+/// The handshake type of a CertificateStatus message.
+pub const HANDSHAKE_CERTIFICATE_STATUS: u8 = 22;
+
+/// The protocol version value for TLS 1.0.
+pub const VERSION_TLS10: u16 = 0x0301;
+
+/// The protocol version value for TLS 1.2.
+pub const VERSION_TLS12: u16 = 0x0303;
+
+/// The protocol version value for TLS 1.3.
+pub const VERSION_TLS13: u16 = 0x0304;
+
+
+//------------ vec8, vec16, vec24 --------------------------------------------
+
+/// Returns a recipe for a vector with a one octet length.
+pub fn vec8<R: Recipe>(content: R) -> Vector<R> {
+    lp_string(Width::U8, content)
+}
+
+/// Returns a recipe for a vector with a two octet length.
+pub fn vec16<R: Recipe>(content: R) -> Vector<R> {
+    lp_string(Width::U16, content)
+}
+
+/// Returns a recipe for a vector with a three octet length.
+pub fn vec24<R: Recipe>(content: R) -> Vector<R> {
+    lp_string(Width::U24, content)
+}
+
+/// A variable-length vector.
+///
+/// The vector consists of the length of the content in network byte order
+/// followed by the content itself. This is a
+/// [length prefix][super::core::length_prefix] in big-endian byte order.
+pub type Vector<R> = LengthPrefix<R>;
+
+
+//------------ extension -----------------------------------------------------
+
+/// Returns a recipe for an extension.
+///
+/// The extension consists of the extension type followed by the extension
+/// data provided by _data_ as a vector with a two octet length.
+pub fn extension<R: Recipe>(extension_type: u16, data: R) -> impl Recipe {
+    (be(extension_type), vec16(data))
+}
+
+/// Returns a recipe for a list of extensions.
+///
+/// The recipe _items_ should produce a sequence of [`extension`]s.
+pub fn extensions<R: Recipe>(items: R) -> impl Recipe {
+    vec16(items)
+}
+
+
+//------------ handshake -----------------------------------------------------
+
+/// Returns a recipe for a handshake message.
+///
+/// The message consists of the message type followed by the body provided
+/// by _body_ as a vector with a three octet length.
+pub fn handshake<R: Recipe>(msg_type: u8, body: R) -> impl Recipe {
+    (be(msg_type), vec24(body))
+}
+
+
+//------------ record --------------------------------------------------------
+
+/// Returns a recipe for a plaintext record.
+///
+/// The record consists of the content type, the legacy record version, and
+/// the fragment provided by _fragment_ as a vector with a two octet length.
+pub fn record<R: Recipe>(
+    content_type: u8, version: u16, fragment: R
+) -> impl Recipe {
+    (be(content_type), be(version), vec16(fragment))
+}
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::recipe::core::{hex, literal, repeat};
+
+    #[test]
+    fn vectors() {
+        assert_eq!(vec8(hex("aabb")).to_fragment(), b"\x02\xaa\xbb");
+        assert_eq!(vec16(hex("aabb")).to_fragment(), b"\x00\x02\xaa\xbb");
+        assert_eq!(
+            vec24(hex("aabb")).to_fragment(), b"\x00\x00\x02\xaa\xbb"
+        );
+        let long = vec8(repeat(257, hex("00")));
+        assert_eq!(long.to_fragment()[0], 1);
+        assert_eq!(
+            long.to_strict_fragment().unwrap_err().problems(),
+            ["length 257 exceeds 1 octet length prefix"]
+        );
+        assert_eq!(
+            record(
+                CONTENT_HANDSHAKE, VERSION_TLS12,
+                handshake(HANDSHAKE_SERVER_HELLO, hex("ff"))
+            ).to_fragment(),
+            b"\x16\x03\x03\x00\x05\x02\x00\x00\x01\xff"
+        );
+    }
+
+    #[test]
+    fn broken_lengths() {
+        let long = vec16(literal(vec![0u8; 0x10002]));
+        assert_eq!(&long.to_fragment()[..2], b"\x00\x02");
+
+        // Only the innermost vector is broken, the outer lengths are right.
+        let record = record(
+            CONTENT_HANDSHAKE, VERSION_TLS13,
+            handshake(HANDSHAKE_CLIENT_HELLO, extensions(
+                extension(0, vec8(literal(vec![0u8; 256])))
+            ))
+        );
+        assert_eq!(
+            &record.to_fragment()[..15],
+            b"\x16\x03\x04\x01\x0b\x01\x00\x01\x07\x01\x05\x00\x00\x01\x01"
+        );
+        assert_eq!(vec24(literal(b"")).to_fragment(), b"\0\0\0");
+    }
+
+    #[test]
+    fn strict_lengths() {
+        assert_eq!(
+            vec16(repeat(0x10002, hex("00"))).to_strict_fragment()
+                .unwrap_err().problems(),
+            ["length 65538 exceeds 2 octet length prefix"]
+        );
+        assert_eq!(
+            record(
+                CONTENT_HANDSHAKE, VERSION_TLS13,
+                handshake(HANDSHAKE_CLIENT_HELLO, extensions(
+                    extension(0, vec8(repeat(256, hex("00"))))
+                ))
+            ).to_strict_fragment().unwrap_err().problems(),
+            ["length 256 exceeds 1 octet length prefix"]
+        );
+    }
+}
@@ -0,0 +1,55 @@
+//! Well-known DNS messages.
+
+use crate::recipe::core::{Literal, literal};
+
+
+//------------ Priming Queries -----------------------------------------------
+
+/// The root priming query as described in RFC 8109 without EDNS.
+///
+/// The query has a message ID of zero, no flags set, and asks for the NS
+/// records of the root zone in class IN.
+pub const ROOT_PRIMING_QUERY: Literal<&[u8]> = literal(
+    b"\x00\x00\x00\x00\x00\x01\x00\x00\x00\x00\x00\x00\x00\x00\x02\x00\
+      \x01"
+);
+
+/// The root priming query with an EDNS OPT record.
+///
+/// This is [`ROOT_PRIMING_QUERY`] with an OPT record advertising a UDP
+/// payload size of 1024 octets, as recommended by RFC 8109, and the DO
+/// bit set.
+pub const ROOT_PRIMING_QUERY_EDNS: Literal<&[u8]> = literal(
+    b"\x00\x00\x00\x00\x00\x01\x00\x00\x00\x00\x00\x01\x00\x00\x02\x00\
+      \x01\x00\x00\x29\x04\x00\x00\x00\x80\x00\x00\x00"
+);
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::recipe::Recipe;
+    use crate::recipe::core::hex;
+
+    #[test]
+    fn priming_queries() {
+        assert_eq!(
+            ROOT_PRIMING_QUERY.to_fragment(),
+            hex("0000 0000 0001 0000 0000 0000 00 0002 0001").to_fragment()
+        );
+
+        // The query with the ARCOUNT of one, followed by an OPT record
+        // for the root with a UDP payload size of 1024, extended RCODE
+        // and version zero, the DO bit, and no options.
+        let edns = ROOT_PRIMING_QUERY_EDNS.to_fragment();
+        assert_eq!(&edns[..10], &ROOT_PRIMING_QUERY.to_fragment()[..10]);
+        assert_eq!(&edns[10..12], b"\x00\x01");
+        assert_eq!(&edns[12..17], &ROOT_PRIMING_QUERY.to_fragment()[12..]);
+        assert_eq!(
+            edns[17..],
+            *hex("00 0029 0400 00 00 8000 0000").to_fragment()
+        );
+    }
+}
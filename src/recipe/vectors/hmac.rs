@@ -0,0 +1,116 @@
+//! HMAC test vectors from RFC 4231.
+//!
+//! Each test case provides the key, the data, and the resulting HMAC for
+//! SHA-256 and SHA-512. Only the test cases that use the full output and
+//! a key shorter than the block size are included.
+
+use crate::recipe::core::{Literal, literal};
+
+
+//------------ HmacVector ----------------------------------------------------
+
+/// A test case for HMAC.
+pub struct HmacVector {
+    /// The key.
+    pub key: Literal<&'static [u8]>,
+
+    /// The data to authenticate.
+    pub data: Literal<&'static [u8]>,
+
+    /// The HMAC-SHA-256 of the data.
+    pub sha256: Literal<&'static [u8]>,
+
+    /// The HMAC-SHA-512 of the data.
+    pub sha512: Literal<&'static [u8]>,
+}
+
+
+//------------ Test Cases ----------------------------------------------------
+
+/// Test case 1 from section 4.2 of RFC 4231.
+pub const TEST_CASE_1: HmacVector = HmacVector {
+    key: literal(
+        b"\x0b\x0b\x0b\x0b\x0b\x0b\x0b\x0b\x0b\x0b\x0b\x0b\x0b\x0b\x0b\x0b\
+          \x0b\x0b\x0b\x0b"
+    ),
+    data: literal(b"Hi There"),
+    sha256: literal(
+        b"\xb0\x34\x4c\x61\xd8\xdb\x38\x53\x5c\xa8\xaf\xce\xaf\x0b\xf1\x2b\
+          \x88\x1d\xc2\x00\xc9\x83\x3d\xa7\x26\xe9\x37\x6c\x2e\x32\xcf\xf7"
+    ),
+    sha512: literal(
+        b"\x87\xaa\x7c\xde\xa5\xef\x61\x9d\x4f\xf0\xb4\x24\x1a\x1d\x6c\xb0\
+          \x23\x79\xf4\xe2\xce\x4e\xc2\x78\x7a\xd0\xb3\x05\x45\xe1\x7c\xde\
+          \xda\xa8\x33\xb7\xd6\xb8\xa7\x02\x03\x8b\x27\x4e\xae\xa3\xf4\xe4\
+          \xbe\x9d\x91\x4e\xeb\x61\xf1\x70\x2e\x69\x6c\x20\x3a\x12\x68\x54"
+    ),
+};
+
+/// Test case 2 from section 4.3 of RFC 4231.
+pub const TEST_CASE_2: HmacVector = HmacVector {
+    key: literal(b"Jefe"),
+    data: literal(b"what do ya want for nothing?"),
+    sha256: literal(
+        b"\x5b\xdc\xc1\x46\xbf\x60\x75\x4e\x6a\x04\x24\x26\x08\x95\x75\xc7\
+          \x5a\x00\x3f\x08\x9d\x27\x39\x83\x9d\xec\x58\xb9\x64\xec\x38\x43"
+    ),
+    sha512: literal(
+        b"\x16\x4b\x7a\x7b\xfc\xf8\x19\xe2\xe3\x95\xfb\xe7\x3b\x56\xe0\xa3\
+          \x87\xbd\x64\x22\x2e\x83\x1f\xd6\x10\x27\x0c\xd7\xea\x25\x05\x54\
+          \x97\x58\xbf\x75\xc0\x5a\x99\x4a\x6d\x03\x4f\x65\xf8\xf0\xe6\xfd\
+          \xca\xea\xb1\xa3\x4d\x4a\x6b\x4b\x63\x6e\x07\x0a\x38\xbc\xe7\x37"
+    ),
+};
+
+/// Test case 3 from section 4.4 of RFC 4231.
+pub const TEST_CASE_3: HmacVector = HmacVector {
+    key: literal(
+        b"\xaa\xaa\xaa\xaa\xaa\xaa\xaa\xaa\xaa\xaa\xaa\xaa\xaa\xaa\xaa\xaa\
+          \xaa\xaa\xaa\xaa"
+    ),
+    data: literal(
+        b"\xdd\xdd\xdd\xdd\xdd\xdd\xdd\xdd\xdd\xdd\xdd\xdd\xdd\xdd\xdd\xdd\
+          \xdd\xdd\xdd\xdd\xdd\xdd\xdd\xdd\xdd\xdd\xdd\xdd\xdd\xdd\xdd\xdd\
+          \xdd\xdd\xdd\xdd\xdd\xdd\xdd\xdd\xdd\xdd\xdd\xdd\xdd\xdd\xdd\xdd\
+          \xdd\xdd"
+    ),
+    sha256: literal(
+        b"\x77\x3e\xa9\x1e\x36\x80\x0e\x46\x85\x4d\xb8\xeb\xd0\x91\x81\xa7\
+          \x29\x59\x09\x8b\x3e\xf8\xc1\x22\xd9\x63\x55\x14\xce\xd5\x65\xfe"
+    ),
+    sha512: literal(
+        b"\xfa\x73\xb0\x08\x9d\x56\xa2\x84\xef\xb0\xf0\x75\x6c\x89\x0b\xe9\
+          \xb1\xb5\xdb\xdd\x8e\xe8\x1a\x36\x55\xf8\x3e\x33\xb2\x27\x9d\x39\
+          \xbf\x3e\x84\x82\x79\xa7\x22\xc8\x06\xb4\x85\xa4\x7e\x67\xc8\x07\
+          \xb9\x46\xa3\x37\xbe\xe8\x94\x26\x74\x27\x88\x59\xe1\x32\x92\xfb"
+    ),
+};
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+#[cfg(feature = "sha2")]
+mod test {
+    use super::*;
+    use sha2::{Digest, Sha512};
+    use crate::recipe::Recipe;
+
+    #[test]
+    fn sha512() {
+        // HMAC as defined in RFC 2104 with the 128 octet block size of
+        // SHA-512.
+        for case in [TEST_CASE_1, TEST_CASE_2, TEST_CASE_3] {
+            let mut key = case.key.to_fragment().into_vec();
+            key.resize(128, 0);
+            let pad = |value: u8| -> Vec<u8> {
+                key.iter().map(|ch| ch ^ value).collect()
+            };
+            let inner = Sha512::new().chain_update(pad(0x36))
+                .chain_update(case.data.to_fragment()).finalize();
+            let outer = Sha512::new().chain_update(pad(0x5c))
+                .chain_update(inner).finalize();
+            assert_eq!(case.sha512.to_fragment(), outer.as_slice());
+        }
+    }
+}
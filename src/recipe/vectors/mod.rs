@@ -0,0 +1,101 @@
+//! Well-known test vectors.
+//!
+//! This module collects byte sequences that are published in standards or
+//! are otherwise well known as ready-made recipes, so tests can refer to
+//! them by name instead of pasting the bytes.
+//!
+//! The vectors are versioned via [`VERSION`]. Existing vectors never
+//! change their value within a version. If a vector has to be corrected,
+//! the version is increased.
+
+pub mod dns;
+pub mod hmac;
+pub mod pkcs1;
+pub mod tls;
+
+
+//------------ VERSION -------------------------------------------------------
+
+/// The version of the set of test vectors.
+pub const VERSION: u32 = 1;
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use crate::recipe::Recipe;
+    use crate::recipe::core::{be, literal};
+    use crate::recipe::{der, dns as wire, tls as record};
+    use super::*;
+
+    #[test]
+    fn dns() {
+        assert_eq!(
+            dns::ROOT_PRIMING_QUERY.to_fragment(),
+            (
+                (be(0u16), be(0u16), be(1u16), be(0u16), be(0u16), be(0u16)),
+                wire::Name::root(), be(wire::TYPE_NS), be(wire::CLASS_IN),
+            ).to_fragment()
+        );
+        assert!(dns::ROOT_PRIMING_QUERY_EDNS.to_fragment().starts_with(
+            &dns::ROOT_PRIMING_QUERY.to_fragment()[..10]
+        ));
+    }
+
+    /// Returns the DigestInfo prefix for a hash algorithm.
+    fn digest_info<R: Recipe>(oid: R, len: usize) -> Vec<u8> {
+        let mut res = der::sequence((
+            der::sequence((oid, der::null())),
+            der::octetstring(literal(vec![0u8; len])),
+        )).to_fragment().into_vec();
+        res.truncate(res.len() - len);
+        res
+    }
+
+    #[test]
+    fn pkcs1() {
+        assert_eq!(
+            pkcs1::DIGEST_INFO_SHA1.to_fragment(),
+            digest_info(der::oid([1, 3, 14, 3, 2, 26]), 20)
+        );
+        for (prefix, n, len) in [
+            (pkcs1::DIGEST_INFO_SHA256, 1, 32),
+            (pkcs1::DIGEST_INFO_SHA384, 2, 48),
+            (pkcs1::DIGEST_INFO_SHA512, 3, 64),
+        ] {
+            assert_eq!(
+                prefix.to_fragment(),
+                digest_info(der::oid([2, 16, 840, 1, 101, 3, 4, 2, n]), len)
+            );
+        }
+    }
+
+    #[test]
+    fn tls() {
+        assert_eq!(
+            tls::HANDSHAKE_FAILURE.to_fragment(),
+            record::record(21, record::VERSION_TLS12, be(0x0228u16))
+                .to_fragment()
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "sha2")]
+    fn hmac() {
+        use sha2::{Digest, Sha256};
+
+        for case in [hmac::TEST_CASE_1, hmac::TEST_CASE_2, hmac::TEST_CASE_3] {
+            let mut key = case.key.to_fragment().into_vec();
+            key.resize(64, 0);
+            let pad = |value: u8| -> Vec<u8> {
+                key.iter().map(|ch| ch ^ value).collect()
+            };
+            let inner = Sha256::new().chain_update(pad(0x36))
+                .chain_update(case.data.to_fragment()).finalize();
+            let outer = Sha256::new().chain_update(pad(0x5c))
+                .chain_update(inner).finalize();
+            assert_eq!(case.sha256.to_fragment(), outer.as_slice());
+        }
+    }
+}
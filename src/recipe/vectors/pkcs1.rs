@@ -0,0 +1,79 @@
+//! DigestInfo prefixes from PKCS #1 as defined in RFC 8017.
+//!
+//! The module only contains the DigestInfo prefixes. These are the DER
+//! encodings of the DigestInfo value up to the hash value itself as used
+//! by EMSA-PKCS1-v1_5. They are given in note 1 of section 9.2 of RFC
+//! 8017. Together with a hash, they form the encoded message that is
+//! padded and signed with RSASSA-PKCS1-v1_5. Complete example signatures
+//! are not included since RFC 8017 doesn’t contain any.
+
+use crate::recipe::core::{Literal, literal};
+
+
+//------------ DigestInfo Prefixes -------------------------------------------
+
+/// The DigestInfo prefix for SHA-1.
+pub const DIGEST_INFO_SHA1: Literal<&[u8]> = literal(
+    b"\x30\x21\x30\x09\x06\x05\x2b\x0e\x03\x02\x1a\x05\x00\x04\x14"
+);
+
+/// The DigestInfo prefix for SHA-256.
+pub const DIGEST_INFO_SHA256: Literal<&[u8]> = literal(
+    b"\x30\x31\x30\x0d\x06\x09\x60\x86\x48\x01\x65\x03\x04\x02\x01\x05\
+      \x00\x04\x20"
+);
+
+/// The DigestInfo prefix for SHA-384.
+pub const DIGEST_INFO_SHA384: Literal<&[u8]> = literal(
+    b"\x30\x41\x30\x0d\x06\x09\x60\x86\x48\x01\x65\x03\x04\x02\x02\x05\
+      \x00\x04\x30"
+);
+
+/// The DigestInfo prefix for SHA-512.
+pub const DIGEST_INFO_SHA512: Literal<&[u8]> = literal(
+    b"\x30\x51\x30\x0d\x06\x09\x60\x86\x48\x01\x65\x03\x04\x02\x03\x05\
+      \x00\x04\x40"
+);
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::recipe::Recipe;
+    use crate::recipe::core::hex;
+
+    #[test]
+    fn digest_info_prefixes() {
+        // The values as given in note 1 of section 9.2 of RFC 8017.
+        for (prefix, value, hash_len) in [
+            (
+                DIGEST_INFO_SHA1,
+                "30 21 30 09 06 05 2b 0e 03 02 1a 05 00 04 14", 20
+            ),
+            (
+                DIGEST_INFO_SHA256,
+                "30 31 30 0d 06 09 60 86 48 01 65 03 04 02 01 05 00 04 20",
+                32
+            ),
+            (
+                DIGEST_INFO_SHA384,
+                "30 41 30 0d 06 09 60 86 48 01 65 03 04 02 02 05 00 04 30",
+                48
+            ),
+            (
+                DIGEST_INFO_SHA512,
+                "30 51 30 0d 06 09 60 86 48 01 65 03 04 02 03 05 00 04 40",
+                64
+            ),
+        ] {
+            let prefix = prefix.to_fragment();
+            assert_eq!(prefix, hex(value).to_fragment());
+
+            // The outer length covers the rest of the prefix and the hash.
+            assert_eq!(usize::from(prefix[1]), prefix.len() - 2 + hash_len);
+            assert_eq!(usize::from(prefix[prefix.len() - 1]), hash_len);
+        }
+    }
+}
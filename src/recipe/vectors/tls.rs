@@ -0,0 +1,79 @@
+//! Standard TLS alerts.
+//!
+//! Each alert is a complete plaintext alert record with the legacy record
+//! version 0x0303. Apart from close_notify, all alerts have the fatal
+//! level as required by TLS 1.3.
+
+use crate::recipe::core::{Literal, literal};
+
+
+//------------ Alerts --------------------------------------------------------
+
+/// The close_notify alert record.
+pub const CLOSE_NOTIFY: Literal<&[u8]> = literal(
+    b"\x15\x03\x03\x00\x02\x01\x00"
+);
+
+/// The unexpected_message alert record.
+pub const UNEXPECTED_MESSAGE: Literal<&[u8]> = literal(
+    b"\x15\x03\x03\x00\x02\x02\x0a"
+);
+
+/// The bad_record_mac alert record.
+pub const BAD_RECORD_MAC: Literal<&[u8]> = literal(
+    b"\x15\x03\x03\x00\x02\x02\x14"
+);
+
+/// The handshake_failure alert record.
+pub const HANDSHAKE_FAILURE: Literal<&[u8]> = literal(
+    b"\x15\x03\x03\x00\x02\x02\x28"
+);
+
+/// The decode_error alert record.
+pub const DECODE_ERROR: Literal<&[u8]> = literal(
+    b"\x15\x03\x03\x00\x02\x02\x32"
+);
+
+/// The protocol_version alert record.
+pub const PROTOCOL_VERSION: Literal<&[u8]> = literal(
+    b"\x15\x03\x03\x00\x02\x02\x46"
+);
+
+/// The internal_error alert record.
+pub const INTERNAL_ERROR: Literal<&[u8]> = literal(
+    b"\x15\x03\x03\x00\x02\x02\x50"
+);
+
+/// The unrecognized_name alert record.
+pub const UNRECOGNIZED_NAME: Literal<&[u8]> = literal(
+    b"\x15\x03\x03\x00\x02\x02\x70"
+);
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::recipe::Recipe;
+
+    #[test]
+    fn alerts() {
+        // The alert descriptions from section 6 of RFC 8446.
+        for (alert, level, description) in [
+            (CLOSE_NOTIFY, 1, 0),
+            (UNEXPECTED_MESSAGE, 2, 10),
+            (BAD_RECORD_MAC, 2, 20),
+            (HANDSHAKE_FAILURE, 2, 40),
+            (DECODE_ERROR, 2, 50),
+            (PROTOCOL_VERSION, 2, 70),
+            (INTERNAL_ERROR, 2, 80),
+            (UNRECOGNIZED_NAME, 2, 112),
+        ] {
+            assert_eq!(
+                alert.to_fragment(),
+                [21, 3, 3, 0, 2, level, description]
+            );
+        }
+    }
+}
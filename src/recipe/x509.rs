@@ -0,0 +1,552 @@
+//! X.509 certificates that are almost right.
+//!
+//! Validators need to be checked against certificates that break exactly
+//! one rule of RFC 5280 or of the profile for resource certificates used
+//! by the RPKI defined in RFC 6487. This module provides a simple
+//! [`Certificate`] that keeps all its parts as separate fields, so they
+//! can be broken individually, and the [`almost_right`] corpus: a valid
+//! RPKI CA certificate and a family of variants each violating a single
+//! rule.
+//!
+//! Signatures are never calculated. The signature value is just a
+//! placeholder, so the corpus is intended for checking the structural
+//! validation of certificates.
+
+use super::core::{Fragment, Recipe, empty, iter, literal};
+use super::der;
+use super::set::RecipeSet;
+use super::time::DateTime;
+
+
+//------------ Certificate ---------------------------------------------------
+
+/// A certificate with all its parts kept separately.
+///
+/// Parts that are complete DER values are kept in their encoded form.
+#[derive(Clone, Debug)]
+pub struct Certificate {
+    /// The raw version value.
+    ///
+    /// The value is one less than the version, i.e., 2 for a version 3
+    /// certificate. If this is `None`, the field is omitted which means
+    /// version 1.
+    pub version: Option<u8>,
+
+    /// The content octets of the serial number.
+    pub serial: Vec<u8>,
+
+    /// The signature algorithm identifier inside the signed portion.
+    pub signature_algorithm: Vec<u8>,
+
+    /// The encoded issuer name.
+    pub issuer: Vec<u8>,
+
+    /// The start of the validity period.
+    pub not_before: DateTime,
+
+    /// The end of the validity period.
+    pub not_after: DateTime,
+
+    /// Write validity times always as GeneralizedTime?
+    ///
+    /// If this is `false`, times are written as required by RFC 5280:
+    /// as UTCTime before 2050 and as GeneralizedTime otherwise.
+    pub generalized_times: bool,
+
+    /// The encoded subject name.
+    pub subject: Vec<u8>,
+
+    /// The encoded subject public key info.
+    pub subject_public_key_info: Vec<u8>,
+
+    /// The extensions.
+    ///
+    /// If this is `None`, the extensions field is omitted.
+    pub extensions: Option<Vec<Extension>>,
+
+    /// The signature algorithm identifier of the outer signature.
+    pub outer_signature_algorithm: Vec<u8>,
+
+    /// The content of the signature bit string.
+    pub signature: Vec<u8>,
+}
+
+impl Certificate {
+    /// Creates a CA certificate following the RPKI profile.
+    ///
+    /// The certificate is issued by a parent CA, so it contains an
+    /// authority key identifier, authority information access, and CRL
+    /// distribution points. Its resources are inherited from the parent.
+    /// All extensions required for a CA certificate by section 4.8 of
+    /// RFC 6487 are present.
+    pub fn rpki_ca() -> Self {
+        Certificate {
+            version: Some(2),
+            serial: vec![0x01, 0x23, 0x45, 0x67],
+            signature_algorithm: sha256_with_rsa(),
+            issuer: name("parent"),
+            not_before: DateTime::new(2024, 1, 1, 0, 0, 0),
+            not_after: DateTime::new(2025, 1, 1, 0, 0, 0),
+            generalized_times: false,
+            subject: name("child"),
+            subject_public_key_info: subject_public_key_info(),
+            extensions: Some(vec![
+                Extension::basic_constraints(true),
+                Extension::subject_key_identifier([0x11; 20]),
+                Extension::authority_key_identifier([0x22; 20]),
+                Extension::key_usage(KEY_CERT_SIGN | CRL_SIGN),
+                Extension::crl_distribution_points(
+                    "rsync://rpki.example.net/repo/parent/parent.crl"
+                ),
+                Extension::authority_info_access(
+                    "rsync://rpki.example.net/repo/parent.cer"
+                ),
+                Extension::ca_subject_info_access(
+                    "rsync://rpki.example.net/repo/child/",
+                    "rsync://rpki.example.net/repo/child/child.mft"
+                ),
+                Extension::rpki_policy(),
+                Extension::ip_resources_inherit(),
+            ]),
+            outer_signature_algorithm: sha256_with_rsa(),
+            signature: vec![0x5a; 256],
+        }
+    }
+
+    /// Returns a mutable reference to the list of extensions.
+    ///
+    /// Adds an empty list if the extensions field is currently omitted.
+    pub fn extensions_mut(&mut self) -> &mut Vec<Extension> {
+        self.extensions.get_or_insert_with(Vec::new)
+    }
+
+    /// Returns the extension with the given OID if present.
+    ///
+    /// The _oid_ is the encoded OID value as produced by [`id_ce`] or
+    /// [`id_pe`].
+    pub fn extension_mut(&mut self, oid: &[u8]) -> Option<&mut Extension> {
+        self.extensions.as_mut()?.iter_mut().find(|ext| ext.oid == oid)
+    }
+
+    /// Removes all extensions with the given OID.
+    pub fn remove_extension(&mut self, oid: &[u8]) {
+        if let Some(extensions) = self.extensions.as_mut() {
+            extensions.retain(|ext| ext.oid != oid)
+        }
+    }
+
+    /// Returns a recipe for the time with the configured encoding.
+    fn time(&self, time: DateTime) -> impl Recipe {
+        (
+            iter(self.generalized_times.then(|| {
+                der::generalized_time(time)
+            })),
+            iter((!self.generalized_times).then(|| der::x509_time(time))),
+        )
+    }
+}
+
+impl Recipe for Certificate {
+    fn assemble(&self, target: &mut Fragment) {
+        der::sequence((
+            der::sequence((
+                (
+                    iter(self.version.map(|version| {
+                        der::explicit(0, der::integer(version))
+                    })),
+                    der::integer_slice(&self.serial),
+                ),
+                Raw(&self.signature_algorithm),
+                Raw(&self.issuer),
+                der::sequence((
+                    self.time(self.not_before),
+                    self.time(self.not_after),
+                )),
+                (Raw(&self.subject), Raw(&self.subject_public_key_info)),
+                iter(self.extensions.as_ref().map(|extensions| {
+                    der::explicit(3, der::sequence(List(extensions)))
+                })),
+            )),
+            Raw(&self.outer_signature_algorithm),
+            der::bitstring(0, Raw(&self.signature)),
+        )).assemble(target)
+    }
+}
+
+
+//------------ Extension -----------------------------------------------------
+
+/// A certificate extension.
+#[derive(Clone, Debug)]
+pub struct Extension {
+    /// The encoded OID of the extension.
+    pub oid: Vec<u8>,
+
+    /// Is the extension critical?
+    ///
+    /// As required by DER, the critical field is omitted if this is
+    /// `false`.
+    pub critical: bool,
+
+    /// The content of the octet string holding the extension value.
+    pub value: Vec<u8>,
+}
+
+impl Extension {
+    /// Creates an extension from its parts.
+    pub fn new(oid: Vec<u8>, critical: bool, value: impl Recipe) -> Self {
+        Extension { oid, critical, value: value.to_fragment().into_vec() }
+    }
+
+    /// Creates a critical basic constraints extension.
+    ///
+    /// If _ca_ is `false`, the cA field is omitted.
+    pub fn basic_constraints(ca: bool) -> Self {
+        Self::new(
+            id_ce(19), true,
+            der::sequence(iter(ca.then(|| der::boolean(true))))
+        )
+    }
+
+    /// Creates a non-critical subject key identifier extension.
+    pub fn subject_key_identifier(id: [u8; 20]) -> Self {
+        Self::new(id_ce(14), false, der::octetstring(literal(id)))
+    }
+
+    /// Creates a non-critical authority key identifier extension.
+    pub fn authority_key_identifier(id: [u8; 20]) -> Self {
+        Self::new(
+            id_ce(35), false,
+            der::sequence(
+                der::context(0, der::simple(literal(id)))
+            )
+        )
+    }
+
+    /// Creates a critical key usage extension.
+    ///
+    /// The _bits_ contain the first eight bits of the key usage in the
+    /// order of the bit string, i.e., digitalSignature is the most
+    /// significant bit. Trailing zero bits are omitted.
+    pub fn key_usage(bits: u8) -> Self {
+        let unused = if bits == 0 { 0 } else { bits.trailing_zeros() as u8 };
+        Self::new(
+            id_ce(15), true,
+            der::bitstring(
+                unused, iter((bits != 0).then(|| literal([bits])))
+            )
+        )
+    }
+
+    /// Creates a non-critical CRL distribution points extension.
+    ///
+    /// The extension contains a single distribution point with _uri_ as
+    /// its full name.
+    pub fn crl_distribution_points(uri: &str) -> Self {
+        Self::new(
+            id_ce(31), false,
+            der::sequence(der::sequence(
+                der::explicit(0, der::explicit(0, uri_name(uri)))
+            ))
+        )
+    }
+
+    /// Creates a non-critical authority information access extension.
+    ///
+    /// The extension points to the issuer’s certificate at _uri._
+    pub fn authority_info_access(uri: &str) -> Self {
+        Self::new(
+            id_pe(1), false,
+            der::sequence(access_description(AD_CA_ISSUERS, uri))
+        )
+    }
+
+    /// Creates a non-critical subject information access extension.
+    ///
+    /// The extension points to the _repository_ and _manifest_ of a CA.
+    pub fn ca_subject_info_access(repository: &str, manifest: &str) -> Self {
+        Self::new(
+            id_pe(11), false,
+            der::sequence((
+                access_description(AD_CA_REPOSITORY, repository),
+                access_description(AD_RPKI_MANIFEST, manifest),
+            ))
+        )
+    }
+
+    /// Creates a critical certificate policies extension for the RPKI.
+    pub fn rpki_policy() -> Self {
+        Self::new(
+            id_ce(32), true,
+            der::sequence(der::sequence(
+                der::oid([1, 3, 6, 1, 5, 5, 7, 14, 2])
+            ))
+        )
+    }
+
+    /// Creates a critical IP resources extension inheriting IPv4.
+    pub fn ip_resources_inherit() -> Self {
+        Self::new(
+            id_pe(7), true,
+            der::sequence(der::sequence((
+                der::octetstring(literal([0, 1])),
+                der::null(),
+            )))
+        )
+    }
+}
+
+impl Recipe for Extension {
+    fn assemble(&self, target: &mut Fragment) {
+        der::sequence((
+            Raw(&self.oid),
+            iter(self.critical.then(|| der::boolean(true))),
+            der::octetstring(Raw(&self.value)),
+        )).assemble(target)
+    }
+}
+
+
+//------------ Key Usage Bits ------------------------------------------------
+
+/// The digitalSignature bit of the key usage extension.
+pub const DIGITAL_SIGNATURE: u8 = 0x80;
+
+/// The keyCertSign bit of the key usage extension.
+pub const KEY_CERT_SIGN: u8 = 0x04;
+
+/// The cRLSign bit of the key usage extension.
+pub const CRL_SIGN: u8 = 0x02;
+
+
+//------------ id_ce and id_pe -----------------------------------------------
+
+/// Returns the encoded OID of the certificate extension with number _n._
+///
+/// These are the extensions defined in RFC 5280 under the id-ce arc.
+pub fn id_ce(n: u128) -> Vec<u8> {
+    der::oid([2, 5, 29, n]).to_fragment().into_vec()
+}
+
+/// Returns the encoded OID of the PKIX extension with number _n._
+///
+/// These are the extensions under the id-pe arc, such as the resource
+/// extensions of RFC 3779.
+pub fn id_pe(n: u128) -> Vec<u8> {
+    der::oid([1, 3, 6, 1, 5, 5, 7, 1, n]).to_fragment().into_vec()
+}
+
+
+//------------ almost_right --------------------------------------------------
+
+/// Returns the corpus of almost right certificates.
+///
+/// The baseline of the set is [`Certificate::rpki_ca`]. Each variant is
+/// named after the single rule it violates.
+pub fn almost_right() -> RecipeSet {
+    let base = Certificate::rpki_ca();
+    let mut res = RecipeSet::new().baseline(base.clone());
+    let mut variant = |name: &str, op: &dyn Fn(&mut Certificate)| {
+        let mut cert = base.clone();
+        op(&mut cert);
+        res.insert(name, cert);
+    };
+
+    variant("version-1", &|cert| cert.version = None);
+    variant("version-2", &|cert| cert.version = Some(1));
+    variant("serial-zero", &|cert| cert.serial = vec![0]);
+    variant("serial-negative", &|cert| cert.serial = vec![0x80, 0x01]);
+    variant("serial-too-long", &|cert| cert.serial = vec![0x01; 21]);
+    variant("signature-algorithm-mismatch", &|cert| {
+        cert.outer_signature_algorithm = algorithm(12)
+    });
+    variant("validity-reversed", &|cert| {
+        std::mem::swap(&mut cert.not_before, &mut cert.not_after)
+    });
+    variant("generalized-time-before-2050", &|cert| {
+        cert.generalized_times = true
+    });
+    variant("empty-issuer", &|cert| {
+        cert.issuer = der::sequence(empty()).to_fragment().into_vec()
+    });
+    variant("extensions-missing", &|cert| cert.extensions = None);
+    variant("extensions-empty", &|cert| cert.extensions = Some(Vec::new()));
+    variant("duplicate-extension", &|cert| {
+        cert.extensions_mut().push(Extension::basic_constraints(true))
+    });
+    variant("unknown-critical-extension", &|cert| {
+        cert.extensions_mut().push(Extension::new(
+            der::oid([1, 3, 6, 1, 4, 1, 99999, 1]).to_fragment().into_vec(),
+            true, der::null()
+        ))
+    });
+    variant("basic-constraints-not-critical", &|cert| {
+        if let Some(ext) = cert.extension_mut(&id_ce(19)) {
+            ext.critical = false
+        }
+    });
+    variant("basic-constraints-not-ca", &|cert| {
+        if let Some(ext) = cert.extension_mut(&id_ce(19)) {
+            *ext = Extension::basic_constraints(false)
+        }
+    });
+    variant("missing-ski", &|cert| cert.remove_extension(&id_ce(14)));
+    variant("missing-aki", &|cert| cert.remove_extension(&id_ce(35)));
+    variant("key-usage-not-critical", &|cert| {
+        if let Some(ext) = cert.extension_mut(&id_ce(15)) {
+            ext.critical = false
+        }
+    });
+    variant("key-usage-missing-cert-sign", &|cert| {
+        if let Some(ext) = cert.extension_mut(&id_ce(15)) {
+            *ext = Extension::key_usage(CRL_SIGN)
+        }
+    });
+    variant("key-usage-digital-signature", &|cert| {
+        if let Some(ext) = cert.extension_mut(&id_ce(15)) {
+            *ext = Extension::key_usage(
+                DIGITAL_SIGNATURE | KEY_CERT_SIGN | CRL_SIGN
+            )
+        }
+    });
+    variant("missing-crldp", &|cert| cert.remove_extension(&id_ce(31)));
+    variant("missing-aia", &|cert| cert.remove_extension(&id_pe(1)));
+    variant("missing-sia", &|cert| cert.remove_extension(&id_pe(11)));
+    variant("missing-policies", &|cert| cert.remove_extension(&id_ce(32)));
+    variant("missing-resources", &|cert| cert.remove_extension(&id_pe(7)));
+    res
+}
+
+
+//------------ Helpers -------------------------------------------------------
+
+/// Encoded data to be included as is.
+struct Raw<'a>(&'a [u8]);
+
+impl Recipe for Raw<'_> {
+    fn assemble(&self, target: &mut Fragment) {
+        target.extend_from_slice(self.0)
+    }
+}
+
+/// A list of extensions.
+struct List<'a>(&'a [Extension]);
+
+impl Recipe for List<'_> {
+    fn assemble(&self, target: &mut Fragment) {
+        self.0.iter().for_each(|ext| ext.assemble(target))
+    }
+}
+
+/// The id-ad-caIssuers access method.
+const AD_CA_ISSUERS: u128 = 2;
+
+/// The id-ad-caRepository access method.
+const AD_CA_REPOSITORY: u128 = 5;
+
+/// The id-ad-rpkiManifest access method.
+const AD_RPKI_MANIFEST: u128 = 10;
+
+/// Returns an access description for an access method under id-ad.
+fn access_description(method: u128, uri: &str) -> impl Recipe {
+    der::sequence((
+        der::oid([1, 3, 6, 1, 5, 5, 7, 48, method]),
+        uri_name(uri),
+    ))
+}
+
+/// Returns a general name holding a URI.
+fn uri_name(uri: &str) -> impl Recipe {
+    der::context(6, der::simple(literal(String::from(uri))))
+}
+
+/// Returns an encoded PKCS #1 algorithm identifier with NULL parameters.
+fn algorithm(n: u128) -> Vec<u8> {
+    der::sequence((
+        der::oid([1, 2, 840, 113549, 1, 1, n]),
+        der::null(),
+    )).to_fragment().into_vec()
+}
+
+/// Returns the encoded sha256WithRSAEncryption algorithm identifier.
+fn sha256_with_rsa() -> Vec<u8> {
+    algorithm(11)
+}
+
+/// Returns an encoded name with only a common name.
+fn name(common_name: &'static str) -> Vec<u8> {
+    der::sequence(der::set(der::sequence((
+        der::oid([2, 5, 4, 3]),
+        der::printable_string(literal(common_name)),
+    )))).to_fragment().into_vec()
+}
+
+/// Returns an encoded RSA public key with a placeholder modulus.
+fn subject_public_key_info() -> Vec<u8> {
+    der::sequence((
+        der::sequence((der::oid([1, 2, 840, 113549, 1, 1, 1]), der::null())),
+        der::bitstring(0, der::sequence((
+            der::integer(vec![0x45; 256]),
+            der::integer(65537u32),
+        ))),
+    )).to_fragment().into_vec()
+}
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn almost_right_corpus() {
+        let set = almost_right();
+        assert_eq!(set.names().next(), Some(RecipeSet::BASELINE));
+        let all = set.assemble_all();
+        for (name, data) in &all[1..] {
+            assert_ne!(data.as_slice(), all[0].1.as_slice(), "{}", name);
+        }
+        let base = &all[0].1;
+        assert_eq!(base[..2], [0x30, 0x82]);
+        assert_eq!(
+            usize::from(u16::from_be_bytes([base[2], base[3]])),
+            base.len() - 4
+        );
+    }
+
+    #[test]
+    fn complete_profile() {
+        let required = [
+            id_ce(19), id_ce(14), id_ce(35), id_ce(15), id_ce(31),
+            id_pe(1), id_pe(11), id_ce(32), id_pe(7),
+        ];
+        let contains = |data: &[u8], oid: &[u8]| {
+            data.windows(oid.len()).any(|window| window == oid)
+        };
+        let complete = |data: &[u8]| {
+            required.iter().all(|oid| contains(data, oid))
+        };
+        let set = almost_right();
+        let all = set.assemble_all();
+        assert!(complete(&all[0].1));
+        let mut missing = 0;
+        for (name, data) in &all[1..] {
+            if name.starts_with("missing-") || name.starts_with("extensions-")
+            {
+                assert!(!complete(data), "{}", name);
+                missing += 1;
+            }
+        }
+        assert_eq!(missing, 9);
+    }
+
+    #[test]
+    fn key_usage() {
+        assert_eq!(
+            Extension::key_usage(KEY_CERT_SIGN | CRL_SIGN).value,
+            b"\x03\x02\x01\x06"
+        );
+        assert_eq!(Extension::key_usage(0).value, b"\x03\x01\x00");
+    }
+}
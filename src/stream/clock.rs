@@ -0,0 +1,138 @@
+//! The source of the virtual time of streams and sockets.
+//!
+//! Timing rules and assertions of the stand-ins of the [stream][super]
+//! module measure time via a [`Clock`]. By default, a clock follows the
+//! real time. A manual clock only advances when told to, which allows
+//! checking timers and backoff strategies deterministically.
+
+use std::{fmt, thread};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+
+//------------ Clock ---------------------------------------------------------
+
+/// A clock providing the virtual time of a stream or socket.
+///
+/// The time is measured as the duration since the clock was created.
+/// Clones of a clock share the same time, so a test can keep a clone of
+/// a manual clock given to a stream and advance it as needed.
+#[derive(Clone)]
+pub struct Clock {
+    inner: Inner,
+}
+
+#[derive(Clone)]
+enum Inner {
+    /// The real time since the given instant.
+    Real(Instant),
+
+    /// A time that is advanced explicitly.
+    Manual(Arc<Mutex<Duration>>),
+}
+
+impl Clock {
+    /// Creates a clock following the real time.
+    pub fn real() -> Self {
+        Clock { inner: Inner::Real(Instant::now()) }
+    }
+
+    /// Creates a clock that only advances when told to.
+    ///
+    /// The clock starts at zero. It is advanced via
+    /// [`advance`][Self::advance] or by waiting via
+    /// [`sleep`][Self::sleep].
+    pub fn manual() -> Self {
+        Clock { inner: Inner::Manual(Default::default()) }
+    }
+
+    /// Returns whether this is a manual clock.
+    pub fn is_manual(&self) -> bool {
+        matches!(self.inner, Inner::Manual(_))
+    }
+
+    /// Returns the time since the clock was created.
+    pub fn now(&self) -> Duration {
+        match self.inner {
+            Inner::Real(started) => started.elapsed(),
+            Inner::Manual(ref now) => *now.lock().unwrap(),
+        }
+    }
+
+    /// Advances a manual clock by _duration._
+    ///
+    /// # Panics
+    ///
+    /// The method panics if the clock follows the real time.
+    pub fn advance(&self, duration: Duration) {
+        match self.inner {
+            Inner::Real(_) => panic!("cannot advance a real time clock"),
+            Inner::Manual(ref now) => *now.lock().unwrap() += duration,
+        }
+    }
+
+    /// Waits for _duration._
+    ///
+    /// A real time clock puts the current thread to sleep while a manual
+    /// clock is advanced right away.
+    pub fn sleep(&self, duration: Duration) {
+        match self.inner {
+            Inner::Real(_) => thread::sleep(duration),
+            Inner::Manual(_) => self.advance(duration),
+        }
+    }
+}
+
+impl Default for Clock {
+    fn default() -> Self {
+        Self::real()
+    }
+}
+
+impl fmt::Debug for Clock {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Clock")
+            .field("manual", &self.is_manual())
+            .field("now", &self.now())
+            .finish()
+    }
+}
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn manual() {
+        let clock = Clock::manual();
+        let other = clock.clone();
+        assert_eq!(clock.now(), Duration::ZERO);
+        other.advance(Duration::from_secs(2));
+        clock.sleep(Duration::from_secs(1));
+        assert_eq!(clock.now(), Duration::from_secs(3));
+        assert_eq!(other.now(), Duration::from_secs(3));
+        assert_eq!(
+            format!("{:?}", clock), "Clock { manual: true, now: 3s }"
+        );
+    }
+
+    #[test]
+    fn real() {
+        let clock = Clock::default();
+        assert!(!clock.is_manual());
+        let start = clock.now();
+        clock.sleep(Duration::from_millis(5));
+        assert!(clock.now() >= start + Duration::from_millis(5));
+        // Clones share the instant the clock started at.
+        assert!(clock.clone().now() >= Duration::from_millis(5));
+    }
+
+    #[test]
+    #[should_panic(expected = "cannot advance a real time clock")]
+    fn advance_real() {
+        Clock::real().advance(Duration::from_secs(1))
+    }
+}
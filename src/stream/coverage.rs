@@ -0,0 +1,245 @@
+//! Recording which rules a test run exercised.
+//!
+//! A large suite of scripts – particularly one expanded from a
+//! [`Matrix`][super::matrix::Matrix] – can easily contain scripts or
+//! parts of scripts that no test ever gets to because the implementation
+//! closes the connection early or a test forgets to drive the stream to
+//! the end. A [`Coverage`] collects how far each stream got once a test
+//! is done with it and keeps count of how often each rule of each script
+//! was completed. Its summary lists the rules that were never reached.
+//!
+//! Scripts are identified by a name chosen by the test, such as the name
+//! of the script file or the parameters of a matrix combination.
+
+use std::fmt;
+use std::collections::BTreeMap;
+use super::{AssertStream, FragmentRule};
+
+
+//------------ Coverage ------------------------------------------------------
+
+/// The rules exercised by a number of streams.
+#[derive(Clone, Debug, Default)]
+pub struct Coverage {
+    scripts: BTreeMap<String, ScriptCoverage>,
+}
+
+impl Coverage {
+    /// Creates a new, empty coverage record.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records the progress of a stream under the given script name.
+    ///
+    /// All rules the stream has completed are counted as exercised. Since
+    /// the close rules are never left, they count as soon as the stream
+    /// has reached them. If a stream has been recorded under the same name
+    /// before, the counts are added up. The number of rules is taken from
+    /// the longest stream recorded under the name.
+    pub fn record(
+        &mut self, name: impl Into<String>, stream: &AssertStream
+    ) {
+        let rules = &stream.rules.fragments;
+        let script = self.scripts.entry(name.into()).or_default();
+        if script.counts.len() < rules.len() {
+            script.counts.resize(rules.len(), 0);
+            script.kinds = rules.iter().map(FragmentRule::kind).collect();
+        }
+        script.runs += 1;
+        let done = match rules.get(stream.rule_index) {
+            Some(FragmentRule::SendClose) | Some(FragmentRule::RecvClose) => {
+                stream.rule_index + 1
+            }
+            _ => stream.rule_index.min(rules.len())
+        };
+        for count in &mut script.counts[..done] {
+            *count += 1
+        }
+    }
+
+    /// Returns the coverage of the script with the given name.
+    pub fn script(&self, name: &str) -> Option<&ScriptCoverage> {
+        self.scripts.get(name)
+    }
+
+    /// Returns an iterator over the names and coverage of all scripts.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &ScriptCoverage)> {
+        self.scripts.iter().map(|(name, script)| (name.as_str(), script))
+    }
+
+    /// Returns whether every rule of every script was exercised.
+    pub fn is_complete(&self) -> bool {
+        self.scripts.values().all(ScriptCoverage::is_complete)
+    }
+
+    /// Panics unless every rule of every script was exercised.
+    ///
+    /// The panic message contains the coverage summary.
+    pub fn assert_complete(&self) {
+        if !self.is_complete() {
+            panic!("incomplete rule coverage:\n{}", self)
+        }
+    }
+}
+
+impl fmt::Display for Coverage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let width = self.scripts.keys().map(String::len).max()
+            .unwrap_or(0);
+        for (name, script) in &self.scripts {
+            writeln!(
+                f, "{:width$}  {}/{} rules in {} runs",
+                name, script.exercised(), script.counts.len(), script.runs,
+                width = width
+            )?;
+            for idx in script.missed() {
+                writeln!(
+                    f, "{:width$}    rule {} ({}) not reached",
+                    "", idx, script.kinds[idx], width = width
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+
+//------------ ScriptCoverage ------------------------------------------------
+
+/// The rules exercised for a single script.
+#[derive(Clone, Debug, Default)]
+pub struct ScriptCoverage {
+    /// The number of streams recorded.
+    runs: usize,
+
+    /// The number of runs that completed each rule.
+    counts: Vec<usize>,
+
+    /// The kind of each rule.
+    kinds: Vec<&'static str>,
+}
+
+impl ScriptCoverage {
+    /// Returns the number of streams recorded for the script.
+    pub fn runs(&self) -> usize {
+        self.runs
+    }
+
+    /// Returns how often each rule was completed.
+    pub fn counts(&self) -> &[usize] {
+        &self.counts
+    }
+
+    /// Returns the number of rules completed at least once.
+    pub fn exercised(&self) -> usize {
+        self.counts.iter().filter(|count| **count > 0).count()
+    }
+
+    /// Returns the indexes of the rules that were never completed.
+    pub fn missed(&self) -> impl Iterator<Item = usize> + '_ {
+        self.counts.iter().enumerate().filter_map(|(idx, count)| {
+            (*count == 0).then_some(idx)
+        })
+    }
+
+    /// Returns whether all rules were completed at least once.
+    pub fn is_complete(&self) -> bool {
+        self.counts.iter().all(|count| *count > 0)
+    }
+}
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::{Read, Write};
+    use crate::stream::AssertRules;
+
+    fn stream() -> AssertStream {
+        AssertStream::new(AssertRules {
+            fragments: vec![
+                FragmentRule::SendAll(b"ping".to_vec()),
+                FragmentRule::Recv(b"pong".to_vec()),
+                FragmentRule::SendClose,
+            ]
+        })
+    }
+
+    #[test]
+    fn coverage() {
+        let mut coverage = Coverage::new();
+        let mut buf = [0u8; 4];
+
+        let mut sock = stream();
+        sock.write_all(b"ping").unwrap();
+        coverage.record("ping", &sock);
+        assert!(!coverage.is_complete());
+        assert_eq!(
+            coverage.to_string(),
+            "ping  1/3 rules in 1 runs\n\
+             \x20       rule 1 (Recv) not reached\n\
+             \x20       rule 2 (SendClose) not reached\n"
+        );
+
+        let mut sock = stream();
+        sock.write_all(b"ping").unwrap();
+        sock.read_exact(&mut buf).unwrap();
+        sock.shutdown().unwrap();
+        coverage.record("ping", &sock);
+        let script = coverage.script("ping").unwrap();
+        assert_eq!(script.runs(), 2);
+        assert_eq!(script.counts(), [2, 1, 1]);
+        coverage.assert_complete();
+    }
+
+    #[test]
+    fn untouched_and_longer_scripts() {
+        let mut coverage = Coverage::new();
+        coverage.record("a-long-name", &stream());
+        let script = coverage.script("a-long-name").unwrap();
+        assert_eq!(script.exercised(), 0);
+        assert_eq!(script.missed().collect::<Vec<_>>(), [0, 1, 2]);
+        assert!(coverage.script("missing").is_none());
+
+        // A longer script under the same name extends the counts.
+        let mut sock = AssertStream::new(AssertRules {
+            fragments: vec![
+                FragmentRule::SendAll(b"ping".to_vec()),
+                FragmentRule::Recv(b"pong".to_vec()),
+                FragmentRule::Recv(b"pong".to_vec()),
+                FragmentRule::RecvClose,
+            ]
+        });
+        sock.write_all(b"ping").unwrap();
+        coverage.record("a-long-name", &sock);
+        coverage.record("b", &stream());
+        let script = coverage.script("a-long-name").unwrap();
+        assert_eq!(script.counts(), [1, 0, 0, 0]);
+        assert_eq!(
+            coverage.iter().map(|(name, _)| name).collect::<Vec<_>>(),
+            ["a-long-name", "b"]
+        );
+        assert_eq!(
+            coverage.to_string(),
+            "a-long-name  1/4 rules in 2 runs\n\
+             \x20              rule 1 (Recv) not reached\n\
+             \x20              rule 2 (Recv) not reached\n\
+             \x20              rule 3 (RecvClose) not reached\n\
+             b            0/3 rules in 1 runs\n\
+             \x20              rule 0 (SendAll) not reached\n\
+             \x20              rule 1 (Recv) not reached\n\
+             \x20              rule 2 (SendClose) not reached\n"
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "incomplete rule coverage")]
+    fn assert_incomplete() {
+        let mut coverage = Coverage::new();
+        coverage.record("ping", &stream());
+        coverage.assert_complete()
+    }
+}
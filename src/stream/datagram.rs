@@ -0,0 +1,424 @@
+//! A scripted stand-in for datagram sockets.
+//!
+//! An [`AssertSocket`] is used in place of a UDP socket by a generic
+//! protocol implementation. As with [`AssertStream`][super::AssertStream],
+//! its rules are seen from the perspective of that implementation. Each
+//! datagram is exchanged with a single peer whose apparent address can
+//! change mid-conversation through a [`DatagramRule::Migrate`] rule. This
+//! allows testing how an implementation deals with connection migration
+//! as in QUIC or with a NAT rebinding the peer’s port.
+//!
+//! Retransmissions of a datagram can be checked via a
+//! [`DatagramRule::ExpectRetransmit`] rule against the socket’s
+//! [`Clock`]. A [`DatagramRule::Icmp`] rule reports a synthetic ICMP error
+//! for a datagram sent earlier.
+
+use std::{error, fmt, io};
+use std::net::SocketAddr;
+use std::time::Duration;
+use serde::{Deserialize, Serialize};
+use super::clock::Clock;
+
+
+//------------ AssertSocket --------------------------------------------------
+
+/// A datagram socket that sends and expects datagrams according to rules.
+#[derive(Clone, Debug)]
+pub struct AssertSocket {
+    /// The rules that drive this socket.
+    rules: Vec<DatagramRule>,
+
+    /// The index of the current rule.
+    rule_index: usize,
+
+    /// The current address of the peer.
+    peer: SocketAddr,
+
+    /// The clock providing the virtual time.
+    clock: Clock,
+
+    /// The virtual time the last datagram was sent at.
+    sent_at: Duration,
+
+    /// The interval before the last retransmission.
+    backoff: Option<Duration>,
+
+    /// The number of retransmissions seen under the current rule.
+    retransmits: usize,
+}
+
+impl AssertSocket {
+    /// Creates a new socket talking to a peer at the given address.
+    pub fn new(peer: SocketAddr, rules: Vec<DatagramRule>) -> Self {
+        AssertSocket {
+            rules,
+            rule_index: 0,
+            peer,
+            clock: Clock::real(),
+            sent_at: Duration::ZERO,
+            backoff: None,
+            retransmits: 0,
+        }
+    }
+
+    /// Creates a new socket from rules in RON format.
+    pub fn from_ron_str(
+        peer: SocketAddr, s: &str
+    ) -> Result<Self, ron::error::Error> {
+        ron::de::from_str(s).map(|rules| Self::new(peer, rules))
+    }
+
+    /// Sets the clock used to check retransmissions.
+    ///
+    /// By default, the socket uses the real time.
+    pub fn set_clock(&mut self, clock: Clock) {
+        self.clock = clock
+    }
+
+    /// Returns the clock used to check retransmissions.
+    pub fn clock(&self) -> &Clock {
+        &self.clock
+    }
+
+    /// Returns the current address of the peer.
+    pub fn peer_addr(&self) -> SocketAddr {
+        self.peer
+    }
+
+    /// Returns whether all rules have been processed.
+    pub fn is_finished(&self) -> bool {
+        self.rule_index >= self.rules.len()
+    }
+
+    /// Sends a datagram to the given address.
+    ///
+    /// The current rule must be a `Send(_)` rule with the same data or
+    /// an `ExpectRetransmit { .. }` rule with the data of the last
+    /// `Send(_)` rule. In either case, _addr_ must be the current address
+    /// of the peer. Otherwise, the socket will panic.
+    ///
+    /// If the current rule is an `Icmp(_)` rule, fails with its error
+    /// instead.
+    pub fn send_to(
+        &mut self, buf: &[u8], addr: SocketAddr
+    ) -> Result<usize, io::Error> {
+        self.migrate();
+        let data = match self.rules.get(self.rule_index) {
+            Some(DatagramRule::Send(ref data)) => data,
+            Some(DatagramRule::ExpectRetransmit { .. }) => {
+                match self.last_sent() {
+                    Some(data) => data,
+                    None => self.fail("no datagram to retransmit")
+                }
+            }
+            Some(DatagramRule::Icmp(err)) => {
+                let err = *err;
+                self.rule_index += 1;
+                return Err(err.into())
+            }
+            Some(_) => self.fail("expected recv"),
+            None => self.fail("no more datagram rules"),
+        };
+        if addr != self.peer {
+            self.fail(format_args!(
+                "datagram sent to {}, expected {}", addr, self.peer
+            ))
+        }
+        if buf != data.as_slice() {
+            self.fail("data mismatch")
+        }
+        let now = self.clock.now();
+        if let Some(DatagramRule::ExpectRetransmit {
+            times, min_backoff
+        }) = self.rules.get(self.rule_index) {
+            let (times, min_backoff) = (*times, *min_backoff);
+            let interval = now.saturating_sub(self.sent_at);
+            let expected = self.backoff.map_or(min_backoff, |backoff| {
+                backoff.max(min_backoff)
+            });
+            if interval < expected {
+                self.fail(format_args!(
+                    "retransmission {} after {:?}, expected at least {:?}",
+                    self.retransmits + 1, interval, expected
+                ))
+            }
+            self.backoff = Some(interval);
+            self.retransmits += 1;
+            if self.retransmits == times {
+                self.rule_index += 1;
+                self.retransmits = 0;
+            }
+        }
+        else {
+            self.backoff = None;
+            self.rule_index += 1;
+        }
+        self.sent_at = now;
+        Ok(buf.len())
+    }
+
+    /// Receives a datagram.
+    ///
+    /// If the current rule is a `Recv(_)` rule, returns its data and the
+    /// current address of the peer. If the datagram is longer than _buf,_
+    /// the rest of it is discarded as with a real socket. If the current
+    /// rule is a `Send(_)` or `ExpectRetransmit { .. }` rule, fails with
+    /// a would-block error. If it is an `Icmp(_)` rule, fails with its
+    /// error.
+    pub fn recv_from(
+        &mut self, buf: &mut [u8]
+    ) -> Result<(usize, SocketAddr), io::Error> {
+        self.migrate();
+        match self.rules.get(self.rule_index) {
+            Some(DatagramRule::Recv(ref data)) => {
+                let len = data.len().min(buf.len());
+                buf[..len].copy_from_slice(&data[..len]);
+                self.rule_index += 1;
+                Ok((len, self.peer))
+            }
+            Some(DatagramRule::Icmp(err)) => {
+                let err = *err;
+                self.rule_index += 1;
+                Err(err.into())
+            }
+            Some(_) => {
+                Err(io::Error::new(
+                    io::ErrorKind::WouldBlock,
+                    "expected send"
+                ))
+            }
+            None => self.fail("no more datagram rules"),
+        }
+    }
+
+    /// Processes all migrate rules at the current position.
+    ///
+    /// Also skips over retransmit rules that expect no retransmissions.
+    fn migrate(&mut self) {
+        loop {
+            match self.rules.get(self.rule_index) {
+                Some(DatagramRule::Migrate(addr)) => self.peer = *addr,
+                Some(DatagramRule::ExpectRetransmit { times: 0, .. }) => { }
+                _ => break
+            }
+            self.rule_index += 1;
+        }
+    }
+
+    /// Returns the data of the last `Send(_)` rule before the current one.
+    fn last_sent(&self) -> Option<&Vec<u8>> {
+        self.rules[..self.rule_index].iter().rev().find_map(|rule| {
+            match rule {
+                DatagramRule::Send(data) => Some(data),
+                _ => None
+            }
+        })
+    }
+
+    /// Fails an assertion.
+    fn fail(&self, msg: impl std::fmt::Display) -> ! {
+        panic!("{} in datagram rule {}", msg, self.rule_index)
+    }
+}
+
+
+//------------ DatagramRule --------------------------------------------------
+
+/// A rule for sending or receiving a datagram.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum DatagramRule {
+    /// A datagram should be sent to the peer.
+    ///
+    /// The datagram has to be sent to the current address of the peer.
+    Send(Vec<u8>),
+
+    /// A datagram is received from the peer.
+    ///
+    /// The datagram appears to originate from the current address of the
+    /// peer.
+    Recv(Vec<u8>),
+
+    /// The apparent address of the peer changes.
+    ///
+    /// All datagrams received afterwards originate from the new address
+    /// and all datagrams sent afterwards are expected to be sent to it.
+    Migrate(SocketAddr),
+
+    /// The last datagram should be retransmitted a number of times.
+    ///
+    /// The datagram has to have the data of the last `Send(_)` rule and
+    /// be sent _times_ times. The time between the first retransmission
+    /// and the datagram sent before must be at least _min_backoff._ Each
+    /// following retransmission has to wait at least as long as the one
+    /// before it. The times are taken from the socket’s [`Clock`].
+    ExpectRetransmit {
+        times: usize,
+        min_backoff: Duration,
+    },
+
+    /// An ICMP error is received for the last datagram sent.
+    ///
+    /// The next attempt to send or receive a datagram fails with the
+    /// error.
+    Icmp(IcmpError),
+}
+
+
+//------------ IcmpError -----------------------------------------------------
+
+/// A synthetic ICMP error.
+///
+/// The error is surfaced as an [`io::Error`] with the kind given for each
+/// variant and the value itself as the inner error. It can be accessed
+/// via `io::Error::get_ref` and downcasting.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub enum IcmpError {
+    /// The port of the peer is unreachable.
+    ///
+    /// This is surfaced as an error of kind `ConnectionRefused` as with
+    /// a connected UDP socket.
+    PortUnreachable,
+
+    /// The datagram was too big for a link on the path to the peer.
+    ///
+    /// The value is the MTU of the link reported by the ICMP message.
+    /// This is surfaced as an error of kind `Other`.
+    FragmentationNeeded(u16),
+}
+
+impl IcmpError {
+    /// Returns the kind of the I/O error the ICMP error is surfaced as.
+    pub fn kind(self) -> io::ErrorKind {
+        match self {
+            IcmpError::PortUnreachable => io::ErrorKind::ConnectionRefused,
+            IcmpError::FragmentationNeeded(_) => io::ErrorKind::Other,
+        }
+    }
+}
+
+impl From<IcmpError> for io::Error {
+    fn from(err: IcmpError) -> Self {
+        io::Error::new(err.kind(), err)
+    }
+}
+
+impl fmt::Display for IcmpError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            IcmpError::PortUnreachable => f.write_str("port unreachable"),
+            IcmpError::FragmentationNeeded(mtu) => {
+                write!(f, "fragmentation needed, MTU {}", mtu)
+            }
+        }
+    }
+}
+
+impl error::Error for IcmpError { }
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn migrate() {
+        let old: SocketAddr = "192.0.2.1:443".parse().unwrap();
+        let new: SocketAddr = "192.0.2.1:50000".parse().unwrap();
+        let mut sock = AssertSocket::from_ron_str(
+            old,
+            r#"[ Send([1]), Recv([2, 3]), Migrate("192.0.2.1:50000"),
+                 Recv([4]), Send([5]) ]"#
+        ).unwrap();
+        let mut buf = [0u8; 1];
+        assert_eq!(
+            sock.recv_from(&mut buf).unwrap_err().kind(),
+            io::ErrorKind::WouldBlock
+        );
+        assert_eq!(sock.send_to(&[1], old).unwrap(), 1);
+        assert_eq!(sock.recv_from(&mut buf).unwrap(), (1, old));
+        assert_eq!(sock.recv_from(&mut buf).unwrap(), (1, new));
+        assert_eq!(sock.peer_addr(), new);
+        assert_eq!(sock.send_to(&[5], new).unwrap(), 1);
+        assert!(sock.is_finished());
+    }
+
+    #[test]
+    #[should_panic(expected = "datagram sent to 192.0.2.1:443, expected")]
+    fn send_to_old_address() {
+        let old: SocketAddr = "192.0.2.1:443".parse().unwrap();
+        let mut sock = AssertSocket::new(old, vec![
+            DatagramRule::Migrate("192.0.2.2:443".parse().unwrap()),
+            DatagramRule::Send(vec![1]),
+        ]);
+        let _ = sock.send_to(&[1], old);
+    }
+
+    #[test]
+    fn retransmit() {
+        let peer: SocketAddr = "192.0.2.1:4433".parse().unwrap();
+        let mut sock = AssertSocket::from_ron_str(
+            peer,
+            r#"[ Send([1]),
+                 ExpectRetransmit(times: 3, min_backoff: (secs: 1, nanos: 0)),
+                 Recv([2]) ]"#
+        ).unwrap();
+        sock.set_clock(Clock::manual());
+        let clock = sock.clock().clone();
+        let mut buf = [0u8; 1];
+        sock.send_to(&[1], peer).unwrap();
+        for secs in [1, 2, 4] {
+            assert_eq!(
+                sock.recv_from(&mut buf).unwrap_err().kind(),
+                io::ErrorKind::WouldBlock
+            );
+            clock.advance(Duration::from_secs(secs));
+            sock.send_to(&[1], peer).unwrap();
+        }
+        assert_eq!(sock.recv_from(&mut buf).unwrap(), (1, peer));
+        assert!(sock.is_finished());
+    }
+
+    #[test]
+    #[should_panic(expected = "retransmission 2 after 1s, expected at least")]
+    fn retransmit_too_fast() {
+        let peer: SocketAddr = "192.0.2.1:4433".parse().unwrap();
+        let mut sock = AssertSocket::new(peer, vec![
+            DatagramRule::Send(vec![1]),
+            DatagramRule::ExpectRetransmit {
+                times: 2, min_backoff: Duration::from_secs(1)
+            },
+        ]);
+        sock.set_clock(Clock::manual());
+        sock.send_to(&[1], peer).unwrap();
+        sock.clock().advance(Duration::from_secs(2));
+        sock.send_to(&[1], peer).unwrap();
+        sock.clock().advance(Duration::from_secs(1));
+        let _ = sock.send_to(&[1], peer);
+    }
+
+    #[test]
+    fn icmp() {
+        let peer: SocketAddr = "192.0.2.1:53".parse().unwrap();
+        let mut sock = AssertSocket::from_ron_str(
+            peer,
+            r#"[ Send([1]), Icmp(PortUnreachable),
+                 Send([2]), Icmp(FragmentationNeeded(1280)), Send([3]) ]"#
+        ).unwrap();
+        let mut buf = [0u8; 1];
+        sock.send_to(&[1], peer).unwrap();
+        assert_eq!(
+            sock.recv_from(&mut buf).unwrap_err().kind(),
+            io::ErrorKind::ConnectionRefused
+        );
+        sock.send_to(&[2], peer).unwrap();
+        let err = sock.send_to(&[2], peer).unwrap_err();
+        assert_eq!(
+            err.get_ref().unwrap().downcast_ref::<IcmpError>(),
+            Some(&IcmpError::FragmentationNeeded(1280))
+        );
+        sock.send_to(&[3], peer).unwrap();
+        assert!(sock.is_finished());
+    }
+}
@@ -0,0 +1,359 @@
+//! Scripts for DNS conversations over UDP with fallback to TCP.
+//!
+//! This module provides [`DnsScript`], a helper for building the rules
+//! for the retry behaviour resolvers have to implement: DNS cookies as
+//! defined in RFC 7873, including server cookie rotation and the retry
+//! after a BADCOOKIE response, falling back to TCP after a truncated
+//! response as sent by servers when the response is too large or when
+//! response rate limiting (RRL) lets a response slip, and retransmitting
+//! after a response was dropped.
+//!
+//! The UDP part of the conversation is described by rules for an
+//! [`AssertSocket`][super::datagram::AssertSocket] while the TCP part is
+//! described by [`AssertRules`] for an
+//! [`AssertStream`][super::AssertStream]. The script can be created for
+//! testing either a client or a server.
+//!
+//! The templates take a recipe for the query and the response. These
+//! should produce complete messages without an OPT record. The script
+//! adds an OPT record with a COOKIE option where necessary and derives
+//! the BADCOOKIE and truncated responses from them. Messages on TCP are
+//! given their two octet length prefix.
+
+use super::{AssertRules, FragmentRule, Role};
+use super::datagram::DatagramRule;
+use crate::recipe::Recipe;
+
+
+//------------ Constants -----------------------------------------------------
+
+/// The extended response code for a bad server cookie.
+pub const RCODE_BADCOOKIE: u16 = 23;
+
+/// The option code of the COOKIE option.
+pub const OPTION_COOKIE: u16 = 10;
+
+/// The UDP payload size advertised in the OPT records.
+pub const UDP_PAYLOAD_SIZE: u16 = 1232;
+
+
+//------------ DnsScript -----------------------------------------------------
+
+/// A builder for the rules of a DNS conversation.
+///
+/// The builder is created via [`client`][Self::client] or
+/// [`server`][Self::server] depending on which side of the conversation
+/// is being tested. Steps are then added in order through the various
+/// methods. Finally, [`into_rules`][Self::into_rules] produces the rules
+/// for UDP and TCP.
+#[derive(Clone, Debug)]
+pub struct DnsScript {
+    /// The role of the implementation under test.
+    role: Role,
+
+    /// The rules for UDP produced so far.
+    udp: Vec<DatagramRule>,
+
+    /// The rules for TCP produced so far.
+    tcp: Vec<FragmentRule>,
+}
+
+impl DnsScript {
+    /// Creates a new script for testing a client.
+    pub fn client() -> Self {
+        Self::new(Role::Client)
+    }
+
+    /// Creates a new script for testing a server.
+    pub fn server() -> Self {
+        Self::new(Role::Server)
+    }
+
+    /// Creates a new script for the given role.
+    pub fn new(role: Role) -> Self {
+        DnsScript { role, udp: Vec::new(), tcp: Vec::new() }
+    }
+
+    /// Returns the rules for UDP and for TCP.
+    pub fn into_rules(self) -> (Vec<DatagramRule>, AssertRules) {
+        (self.udp, AssertRules { fragments: self.tcp })
+    }
+
+    /// Adds a message sent by the client over UDP.
+    pub fn udp_query(mut self, msg: impl Into<Vec<u8>>) -> Self {
+        let msg = msg.into();
+        self.udp.push(match self.role {
+            Role::Client => DatagramRule::Send(msg),
+            Role::Server => DatagramRule::Recv(msg),
+        });
+        self
+    }
+
+    /// Adds a message sent by the server over UDP.
+    pub fn udp_response(mut self, msg: impl Into<Vec<u8>>) -> Self {
+        let msg = msg.into();
+        self.udp.push(match self.role {
+            Role::Client => DatagramRule::Recv(msg),
+            Role::Server => DatagramRule::Send(msg),
+        });
+        self
+    }
+
+    /// Adds a message sent by the client over TCP.
+    pub fn tcp_query(mut self, msg: &[u8]) -> Self {
+        let msg = length_prefixed(msg);
+        self.tcp.push(match self.role {
+            Role::Client => FragmentRule::SendAll(msg),
+            Role::Server => FragmentRule::RecvAll(msg),
+        });
+        self
+    }
+
+    /// Adds a message sent by the server over TCP.
+    pub fn tcp_response(mut self, msg: &[u8]) -> Self {
+        let msg = length_prefixed(msg);
+        self.tcp.push(match self.role {
+            Role::Client => FragmentRule::RecvAll(msg),
+            Role::Server => FragmentRule::SendAll(msg),
+        });
+        self
+    }
+
+    /// Adds a query and its response over UDP.
+    pub fn exchange(
+        self, query: impl Recipe, response: impl Recipe
+    ) -> Self {
+        self.udp_query(query.to_fragment().into_vec())
+            .udp_response(response.to_fragment().into_vec())
+    }
+
+    /// Adds an exchange over UDP in which the server rotates its cookie.
+    ///
+    /// The client sends the query with its _client_ cookie and the server
+    /// cookie _old._ The server responds with the new server cookie _new._
+    /// The client then sends the query again with the new server cookie
+    /// which the server echoes in its response.
+    pub fn cookie_rotation(
+        self, query: impl Recipe, response: impl Recipe,
+        client: [u8; 8], old: &[u8], new: &[u8]
+    ) -> Self {
+        let query = query.to_fragment();
+        let response = response.to_fragment();
+        self.udp_query(with_cookie(&query, &client, old, 0))
+            .udp_response(with_cookie(&response, &client, new, 0))
+            .udp_query(with_cookie(&query, &client, new, 0))
+            .udp_response(with_cookie(&response, &client, new, 0))
+    }
+
+    /// Adds a query answered with BADCOOKIE and the client’s retry.
+    ///
+    /// The client sends the query with its _client_ cookie and the server
+    /// cookie _stale,_ which can be empty for a query with only a client
+    /// cookie. The server responds with a BADCOOKIE error that carries
+    /// the server cookie _server._ The client retries the query with this
+    /// server cookie and the server gives its normal response.
+    pub fn bad_cookie_retry(
+        self, query: impl Recipe, response: impl Recipe,
+        client: [u8; 8], stale: &[u8], server: &[u8]
+    ) -> Self {
+        let query = query.to_fragment();
+        let response = response.to_fragment();
+        let mut error = query.clone().into_vec();
+        if let Some(flags) = error.get_mut(2..4) {
+            flags[0] |= 0x80;
+            flags[1] = (flags[1] & 0xF0) | (RCODE_BADCOOKIE & 0x0F) as u8;
+        }
+        let error = with_cookie(
+            &error, &client, server, (RCODE_BADCOOKIE >> 4) as u8
+        );
+        self.udp_query(with_cookie(&query, &client, stale, 0))
+            .udp_response(error)
+            .udp_query(with_cookie(&query, &client, server, 0))
+            .udp_response(with_cookie(&response, &client, server, 0))
+    }
+
+    /// Adds a truncated response over UDP and the fallback to TCP.
+    ///
+    /// The client sends the query over UDP. The server responds with the
+    /// header and question section of the response with the TC flag set.
+    /// This is also what a server using RRL sends when letting a response
+    /// slip. The client then repeats the query over TCP where the server
+    /// gives the complete response.
+    pub fn tcp_fallback(
+        self, query: impl Recipe, response: impl Recipe
+    ) -> Self {
+        let query = query.to_fragment();
+        let response = response.to_fragment();
+        self.udp_query(query.as_slice())
+            .udp_response(truncated(&response))
+            .tcp_query(&query)
+            .tcp_response(&response)
+    }
+
+    /// Adds a query whose responses are dropped and the client’s retries.
+    ///
+    /// The client sends the query _drops_ times without a response, as
+    /// happens when a server using RRL drops responses, and once more
+    /// to finally receive the response. How long the client waits before
+    /// retrying isn’t checked.
+    pub fn dropped_retry(
+        mut self, query: impl Recipe, response: impl Recipe, drops: usize
+    ) -> Self {
+        let query = query.to_fragment();
+        for _ in 0..drops {
+            self = self.udp_query(query.as_slice());
+        }
+        self.udp_query(query.into_vec())
+            .udp_response(response.to_fragment().into_vec())
+    }
+}
+
+
+//------------ Helpers -------------------------------------------------------
+
+/// Returns a message with an OPT record carrying a COOKIE option added.
+///
+/// The record is appended to the additional section with the upper eight
+/// bits of the extended response code set to _ext_rcode._
+fn with_cookie(
+    msg: &[u8], client: &[u8], server: &[u8], ext_rcode: u8
+) -> Vec<u8> {
+    let mut res = msg.to_vec();
+    if let Some(arcount) = res.get_mut(10..12) {
+        let count = u16::from_be_bytes([arcount[0], arcount[1]]) + 1;
+        arcount.copy_from_slice(&count.to_be_bytes());
+    }
+    let option_len = (client.len() + server.len()) as u16;
+    res.push(0);
+    res.extend_from_slice(&41u16.to_be_bytes());
+    res.extend_from_slice(&UDP_PAYLOAD_SIZE.to_be_bytes());
+    res.extend_from_slice(&[ext_rcode, 0, 0, 0]);
+    res.extend_from_slice(&(option_len + 4).to_be_bytes());
+    res.extend_from_slice(&OPTION_COOKIE.to_be_bytes());
+    res.extend_from_slice(&option_len.to_be_bytes());
+    res.extend_from_slice(client);
+    res.extend_from_slice(server);
+    res
+}
+
+/// Returns the header and question section of a message with TC set.
+fn truncated(msg: &[u8]) -> Vec<u8> {
+    let mut res = msg[..question_end(msg)].to_vec();
+    if res.len() >= 12 {
+        res[2] |= 0x02;
+        res[6..12].fill(0);
+    }
+    res
+}
+
+/// Returns the end of the question section of a message.
+///
+/// If the message is too short, returns its length.
+fn question_end(msg: &[u8]) -> usize {
+    let count = match msg.get(4..6) {
+        Some(count) => u16::from_be_bytes([count[0], count[1]]),
+        None => return msg.len()
+    };
+    let mut pos = 12;
+    for _ in 0..count {
+        loop {
+            match msg.get(pos) {
+                Some(0) => {
+                    pos += 1;
+                    break
+                }
+                Some(len) if len & 0xC0 == 0xC0 => {
+                    pos += 2;
+                    break
+                }
+                Some(len) => pos += usize::from(*len) + 1,
+                None => return msg.len()
+            }
+        }
+        pos += 4;
+    }
+    pos.min(msg.len())
+}
+
+/// Returns a message with the two octet length prefix used on TCP.
+fn length_prefixed(msg: &[u8]) -> Vec<u8> {
+    let mut res = (msg.len() as u16).to_be_bytes().to_vec();
+    res.extend_from_slice(msg);
+    res
+}
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::{Read, Write};
+    use crate::recipe::core::hex;
+    use crate::stream::AssertStream;
+    use crate::stream::datagram::AssertSocket;
+
+    const QUERY: &str = "1234 0100 0001 0000 0000 0000 \
+                         076578616d706c6500 0001 0001";
+    const RESPONSE: &str = "1234 8180 0001 0001 0000 0000 \
+                            076578616d706c6500 0001 0001 \
+                            c00c 0001 0001 00000e10 0004 c0000201";
+
+    #[test]
+    fn cookies() {
+        let (udp, tcp) = DnsScript::client()
+            .bad_cookie_retry(
+                hex(QUERY), hex(RESPONSE), [1; 8], b"", &[2; 8]
+            )
+            .into_rules();
+        assert!(tcp.fragments.is_empty());
+        let mut sock = AssertSocket::new(
+            "192.0.2.1:53".parse().unwrap(), udp
+        );
+        let peer = sock.peer_addr();
+        let mut buf = [0u8; 512];
+
+        let query = hex(QUERY).to_fragment();
+        sock.send_to(
+            &with_cookie(&query, &[1; 8], b"", 0), peer
+        ).unwrap();
+        let (len, _) = sock.recv_from(&mut buf).unwrap();
+        assert_eq!(&buf[2..4], b"\x81\x07");
+        assert_eq!(&buf[10..12], b"\0\x01");
+        assert_eq!(
+            &buf[len - 31..len - 20], b"\0\0\x29\x04\xd0\x01\0\0\0\0\x14"
+        );
+        sock.send_to(
+            &with_cookie(&query, &[1; 8], &[2; 8], 0), peer
+        ).unwrap();
+        sock.recv_from(&mut buf).unwrap();
+        assert!(sock.is_finished());
+    }
+
+    #[test]
+    fn fallback() {
+        let (udp, tcp) = DnsScript::client()
+            .tcp_fallback(hex(QUERY), hex(RESPONSE))
+            .into_rules();
+        let response = hex(RESPONSE).to_fragment();
+        match udp[1] {
+            DatagramRule::Recv(ref data) => {
+                assert_eq!(
+                    hex(
+                        "1234 8380 0001 0000 0000 0000 \
+                         076578616d706c6500 0001 0001"
+                    ).to_fragment(),
+                    data
+                )
+            }
+            _ => panic!("expected recv rule")
+        }
+        let mut stream = AssertStream::new(tcp);
+        stream.write_all(b"\0\x19").unwrap();
+        stream.write_all(&hex(QUERY).to_fragment()).unwrap();
+        let mut buf = vec![0; response.len() + 2];
+        stream.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf[2..], response.as_slice());
+        assert!(stream.is_finished());
+    }
+}
@@ -0,0 +1,297 @@
+//! Expected outcomes of a conversation.
+//!
+//! Next to the rules for the conversation itself, a script can declare
+//! what the protocol implementation should have concluded from it, e.g.,
+//! the number of messages it should have parsed. These post-conditions are
+//! expressed as named values in [`Expectations`]. After running the
+//! conversation, the test collects the actual results under the same
+//! names and checks them against the expectations.
+
+use std::fmt;
+use std::collections::BTreeMap;
+use serde::{Deserialize, Serialize};
+
+
+//------------ Expectations --------------------------------------------------
+
+/// A set of named expected values.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(transparent)]
+pub struct Expectations {
+    values: BTreeMap<String, Value>,
+}
+
+impl Expectations {
+    /// Creates a new, empty set of expectations.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Adds an expected value.
+    pub fn insert(
+        &mut self, name: impl Into<String>, value: impl Into<Value>
+    ) {
+        self.values.insert(name.into(), value.into());
+    }
+
+    /// Returns the expected value with the given name if present.
+    pub fn get(&self, name: &str) -> Option<&Value> {
+        self.values.get(name)
+    }
+
+    /// Returns whether there are no expectations.
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// Checks the actual results against the expectations.
+    ///
+    /// The actual results are given as pairs of names and values. Each
+    /// expected value must be present among them with an equal value.
+    /// Actual values without an expectation are ignored.
+    ///
+    /// If any expectation isn’t met, returns an error listing all
+    /// mismatches.
+    pub fn check<'a>(
+        &self, actual: impl IntoIterator<Item = (&'a str, Value)>
+    ) -> Result<(), ExpectationError> {
+        let mut actual: BTreeMap<_, _> = actual.into_iter().collect();
+        let mut mismatches = Vec::new();
+        for (name, expected) in &self.values {
+            match actual.remove(name.as_str()) {
+                Some(value) if value == *expected => { }
+                value => {
+                    mismatches.push(Mismatch {
+                        name: name.clone(),
+                        expected: expected.clone(),
+                        actual: value,
+                    })
+                }
+            }
+        }
+        if mismatches.is_empty() {
+            Ok(())
+        }
+        else {
+            Err(ExpectationError { mismatches })
+        }
+    }
+
+    /// Checks the actual results and panics if they don’t match.
+    ///
+    /// See [`check`][Self::check] for details.
+    pub fn assert<'a>(
+        &self, actual: impl IntoIterator<Item = (&'a str, Value)>
+    ) {
+        if let Err(err) = self.check(actual) {
+            panic!("{}", err)
+        }
+    }
+}
+
+
+//------------ Value ---------------------------------------------------------
+
+/// An expected or actual value.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub enum Value {
+    Bool(bool),
+    Int(i128),
+    Str(String),
+    Bytes(Vec<u8>),
+}
+
+impl From<bool> for Value {
+    fn from(value: bool) -> Self {
+        Value::Bool(value)
+    }
+}
+
+macro_rules! value_from_int {
+    ( $( $type:ident ),* ) => {
+        $(
+            impl From<$type> for Value {
+                fn from(value: $type) -> Self {
+                    Value::Int(value.into())
+                }
+            }
+        )*
+    }
+}
+
+value_from_int!(u8, u16, u32, u64, i8, i16, i32, i64, i128);
+
+impl From<usize> for Value {
+    fn from(value: usize) -> Self {
+        Value::Int(value as i128)
+    }
+}
+
+impl<'a> From<&'a str> for Value {
+    fn from(value: &'a str) -> Self {
+        Value::Str(value.into())
+    }
+}
+
+impl From<String> for Value {
+    fn from(value: String) -> Self {
+        Value::Str(value)
+    }
+}
+
+impl<'a> From<&'a [u8]> for Value {
+    fn from(value: &'a [u8]) -> Self {
+        Value::Bytes(value.into())
+    }
+}
+
+impl From<Vec<u8>> for Value {
+    fn from(value: Vec<u8>) -> Self {
+        Value::Bytes(value)
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::Bool(value) => write!(f, "{}", value),
+            Value::Int(value) => write!(f, "{}", value),
+            Value::Str(value) => write!(f, "{:?}", value),
+            Value::Bytes(value) => {
+                for octet in value {
+                    write!(f, "{:02x}", octet)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+
+//------------ ExpectationError ----------------------------------------------
+
+/// The actual results didn’t meet the expectations.
+#[derive(Clone, Debug)]
+pub struct ExpectationError {
+    mismatches: Vec<Mismatch>,
+}
+
+/// A single expectation that wasn’t met.
+#[derive(Clone, Debug)]
+pub struct Mismatch {
+    /// The name of the value.
+    pub name: String,
+
+    /// The expected value.
+    pub expected: Value,
+
+    /// The actual value or `None` if it wasn’t provided.
+    pub actual: Option<Value>,
+}
+
+impl ExpectationError {
+    /// Returns the individual mismatches.
+    pub fn mismatches(&self) -> &[Mismatch] {
+        &self.mismatches
+    }
+}
+
+impl fmt::Display for ExpectationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{} expectation(s) not met:", self.mismatches.len())?;
+        for item in &self.mismatches {
+            match item.actual {
+                Some(ref actual) => writeln!(
+                    f, "  {}: expected {}, got {}",
+                    item.name, item.expected, actual
+                )?,
+                None => writeln!(
+                    f, "  {}: expected {}, got nothing",
+                    item.name, item.expected
+                )?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ExpectationError { }
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn check() {
+        let mut expected = Expectations::new();
+        expected.insert("count", 3u8);
+        expected.insert("name", "foo");
+        assert!(
+            expected.check([
+                ("count", 3usize.into()), ("name", "foo".into()),
+                ("other", true.into()),
+            ]).is_ok()
+        );
+        let err = expected.check([("count", 4u32.into())]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "2 expectation(s) not met:\n  \
+             count: expected 3, got 4\n  \
+             name: expected \"foo\", got nothing\n"
+        );
+        assert_eq!(err.mismatches().len(), 2);
+        assert_eq!(err.mismatches()[0].actual, Some(Value::Int(4)));
+    }
+
+    #[test]
+    fn values() {
+        let mut expected = Expectations::new();
+        assert!(expected.is_empty());
+        assert!(expected.check([("any", true.into())]).is_ok());
+        expected.insert("data", b"\x01\xab".as_ref());
+        expected.insert("flag", false);
+        expected.insert("flag", true);
+        assert_eq!(expected.get("flag"), Some(&Value::Bool(true)));
+        assert_eq!(expected.get("missing"), None);
+
+        // Values of a different kind never match.
+        let err = expected.check([
+            ("data", "\x01\u{ab}".into()), ("flag", 1u8.into()),
+        ]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "2 expectation(s) not met:\n  \
+             data: expected 01ab, got \"\\u{1}\u{ab}\"\n  \
+             flag: expected true, got 1\n"
+        );
+
+        // Later values for the same name win.
+        assert!(expected.check([
+            ("flag", false.into()), ("flag", true.into()),
+            ("data", vec![1u8, 0xab].into()),
+        ]).is_ok());
+    }
+
+    #[test]
+    fn serialize() {
+        let mut expected = Expectations::new();
+        expected.insert("count", -1i8);
+        expected.insert("name", String::from("a"));
+        let text = ron::to_string(&expected).unwrap();
+        assert_eq!(text, r#"{"count":Int(-1),"name":Str("a")}"#);
+        assert_eq!(
+            ron::from_str::<Expectations>(&text).unwrap(), expected
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "count: expected 1, got nothing")]
+    fn assert_missing() {
+        let mut expected = Expectations::new();
+        expected.insert("count", 1usize);
+        expected.assert([])
+    }
+}
@@ -0,0 +1,142 @@
+//! Extracting fields from received requests.
+//!
+//! When a response depends on the request – most commonly because it has
+//! to echo an identifier back – the request needs to be parsed at least a
+//! little bit. This module provides small extractors for the fields that
+//! are needed most often, so tests don’t each have to write their own
+//! parser. All extractors return `None` if the data is too short.
+
+
+//------------ length_prefix16 and length_prefix32 ---------------------------
+
+/// Returns the message after a two octet length prefix.
+///
+/// This is the framing used, e.g., by DNS over TCP. Returns `None` if
+/// the data doesn’t contain the complete message.
+pub fn length_prefix16(data: &[u8]) -> Option<&[u8]> {
+    let len = u16::from_be_bytes(data.get(..2)?.try_into().ok()?);
+    data.get(2..2 + usize::from(len))
+}
+
+/// Returns the message after a four octet length prefix.
+///
+/// Returns `None` if the data doesn’t contain the complete message.
+pub fn length_prefix32(data: &[u8]) -> Option<&[u8]> {
+    let len = u32::from_be_bytes(data.get(..4)?.try_into().ok()?);
+    data.get(4..4usize.checked_add(usize::try_from(len).ok()?)?)
+}
+
+
+//------------ dns_id --------------------------------------------------------
+
+/// Returns the message ID of a DNS message.
+///
+/// For DNS over TCP, the length prefix needs to be removed first, e.g.,
+/// via [`length_prefix16`].
+pub fn dns_id(msg: &[u8]) -> Option<u16> {
+    Some(u16::from_be_bytes(msg.get(..2)?.try_into().ok()?))
+}
+
+
+//------------ tls_record_header ---------------------------------------------
+
+/// The header of a TLS record.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct RecordHeader {
+    /// The content type of the record.
+    pub content_type: u8,
+
+    /// The legacy record version.
+    pub version: u16,
+
+    /// The length of the record’s fragment.
+    pub length: u16,
+}
+
+/// Returns the header of the TLS record at the start of the data.
+pub fn tls_record_header(data: &[u8]) -> Option<RecordHeader> {
+    let header = data.get(..5)?;
+    Some(RecordHeader {
+        content_type: header[0],
+        version: u16::from_be_bytes([header[1], header[2]]),
+        length: u16::from_be_bytes([header[3], header[4]]),
+    })
+}
+
+
+//------------ rtr_session_id ------------------------------------------------
+
+/// Returns the session ID of an RPKI-to-Router PDU.
+///
+/// Only the Serial Notify, Serial Query, Cache Response, and End of Data
+/// PDUs carry a session ID. For all other PDUs, the field has a different
+/// meaning and `None` is returned.
+pub fn rtr_session_id(pdu: &[u8]) -> Option<u16> {
+    let header = pdu.get(..4)?;
+    match header[1] {
+        0 | 1 | 3 | 7 => Some(u16::from_be_bytes([header[2], header[3]])),
+        _ => None
+    }
+}
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn extract() {
+        let tcp = b"\x00\x03\x12\x34\xff\x00";
+        assert_eq!(length_prefix16(tcp), Some(b"\x12\x34\xff".as_ref()));
+        assert_eq!(dns_id(length_prefix16(tcp).unwrap()), Some(0x1234));
+        assert_eq!(length_prefix16(&tcp[..4]), None);
+        assert_eq!(length_prefix32(b"\0\0\0\x01a"), Some(b"a".as_ref()));
+        assert_eq!(dns_id(b"\x12"), None);
+        assert_eq!(
+            tls_record_header(b"\x16\x03\x01\x00\x05"),
+            Some(RecordHeader {
+                content_type: 22, version: 0x0301, length: 5
+            })
+        );
+        assert_eq!(
+            rtr_session_id(b"\x01\x01\xab\xcd\0\0\0\x0c"), Some(0xabcd)
+        );
+        assert_eq!(rtr_session_id(b"\x01\x02\xab\xcd\0\0\0\x08"), None);
+    }
+
+    #[test]
+    fn truncated() {
+        assert_eq!(length_prefix16(b""), None);
+        assert_eq!(length_prefix16(b"\x00"), None);
+        assert_eq!(length_prefix16(b"\x00\x00"), Some(b"".as_ref()));
+        assert_eq!(length_prefix16(b"\x00\x02a"), None);
+        assert_eq!(length_prefix32(b"\0\0\0"), None);
+        assert_eq!(length_prefix32(b"\0\0\0\x02a"), None);
+        assert_eq!(length_prefix32(b"\xff\xff\xff\xffa"), None);
+        assert_eq!(tls_record_header(b"\x16\x03\x01\x00"), None);
+        assert_eq!(rtr_session_id(b"\x01\x00\xab"), None);
+        assert_eq!(dns_id(b""), None);
+    }
+
+    #[test]
+    fn extra_data() {
+        // Data after the message or header is ignored.
+        assert_eq!(length_prefix32(b"\0\0\0\x01ab"), Some(b"a".as_ref()));
+        assert_eq!(
+            tls_record_header(b"\x17\x03\x03\x40\x00xyz"),
+            Some(RecordHeader {
+                content_type: 23, version: 0x0303, length: 0x4000
+            })
+        );
+        for (pdu_type, expected) in [
+            (0, Some(1)), (1, Some(1)), (2, None), (3, Some(1)),
+            (4, None), (7, Some(1)), (10, None),
+        ] {
+            assert_eq!(
+                rtr_session_id(&[1, pdu_type, 0, 1, 0, 0, 0, 12]), expected
+            );
+        }
+    }
+}
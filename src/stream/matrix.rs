@@ -0,0 +1,257 @@
+//! Expanding script templates across parameter combinations.
+//!
+//! A protocol with several versions or optional features usually needs
+//! the same conversation tested for each of them with only small
+//! differences in the data. Instead of keeping a copy of the script per
+//! combination, a [`Matrix`] describes the parameters and their possible
+//! values and expands a single template into the rules for every
+//! combination.
+//!
+//! The template can either be a closure producing [`AssertRules`] from
+//! the [`Params`] of a combination or a script in RON format with
+//! `{{name}}` placeholders that are replaced with the parameter values
+//! before the script is parsed.
+
+use std::fmt;
+use std::str::FromStr;
+use super::AssertRules;
+
+
+//------------ Matrix --------------------------------------------------------
+
+/// A set of parameters and their possible values.
+///
+/// The combinations are produced in order with the values of the last
+/// parameter changing fastest.
+#[derive(Clone, Debug, Default)]
+pub struct Matrix {
+    axes: Vec<(String, Vec<String>)>,
+    excluded: Vec<Vec<(String, String)>>,
+}
+
+impl Matrix {
+    /// Creates a new, empty matrix.
+    ///
+    /// An empty matrix has exactly one combination without any parameters.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Adds a parameter with the given possible values.
+    pub fn param<T: fmt::Display>(
+        mut self, name: impl Into<String>, values: impl IntoIterator<Item = T>
+    ) -> Self {
+        self.axes.push((
+            name.into(),
+            values.into_iter().map(|value| value.to_string()).collect()
+        ));
+        self
+    }
+
+    /// Excludes all combinations that contain the given parameter values.
+    ///
+    /// This can be used to skip combinations that make no sense, such as
+    /// options that don’t exist in a certain protocol version.
+    pub fn exclude<T: fmt::Display>(
+        mut self, values: impl IntoIterator<Item = (&'static str, T)>
+    ) -> Self {
+        self.excluded.push(
+            values.into_iter().map(|(name, value)| {
+                (name.into(), value.to_string())
+            }).collect()
+        );
+        self
+    }
+
+    /// Returns all combinations of parameter values.
+    pub fn combinations(&self) -> Vec<Params> {
+        let mut res = vec![Params::default()];
+        for (name, values) in &self.axes {
+            res = res.into_iter().flat_map(|params| {
+                values.iter().map(move |value| {
+                    let mut params = params.clone();
+                    params.values.push((name.clone(), value.clone()));
+                    params
+                })
+            }).collect();
+        }
+        res.retain(|params| {
+            !self.excluded.iter().any(|excluded| {
+                excluded.iter().all(|(name, value)| {
+                    params.get(name) == Some(value.as_str())
+                })
+            })
+        });
+        res
+    }
+
+    /// Expands a template closure for all combinations.
+    ///
+    /// Returns the rules produced by _template_ together with the
+    /// parameters they were produced for.
+    pub fn expand(
+        &self, mut template: impl FnMut(&Params) -> AssertRules
+    ) -> Vec<(Params, AssertRules)> {
+        self.combinations().into_iter().map(|params| {
+            let rules = template(&params);
+            (params, rules)
+        }).collect()
+    }
+
+    /// Expands a template script in RON format for all combinations.
+    ///
+    /// Each occurrence of `{{name}}` in _template_ is replaced with the
+    /// value of the parameter _name._ The resulting script is then parsed.
+    ///
+    /// Returns an error if any of the scripts fails to parse. The error
+    /// names the parameters of the offending combination.
+    pub fn expand_ron(
+        &self, template: &str
+    ) -> Result<Vec<(Params, AssertRules)>, MatrixError> {
+        self.combinations().into_iter().map(|params| {
+            match ron::de::from_str(&params.substitute(template)) {
+                Ok(rules) => Ok((params, rules)),
+                Err(err) => Err(MatrixError { params, err })
+            }
+        }).collect()
+    }
+}
+
+
+//------------ Params --------------------------------------------------------
+
+/// The parameter values of one combination.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Params {
+    values: Vec<(String, String)>,
+}
+
+impl Params {
+    /// Returns the value of the parameter with the given name.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.values.iter().find(|item| item.0 == name).map(|item| {
+            item.1.as_str()
+        })
+    }
+
+    /// Returns the value of a parameter converted into some type.
+    ///
+    /// # Panics
+    ///
+    /// The method panics if there is no parameter of the given name or if
+    /// the value cannot be converted.
+    pub fn parse<T: FromStr>(&self, name: &str) -> T {
+        let value = match self.get(name) {
+            Some(value) => value,
+            None => panic!("unknown parameter '{}'", name),
+        };
+        match value.parse() {
+            Ok(value) => value,
+            Err(_) => panic!("invalid value '{}' for '{}'", value, name),
+        }
+    }
+
+    /// Returns an iterator over the names and values of the parameters.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.values.iter().map(|item| (item.0.as_str(), item.1.as_str()))
+    }
+
+    /// Replaces all `{{name}}` placeholders in a string.
+    pub fn substitute(&self, template: &str) -> String {
+        let mut res = template.to_string();
+        for (name, value) in self.iter() {
+            res = res.replace(&format!("{{{{{}}}}}", name), value);
+        }
+        res
+    }
+}
+
+impl fmt::Display for Params {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (idx, (name, value)) in self.iter().enumerate() {
+            if idx > 0 {
+                f.write_str(",")?;
+            }
+            write!(f, "{}={}", name, value)?;
+        }
+        Ok(())
+    }
+}
+
+
+//------------ MatrixError ---------------------------------------------------
+
+/// A template failed to parse for a combination of parameters.
+#[derive(Debug)]
+pub struct MatrixError {
+    params: Params,
+    err: ron::error::Error,
+}
+
+impl MatrixError {
+    /// Returns the parameters of the failed combination.
+    pub fn params(&self) -> &Params {
+        &self.params
+    }
+}
+
+impl fmt::Display for MatrixError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "template failed for {}: {}", self.params, self.err)
+    }
+}
+
+impl std::error::Error for MatrixError { }
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::stream::FragmentRule;
+
+    fn matrix() -> Matrix {
+        Matrix::new()
+            .param("version", 0..3)
+            .param("flag", ["a", "b"])
+            .exclude([("version", "0"), ("flag", "b")])
+    }
+
+    #[test]
+    fn combinations() {
+        assert_eq!(
+            matrix().combinations().iter().map(ToString::to_string)
+                .collect::<Vec<_>>(),
+            [
+                "version=0,flag=a", "version=1,flag=a", "version=1,flag=b",
+                "version=2,flag=a", "version=2,flag=b",
+            ]
+        );
+        assert_eq!(Matrix::new().combinations(), [Params::default()]);
+    }
+
+    #[test]
+    fn expand() {
+        let expanded = matrix().expand(|params| AssertRules {
+            fragments: vec![
+                FragmentRule::Recv(vec![params.parse("version")])
+            ]
+        });
+        assert_eq!(expanded.len(), 5);
+        assert!(matches!(
+            expanded[4].1.fragments[0], FragmentRule::Recv(ref data)
+                if data == &[2]
+        ));
+
+        let expanded = matrix().expand_ron(
+            "AssertRules(fragments: [ Send([{{version}}]) ])"
+        ).unwrap();
+        assert!(matches!(
+            expanded[2].1.fragments[0], FragmentRule::Send(ref data)
+                if data == &[1]
+        ));
+        let err = matrix().expand_ron("AssertRules(fragments: [{{flag}}])");
+        assert_eq!(err.unwrap_err().params().get("flag"), Some("a"));
+    }
+}
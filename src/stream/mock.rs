@@ -0,0 +1,225 @@
+//! Interoperating with the I/O mocks of tokio-test.
+//!
+//! Test suites written against `tokio_test::io::Mock` describe their
+//! conversations through a `tokio_test::io::Builder`. This module helps
+//! moving between the two in both directions.
+//!
+//! [`to_tokio_test`] converts rules into a tokio-test builder, so scripts
+//! can be run by existing tests unchanged. The tokio-test builder doesn’t
+//! allow looking at its content, so the other direction is covered by
+//! [`MockBuilder`] which has the same methods for building a script. A
+//! test can switch to it by replacing the type and then turn the script
+//! into rules or into a tokio-test builder.
+//!
+//! This module is only available with the `tokio-test` feature.
+
+use std::fmt;
+use std::time::Duration;
+use super::{AssertRules, AssertStream, FragmentRule};
+
+
+//------------ to_tokio_test -------------------------------------------------
+
+/// Converts rules into a tokio-test builder.
+///
+/// Send rules become writes, receive rules become reads, and delays
+/// become waits. A `SendReset(_)` rule becomes a write followed by a
+/// write error of kind `ConnectionReset`. Because a tokio-test mock ends
+/// the conversation when it runs out of actions and accepts shutdowns at
+/// any time, the close rules are dropped if they are the last rule.
+///
+/// All other rules cannot be expressed by tokio-test and result in an
+/// error.
+pub fn to_tokio_test(
+    rules: &AssertRules
+) -> Result<tokio_test::io::Builder, MockError> {
+    let mut res = tokio_test::io::Builder::new();
+    let last = rules.fragments.len().saturating_sub(1);
+    for (index, rule) in rules.fragments.iter().enumerate() {
+        match *rule {
+            FragmentRule::Send(ref data) | FragmentRule::SendAll(ref data) |
+            FragmentRule::SendFirst(ref data) => {
+                res.write(data);
+            }
+            FragmentRule::Recv(ref data) | FragmentRule::RecvAll(ref data) => {
+                res.read(data);
+            }
+            FragmentRule::Delay(duration) => {
+                res.wait(duration);
+            }
+            FragmentRule::SendReset(ref data) if index == last => {
+                res.write(data).write_error(
+                    std::io::ErrorKind::ConnectionReset.into()
+                );
+            }
+            FragmentRule::SendClose | FragmentRule::RecvClose
+                if index == last => { }
+            _ => return Err(MockError { index, kind: rule.kind() })
+        }
+    }
+    Ok(res)
+}
+
+
+//------------ MockBuilder ---------------------------------------------------
+
+/// A builder for a script with the methods of a tokio-test builder.
+///
+/// Reads become `RecvAll(_)` rules, writes become `SendAll(_)` rules, and
+/// waits become `Delay(_)` rules.
+#[derive(Clone, Debug, Default)]
+pub struct MockBuilder {
+    fragments: Vec<FragmentRule>,
+}
+
+impl MockBuilder {
+    /// Creates a new, empty builder.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds data to be read by the implementation.
+    pub fn read(&mut self, buf: &[u8]) -> &mut Self {
+        self.fragments.push(FragmentRule::RecvAll(buf.into()));
+        self
+    }
+
+    /// Adds data expected to be written by the implementation.
+    pub fn write(&mut self, buf: &[u8]) -> &mut Self {
+        self.fragments.push(FragmentRule::SendAll(buf.into()));
+        self
+    }
+
+    /// Adds a wait.
+    pub fn wait(&mut self, duration: Duration) -> &mut Self {
+        self.fragments.push(FragmentRule::Delay(duration));
+        self
+    }
+
+    /// Returns the rules for the script.
+    pub fn rules(&self) -> AssertRules {
+        AssertRules { fragments: self.fragments.clone() }
+    }
+
+    /// Creates a stream running the script.
+    pub fn build(&mut self) -> AssertStream {
+        AssertStream::new(self.rules())
+    }
+
+    /// Creates a tokio-test builder for the script.
+    pub fn to_tokio_test(&self) -> tokio_test::io::Builder {
+        // All rules produced by the builder can be converted.
+        to_tokio_test(&self.rules()).unwrap()
+    }
+}
+
+
+//------------ MockError -----------------------------------------------------
+
+/// A rule cannot be expressed by a tokio-test builder.
+#[derive(Clone, Debug)]
+pub struct MockError {
+    index: usize,
+    kind: &'static str,
+}
+
+impl MockError {
+    /// Returns the index of the rule that cannot be expressed.
+    pub fn index(&self) -> usize {
+        self.index
+    }
+}
+
+impl fmt::Display for MockError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f, "rule {} ({}) not supported by tokio-test",
+            self.index, self.kind
+        )
+    }
+}
+
+impl std::error::Error for MockError { }
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::{Read, Write};
+
+    #[test]
+    fn convert() {
+        let mut builder = MockBuilder::new();
+        builder.write(b"ping").read(b"pong");
+        let mut stream = builder.build();
+        stream.write_all(b"ping").unwrap();
+        let mut buf = [0u8; 4];
+        stream.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"pong");
+        assert!(stream.is_finished());
+
+        let mut rules = builder.rules();
+        rules.fragments.push(FragmentRule::RecvClose);
+        assert!(to_tokio_test(&rules).is_ok());
+        rules.fragments.push(FragmentRule::WriteBlocked(1));
+        assert_eq!(
+            to_tokio_test(&rules).unwrap_err().to_string(),
+            "rule 2 (RecvClose) not supported by tokio-test"
+        );
+    }
+
+    #[test]
+    fn convertible_rules() {
+        let rules = |fragments| AssertRules { fragments };
+        assert!(to_tokio_test(&rules(Vec::new())).is_ok());
+        assert!(
+            to_tokio_test(&rules(vec![
+                FragmentRule::Send(b"a".to_vec()),
+                FragmentRule::Recv(b"c".to_vec()),
+                FragmentRule::Delay(Duration::from_millis(1)),
+                FragmentRule::SendReset(b"d".to_vec()),
+            ])).is_ok()
+        );
+
+        // Resets and closes are only supported at the end.
+        let err = to_tokio_test(&rules(vec![
+            FragmentRule::SendReset(b"d".to_vec()),
+            FragmentRule::Recv(b"c".to_vec()),
+        ])).unwrap_err();
+        assert_eq!(err.index(), 0);
+        assert_eq!(
+            err.to_string(), "rule 0 (SendReset) not supported by tokio-test"
+        );
+        assert_eq!(
+            to_tokio_test(&rules(vec![
+                FragmentRule::SendClose, FragmentRule::RecvClose,
+            ])).unwrap_err().index(),
+            0
+        );
+        assert_eq!(
+            to_tokio_test(&rules(vec![
+                FragmentRule::Recv(b"c".to_vec()),
+                FragmentRule::WriteBlocked(1),
+            ])).unwrap_err().to_string(),
+            "rule 1 (WriteBlocked) not supported by tokio-test"
+        );
+    }
+
+    #[test]
+    fn builder_rules() {
+        let mut builder = MockBuilder::new();
+        builder.read(b"a").wait(Duration::from_secs(1)).write(b"b");
+        let rules = builder.rules();
+        assert!(matches!(
+            rules.fragments.as_slice(),
+            [
+                FragmentRule::RecvAll(a),
+                FragmentRule::Delay(delay),
+                FragmentRule::SendAll(b),
+            ] if a == b"a" && *delay == Duration::from_secs(1) && b == b"b"
+        ));
+        let _ = builder.to_tokio_test();
+    }
+}
@@ -0,0 +1,2634 @@
+//! Streams sending and receiving sequences of data.
+
+use std::{cmp, env, fmt, fs, io};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::cmp::Ordering;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "sha2")]
+use sha2::Digest as _;
+use crate::crc::Crc32;
+#[cfg(feature = "tokio")]
+use {
+    std::pin::Pin,
+    std::task::{Context, Poll, Waker},
+    std::thread,
+    tokio::io::{AsyncRead, AsyncWrite, ReadBuf},
+};
+
+pub use self::clock::Clock;
+pub use self::expect::Expectations;
+pub use self::tls::ClientHelloMatch;
+
+pub mod clock;
+pub mod coverage;
+pub mod datagram;
+pub mod dns;
+pub mod expect;
+pub mod extract;
+pub mod matrix;
+#[cfg(feature = "tokio-test")]
+pub mod mock;
+pub mod process;
+pub mod rtr;
+pub mod smtp;
+pub mod split;
+pub mod tls;
+pub mod transport;
+pub mod transcript;
+
+
+//------------ AssertStream --------------------------------------------------
+
+/// A stream that sends data and expects certain values in return.
+///
+/// This stream is intended to be used instead of, e.g., `TcpStream` in a
+/// generic protocol implementation. Thus, its rules are to be viewed from
+/// the perspective of that protcol implemenatation, _not_ from the
+/// perspective of the other end of the conversation.
+#[derive(Clone, Debug)]
+pub struct AssertStream {
+    /// The rules that drive this stream.
+    rules: AssertRules,
+
+    /// The index of the current rule.
+    rule_index: usize,
+
+    /// The index of the data of a send all or recv all rule.
+    all_index: usize,
+
+    /// The named readers available as data sources.
+    sources: HashMap<String, SharedReader>,
+
+    /// The seeds used for creating the rules, with their names.
+    seeds: Vec<(String, u64)>,
+
+    /// The data source of the current rule if it has one.
+    active_source: Option<ActiveSource>,
+
+    /// The digest calculation of the current rule if it has one.
+    active_digest: Option<DigestState>,
+
+    /// The read timeout of the sync stream.
+    read_timeout: Option<Duration>,
+
+    /// The time left of the current delay rule if it has been started.
+    delay_left: Option<Duration>,
+
+    /// The virtual time the current delay rule ends in the async stream.
+    #[cfg(feature = "tokio")]
+    delay_until: Option<Duration>,
+
+    /// The number of writes refused by the current rule.
+    refused_writes: usize,
+
+    /// The data of a TLS record collected for the current rule.
+    record_data: Vec<u8>,
+
+    /// The waker of an async write waiting for the window to open.
+    #[cfg(feature = "tokio")]
+    write_waker: Option<Waker>,
+
+    /// The data exchanged so far.
+    transcript: Vec<TranscriptEntry>,
+
+    /// The maximum number of octets kept in the transcript.
+    capture_limit: Option<usize>,
+
+    /// The number of octets kept in the transcript so far.
+    captured: usize,
+
+    /// The clock providing the virtual time.
+    clock: Clock,
+
+    /// When each piece of data was exchanged.
+    timeline: Vec<TimelineEntry>,
+
+    /// The virtual time when the last rule was completed.
+    finished_at: Option<Duration>,
+
+    /// The maximum number of octets the implementation may send.
+    send_limit: Option<usize>,
+
+    /// The maximum number of octets the implementation may receive.
+    recv_limit: Option<usize>,
+
+    /// The maximum number of octets sent under a single rule.
+    message_size_limit: Option<usize>,
+
+    /// The number of octets sent so far.
+    sent: usize,
+
+    /// The number of octets received so far.
+    received: usize,
+}
+
+impl AssertStream {
+    pub fn new(rules: AssertRules) -> Self {
+        AssertStream {
+            rules,
+            rule_index: 0,
+            all_index: 0,
+            sources: HashMap::new(),
+            seeds: Vec::new(),
+            active_source: None,
+            active_digest: None,
+            read_timeout: None,
+            delay_left: None,
+            #[cfg(feature = "tokio")]
+            delay_until: None,
+            refused_writes: 0,
+            record_data: Vec::new(),
+            #[cfg(feature = "tokio")]
+            write_waker: None,
+            transcript: Vec::new(),
+            capture_limit: Some(DEFAULT_CAPTURE_LIMIT),
+            captured: 0,
+            clock: Clock::real(),
+            timeline: Vec::new(),
+            finished_at: None,
+            send_limit: None,
+            recv_limit: None,
+            message_size_limit: None,
+            sent: 0,
+            received: 0,
+        }
+    }
+
+    pub fn from_ron_str(s: &str) -> Result<Self, ron::error::Error> {
+        ron::de::from_str(s).map(Self::new)
+    }
+
+    /// Creates a stream from rules in transcript format.
+    ///
+    /// See the [`transcript`] module for the format.
+    pub fn from_transcript(
+        s: &str
+    ) -> Result<Self, transcript::TranscriptError> {
+        transcript::parse(s).map(Self::new)
+    }
+
+    /// Adds a named reader as a data source.
+    ///
+    /// Rules can refer to the reader via [`DataSource::Named`] using the
+    /// given name.
+    pub fn add_source(
+        &mut self, name: impl Into<String>, reader: impl Read + Send + 'static
+    ) {
+        self.sources.insert(name.into(), SharedReader::new(reader));
+    }
+
+    /// Adds a named seed to the replay log.
+    ///
+    /// Seeded recipes receive their seed when they are created and the
+    /// rules only contain the data they produced. A seed that was
+    /// injected into a test, e.g., from the environment, is therefore
+    /// lost unless it is added here. The seeds are listed in the
+    /// [replay log][Self::replay_log] in the order they were added.
+    pub fn add_seed(&mut self, name: impl Into<String>, seed: u64) {
+        self.seeds.push((name.into(), seed))
+    }
+
+    /// Sets the read timeout of the sync stream.
+    ///
+    /// This mirrors `TcpStream::set_read_timeout`. If a read encounters a
+    /// [`FragmentRule::Delay`] with more time left than the timeout, it
+    /// waits for the duration of the timeout and then fails with an error
+    /// of kind `TimedOut`. The remainder of the delay is left for the next
+    /// read. If the timeout is `None`, reads wait for the whole delay.
+    ///
+    /// As with `TcpStream`, a zero duration results in an error.
+    pub fn set_read_timeout(
+        &mut self, timeout: Option<Duration>
+    ) -> Result<(), io::Error> {
+        if timeout == Some(Duration::ZERO) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "cannot set a 0 duration timeout"
+            ))
+        }
+        self.read_timeout = timeout;
+        Ok(())
+    }
+
+    /// Returns the read timeout of the sync stream.
+    pub fn read_timeout(&self) -> Option<Duration> {
+        self.read_timeout
+    }
+
+    /// Sets the maximum number of octets the implementation may send.
+    ///
+    /// If the implementation sends more octets in total, the stream
+    /// panics right away rather than when the data no longer matches the
+    /// rules. This catches runaway retransmissions quickly. If the limit
+    /// is `None`, any amount may be sent.
+    pub fn set_send_limit(&mut self, limit: Option<usize>) {
+        self.send_limit = limit
+    }
+
+    /// Sets the maximum number of octets the implementation may receive.
+    ///
+    /// If the implementation reads more octets in total, the stream
+    /// panics. If the limit is `None`, any amount may be received.
+    pub fn set_recv_limit(&mut self, limit: Option<usize>) {
+        self.recv_limit = limit
+    }
+
+    /// Sets the maximum number of octets in a single sent message.
+    ///
+    /// A message is all the data the implementation sends while a single
+    /// rule is current, no matter how many writes it uses. If a message
+    /// grows beyond the limit, the stream panics. This is most useful
+    /// with rules that accept large amounts of data such as
+    /// [`FragmentRule::SendAllFrom`] or [`FragmentRule::SendDigest`]. If
+    /// the limit is `None`, messages of any size are allowed.
+    pub fn set_message_size_limit(&mut self, limit: Option<usize>) {
+        self.message_size_limit = limit
+    }
+
+    /// Sets the maximum number of octets kept in the transcript.
+    ///
+    /// The transcript used by the [replay log][Self::replay_log],
+    /// [`recorded_rules`][Self::recorded_rules], and
+    /// [`transcript_hash`][Self::transcript_hash] keeps a copy of the
+    /// exchanged data. Once _limit_ octets have been kept, only the amount
+    /// of any further data is recorded. If the limit is `None`, all data
+    /// is kept. The default is [`DEFAULT_CAPTURE_LIMIT`].
+    ///
+    /// The data of [`FragmentRule::SendAllFrom`],
+    /// [`FragmentRule::RecvAllFrom`], and [`FragmentRule::SendDigest`]
+    /// rules is never kept. Only its amount is recorded and, for the
+    /// latter, the digest is available from the rule.
+    pub fn set_capture_limit(&mut self, limit: Option<usize>) {
+        self.capture_limit = limit
+    }
+
+    /// Sets the clock providing the virtual time.
+    ///
+    /// By default, the stream uses the real time. With a
+    /// [manual clock][Clock::manual], delay rules advance the clock
+    /// instead of waiting, which makes timing assertions deterministic.
+    /// The clock should be set before any data is exchanged.
+    pub fn set_clock(&mut self, clock: Clock) {
+        self.clock = clock
+    }
+
+    /// Returns the clock providing the virtual time.
+    pub fn clock(&self) -> &Clock {
+        &self.clock
+    }
+
+    /// Returns the number of octets the implementation has sent so far.
+    pub fn sent(&self) -> usize {
+        self.sent
+    }
+
+    /// Returns the number of octets the implementation has received so
+    /// far.
+    pub fn received(&self) -> usize {
+        self.received
+    }
+
+    /// Shuts down the sending side of the sync stream.
+    ///
+    /// This mirrors `TcpStream::shutdown` for the write direction and
+    /// behaves like `AsyncWrite::poll_shutdown` on the async stream: it
+    /// succeeds if the current rule is `SendClose` and fails with a
+    /// `ConnectionReset` error if the connection has been reset.
+    /// Otherwise, the `AssertStream` will panic.
+    pub fn shutdown(&mut self) -> Result<(), io::Error> {
+        match self.rules.fragments.get(self.rule_index) {
+            Some(FragmentRule::SendClose) => {
+                if self.rule_index + 1 == self.rules.fragments.len() {
+                    self.mark_finished();
+                }
+                Ok(())
+            }
+            Some(FragmentRule::SendReset(ref data))
+                if self.all_index == data.len()
+            => {
+                Err(reset_error())
+            }
+            _ => self.fail("expected send close")
+        }
+    }
+
+    /// Returns whether all rules have been processed.
+    pub fn is_finished(&self) -> bool {
+        self.rule_index >= self.rules.fragments.len()
+    }
+
+    /// Returns a replay log of the conversation so far.
+    ///
+    /// The log contains the complete rules in RON format, how far the
+    /// conversation has progressed, the seeds added via
+    /// [`add_seed`][Self::add_seed], and a transcript of the data that
+    /// was exchanged annotated with the rule it was exchanged under. See
+    /// [`set_capture_limit`][Self::set_capture_limit] for which data is
+    /// included in the transcript. The log is
+    /// included in the panic message whenever an assertion of the stream
+    /// fails. If the environment variable named by [`REPLAY_LOG_VAR`] is
+    /// set, the log is written to the file it names instead.
+    pub fn replay_log(&self) -> String {
+        use std::fmt::Write as _;
+
+        let mut res = String::new();
+        writeln!(
+            res, "progress: rule {} of {}, offset {}",
+            self.rule_index, self.rules.fragments.len(), self.all_index
+        ).unwrap();
+        res.push_str("rules:\n");
+        match ron::ser::to_string_pretty(
+            &self.rules, ron::ser::PrettyConfig::default()
+        ) {
+            Ok(rules) => res.push_str(&rules),
+            Err(err) => write!(res, "<cannot serialize: {}>", err).unwrap(),
+        }
+        if !self.seeds.is_empty() {
+            res.push_str("\nseeds:\n");
+            for (name, seed) in &self.seeds {
+                writeln!(res, "  {}: {}", name, seed).unwrap();
+            }
+        }
+        res.push_str("\ntranscript:\n");
+        for entry in &self.transcript {
+            writeln!(res, "  {}", entry).unwrap();
+        }
+        res
+    }
+
+    /// Returns rules reproducing the data exchanged so far.
+    ///
+    /// Each run of data sent or received under the same rule becomes a
+    /// `SendAll(_)` or `RecvAll(_)` rule, respectively. This can be used
+    /// to dump a recorded conversation, e.g., via
+    /// [`AssertRules::to_transcript`]. Data completely sent under a
+    /// `SendDigest(_)` rule becomes that rule.
+    ///
+    /// # Panics
+    ///
+    /// The method panics if the transcript lacks some of the data. See
+    /// [`set_capture_limit`][Self::set_capture_limit] for details.
+    pub fn recorded_rules(&self) -> AssertRules {
+        AssertRules {
+            fragments: self.transcript.iter().map(|entry| {
+                if let Some(FragmentRule::SendDigest(len, ref digest)) =
+                    self.rules.fragments.get(entry.rule)
+                {
+                    if *len == entry.len {
+                        return FragmentRule::SendDigest(*len, digest.clone())
+                    }
+                }
+                match entry.direction {
+                    Direction::Send => FragmentRule::SendAll(
+                        entry.captured().into()
+                    ),
+                    Direction::Recv => FragmentRule::RecvAll(
+                        entry.captured().into()
+                    ),
+                }
+            }).collect()
+        }
+    }
+
+    /// Returns a hash of the data exchanged so far.
+    ///
+    /// The hash is calculated with _algo_ over the conversation split
+    /// into runs of data in the same direction. Each run is hashed as an
+    /// octet `b'S'` for sent or `b'R'` for received data, the length of
+    /// the run as a 64 bit big-endian integer, and the data itself. How
+    /// the data was split into individual reads and writes or rules
+    /// doesn’t change the hash. It can be used to check that the behaviour
+    /// of an implementation stays the same without keeping a complete
+    /// transcript around.
+    ///
+    /// # Panics
+    ///
+    /// The method panics if the transcript lacks some of the data. See
+    /// [`set_capture_limit`][Self::set_capture_limit] for details. It
+    /// also panics if _algo_ isn’t
+    /// [supported][DigestAlgorithm::is_supported].
+    pub fn transcript_hash(&self, algo: DigestAlgorithm) -> Digest {
+        let mut state = match DigestState::with_algorithm(algo) {
+            Some(state) => state,
+            None => panic!("digest algorithm {} not supported", algo),
+        };
+        let mut entries = self.transcript.iter().peekable();
+        while let Some(first) = entries.next() {
+            let mut run = vec![first];
+            while let Some(entry) = entries.next_if(|entry| {
+                entry.direction == first.direction
+            }) {
+                run.push(entry)
+            }
+            state.update(match first.direction {
+                Direction::Send => b"S",
+                Direction::Recv => b"R",
+            });
+            let len: usize = run.iter().map(|entry| entry.len).sum();
+            state.update(&(len as u64).to_be_bytes());
+            for entry in run {
+                state.update(entry.captured())
+            }
+        }
+        state.finalize()
+    }
+
+    /// Asserts that the hash of the data exchanged so far is _expected._
+    ///
+    /// The hash is calculated via [`transcript_hash`][Self::transcript_hash]
+    /// with the algorithm of _expected._ On failure, the panic message
+    /// contains the actual hash and the replay log.
+    pub fn assert_transcript_hash(&self, expected: &Digest) {
+        let hash = self.transcript_hash(expected.algorithm());
+        if hash != *expected {
+            self.fail(format_args!(
+                "transcript hash mismatch: expected {}, got {}",
+                expected, hash
+            ))
+        }
+    }
+
+    /// Returns the virtual time since the stream’s clock was started.
+    ///
+    /// The virtual time is the time of the stream’s [`Clock`].
+    pub fn elapsed(&self) -> Duration {
+        self.clock.now()
+    }
+
+    /// Returns a timeline of the conversation so far.
+    ///
+    /// The timeline lists the virtual time, rule, direction, and amount
+    /// of every piece of data exchanged. Data exchanged under the same
+    /// rule in the same direction within a millisecond is combined into
+    /// a single entry.
+    pub fn timeline(&self) -> String {
+        use std::fmt::Write as _;
+
+        let mut res = String::new();
+        for entry in &self.timeline {
+            writeln!(res, "  {}", entry).unwrap();
+        }
+        if let Some(at) = self.finished_at {
+            writeln!(res, "  {:>10.3?}: finished", at).unwrap();
+        }
+        res
+    }
+
+    /// Asserts that a minimum amount of data was exchanged in time.
+    ///
+    /// Panics with the timeline unless at least _octets_ octets in either
+    /// direction were exchanged within the virtual time _within_ as
+    /// measured by [`elapsed`][Self::elapsed].
+    pub fn assert_progress(&self, octets: usize, within: Duration) {
+        let done: usize = self.timeline.iter().filter(|entry| {
+            entry.at <= within
+        }).map(|entry| entry.len).sum();
+        if done < octets {
+            self.fail_timeline(format_args!(
+                "progress too slow: {} of {} octets within {:?}",
+                done, octets, within
+            ))
+        }
+    }
+
+    /// Asserts that the conversation was completed in time.
+    ///
+    /// Panics with the timeline unless all rules were completed within
+    /// the virtual time _within_ as measured by
+    /// [`elapsed`][Self::elapsed].
+    pub fn assert_completed_within(&self, within: Duration) {
+        match self.finished_at {
+            Some(at) if at <= within => { }
+            Some(at) => self.fail_timeline(format_args!(
+                "conversation completed after {:?}, expected within {:?}",
+                at, within
+            )),
+            None => self.fail_timeline(format_args!(
+                "conversation not completed, expected within {:?}",
+                within
+            )),
+        }
+    }
+
+    /// Returns the times at which the implementation sent each message.
+    ///
+    /// Each rule the implementation sent data for counts as one message.
+    /// The time of a message is the virtual time of its first octets.
+    pub fn send_times(&self) -> Vec<Duration> {
+        let mut res = Vec::new();
+        let mut last_rule = None;
+        for entry in &self.timeline {
+            if entry.direction == Direction::Send
+                && last_rule != Some(entry.rule)
+            {
+                res.push(entry.at);
+                last_rule = Some(entry.rule);
+            }
+        }
+        res
+    }
+
+    /// Asserts that messages were sent at a regular interval.
+    ///
+    /// Panics with the timeline unless the time between any two
+    /// consecutive messages as returned by [`send_times`][Self::send_times]
+    /// differs from _interval_ by at most _tolerance._ This is useful for
+    /// checking keepalive timers.
+    pub fn assert_send_interval(
+        &self, interval: Duration, tolerance: Duration
+    ) {
+        let times = self.send_times();
+        for (idx, pair) in times.windows(2).enumerate() {
+            let actual = pair[1] - pair[0];
+            if actual.abs_diff(interval) > tolerance {
+                self.fail_timeline(format_args!(
+                    "message {} sent {:?} after previous one, \
+                     expected {:?} ± {:?}",
+                    idx + 1, actual, interval, tolerance
+                ))
+            }
+        }
+    }
+
+    /// Asserts that messages were not sent faster than a maximum rate.
+    ///
+    /// Panics with the timeline if more than _count_ messages as returned
+    /// by [`send_times`][Self::send_times] were sent within any period of
+    /// length _period._ This is useful for checking rate limiters.
+    pub fn assert_send_rate(&self, count: usize, period: Duration) {
+        let times = self.send_times();
+        for (idx, window) in times.windows(count + 1).enumerate() {
+            if window[count] - window[0] < period {
+                self.fail_timeline(format_args!(
+                    "messages {} to {} sent within {:?}, \
+                     expected at most {} per {:?}",
+                    idx, idx + count, window[count] - window[0],
+                    count, period
+                ))
+            }
+        }
+    }
+
+    /// Fails an assertion on timing.
+    fn fail_timeline(&self, msg: impl fmt::Display) -> ! {
+        panic!("{}\ntimeline:\n{}", msg, self.timeline())
+    }
+
+    /// Records exchanged data in the transcript.
+    ///
+    /// The data is recorded under the current rule, so this needs to be
+    /// called by the rule that exchanged the data before moving on to the
+    /// next rule. Also fails if this exceeds the limit for the direction.
+    fn record(&mut self, direction: Direction, data: &[u8]) {
+        if data.is_empty() {
+            return
+        }
+        let rule = self.rule_index;
+        if let (Direction::Send, Some(limit)) = (
+            direction, self.message_size_limit
+        ) {
+            let len = match self.transcript.last() {
+                Some(last)
+                    if last.rule == rule && last.direction == direction
+                => {
+                    last.len + data.len()
+                }
+                _ => data.len()
+            };
+            if len > limit {
+                self.fail(format_args!(
+                    "message of {} octets in rule {} exceeds limit of {}",
+                    len, rule, limit
+                ))
+            }
+        }
+        let (total, limit, name) = match direction {
+            Direction::Send => (&mut self.sent, self.send_limit, "send"),
+            Direction::Recv => {
+                (&mut self.received, self.recv_limit, "receive")
+            }
+        };
+        *total += data.len();
+        if let Some(limit) = limit {
+            if *total > limit {
+                let total = *total;
+                self.fail(format_args!(
+                    "{} limit exceeded: {} octets, limit is {}",
+                    name, total, limit
+                ))
+            }
+        }
+        let at = self.elapsed();
+        match self.timeline.last_mut() {
+            Some(last) if last.rule == rule && last.direction == direction
+                && at.saturating_sub(last.at) < TimelineEntry::RESOLUTION
+            => {
+                last.len += data.len()
+            }
+            _ => {
+                self.timeline.push(TimelineEntry {
+                    at, rule, direction, len: data.len()
+                })
+            }
+        }
+        let keep = match self.rules.fragments.get(rule) {
+            Some(FragmentRule::SendAllFrom(_)) |
+            Some(FragmentRule::RecvAllFrom(_)) |
+            Some(FragmentRule::SendDigest(..)) => 0,
+            _ => match self.capture_limit {
+                Some(limit) => {
+                    cmp::min(data.len(), limit.saturating_sub(self.captured))
+                }
+                None => data.len()
+            }
+        };
+        match self.transcript.last_mut() {
+            Some(last) if last.rule == rule && last.direction == direction => {
+                // Only keep adding data while it is complete.
+                if last.is_complete() {
+                    last.data.extend_from_slice(&data[..keep]);
+                    self.captured += keep;
+                }
+                last.len += data.len();
+            }
+            _ => {
+                self.captured += keep;
+                self.transcript.push(TranscriptEntry {
+                    rule, direction, len: data.len(),
+                    data: data[..keep].into()
+                })
+            }
+        }
+    }
+
+    /// Fails an assertion.
+    ///
+    /// Panics with the given message and the replay log.
+    fn fail(&self, msg: impl fmt::Display) -> ! {
+        let log = self.replay_log();
+        if let Some(path) = env::var_os(REPLAY_LOG_VAR) {
+            match fs::write(&path, log.as_bytes()) {
+                Ok(()) => {
+                    panic!(
+                        "{}\nreplay log written to {}",
+                        msg, PathBuf::from(path).display()
+                    )
+                }
+                Err(err) => {
+                    panic!(
+                        "{}\nfailed to write replay log: {}\n{}",
+                        msg, err, log
+                    )
+                }
+            }
+        }
+        panic!("{}\n{}", msg, log)
+    }
+
+    /// Fails because written data differs from the expected data.
+    fn mismatch(&self, expected: &[u8], actual: &[u8]) -> ! {
+        self.fail(format_args!(
+            "data mismatch in rule {}:\n  expected: {}\n    actual: {}",
+            self.rule_index, HexData(expected), HexData(actual)
+        ))
+    }
+
+    /// Records the time the conversation was completed.
+    ///
+    /// A conversation is completed when all rules have been processed or
+    /// the final rule closing the stream has been reached.
+    fn mark_finished(&mut self) {
+        if self.finished_at.is_none() {
+            self.finished_at = Some(self.elapsed())
+        }
+    }
+
+    fn next_fragment(&mut self) {
+        self.rule_index += 1;
+        self.all_index = 0;
+        self.active_source = None;
+        self.active_digest = None;
+        self.delay_left = None;
+        #[cfg(feature = "tokio")]
+        {
+            self.delay_until = None;
+        }
+        self.refused_writes = 0;
+        self.record_data.clear();
+        if self.is_finished() {
+            self.mark_finished();
+        }
+        #[cfg(feature = "tokio")]
+        if let Some(waker) = self.write_waker.take() {
+            waker.wake()
+        }
+    }
+
+    /// Returns the data source of the current rule.
+    ///
+    /// Opens the source if necessary. Panics if the current rule doesn’t
+    /// have a data source or it can’t be opened.
+    fn active_source(&mut self) -> &mut ActiveSource {
+        if self.active_source.is_none() {
+            let source = match self.rules.fragments.get(self.rule_index) {
+                Some(FragmentRule::RecvAllFrom(ref source)) |
+                Some(FragmentRule::SendAllFrom(ref source)) => source,
+                _ => panic!("current rule has no data source"),
+            };
+            self.active_source = Some(
+                ActiveSource::open(source, &self.sources)
+            );
+        }
+        self.active_source.as_mut().unwrap()
+    }
+
+    /// Reads data for a rule with a data source.
+    fn read_from_source(&mut self, buf: &mut [u8]) -> usize {
+        if buf.is_empty() {
+            return 0
+        }
+        let source = self.active_source();
+        let len = source.take(buf.len(), |data| {
+            buf[..data.len()].copy_from_slice(data)
+        });
+        let done = source.is_done();
+        self.record(Direction::Recv, &buf[..len]);
+        if done {
+            self.next_fragment();
+        }
+        len
+    }
+
+    /// Checks written data for a rule with a data source.
+    fn write_from_source(&mut self, buf: &[u8]) -> usize {
+        let source = self.active_source();
+        let mut expected = None;
+        let len = source.take(buf.len(), |data| {
+            if &buf[..data.len()] != data {
+                expected = Some(data.to_vec())
+            }
+        });
+        let done = source.is_done();
+        if let Some(expected) = expected {
+            self.mismatch(&expected, &buf[..len])
+        }
+        self.record(Direction::Send, &buf[..len]);
+        if done {
+            self.next_fragment();
+        }
+        len
+    }
+
+    /// Waits out a delay rule in the sync stream.
+    ///
+    /// Returns an error if _timeout_ passes before the delay.
+    fn wait_delay(
+        &mut self, delay: Duration, timeout: Option<Duration>
+    ) -> Result<(), io::Error> {
+        let left = *self.delay_left.get_or_insert(delay);
+        match timeout {
+            Some(timeout) if timeout < left => {
+                self.clock.sleep(timeout);
+                self.delay_left = Some(left - timeout);
+                Err(io::Error::new(
+                    io::ErrorKind::TimedOut, "read timed out"
+                ))
+            }
+            _ => {
+                self.clock.sleep(left);
+                self.next_fragment();
+                Ok(())
+            }
+        }
+    }
+
+    /// Refuses a write while the window is closed.
+    ///
+    /// Panics if more than _max_ writes have been refused.
+    fn refuse_write(&mut self, max: usize) {
+        self.refused_writes += 1;
+        if self.refused_writes > max {
+            self.fail(format_args!(
+                "busy write in rule {}: {} writes refused",
+                self.rule_index, self.refused_writes
+            ))
+        }
+    }
+
+    /// Collects and checks written data for a ClientHello rule.
+    ///
+    /// Accepts at most the data of a single TLS record. Once the record is
+    /// complete, checks it and moves on to the next rule.
+    fn write_client_hello(&mut self, buf: &[u8]) -> usize {
+        // First collect the record header, then the rest of the record.
+        let mut len = cmp::min(
+            buf.len(), 5usize.saturating_sub(self.record_data.len())
+        );
+        self.record_data.extend_from_slice(&buf[..len]);
+        let total = match ClientHelloMatch::record_len(&self.record_data) {
+            Some(total) => total,
+            None => {
+                self.record(Direction::Send, &buf[..len]);
+                return len
+            }
+        };
+        let take = cmp::min(buf.len() - len, total - self.record_data.len());
+        self.record_data.extend_from_slice(&buf[len..len + take]);
+        len += take;
+        self.record(Direction::Send, &buf[..len]);
+        if self.record_data.len() < total {
+            return len
+        }
+        let check = match self.rules.fragments.get(self.rule_index) {
+            Some(FragmentRule::SendClientHello(ref expected)) => {
+                expected.check(&self.record_data)
+            }
+            _ => unreachable!()
+        };
+        if let Err(err) = check {
+            self.fail(format_args!(
+                "ClientHello mismatch in rule {}: {}", self.rule_index, err
+            ))
+        }
+        self.next_fragment();
+        len
+    }
+
+    /// Checks written data for a rule with a digest.
+    fn write_digest(&mut self, buf: &[u8]) -> usize {
+        let (len, digest) = match self.rules.fragments.get(self.rule_index) {
+            Some(FragmentRule::SendDigest(len, ref digest)) => {
+                (*len, digest.clone())
+            }
+            _ => panic!("current rule has no digest"),
+        };
+        if self.active_digest.is_none() {
+            match DigestState::new(&digest) {
+                Some(state) => self.active_digest = Some(state),
+                None => {
+                    self.fail(format_args!(
+                        "digest algorithm {} in rule {} not supported",
+                        digest.algorithm(), self.rule_index
+                    ))
+                }
+            }
+        }
+        let state = self.active_digest.as_mut().unwrap();
+        let buf = &buf[..cmp::min(buf.len(), len - state.seen)];
+        state.update(buf);
+        let done = state.seen == len;
+        self.record(Direction::Send, buf);
+        if done {
+            let actual = self.active_digest.as_ref().unwrap().finalize();
+            if actual != digest {
+                self.fail(format_args!(
+                    "digest mismatch in rule {}: expected {}, got {}",
+                    self.rule_index, digest, actual
+                ))
+            }
+            self.next_fragment();
+        }
+        buf.len()
+    }
+}
+
+impl AssertStream {
+    /// Reads data according to the current rule.
+    fn read_rules(
+        &mut self, buf: &mut [u8]
+    ) -> Result<usize, io::Error> {
+        match self.rules.fragments.get(self.rule_index) {
+            Some(FragmentRule::SendReset(ref data))
+                if self.all_index == data.len()
+            => {
+                Err(reset_error())
+            }
+            Some(FragmentRule::SendFirst(_)) => {
+                self.fail("read attempted before send was completed")
+            }
+            Some(FragmentRule::Send(_)) | Some(FragmentRule::SendAll(_)) |
+            Some(FragmentRule::SendAllFrom(_)) |
+            Some(FragmentRule::SendDigest(..)) |
+            Some(FragmentRule::SendClientHello(_)) |
+            Some(FragmentRule::SendReset(_)) => {
+                Err(io::Error::new(
+                    io::ErrorKind::WouldBlock,
+                    "expected send"
+                ))
+            }
+            Some(FragmentRule::Recv(ref data)) => {
+                let len = data.len();
+                if buf.len() < len {
+                    self.fail("short buffer provided")
+                }
+                buf[..len].copy_from_slice(data);
+                self.record(Direction::Recv, &buf[..len]);
+                self.next_fragment();
+                Ok(len)
+            }
+            Some(FragmentRule::RecvAll(ref data)) => {
+                let remaining_data = &data[self.all_index..];
+                let len = remaining_data.len();
+                let buf_remaining = buf.len();
+                if buf_remaining >= remaining_data.len() {
+                    buf[..remaining_data.len()].copy_from_slice(
+                        remaining_data
+                    );
+                    self.record(Direction::Recv, &buf[..len]);
+                    self.next_fragment();
+                    Ok(len)
+                }
+                else {
+                    buf.copy_from_slice(&remaining_data[..buf_remaining]);
+                    self.record(Direction::Recv, buf);
+                    self.all_index += buf_remaining;
+                    Ok(buf_remaining)
+                }
+            }
+            Some(FragmentRule::RecvAllFrom(_)) => {
+                match self.read_from_source(buf) {
+                    // An empty source: move on to the next rule.
+                    0 if !buf.is_empty() => self.read_rules(buf),
+                    len => Ok(len)
+                }
+            }
+            Some(FragmentRule::Delay(delay)) => {
+                self.wait_delay(*delay, self.read_timeout)?;
+                self.read_rules(buf)
+            }
+            Some(FragmentRule::WriteBlocked(_)) => {
+                self.next_fragment();
+                self.read_rules(buf)
+            }
+            Some(FragmentRule::SendClose) => {
+                self.fail("expected send close")
+            }
+            Some(FragmentRule::RecvClose) => {
+                if self.rule_index + 1 == self.rules.fragments.len() {
+                    self.mark_finished();
+                }
+                Ok(0)
+            }
+            None => {
+                self.fail("no more fragment rules")
+            }
+        }
+    }
+}
+
+impl Read for AssertStream {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, io::Error> {
+        self.read_rules(buf)
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl AssertStream {
+    /// Waits out a delay rule in the async stream.
+    ///
+    /// A manual clock is advanced right away. Otherwise, returns
+    /// `Poll::Pending` and wakes up the task once the delay has passed.
+    fn poll_delay(
+        &mut self, cx: &mut Context<'_>, delay: Duration
+    ) -> Poll<()> {
+        if self.clock.is_manual() {
+            self.clock.sleep(delay);
+            self.next_fragment();
+            return Poll::Ready(())
+        }
+        let now = self.clock.now();
+        let until = *self.delay_until.get_or_insert(now + delay);
+        if now >= until {
+            self.next_fragment();
+            return Poll::Ready(())
+        }
+        let waker = cx.waker().clone();
+        thread::spawn(move || {
+            thread::sleep(until - now);
+            waker.wake()
+        });
+        Poll::Pending
+    }
+
+    /// Reads data according to the current rule.
+    fn poll_read_rules(
+        &mut self,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<Result<(), io::Error>> {
+        match self.rules.fragments.get(self.rule_index) {
+            Some(FragmentRule::SendReset(ref data))
+                if self.all_index == data.len()
+            => {
+                Poll::Ready(Err(reset_error()))
+            }
+            Some(FragmentRule::SendFirst(_)) => {
+                self.fail("read attempted before send was completed")
+            }
+            Some(FragmentRule::Send(_)) | Some(FragmentRule::SendAll(_)) |
+            Some(FragmentRule::SendAllFrom(_)) |
+            Some(FragmentRule::SendDigest(..)) |
+            Some(FragmentRule::SendClientHello(_)) |
+            Some(FragmentRule::SendReset(_)) => {
+                Poll::Pending
+            }
+            Some(FragmentRule::Recv(ref data)) => {
+                let start = buf.filled().len();
+                buf.put_slice(data);
+                self.record(Direction::Recv, &buf.filled()[start..]);
+                self.next_fragment();
+                Poll::Ready(Ok(()))
+            }
+            Some(FragmentRule::RecvAll(ref data)) => {
+                let remaining_data = &data[self.all_index..];
+                let start = buf.filled().len();
+                let buf_remaining = buf.remaining();
+                let done = buf_remaining >= remaining_data.len();
+                if done {
+                    buf.put_slice(remaining_data);
+                }
+                else {
+                    buf.put_slice(&remaining_data[..buf_remaining]);
+                }
+                let len = buf.filled().len() - start;
+                self.record(Direction::Recv, &buf.filled()[start..]);
+                if done {
+                    self.next_fragment();
+                }
+                else {
+                    self.all_index += len;
+                }
+                Poll::Ready(Ok(()))
+            }
+            Some(FragmentRule::RecvAllFrom(_)) => {
+                let len = self.read_from_source(buf.initialize_unfilled());
+                buf.advance(len);
+                if len == 0 && buf.remaining() > 0 {
+                    // An empty source: move on to the next rule.
+                    return self.poll_read_rules(cx, buf)
+                }
+                Poll::Ready(Ok(()))
+            }
+            Some(FragmentRule::Delay(delay)) => {
+                let delay = *delay;
+                match self.poll_delay(cx, delay) {
+                    Poll::Ready(()) => self.poll_read_rules(cx, buf),
+                    Poll::Pending => Poll::Pending,
+                }
+            }
+            Some(FragmentRule::WriteBlocked(_)) => {
+                self.next_fragment();
+                self.poll_read_rules(cx, buf)
+            }
+            Some(FragmentRule::SendClose) => {
+                self.fail("expected send close")
+            }
+            Some(FragmentRule::RecvClose) => {
+                if self.rule_index + 1 == self.rules.fragments.len() {
+                    self.mark_finished();
+                }
+                Poll::Ready(Ok(()))
+            }
+            None => {
+                self.fail("no more fragment rules")
+            }
+        }
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl AsyncRead for AssertStream {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<Result<(), io::Error>> {
+        self.poll_read_rules(cx, buf)
+    }
+}
+
+impl AssertStream {
+    /// Checks written data against the current rule.
+    fn write_rules(&mut self, mut buf: &[u8]) -> Result<usize, io::Error> {
+        match self.rules.fragments.get(self.rule_index) {
+            Some(FragmentRule::Send(ref data)) => {
+                if buf.len() > data.len() {
+                    buf = &buf[..data.len()];
+                }
+                if buf != data {
+                    self.mismatch(data, buf)
+                }
+                self.record(Direction::Send, buf);
+                self.next_fragment();
+                Ok(buf.len())
+            }
+            Some(FragmentRule::SendAll(ref full_data)) |
+            Some(FragmentRule::SendFirst(ref full_data)) => {
+                let mut data = &full_data[self.all_index..];
+                match buf.len().cmp(&data.len()) {
+                    Ordering::Greater => {
+                        buf = &buf[..data.len()];
+                    }
+                    Ordering::Less => {
+                        data = &data[..buf.len()];
+                    }
+                    Ordering::Equal => { }
+                }
+                if buf != data {
+                    self.mismatch(data, buf)
+                }
+                let done = self.all_index + buf.len() == full_data.len();
+                self.record(Direction::Send, buf);
+                self.all_index += buf.len();
+                if done {
+                    self.next_fragment();
+                }
+                Ok(buf.len())
+            }
+            Some(FragmentRule::SendReset(ref data)) => {
+                let data = &data[self.all_index..];
+                if data.is_empty() {
+                    return Err(reset_error())
+                }
+                let len = cmp::min(buf.len(), data.len());
+                if buf[..len] != data[..len] {
+                    self.mismatch(&data[..len], &buf[..len])
+                }
+                self.record(Direction::Send, &buf[..len]);
+                self.all_index += len;
+                Ok(len)
+            }
+            Some(FragmentRule::SendAllFrom(_)) => {
+                match self.write_from_source(buf) {
+                    // An empty source: move on to the next rule.
+                    0 if !buf.is_empty() => self.write_rules(buf),
+                    len => Ok(len)
+                }
+            }
+            Some(FragmentRule::SendDigest(..)) => {
+                let len = self.write_digest(buf);
+                if len == 0 && !buf.is_empty() {
+                    // An empty payload: move on to the next rule.
+                    return self.write_rules(buf)
+                }
+                Ok(len)
+            }
+            Some(FragmentRule::SendClientHello(_)) => {
+                Ok(self.write_client_hello(buf))
+            }
+            Some(FragmentRule::Recv(_)) | Some(FragmentRule::RecvAll(_)) |
+            Some(FragmentRule::RecvAllFrom(_)) => {
+                self.fail("expected recv")
+            }
+            Some(FragmentRule::WriteBlocked(max)) => {
+                self.refuse_write(*max);
+                Err(io::Error::new(
+                    io::ErrorKind::WouldBlock,
+                    "write window closed"
+                ))
+            }
+            Some(FragmentRule::Delay(delay)) => {
+                self.wait_delay(*delay, None)?;
+                self.write_rules(buf)
+            }
+            Some(FragmentRule::SendClose) => self.fail("expected send close"),
+            Some(FragmentRule::RecvClose) => self.fail("expected recv close"),
+            None => {
+                self.fail("no more fragment rules")
+            }
+        }
+    }
+
+}
+
+impl Write for AssertStream {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, io::Error> {
+        self.write_rules(buf)
+    }
+
+    fn flush(&mut self) -> Result<(), io::Error> {
+        Ok(())
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl AsyncWrite for AssertStream {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8]
+    ) -> Poll<Result<usize, io::Error>> {
+        loop {
+            match self.rules.fragments.get(self.rule_index) {
+                Some(FragmentRule::WriteBlocked(max)) => {
+                    let max = *max;
+                    self.refuse_write(max);
+                    self.write_waker = Some(cx.waker().clone());
+                    return Poll::Pending
+                }
+                Some(FragmentRule::Delay(delay)) => {
+                    let delay = *delay;
+                    if self.poll_delay(cx, delay).is_pending() {
+                        return Poll::Pending
+                    }
+                }
+                _ => break
+            }
+        }
+        Poll::Ready(self.write(buf))
+    }
+
+    fn poll_flush(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>
+    ) -> Poll<Result<(), io::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(
+        mut self: Pin<&mut Self>,
+        _cx: &mut Context<'_>
+    ) -> Poll<Result<(), io::Error>> {
+        Poll::Ready(self.shutdown())
+    }
+}
+
+
+//------------ REPLAY_LOG_VAR ------------------------------------------------
+
+/// The environment variable with the path for replay logs.
+///
+/// See [`AssertStream::replay_log`] for details.
+pub const REPLAY_LOG_VAR: &str = "PROTOTEST_REPLAY_LOG";
+
+
+//------------ DEFAULT_CAPTURE_LIMIT -----------------------------------------
+
+/// The default maximum number of octets kept in a stream’s transcript.
+///
+/// See [`AssertStream::set_capture_limit`] for details.
+pub const DEFAULT_CAPTURE_LIMIT: usize = 1024 * 1024;
+
+
+//------------ AssertRules ---------------------------------------------------
+
+/// The rules an followed by an assert stream.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct AssertRules {
+    pub fragments: Vec<FragmentRule>,
+}
+
+impl AssertRules {
+    /// Reads rules from a string in transcript format.
+    ///
+    /// See the [`transcript`] module for the format.
+    pub fn from_transcript(
+        s: &str
+    ) -> Result<Self, transcript::TranscriptError> {
+        transcript::parse(s)
+    }
+
+    /// Writes the rules in transcript format.
+    ///
+    /// Returns an error if a rule can’t be expressed in that format.
+    pub fn to_transcript(
+        &self
+    ) -> Result<String, transcript::TranscriptError> {
+        transcript::format(self)
+    }
+}
+
+
+//------------ conversation! -------------------------------------------------
+
+/// Creates [`AssertRules`] from an inline script.
+///
+/// The script is a sequence of statements, each terminated by a
+/// semicolon. As with the rules, the direction is seen from the protocol
+/// implementation:
+///
+/// * `send: <recipe>;` expects the implementation to send the data of the
+///   recipe and becomes a `SendAll(_)` rule,
+/// * `recv: <recipe>;` makes the implementation receive the data of the
+///   recipe and becomes a `RecvAll(_)` rule,
+/// * `send: hex "<hex>";` and `recv: hex "<hex>";` are shorthands for
+///   using a [`hex`][crate::recipe::core::hex] recipe,
+/// * `send: close;` expects the implementation to close the connection,
+/// * `recv: close;` or just `close;` closes the connection from the other
+///   end.
+///
+/// The recipes are assembled when the rules are created.
+#[macro_export]
+macro_rules! conversation {
+    ( @rules [ $( $rule:expr, )* ] ) => {
+        $crate::stream::AssertRules { fragments: vec![ $( $rule, )* ] }
+    };
+    ( @rules [ $( $rule:expr, )* ] send: close; $( $rest:tt )* ) => {
+        $crate::conversation!(
+            @rules [
+                $( $rule, )* $crate::stream::FragmentRule::SendClose,
+            ] $( $rest )*
+        )
+    };
+    ( @rules [ $( $rule:expr, )* ] recv: close; $( $rest:tt )* ) => {
+        $crate::conversation!(
+            @rules [
+                $( $rule, )* $crate::stream::FragmentRule::RecvClose,
+            ] $( $rest )*
+        )
+    };
+    ( @rules [ $( $rule:expr, )* ] close; $( $rest:tt )* ) => {
+        $crate::conversation!(
+            @rules [ $( $rule, )* ] recv: close; $( $rest )*
+        )
+    };
+    (
+        @rules [ $( $rule:expr, )* ] send: hex $hex:literal;
+        $( $rest:tt )*
+    ) => {
+        $crate::conversation!(
+            @rules [ $( $rule, )* ]
+            send: $crate::recipe::core::hex($hex); $( $rest )*
+        )
+    };
+    (
+        @rules [ $( $rule:expr, )* ] recv: hex $hex:literal;
+        $( $rest:tt )*
+    ) => {
+        $crate::conversation!(
+            @rules [ $( $rule, )* ]
+            recv: $crate::recipe::core::hex($hex); $( $rest )*
+        )
+    };
+    ( @rules [ $( $rule:expr, )* ] send: $data:expr; $( $rest:tt )* ) => {
+        $crate::conversation!(
+            @rules [
+                $( $rule, )*
+                $crate::stream::FragmentRule::SendAll(
+                    $crate::recipe::Recipe::to_fragment(&$data).into_vec()
+                ),
+            ] $( $rest )*
+        )
+    };
+    ( @rules [ $( $rule:expr, )* ] recv: $data:expr; $( $rest:tt )* ) => {
+        $crate::conversation!(
+            @rules [
+                $( $rule, )*
+                $crate::stream::FragmentRule::RecvAll(
+                    $crate::recipe::Recipe::to_fragment(&$data).into_vec()
+                ),
+            ] $( $rest )*
+        )
+    };
+    ( $( $script:tt )* ) => {
+        $crate::conversation!(@rules [] $( $script )*)
+    };
+}
+
+
+//------------ AugmentedAssertRules ------------------------------------------
+
+/// Assert rules augmented with additional data.
+///
+/// Next to the rules themselves, a script can carry arbitrary associated
+/// data of type `Asoc` – such as the parameters the implementation should
+/// be configured with – and [`Expectations`] for the outcome of the
+/// conversation that the test can check afterwards. Both can be left out
+/// in a script and will then have their default values.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct AugmentedAssertRules<Asoc = ()> {
+    /// The rules for the conversation.
+    pub rules: AssertRules,
+
+    /// Additional data associated with the rules.
+    #[serde(default)]
+    pub associated: Asoc,
+
+    /// The expected outcome of the conversation.
+    #[serde(default, skip_serializing_if = "Expectations::is_empty")]
+    pub expected: Expectations,
+}
+
+impl<Asoc> AugmentedAssertRules<Asoc> {
+    /// Creates a new stream from a copy of the rules.
+    pub fn stream(&self) -> AssertStream {
+        AssertStream::new(self.rules.clone())
+    }
+
+    /// Checks the actual outcome of a conversation.
+    ///
+    /// See [`Expectations::check`] for details.
+    pub fn check<'a>(
+        &self, actual: impl IntoIterator<Item = (&'a str, expect::Value)>
+    ) -> Result<(), expect::ExpectationError> {
+        self.expected.check(actual)
+    }
+
+    /// Checks the actual outcome of a conversation and panics on mismatch.
+    pub fn assert<'a>(
+        &self, actual: impl IntoIterator<Item = (&'a str, expect::Value)>
+    ) {
+        self.expected.assert(actual)
+    }
+}
+
+impl<'de, Asoc: Default + Deserialize<'de>> AugmentedAssertRules<Asoc> {
+    /// Reads augmented rules from a string in RON format.
+    pub fn from_ron_str(s: &'de str) -> Result<Self, ron::error::Error> {
+        ron::de::from_str(s)
+    }
+}
+
+
+//------------ FragmentRule --------------------------------------------------
+
+/// A rule for sending or receiving a fragment of data.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum FragmentRule {
+    /// A packet should be sent.
+    ///
+    /// An `AssertStream` expects the protocol implementation to send the
+    /// provided data via `AsyncWrite` or `Write`. If the data sent differs
+    /// from the data provided, the `AssertStream` will panic.
+    ///
+    /// If the protocol implementation tries to read, this will fail. An
+    /// async read will simply return `Poll::Pending`. A sync read will
+    /// return a would-block error.
+    ///
+    /// If the buffer sent is longer than the given data but starts with the
+    /// given data, the write will return the length of the given data as
+    /// written.
+    Send(Vec<u8>),
+
+    /// Data should be sent through a sequence of packets.
+    ///
+    /// This is similar to `Send(_)` except that the data may be sent through
+    /// a sequence of packets.
+    SendAll(Vec<u8>),
+
+    /// Data must be sent before the protocol implementation reads.
+    ///
+    /// This is similar to `SendAll(_)` except that the protocol
+    /// implementation must not even attempt to read while the rule is
+    /// current. Instead of failing with a would-block error or returning
+    /// `Poll::Pending`, such a read causes the `AssertStream` to panic.
+    /// This is useful for protocols where the client has to speak first.
+    SendFirst(Vec<u8>),
+
+    /// A packet should be received.
+    ///
+    /// If the protocol implementation tries to read, it will receive the
+    /// provided data. If the protocol implementation tries to write, the
+    /// `AssertStream` will panic.
+    ///
+    /// If the buffer provided is too short, panics.
+    Recv(Vec<u8>),
+
+    /// Data should be read through a sequence of packets.
+    ///
+    /// This is similar to `Recv(_)` except that the data may be read through
+    /// a sequence of packets.
+    RecvAll(Vec<u8>),
+
+    /// Data from a data source should be sent.
+    ///
+    /// This is similar to `SendAll(_)` except that the data is read from
+    /// the data source piece by piece as it is being compared. This avoids
+    /// having to keep large amounts of data in the script.
+    SendAllFrom(DataSource),
+
+    /// Data from a data source should be read.
+    ///
+    /// This is similar to `RecvAll(_)` except that the data is read from
+    /// the data source piece by piece as it is requested.
+    RecvAllFrom(DataSource),
+
+    /// Data of the given length and with the given digest should be sent.
+    ///
+    /// This is similar to `SendAll(_)` except that only the length and a
+    /// digest of the data are kept. The written data is fed into the digest
+    /// as it arrives and compared once all of it has been written. This is
+    /// useful for large but deterministic output.
+    SendDigest(usize, Digest),
+
+    /// The peer stays silent for the given time.
+    ///
+    /// A sync read waits for the duration before continuing with the next
+    /// rule, or fails with a `TimedOut` error if the stream’s read timeout
+    /// is shorter. See [`AssertStream::set_read_timeout`] for details. An
+    /// async read returns `Poll::Pending` until the duration has passed.
+    ///
+    /// A write waits for the duration in the same way before it is checked
+    /// against the next rule, but it never times out. With a
+    /// [manual clock][Clock::manual], both sync and async streams advance
+    /// the clock instead of waiting.
+    Delay(Duration),
+
+    /// The stream refuses writes until the peer opens the window again.
+    ///
+    /// While this rule is current, a sync write fails with a would-block
+    /// error and an async write returns `Poll::Pending`. The window opens
+    /// when the protocol implementation reads, which moves on to the next
+    /// rule – typically a `Recv(_)` with some sort of window update – and
+    /// wakes up a pending async write.
+    ///
+    /// The value is the number of refused writes that are tolerated. If
+    /// the protocol implementation tries to write more often, it is
+    /// busy-writing and the `AssertStream` will panic. For an async
+    /// implementation that correctly waits for its waker, this is 1.
+    WriteBlocked(usize),
+
+    /// The connection is reset by the peer while data is being sent.
+    ///
+    /// The stream accepts writes of the given data the same way as
+    /// `SendAll(_)`. A write that goes beyond the data is cut short. Once
+    /// all the data has been written, the connection is considered reset:
+    /// all further reads and writes fail with an error of kind
+    /// `ConnectionReset`. Because a write is cut short rather than failed,
+    /// the reset surfaces on the write or poll following the one that
+    /// completed the data, as with a real socket.
+    ///
+    /// The rule never completes, so it should be the last rule.
+    SendReset(Vec<u8>),
+
+    /// A TLS record with a ClientHello should be sent.
+    ///
+    /// Instead of comparing the data octet by octet, the record is parsed
+    /// and only the fields given in the [`ClientHelloMatch`] are checked.
+    /// The record may be sent through a sequence of packets. Writes are
+    /// cut short at the end of the record.
+    SendClientHello(ClientHelloMatch),
+
+    /// The protocol implementation should close the stream.
+    ///
+    /// Any reading or writing will cause a panic.
+    SendClose,
+
+    /// The connection should be closed by the peer.
+    ///
+    /// If the protocol implementation tries to read, it will receive a
+    /// zero-sized packet. If it tries to write, the `AssertStream` will
+    /// panic.
+    RecvClose,
+}
+
+impl FragmentRule {
+    /// Returns the name of the kind of rule.
+    pub fn kind(&self) -> &'static str {
+        match *self {
+            FragmentRule::Send(_) => "Send",
+            FragmentRule::SendAll(_) => "SendAll",
+            FragmentRule::SendFirst(_) => "SendFirst",
+            FragmentRule::Recv(_) => "Recv",
+            FragmentRule::RecvAll(_) => "RecvAll",
+            FragmentRule::SendAllFrom(_) => "SendAllFrom",
+            FragmentRule::RecvAllFrom(_) => "RecvAllFrom",
+            FragmentRule::SendDigest(..) => "SendDigest",
+            FragmentRule::Delay(_) => "Delay",
+            FragmentRule::WriteBlocked(_) => "WriteBlocked",
+            FragmentRule::SendReset(_) => "SendReset",
+            FragmentRule::SendClientHello(_) => "SendClientHello",
+            FragmentRule::SendClose => "SendClose",
+            FragmentRule::RecvClose => "RecvClose",
+        }
+    }
+}
+
+
+//------------ Role ----------------------------------------------------------
+
+/// The role of the implementation under test in a conversation.
+///
+/// This is used by the script builders for protocols where one side
+/// opens the conversation and the other side responds.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Role {
+    /// The implementation is the client.
+    Client,
+
+    /// The implementation is the server.
+    Server,
+}
+
+
+//------------ TranscriptEntry -----------------------------------------------
+
+/// Data exchanged under a rule.
+#[derive(Clone, Debug)]
+struct TranscriptEntry {
+    /// The index of the rule.
+    rule: usize,
+
+    /// The direction of the data.
+    direction: Direction,
+
+    /// The number of octets exchanged.
+    len: usize,
+
+    /// The data as far as it has been kept.
+    data: Vec<u8>,
+}
+
+impl TranscriptEntry {
+    /// Returns whether all the data has been kept.
+    fn is_complete(&self) -> bool {
+        self.data.len() == self.len
+    }
+
+    /// Returns the data.
+    ///
+    /// Panics if not all the data has been kept.
+    fn captured(&self) -> &[u8] {
+        if !self.is_complete() {
+            panic!(
+                "transcript incomplete: only {} of {} octets of rule {} kept",
+                self.data.len(), self.len, self.rule
+            )
+        }
+        &self.data
+    }
+}
+
+impl fmt::Display for TranscriptEntry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f, "rule {}: {} {} octets",
+            self.rule,
+            match self.direction {
+                Direction::Send => "sent",
+                Direction::Recv => "received",
+            },
+            self.len
+        )?;
+        if self.is_complete() {
+            write!(f, ": {}", HexData(&self.data))
+        }
+        else if self.data.is_empty() {
+            f.write_str(" (not kept)")
+        }
+        else {
+            write!(
+                f, ", first {} kept: {}", self.data.len(), HexData(&self.data)
+            )
+        }
+    }
+}
+
+/// The time of a piece of exchanged data.
+#[derive(Clone, Copy, Debug)]
+struct TimelineEntry {
+    /// The virtual time the data was exchanged at.
+    at: Duration,
+
+    /// The index of the rule.
+    rule: usize,
+
+    /// The direction of the data.
+    direction: Direction,
+
+    /// The number of octets.
+    len: usize,
+}
+
+impl TimelineEntry {
+    /// The time within which data is combined into a single entry.
+    const RESOLUTION: Duration = Duration::from_millis(1);
+}
+
+impl fmt::Display for TimelineEntry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f, "{:>10.3?}: rule {}: {} {} octets",
+            self.at, self.rule,
+            match self.direction {
+                Direction::Send => "sent",
+                Direction::Recv => "received",
+            },
+            self.len
+        )
+    }
+}
+
+/// The direction of exchanged data.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Direction {
+    /// Data sent by the protocol implementation.
+    Send,
+
+    /// Data received by the protocol implementation.
+    Recv,
+}
+
+/// Displays data in hex.
+struct HexData<'a>(&'a [u8]);
+
+impl fmt::Display for HexData<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (idx, octet) in self.0.iter().enumerate() {
+            if idx > 0 {
+                f.write_str(" ")?;
+            }
+            write!(f, "{:02x}", octet)?;
+        }
+        Ok(())
+    }
+}
+
+
+//------------ reset_error ---------------------------------------------------
+
+/// Returns the error for a connection reset by the peer.
+fn reset_error() -> io::Error {
+    io::Error::new(io::ErrorKind::ConnectionReset, "connection reset by peer")
+}
+
+
+//------------ DataSource ----------------------------------------------------
+
+/// Where the data of a rule is taken from.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum DataSource {
+    /// The data is read from the file at the given path.
+    File(PathBuf),
+
+    /// The data is read from the reader added under the given name.
+    ///
+    /// Readers are added to the stream at runtime via
+    /// [`AssertStream::add_source`].
+    Named(String),
+}
+
+
+//------------ Digest --------------------------------------------------------
+
+/// The digest of some data.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub enum Digest {
+    /// The CRC-32 as used by IEEE 802.3 and zlib.
+    Crc32(u32),
+
+    /// The SHA-256 hash.
+    ///
+    /// Checking data against this digest needs the `sha2` feature.
+    Sha256(Vec<u8>),
+}
+
+impl Digest {
+    /// Returns the algorithm of the digest.
+    pub fn algorithm(&self) -> DigestAlgorithm {
+        match *self {
+            Digest::Crc32(_) => DigestAlgorithm::Crc32,
+            Digest::Sha256(_) => DigestAlgorithm::Sha256,
+        }
+    }
+
+    /// Returns the CRC-32 digest of the given data.
+    pub fn crc32(data: &[u8]) -> Self {
+        Digest::Crc32(Crc32::checksum(data))
+    }
+
+    /// Returns the SHA-256 digest of the given data.
+    #[cfg(feature = "sha2")]
+    pub fn sha256(data: &[u8]) -> Self {
+        Digest::Sha256(sha2::Sha256::digest(data).to_vec())
+    }
+}
+
+impl fmt::Display for Digest {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Digest::Crc32(value) => write!(f, "crc32:{:08x}", value),
+            Digest::Sha256(ref value) => {
+                f.write_str("sha256:")?;
+                for octet in value {
+                    write!(f, "{:02x}", octet)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+
+//------------ DigestAlgorithm -----------------------------------------------
+
+/// The algorithm of a [`Digest`].
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum DigestAlgorithm {
+    /// The CRC-32 as used by IEEE 802.3 and zlib.
+    Crc32,
+
+    /// The SHA-256 hash.
+    ///
+    /// Calculating this digest needs the `sha2` feature.
+    Sha256,
+}
+
+impl DigestAlgorithm {
+    /// Returns whether digests can be calculated with this algorithm.
+    ///
+    /// This depends on the features the crate was built with.
+    pub fn is_supported(self) -> bool {
+        match self {
+            DigestAlgorithm::Crc32 => true,
+            DigestAlgorithm::Sha256 => cfg!(feature = "sha2"),
+        }
+    }
+}
+
+impl fmt::Display for DigestAlgorithm {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            DigestAlgorithm::Crc32 => "crc32",
+            DigestAlgorithm::Sha256 => "sha256",
+        })
+    }
+}
+
+
+//------------ DigestState ---------------------------------------------------
+
+/// An ongoing digest calculation.
+#[derive(Clone, Debug)]
+struct DigestState {
+    /// The number of octets digested so far.
+    seen: usize,
+
+    /// The calculation itself.
+    hasher: Hasher,
+}
+
+#[derive(Clone, Debug)]
+enum Hasher {
+    Crc32(Crc32),
+    #[cfg(feature = "sha2")]
+    Sha256(sha2::Sha256),
+}
+
+impl DigestState {
+    /// Starts a calculation for the same algorithm as _digest_.
+    ///
+    /// Returns `None` if the algorithm isn’t supported.
+    fn new(digest: &Digest) -> Option<Self> {
+        Self::with_algorithm(digest.algorithm())
+    }
+
+    /// Starts a calculation for _algo._
+    ///
+    /// Returns `None` if the algorithm isn’t supported.
+    fn with_algorithm(algo: DigestAlgorithm) -> Option<Self> {
+        Some(DigestState {
+            seen: 0,
+            hasher: match algo {
+                DigestAlgorithm::Crc32 => Hasher::Crc32(Crc32::new()),
+                #[cfg(feature = "sha2")]
+                DigestAlgorithm::Sha256 => {
+                    Hasher::Sha256(sha2::Sha256::new())
+                }
+                #[cfg(not(feature = "sha2"))]
+                DigestAlgorithm::Sha256 => return None,
+            }
+        })
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        self.seen += data.len();
+        match self.hasher {
+            Hasher::Crc32(ref mut crc) => crc.update(data),
+            #[cfg(feature = "sha2")]
+            Hasher::Sha256(ref mut hash) => hash.update(data),
+        }
+    }
+
+    fn finalize(&self) -> Digest {
+        match self.hasher {
+            Hasher::Crc32(ref crc) => Digest::Crc32(crc.value()),
+            #[cfg(feature = "sha2")]
+            Hasher::Sha256(ref hash) => {
+                Digest::Sha256(hash.clone().finalize().to_vec())
+            }
+        }
+    }
+}
+
+
+//------------ SharedReader --------------------------------------------------
+
+/// A reader that can be shared between clones of a stream.
+#[derive(Clone)]
+struct SharedReader(Arc<Mutex<dyn Read + Send>>);
+
+impl SharedReader {
+    fn new(reader: impl Read + Send + 'static) -> Self {
+        SharedReader(Arc::new(Mutex::new(reader)))
+    }
+}
+
+impl fmt::Debug for SharedReader {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("SharedReader(..)")
+    }
+}
+
+
+//------------ ActiveSource --------------------------------------------------
+
+/// The state of the data source of the current rule.
+#[derive(Clone, Debug)]
+struct ActiveSource {
+    /// The reader providing the data.
+    reader: SharedReader,
+
+    /// Data read from the reader but not yet used.
+    buf: Vec<u8>,
+
+    /// Has the reader reached its end?
+    eof: bool,
+}
+
+impl ActiveSource {
+    /// Opens a data source.
+    fn open(
+        source: &DataSource, sources: &HashMap<String, SharedReader>
+    ) -> Self {
+        let reader = match *source {
+            DataSource::File(ref path) => {
+                SharedReader::new(File::open(path).unwrap_or_else(|err| {
+                    panic!(
+                        "failed to open data source {}: {}",
+                        path.display(), err
+                    )
+                }))
+            }
+            DataSource::Named(ref name) => {
+                match sources.get(name) {
+                    Some(reader) => reader.clone(),
+                    None => panic!("unknown data source '{}'", name)
+                }
+            }
+        };
+        ActiveSource { reader, buf: Vec::new(), eof: false }
+    }
+
+    /// Tries to fill the buffer to the given length.
+    fn fill(&mut self, len: usize) {
+        let mut reader = self.reader.0.lock().unwrap();
+        while !self.eof && self.buf.len() < len {
+            let start = self.buf.len();
+            self.buf.resize(len, 0);
+            match reader.read(&mut self.buf[start..]) {
+                Ok(0) => {
+                    self.buf.truncate(start);
+                    self.eof = true;
+                }
+                Ok(read) => self.buf.truncate(start + read),
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {
+                    self.buf.truncate(start)
+                }
+                Err(err) => panic!("failed to read data source: {}", err),
+            }
+        }
+    }
+
+    /// Takes up to _len_ octets from the source.
+    ///
+    /// The data is passed to _op_. Returns the number of octets taken.
+    fn take(&mut self, len: usize, op: impl FnOnce(&[u8])) -> usize {
+        self.fill(len);
+        let len = cmp::min(len, self.buf.len());
+        op(&self.buf[..len]);
+        self.buf.drain(..len);
+        len
+    }
+
+    /// Returns whether all data has been taken from the source.
+    fn is_done(&mut self) -> bool {
+        self.fill(1);
+        self.buf.is_empty()
+    }
+}
+
+
+//============ Tests ========================================================
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn simple() {
+        let mut stream = AssertStream::from_ron_str(r#"
+            AssertRules(
+                fragments: [
+                    Recv([0x20, 0x20, 0x20]),
+                    Send([0x20, 0x20, 0x20]),
+                    RecvClose,
+                ]
+            )
+        "#).unwrap();
+        let mut buf = vec![0; 5];
+        assert_eq!(stream.read(&mut buf).unwrap(), 3);
+        assert_eq!(&buf[..3], b"\x20\x20\x20");
+        assert_eq!(stream.write(b"\x20\x20\x20").unwrap(), 3);
+        assert_eq!(stream.read(&mut buf).unwrap(), 0);
+    }
+
+    #[test]
+    fn send_all_partial() {
+        let mut stream = AssertStream::new(AssertRules {
+            fragments: vec![
+                FragmentRule::SendAll(b"abcd".to_vec()),
+                FragmentRule::Send(b"e".to_vec()),
+            ]
+        });
+        assert_eq!(stream.write(b"ab").unwrap(), 2);
+        assert_eq!(stream.write(b"c").unwrap(), 1);
+        assert_eq!(stream.write(b"de").unwrap(), 1);
+        assert_eq!(stream.write(b"e").unwrap(), 1);
+    }
+
+    #[test]
+    fn conversation_macro() {
+        use crate::recipe::core::{be, hex};
+
+        let prefix = hex("00");
+        let rules = conversation! {
+            send: hex "0102";
+            recv: (be(3u8), be(4u8));
+            send: hex("05");
+            recv: prefix;
+            send: close;
+            close;
+        };
+        let mut stream = AssertStream::new(rules);
+        let mut buf = [0u8; 2];
+        stream.write_all(b"\x01\x02").unwrap();
+        stream.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"\x03\x04");
+        stream.write_all(b"\x05").unwrap();
+        stream.read_exact(&mut buf[..1]).unwrap();
+        assert_eq!(buf[0], 0);
+        assert!(matches!(
+            stream.rules.fragments[4..],
+            [FragmentRule::SendClose, FragmentRule::RecvClose]
+        ));
+        assert_eq!(conversation!{}.fragments.len(), 0);
+    }
+
+    #[test]
+    fn limits() {
+        let mut stream = AssertStream::new(AssertRules {
+            fragments: vec![
+                FragmentRule::SendAll(b"abcd".to_vec()),
+                FragmentRule::Recv(b"efgh".to_vec()),
+            ]
+        });
+        stream.set_send_limit(Some(4));
+        stream.set_message_size_limit(Some(4));
+        stream.write_all(b"ab").unwrap();
+        stream.write_all(b"cd").unwrap();
+        assert_eq!(stream.sent(), 4);
+        let mut buf = [0u8; 4];
+        stream.read_exact(&mut buf).unwrap();
+        assert_eq!(stream.received(), 4);
+    }
+
+    #[test]
+    #[should_panic(expected = "send limit exceeded: 5 octets, limit is 4")]
+    fn send_limit() {
+        let mut stream = AssertStream::new(AssertRules {
+            fragments: vec![FragmentRule::SendAll(b"abcde".to_vec())]
+        });
+        stream.set_send_limit(Some(4));
+        let _ = stream.write_all(b"abcde");
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "message of 3 octets in rule 2 exceeds limit of 2"
+    )]
+    fn message_size_limit() {
+        let mut stream = AssertStream::new(AssertRules {
+            fragments: vec![
+                FragmentRule::SendAll(b"ab".to_vec()),
+                FragmentRule::SendAll(b"cd".to_vec()),
+                FragmentRule::SendAllFrom(DataSource::Named("in".into())),
+            ]
+        });
+        stream.add_source("in", io::Cursor::new(b"efg".to_vec()));
+        stream.set_message_size_limit(Some(2));
+        stream.write_all(b"a").unwrap();
+        stream.write_all(b"bcd").unwrap();
+        stream.write_all(b"e").unwrap();
+        let _ = stream.write_all(b"fg");
+    }
+
+    #[test]
+    fn augmented() {
+        let rules: AugmentedAssertRules<(u32, String)> = {
+            AugmentedAssertRules::from_ron_str(r#"
+                AugmentedAssertRules(
+                    rules: AssertRules(
+                        fragments: [ Recv([0x20]), RecvClose ]
+                    ),
+                    associated: (12, "foo"),
+                    expected: { "count": Int(1) },
+                )
+            "#).unwrap()
+        };
+        assert_eq!(rules.associated, (12, "foo".into()));
+        let mut stream = rules.stream();
+        let mut buf = [0u8; 4];
+        assert_eq!(stream.read(&mut buf).unwrap(), 1);
+        assert!(rules.check([("count", 1u8.into())]).is_ok());
+        assert!(rules.check([("count", 2u8.into())]).is_err());
+
+        let rules: AugmentedAssertRules = {
+            AugmentedAssertRules::from_ron_str(r#"
+                AugmentedAssertRules(
+                    rules: AssertRules(fragments: [ RecvClose ]),
+                )
+            "#).unwrap()
+        };
+        assert!(rules.expected.is_empty());
+    }
+
+    #[test]
+    fn data_sources() {
+        let path = std::env::temp_dir().join(
+            format!("prototest-source-{}", std::process::id())
+        );
+        std::fs::write(&path, b"0123456789").unwrap();
+        let mut stream = AssertStream::new(AssertRules {
+            fragments: vec![
+                FragmentRule::RecvAllFrom(DataSource::Named("in".into())),
+                FragmentRule::SendAllFrom(DataSource::File(path.clone())),
+                FragmentRule::RecvAllFrom(DataSource::Named("empty".into())),
+                FragmentRule::RecvClose,
+            ]
+        });
+        stream.add_source("in", io::Cursor::new(b"abcde".to_vec()));
+        stream.add_source("empty", io::empty());
+
+        let mut buf = [0u8; 3];
+        assert_eq!(stream.read(&mut buf).unwrap(), 3);
+        assert_eq!(&buf, b"abc");
+        assert_eq!(stream.read(&mut buf).unwrap(), 2);
+        assert_eq!(&buf[..2], b"de");
+        assert_eq!(stream.write(b"0123").unwrap(), 4);
+        assert_eq!(stream.write(b"456789abc").unwrap(), 6);
+        assert_eq!(stream.read(&mut buf).unwrap(), 0);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn read_timeout() {
+        let mut stream = AssertStream::new(AssertRules {
+            fragments: vec![
+                FragmentRule::Delay(Duration::from_millis(30)),
+                FragmentRule::Recv(b"abc".to_vec()),
+                FragmentRule::Delay(Duration::from_millis(5)),
+                FragmentRule::RecvClose,
+            ]
+        });
+        assert!(stream.set_read_timeout(Some(Duration::ZERO)).is_err());
+        stream.set_read_timeout(Some(Duration::from_millis(20))).unwrap();
+        let mut buf = [0u8; 3];
+        assert_eq!(
+            stream.read(&mut buf).unwrap_err().kind(),
+            io::ErrorKind::TimedOut
+        );
+        assert_eq!(stream.read(&mut buf).unwrap(), 3);
+        assert_eq!(stream.read(&mut buf).unwrap(), 0);
+    }
+
+    #[cfg(feature = "tokio")]
+    #[test]
+    fn async_delay() {
+        use std::sync::atomic::{AtomicBool, Ordering};
+        use std::task::Wake;
+
+        struct Flag(AtomicBool);
+
+        impl Wake for Flag {
+            fn wake(self: Arc<Self>) {
+                self.0.store(true, Ordering::SeqCst)
+            }
+        }
+
+        let flag = Arc::new(Flag(AtomicBool::new(false)));
+        let waker = Waker::from(flag.clone());
+        let mut cx = Context::from_waker(&waker);
+        let mut stream = AssertStream::new(AssertRules {
+            fragments: vec![
+                FragmentRule::Delay(Duration::from_millis(20)),
+                FragmentRule::Recv(b"ab".to_vec()),
+                FragmentRule::Delay(Duration::from_millis(20)),
+                FragmentRule::Send(b"c".to_vec()),
+            ]
+        });
+
+        let mut data = [0u8; 2];
+        let mut buf = ReadBuf::new(&mut data);
+        assert!(
+            Pin::new(&mut stream).poll_read(&mut cx, &mut buf).is_pending()
+        );
+        while !flag.0.swap(false, Ordering::SeqCst) {
+            thread::sleep(Duration::from_millis(1))
+        }
+        assert!(
+            Pin::new(&mut stream).poll_read(&mut cx, &mut buf).is_ready()
+        );
+        assert_eq!(buf.filled(), b"ab");
+        assert!(stream.elapsed() >= Duration::from_millis(20));
+
+        assert!(
+            Pin::new(&mut stream).poll_write(&mut cx, b"c").is_pending()
+        );
+        while !flag.0.swap(false, Ordering::SeqCst) {
+            thread::sleep(Duration::from_millis(1))
+        }
+        match Pin::new(&mut stream).poll_write(&mut cx, b"c") {
+            Poll::Ready(Ok(1)) => { }
+            res => panic!("unexpected {:?}", res),
+        }
+        assert!(stream.is_finished());
+        assert!(stream.elapsed() >= Duration::from_millis(40));
+    }
+
+    #[test]
+    fn write_after_delay() {
+        let mut stream = AssertStream::new(AssertRules {
+            fragments: vec![
+                FragmentRule::Delay(Duration::from_millis(20)),
+                FragmentRule::Send(b"a".to_vec()),
+            ]
+        });
+        stream.set_clock(Clock::manual());
+        stream.write_all(b"a").unwrap();
+        assert!(stream.is_finished());
+        assert_eq!(stream.send_times(), [Duration::from_millis(20)]);
+    }
+
+    #[test]
+    fn progress() {
+        let mut stream = AssertStream::new(AssertRules {
+            fragments: vec![
+                FragmentRule::Recv(b"abc".to_vec()),
+                FragmentRule::Delay(Duration::from_millis(20)),
+                FragmentRule::Recv(b"de".to_vec()),
+                FragmentRule::RecvClose,
+            ]
+        });
+        stream.set_clock(Clock::manual());
+        let mut buf = [0u8; 3];
+        assert_eq!(stream.read(&mut buf).unwrap(), 3);
+        assert_eq!(stream.read(&mut buf).unwrap(), 2);
+        assert_eq!(stream.elapsed(), Duration::from_millis(20));
+        let err = std::panic::catch_unwind(|| {
+            stream.assert_completed_within(Duration::from_secs(10))
+        }).unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<String>().unwrap().lines().next(),
+            Some("conversation not completed, expected within 10s")
+        );
+        assert_eq!(stream.read(&mut buf).unwrap(), 0);
+        stream.assert_progress(3, Duration::ZERO);
+        stream.assert_progress(5, Duration::from_millis(20));
+        stream.assert_completed_within(Duration::from_millis(20));
+        assert!(std::panic::catch_unwind(|| {
+            stream.assert_progress(4, Duration::from_millis(19))
+        }).is_err());
+        assert!(std::panic::catch_unwind(|| {
+            stream.assert_completed_within(Duration::from_millis(19))
+        }).is_err());
+        assert_eq!(stream.timeline().lines().count(), 3);
+    }
+
+    #[test]
+    fn send_first() {
+        let rules = AssertRules {
+            fragments: vec![
+                FragmentRule::SendFirst(b"ab".to_vec()),
+                FragmentRule::Recv(b"c".to_vec()),
+            ]
+        };
+        let mut stream = AssertStream::new(rules.clone());
+        stream.write_all(b"a").unwrap();
+        stream.write_all(b"b").unwrap();
+        let mut buf = [0u8; 1];
+        assert_eq!(stream.read(&mut buf).unwrap(), 1);
+        assert!(stream.is_finished());
+
+        let mut stream = AssertStream::new(rules);
+        stream.write_all(b"a").unwrap();
+        let err = std::panic::catch_unwind(move || {
+            let _ = stream.read(&mut buf);
+        }).unwrap_err();
+        assert!(
+            err.downcast_ref::<String>().unwrap().starts_with(
+                "read attempted before send was completed"
+            )
+        );
+    }
+
+    #[test]
+    fn pacing() {
+        let mut stream = AssertStream::new(AssertRules {
+            fragments: vec![
+                FragmentRule::SendAll(b"ab".to_vec()),
+                FragmentRule::Delay(Duration::from_millis(20)),
+                FragmentRule::Send(b"c".to_vec()),
+                FragmentRule::Delay(Duration::from_millis(20)),
+                FragmentRule::Send(b"d".to_vec()),
+            ]
+        });
+        stream.set_clock(Clock::manual());
+        stream.write_all(b"a").unwrap();
+        stream.write_all(b"b").unwrap();
+        let mut buf = [0u8; 1];
+        assert!(stream.read(&mut buf).is_err());
+        stream.write_all(b"c").unwrap();
+        assert!(stream.read(&mut buf).is_err());
+        stream.write_all(b"d").unwrap();
+        assert_eq!(
+            stream.send_times(),
+            [
+                Duration::ZERO, Duration::from_millis(20),
+                Duration::from_millis(40),
+            ]
+        );
+        stream.assert_send_interval(Duration::from_millis(20), Duration::ZERO);
+        stream.assert_send_rate(1, Duration::from_millis(20));
+        stream.assert_send_rate(2, Duration::from_millis(40));
+        assert!(std::panic::catch_unwind(|| {
+            stream.assert_send_rate(1, Duration::from_millis(21))
+        }).is_err());
+        assert!(std::panic::catch_unwind(|| {
+            stream.assert_send_rate(2, Duration::from_millis(41))
+        }).is_err());
+        assert!(std::panic::catch_unwind(|| {
+            stream.assert_send_interval(
+                Duration::from_secs(1), Duration::from_millis(10)
+            )
+        }).is_err());
+    }
+
+    #[test]
+    fn write_blocked() {
+        let mut stream = AssertStream::new(AssertRules {
+            fragments: vec![
+                FragmentRule::Send(b"a".to_vec()),
+                FragmentRule::WriteBlocked(2),
+                FragmentRule::Recv(b"w".to_vec()),
+                FragmentRule::Send(b"b".to_vec()),
+            ]
+        });
+        assert_eq!(stream.write(b"ab").unwrap(), 1);
+        assert_eq!(
+            stream.write(b"b").unwrap_err().kind(),
+            io::ErrorKind::WouldBlock
+        );
+        assert!(stream.write(b"b").is_err());
+        assert_eq!(stream.read(&mut [0u8; 4]).unwrap(), 1);
+        assert_eq!(stream.write(b"b").unwrap(), 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "busy write in rule 0: 2 writes refused")]
+    fn write_blocked_busy() {
+        let mut stream = AssertStream::new(AssertRules {
+            fragments: vec![FragmentRule::WriteBlocked(1)]
+        });
+        while stream.write(b"x").is_err() { }
+    }
+
+    #[test]
+    fn send_reset() {
+        let mut stream = AssertStream::new(AssertRules {
+            fragments: vec![
+                FragmentRule::Recv(b"a".to_vec()),
+                FragmentRule::SendReset(b"abc".to_vec()),
+            ]
+        });
+        let mut buf = [0u8; 4];
+        assert_eq!(stream.read(&mut buf).unwrap(), 1);
+        assert_eq!(stream.write(b"ab").unwrap(), 2);
+        assert_eq!(stream.write(b"cdef").unwrap(), 1);
+        assert_eq!(
+            stream.write(b"def").unwrap_err().kind(),
+            io::ErrorKind::ConnectionReset
+        );
+        assert_eq!(
+            stream.read(&mut buf).unwrap_err().kind(),
+            io::ErrorKind::ConnectionReset
+        );
+    }
+
+    #[test]
+    fn replay_log() {
+        let mut stream = AssertStream::new(AssertRules {
+            fragments: vec![
+                FragmentRule::Recv(b"ab".to_vec()),
+                FragmentRule::SendAll(b"cde".to_vec()),
+            ]
+        });
+        stream.read_exact(&mut [0u8; 2]).unwrap();
+        stream.write_all(b"c").unwrap();
+        stream.write_all(b"d").unwrap();
+        assert_eq!(
+            stream.recorded_rules().to_transcript().unwrap(),
+            "<< 61 62\n>> 63 64\n"
+        );
+        let log = stream.replay_log();
+        assert!(log.starts_with("progress: rule 1 of 2, offset 2\nrules:\n"));
+        assert!(log.ends_with(
+            "transcript:\n  rule 0: received 2 octets: 61 62\n  \
+             rule 1: sent 2 octets: 63 64\n"
+        ));
+        assert!(!log.contains("seeds:"));
+        stream.add_seed("payload", 4);
+        stream.add_seed("schedule", 17);
+        assert!(stream.replay_log().contains(
+            ")\nseeds:\n  payload: 4\n  schedule: 17\n\ntranscript:\n"
+        ));
+    }
+
+    #[test]
+    fn replay_log_skipped_rules() {
+        let mut stream = AssertStream::new(AssertRules {
+            fragments: vec![
+                FragmentRule::Delay(Duration::from_millis(5)),
+                FragmentRule::Recv(b"ab".to_vec()),
+                FragmentRule::WriteBlocked(1),
+                FragmentRule::RecvAllFrom(DataSource::Named("empty".into())),
+                FragmentRule::RecvAll(b"c".to_vec()),
+                FragmentRule::SendAllFrom(DataSource::Named("empty".into())),
+                FragmentRule::SendAll(b"d".to_vec()),
+            ]
+        });
+        stream.set_clock(Clock::manual());
+        stream.add_source("empty", io::empty());
+        let mut buf = [0u8; 2];
+        assert_eq!(stream.read(&mut buf).unwrap(), 2);
+        assert_eq!(stream.read(&mut buf).unwrap(), 1);
+        stream.write_all(b"d").unwrap();
+        assert!(stream.is_finished());
+        assert!(stream.replay_log().ends_with(
+            "transcript:\n  rule 1: received 2 octets: 61 62\n  \
+             rule 4: received 1 octets: 63\n  \
+             rule 6: sent 1 octets: 64\n"
+        ));
+        assert_eq!(
+            stream.timeline().lines().map(str::trim).collect::<Vec<_>>(),
+            [
+                "5.000ms: rule 1: received 2 octets",
+                "5.000ms: rule 4: received 1 octets",
+                "5.000ms: rule 6: sent 1 octets",
+                "5.000ms: finished",
+            ]
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "expected: 64\n    actual: 78\nprogress")]
+    fn replay_log_on_failure() {
+        let mut stream = AssertStream::new(AssertRules {
+            fragments: vec![FragmentRule::SendAll(b"cd".to_vec())]
+        });
+        stream.write_all(b"c").unwrap();
+        stream.write_all(b"x").unwrap();
+    }
+
+    #[test]
+    fn capture_limit() {
+        let mut stream = AssertStream::new(AssertRules {
+            fragments: vec![
+                FragmentRule::SendAll(b"abc".to_vec()),
+                FragmentRule::SendDigest(2, Digest::crc32(b"de")),
+                FragmentRule::RecvAll(b"fg".to_vec()),
+            ]
+        });
+        stream.set_capture_limit(Some(4));
+        stream.write_all(b"abcde").unwrap();
+        assert!(matches!(
+            stream.recorded_rules().fragments[..],
+            [FragmentRule::SendAll(_), FragmentRule::SendDigest(2, _)]
+        ));
+        stream.read_exact(&mut [0u8; 2]).unwrap();
+        assert!(stream.replay_log().ends_with(
+            "transcript:\n  rule 0: sent 3 octets: 61 62 63\n  \
+             rule 1: sent 2 octets (not kept)\n  \
+             rule 2: received 2 octets, first 1 kept: 66\n"
+        ));
+        assert!(std::panic::catch_unwind(|| {
+            stream.transcript_hash(DigestAlgorithm::Crc32)
+        }).is_err());
+
+        stream.set_capture_limit(None);
+        assert!(std::panic::catch_unwind(|| {
+            stream.recorded_rules()
+        }).is_err());
+    }
+
+    #[test]
+    fn send_all_pieces() {
+        let mut stream = AssertStream::new(AssertRules {
+            fragments: vec![
+                FragmentRule::SendAll(b"abcdef".to_vec()),
+                FragmentRule::RecvClose,
+            ]
+        });
+        assert_eq!(stream.write(b"ab").unwrap(), 2);
+        assert_eq!(stream.write(b"cd").unwrap(), 2);
+        assert_eq!(stream.write(b"efgh").unwrap(), 2);
+        assert_eq!(stream.read(&mut [0u8; 4]).unwrap(), 0);
+    }
+
+    #[test]
+    fn send_digest() {
+        let data = b"a rather long and deterministic payload";
+        let mut stream = AssertStream::new(AssertRules {
+            fragments: vec![
+                FragmentRule::SendDigest(data.len(), Digest::crc32(data)),
+                FragmentRule::SendDigest(0, Digest::crc32(b"")),
+                FragmentRule::Send(b"end".to_vec()),
+            ]
+        });
+        assert_eq!(stream.write(&data[..10]).unwrap(), 10);
+        assert_eq!(stream.write(&data[10..]).unwrap(), data.len() - 10);
+        assert_eq!(stream.write(b"end").unwrap(), 3);
+    }
+
+    #[test]
+    fn digest_from_ron() {
+        let mut stream = AssertStream::from_ron_str(r#"
+            AssertRules(fragments: [
+                SendDigest(3, Sha256([
+                    0x2c, 0x26, 0xb4, 0x6b, 0x68, 0xff, 0xc6, 0x8f,
+                    0xf9, 0x9b, 0x45, 0x3c, 0x1d, 0x30, 0x41, 0x34,
+                    0x13, 0x42, 0x2d, 0x70, 0x64, 0x83, 0xbf, 0xa0,
+                    0xf9, 0x8a, 0x5e, 0x88, 0x62, 0x66, 0xe7, 0xae,
+                ])),
+            ])
+        "#).unwrap();
+        if DigestAlgorithm::Sha256.is_supported() {
+            stream.write_all(b"foo").unwrap();
+        }
+        else {
+            assert!(std::panic::catch_unwind(move || {
+                stream.write_all(b"foo")
+            }).is_err());
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "sha2")]
+    #[should_panic(expected = "digest mismatch")]
+    fn send_digest_mismatch() {
+        let mut stream = AssertStream::new(AssertRules {
+            fragments: vec![
+                FragmentRule::SendDigest(3, Digest::sha256(b"foo")),
+            ]
+        });
+        stream.write_all(b"fop").unwrap();
+    }
+
+    #[test]
+    fn transcript_hash() {
+        let rules = AssertRules {
+            fragments: vec![
+                FragmentRule::SendAll(b"abc".to_vec()),
+                FragmentRule::Send(b"d".to_vec()),
+                FragmentRule::RecvAll(b"ef".to_vec()),
+            ]
+        };
+        let mut stream = AssertStream::new(rules.clone());
+        stream.write_all(b"abcd").unwrap();
+        stream.read_exact(&mut [0u8; 2]).unwrap();
+        let hash = stream.transcript_hash(DigestAlgorithm::Crc32);
+        assert_eq!(
+            hash,
+            Digest::crc32(
+                b"S\0\0\0\0\0\0\0\x04abcd\
+                  R\0\0\0\0\0\0\0\x02ef"
+            )
+        );
+
+        let mut other = AssertStream::new(rules);
+        other.write_all(b"a").unwrap();
+        other.write_all(b"bc").unwrap();
+        other.write_all(b"d").unwrap();
+        other.read_exact(&mut [0u8; 1]).unwrap();
+        other.read_exact(&mut [0u8; 1]).unwrap();
+        other.assert_transcript_hash(&hash);
+        assert!(std::panic::catch_unwind(|| {
+            other.assert_transcript_hash(&Digest::crc32(b""))
+        }).is_err());
+    }
+}
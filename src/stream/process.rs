@@ -0,0 +1,340 @@
+//! Driving external programs against scripted conversations.
+//!
+//! A [`Harness`] tests an implementation that isn’t written in Rust or
+//! can’t be linked into the test for some other reason. It listens on a
+//! TCP port on the loopback interface, spawns the program, and runs the
+//! conversation of an [`AssertStream`] with the first connection the
+//! program makes. Once the program has exited, the harness returns a
+//! [`Report`] containing both the result of the conversation and the
+//! exit status of the program.
+//!
+//! The program learns the address to connect to either through its
+//! arguments, where the placeholders `{addr}` and `{port}` are replaced
+//! with the address and port, or through the environment variables named
+//! by [`ADDR_VAR`] and [`PORT_VAR`].
+
+use std::{fmt, io, panic, thread};
+use std::ffi::OsStr;
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream};
+use std::process::{Command, ExitStatus};
+use std::time::{Duration, Instant};
+use super::AssertStream;
+use super::transport::Pump;
+
+
+//------------ Constants -----------------------------------------------------
+
+/// The environment variable with the address to connect to.
+pub const ADDR_VAR: &str = "PROTOTEST_ADDR";
+
+/// The environment variable with the port to connect to.
+pub const PORT_VAR: &str = "PROTOTEST_PORT";
+
+/// The interval for checking on the program.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+
+//------------ Harness -------------------------------------------------------
+
+/// A program to be run against a scripted conversation.
+pub struct Harness {
+    /// The program to run.
+    program: String,
+
+    /// The arguments with placeholders not yet replaced.
+    args: Vec<String>,
+
+    /// Additional environment variables.
+    envs: Vec<(String, String)>,
+
+    /// The time the program is given before it is killed.
+    timeout: Duration,
+}
+
+impl Harness {
+    /// The default timeout for the program.
+    pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+    /// Creates a new harness for the given program.
+    pub fn new(program: impl Into<String>) -> Self {
+        Harness {
+            program: program.into(),
+            args: Vec::new(),
+            envs: Vec::new(),
+            timeout: Self::DEFAULT_TIMEOUT,
+        }
+    }
+
+    /// Adds an argument.
+    ///
+    /// The placeholders `{addr}` and `{port}` are replaced with the
+    /// address and port of the listener when the program is started.
+    pub fn arg(mut self, arg: impl Into<String>) -> Self {
+        self.args.push(arg.into());
+        self
+    }
+
+    /// Adds multiple arguments.
+    pub fn args<T: Into<String>>(
+        mut self, args: impl IntoIterator<Item = T>
+    ) -> Self {
+        self.args.extend(args.into_iter().map(Into::into));
+        self
+    }
+
+    /// Sets an environment variable for the program.
+    pub fn env(
+        mut self, key: impl Into<String>, value: impl Into<String>
+    ) -> Self {
+        self.envs.push((key.into(), value.into()));
+        self
+    }
+
+    /// Sets the time the program is given before it is killed.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Runs the program against the conversation of _stream._
+    ///
+    /// Returns an error if the listener can’t be created or the program
+    /// can’t be started. Everything that goes wrong afterwards is
+    /// reported via the returned [`Report`].
+    ///
+    /// The timeout also applies to the conversation. If it hasn’t ended
+    /// by then, for instance because the program left the connection
+    /// open in a child process, the connection is shut down and the
+    /// conversation fails.
+    pub fn run(&self, stream: AssertStream) -> Result<Report, io::Error> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        listener.set_nonblocking(true)?;
+        let addr = listener.local_addr()?;
+        let mut child = self.command(addr).spawn()?;
+        let deadline = Instant::now() + self.timeout;
+        let mut stream = Some(stream);
+        let mut pump = None;
+        let mut timed_out = false;
+        let status = loop {
+            if let Some(status) = child.try_wait()? {
+                break status
+            }
+            if Instant::now() >= deadline {
+                timed_out = true;
+                let _ = child.kill();
+                break child.wait()?
+            }
+            if pump.is_none() {
+                if let Some(sock) = accept(&listener)? {
+                    pump = Self::start(&mut stream, sock)?;
+                    continue
+                }
+            }
+            thread::sleep(POLL_INTERVAL);
+        };
+
+        // The program may have connected right before it exited.
+        if pump.is_none() && !timed_out {
+            if let Some(sock) = accept(&listener)? {
+                pump = Self::start(&mut stream, sock)?;
+            }
+        }
+
+        let script = match pump {
+            Some((pump, sock)) => {
+                while !pump.is_finished() && Instant::now() < deadline {
+                    thread::sleep(POLL_INTERVAL)
+                }
+                let finished = pump.is_finished();
+                if !finished {
+                    // Unblock the pump if it is waiting for data.
+                    let _ = sock.shutdown(Shutdown::Both);
+                }
+                let res = panic::catch_unwind(panic::AssertUnwindSafe(|| {
+                    pump.join()
+                }));
+                match res {
+                    Ok(_) if finished => Ok(()),
+                    Err(err) if finished => Err(panic_message(&err)),
+                    _ => Err("conversation not finished before timeout".into())
+                }
+            }
+            None => Err("program never connected".into()),
+        };
+        Ok(Report { status, timed_out, script })
+    }
+
+    /// Starts the conversation over an accepted connection.
+    ///
+    /// Returns the pump and a handle to the connection for shutting it
+    /// down.
+    fn start(
+        stream: &mut Option<AssertStream>, sock: TcpStream
+    ) -> Result<Option<(Pump, TcpStream)>, io::Error> {
+        sock.set_nonblocking(false)?;
+        let handle = sock.try_clone()?;
+        Ok(stream.take().map(|stream| (Pump::spawn(stream, sock), handle)))
+    }
+
+    /// Creates the command for running the program.
+    fn command(&self, addr: SocketAddr) -> Command {
+        let addr_str = addr.to_string();
+        let port_str = addr.port().to_string();
+        let mut res = Command::new(&self.program);
+        res.args(self.args.iter().map(|arg| {
+            arg.replace("{addr}", &addr_str).replace("{port}", &port_str)
+        }));
+        res.env(ADDR_VAR, &addr_str).env(PORT_VAR, &port_str);
+        res.envs(self.envs.iter().map(|(key, value)| {
+            (OsStr::new(key), OsStr::new(value))
+        }));
+        res
+    }
+}
+
+
+//------------ Report --------------------------------------------------------
+
+/// The result of running a program against a conversation.
+#[derive(Clone, Debug)]
+pub struct Report {
+    /// The exit status of the program.
+    pub status: ExitStatus,
+
+    /// Whether the program was killed because it took too long.
+    pub timed_out: bool,
+
+    /// The result of the conversation.
+    ///
+    /// If the conversation failed, contains the failure message including
+    /// the replay log.
+    pub script: Result<(), String>,
+}
+
+impl Report {
+    /// Returns whether the conversation succeeded and the program exited
+    /// successfully.
+    pub fn is_success(&self) -> bool {
+        self.script.is_ok() && self.status.success() && !self.timed_out
+    }
+
+    /// Panics unless the run was a success.
+    pub fn assert_success(&self) {
+        if !self.is_success() {
+            panic!("{}", self)
+        }
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "program: {}", self.status)?;
+        if self.timed_out {
+            f.write_str(" (killed after timeout)")?;
+        }
+        match self.script {
+            Ok(()) => f.write_str("\nscript: ok"),
+            Err(ref msg) => write!(f, "\nscript: failed: {}", msg),
+        }
+    }
+}
+
+
+//------------ Helpers -------------------------------------------------------
+
+/// Accepts a connection if one is waiting.
+fn accept(listener: &TcpListener) -> Result<Option<TcpStream>, io::Error> {
+    match listener.accept() {
+        Ok((sock, _)) => Ok(Some(sock)),
+        Err(err) if err.kind() == io::ErrorKind::WouldBlock => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
+/// Extracts the message from a panic payload.
+fn panic_message(err: &(dyn std::any::Any + Send)) -> String {
+    if let Some(msg) = err.downcast_ref::<&str>() {
+        (*msg).into()
+    }
+    else if let Some(msg) = err.downcast_ref::<String>() {
+        msg.clone()
+    }
+    else {
+        "unknown panic".into()
+    }
+}
+
+
+//============ Tests =========================================================
+
+#[cfg(all(test, unix))]
+mod test {
+    use super::*;
+    use crate::stream::{AssertRules, FragmentRule};
+
+    fn stream() -> AssertStream {
+        AssertStream::new(AssertRules {
+            fragments: vec![
+                FragmentRule::SendAll(b"ping".to_vec()),
+                FragmentRule::Recv(b"pong".to_vec()),
+            ]
+        })
+    }
+
+    #[test]
+    fn conversation() {
+        let report = Harness::new("bash").args([
+            "-c",
+            "exec 3<>/dev/tcp/127.0.0.1/{port}; printf ping >&3; \
+             test \"$(head -c 4 <&3)\" = pong"
+        ]).run(stream()).unwrap();
+        report.assert_success();
+    }
+
+    #[test]
+    fn failures() {
+        let report = Harness::new("true").run(stream()).unwrap();
+        assert!(report.status.success());
+        assert_eq!(
+            report.script.unwrap_err(), "program never connected"
+        );
+
+        let report = Harness::new("sleep").arg("10")
+            .timeout(Duration::from_millis(50))
+            .run(stream()).unwrap();
+        assert!(report.timed_out);
+        assert!(!report.is_success());
+    }
+
+    #[test]
+    fn quick_exit() {
+        let rules = AssertRules {
+            fragments: vec![
+                FragmentRule::SendAll(b"ping".to_vec()),
+                FragmentRule::SendClose,
+            ]
+        };
+        for _ in 0..5 {
+            let report = Harness::new("bash").args([
+                "-c", "exec 3<>/dev/tcp/127.0.0.1/{port}; printf ping >&3"
+            ]).run(AssertStream::new(rules.clone())).unwrap();
+            report.assert_success();
+        }
+    }
+
+    #[test]
+    fn connection_left_open() {
+        let start = Instant::now();
+        let report = Harness::new("bash").args([
+            "-c",
+            "exec 3<>/dev/tcp/127.0.0.1/{port}; \
+             sleep 5 <&3 >/dev/null 2>&1 &"
+        ]).timeout(Duration::from_millis(300)).run(stream()).unwrap();
+        assert!(start.elapsed() < Duration::from_secs(3));
+        assert!(report.status.success());
+        assert_eq!(
+            report.script.unwrap_err(),
+            "conversation not finished before timeout"
+        );
+    }
+}
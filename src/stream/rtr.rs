@@ -0,0 +1,443 @@
+//! Scripts for RPKI-to-Router conversations.
+//!
+//! This module provides [`RtrScript`], a helper for building
+//! [`AssertRules`] for a conversation using the RPKI-to-Router protocol
+//! defined in RFC 6810 (version 0) and RFC 8210 (version 1). The script
+//! can be created for testing either a router, i.e., the client, or a
+//! cache, i.e., the server. In the first case, the queries are expected
+//! to be sent by the implementation and the responses are received by
+//! it, in the second case it is the other way round.
+//!
+//! Next to methods for the individual PDUs, the script provides the
+//! standard exchanges: a full synchronisation via
+//! [`reset_sync`][RtrScript::reset_sync], an incremental one via
+//! [`serial_sync`][RtrScript::serial_sync], a serial query answered with
+//! a Cache Reset via [`serial_reset`][RtrScript::serial_reset], and a
+//! query answered with an error via
+//! [`query_error`][RtrScript::query_error].
+//!
+//! None of the methods check their input. In particular, PDUs are
+//! encoded in the given version even if the version doesn’t define them.
+
+use std::net::{Ipv4Addr, Ipv6Addr};
+use super::{AssertRules, FragmentRule};
+
+
+//------------ RtrScript -----------------------------------------------------
+
+/// A builder for the rules of an RPKI-to-Router conversation.
+///
+/// The builder is created via [`router`][Self::router] or
+/// [`cache`][Self::cache] depending on which side of the conversation is
+/// being tested. Steps are then added in order through the various
+/// methods. Finally, [`into_rules`][Self::into_rules] produces the rules.
+///
+/// All PDUs are encoded with the protocol version given when creating
+/// the script.
+#[derive(Clone, Debug)]
+pub struct RtrScript {
+    /// The role of the implementation under test.
+    role: RtrRole,
+
+    /// The protocol version of the PDUs.
+    version: u8,
+
+    /// The rules produced so far.
+    fragments: Vec<FragmentRule>,
+}
+
+/// The role of the implementation under test in an RTR conversation.
+///
+/// RTR names its two sides router and cache rather than client and
+/// server, so this type is used instead of [`Role`][super::Role].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum RtrRole {
+    /// The implementation is a router, i.e., the RTR client.
+    Router,
+
+    /// The implementation is a cache, i.e., the RTR server.
+    Cache,
+}
+
+impl RtrScript {
+    /// Creates a new script for testing a router.
+    pub fn router(version: u8) -> Self {
+        Self::new(RtrRole::Router, version)
+    }
+
+    /// Creates a new script for testing a cache.
+    pub fn cache(version: u8) -> Self {
+        Self::new(RtrRole::Cache, version)
+    }
+
+    /// Creates a new script for the given role and protocol version.
+    pub fn new(role: RtrRole, version: u8) -> Self {
+        RtrScript { role, version, fragments: Vec::new() }
+    }
+
+    /// Returns the assert rules for the conversation.
+    pub fn into_rules(self) -> AssertRules {
+        AssertRules { fragments: self.fragments }
+    }
+
+    /// Adds data sent by the router.
+    pub fn router_data(mut self, data: impl Into<Vec<u8>>) -> Self {
+        let data = data.into();
+        self.fragments.push(match self.role {
+            RtrRole::Router => FragmentRule::SendAll(data),
+            RtrRole::Cache => FragmentRule::RecvAll(data),
+        });
+        self
+    }
+
+    /// Adds data sent by the cache.
+    pub fn cache_data(mut self, data: impl Into<Vec<u8>>) -> Self {
+        let data = data.into();
+        self.fragments.push(match self.role {
+            RtrRole::Router => FragmentRule::RecvAll(data),
+            RtrRole::Cache => FragmentRule::SendAll(data),
+        });
+        self
+    }
+
+    /// Adds a Serial Notify PDU sent by the cache.
+    pub fn serial_notify(self, session: u16, serial: u32) -> Self {
+        let pdu = self.pdu(0, session, &serial.to_be_bytes());
+        self.cache_data(pdu)
+    }
+
+    /// Adds a Serial Query PDU sent by the router.
+    pub fn serial_query(self, session: u16, serial: u32) -> Self {
+        let pdu = self.pdu(1, session, &serial.to_be_bytes());
+        self.router_data(pdu)
+    }
+
+    /// Adds a Reset Query PDU sent by the router.
+    pub fn reset_query(self) -> Self {
+        let pdu = self.pdu(2, 0, b"");
+        self.router_data(pdu)
+    }
+
+    /// Adds a Cache Response PDU sent by the cache.
+    pub fn cache_response(self, session: u16) -> Self {
+        let pdu = self.pdu(3, session, b"");
+        self.cache_data(pdu)
+    }
+
+    /// Adds a payload PDU for each payload sent by the cache.
+    ///
+    /// If _announce_ is `true`, the PDUs announce the payloads, otherwise
+    /// they withdraw them.
+    pub fn payloads(mut self, payloads: &[Payload], announce: bool) -> Self {
+        for payload in payloads {
+            let pdu = self.payload_pdu(payload, announce);
+            self = self.cache_data(pdu);
+        }
+        self
+    }
+
+    /// Adds an End of Data PDU sent by the cache.
+    ///
+    /// The _timing_ is only included for versions other than 0.
+    pub fn end_of_data(
+        self, session: u16, serial: u32, timing: Timing
+    ) -> Self {
+        let mut body = serial.to_be_bytes().to_vec();
+        if self.version > 0 {
+            body.extend_from_slice(&timing.refresh.to_be_bytes());
+            body.extend_from_slice(&timing.retry.to_be_bytes());
+            body.extend_from_slice(&timing.expire.to_be_bytes());
+        }
+        let pdu = self.pdu(7, session, &body);
+        self.cache_data(pdu)
+    }
+
+    /// Adds a Cache Reset PDU sent by the cache.
+    pub fn cache_reset(self) -> Self {
+        let pdu = self.pdu(8, 0, b"");
+        self.cache_data(pdu)
+    }
+
+    /// Adds an Error Report PDU sent by the router.
+    ///
+    /// The PDU contains the erroneous PDU _pdu_ and the error text _text,_
+    /// either of which can be empty.
+    pub fn router_error(
+        self, code: ErrorCode, pdu: &[u8], text: &str
+    ) -> Self {
+        let pdu = self.error_pdu(code, pdu, text);
+        self.router_data(pdu)
+    }
+
+    /// Adds an Error Report PDU sent by the cache.
+    ///
+    /// The PDU contains the erroneous PDU _pdu_ and the error text _text,_
+    /// either of which can be empty.
+    pub fn cache_error(
+        self, code: ErrorCode, pdu: &[u8], text: &str
+    ) -> Self {
+        let pdu = self.error_pdu(code, pdu, text);
+        self.cache_data(pdu)
+    }
+
+    /// Adds the cache closing the connection.
+    pub fn cache_close(mut self) -> Self {
+        self.fragments.push(match self.role {
+            RtrRole::Router => FragmentRule::RecvClose,
+            RtrRole::Cache => FragmentRule::SendClose,
+        });
+        self
+    }
+
+    /// Adds a full synchronisation.
+    ///
+    /// The router sends a Reset Query and the cache responds with a Cache
+    /// Response, announcements for all _payloads,_ and End of Data.
+    pub fn reset_sync(
+        self, session: u16, serial: u32, payloads: &[Payload], timing: Timing
+    ) -> Self {
+        self.reset_query()
+            .cache_response(session)
+            .payloads(payloads, true)
+            .end_of_data(session, serial, timing)
+    }
+
+    /// Adds an incremental synchronisation.
+    ///
+    /// The router sends a Serial Query for serial number _from._ The cache
+    /// responds with a Cache Response, announcements for _announced,_
+    /// withdrawals for _withdrawn,_ and End of Data for serial number _to._
+    pub fn serial_sync(
+        self, session: u16, from: u32, to: u32,
+        announced: &[Payload], withdrawn: &[Payload], timing: Timing
+    ) -> Self {
+        self.serial_query(session, from)
+            .cache_response(session)
+            .payloads(announced, true)
+            .payloads(withdrawn, false)
+            .end_of_data(session, to, timing)
+    }
+
+    /// Adds a Serial Query the cache can’t answer incrementally.
+    ///
+    /// The router sends a Serial Query and the cache responds with a Cache
+    /// Reset. Normally, this is followed by a
+    /// [`reset_sync`][Self::reset_sync].
+    pub fn serial_reset(self, session: u16, serial: u32) -> Self {
+        self.serial_query(session, serial).cache_reset()
+    }
+
+    /// Adds a Reset Query answered with an Error Report.
+    ///
+    /// The Error Report contains the query as the erroneous PDU. For
+    /// [`ErrorCode::NO_DATA`], the cache keeps the connection open as the
+    /// router is expected to retry later. For all other codes, the error
+    /// is fatal and the cache closes the connection.
+    pub fn query_error(self, code: ErrorCode, text: &str) -> Self {
+        let query = self.pdu(2, 0, b"");
+        let res = self.reset_query().cache_error(code, &query, text);
+        if code == ErrorCode::NO_DATA { res } else { res.cache_close() }
+    }
+
+    /// Returns a PDU with the given type, session field, and body.
+    fn pdu(&self, pdu_type: u8, session: u16, body: &[u8]) -> Vec<u8> {
+        let mut res = Vec::with_capacity(8 + body.len());
+        res.push(self.version);
+        res.push(pdu_type);
+        res.extend_from_slice(&session.to_be_bytes());
+        res.extend_from_slice(&((8 + body.len()) as u32).to_be_bytes());
+        res.extend_from_slice(body);
+        res
+    }
+
+    /// Returns the PDU for a payload.
+    fn payload_pdu(&self, payload: &Payload, announce: bool) -> Vec<u8> {
+        let flags = u8::from(announce);
+        match *payload {
+            Payload::V4 { prefix, prefix_len, max_len, asn } => {
+                let mut body = vec![flags, prefix_len, max_len, 0];
+                body.extend_from_slice(&prefix.octets());
+                body.extend_from_slice(&asn.to_be_bytes());
+                self.pdu(4, 0, &body)
+            }
+            Payload::V6 { prefix, prefix_len, max_len, asn } => {
+                let mut body = vec![flags, prefix_len, max_len, 0];
+                body.extend_from_slice(&prefix.octets());
+                body.extend_from_slice(&asn.to_be_bytes());
+                self.pdu(6, 0, &body)
+            }
+            Payload::RouterKey { ref ski, asn, ref spki } => {
+                let mut body = ski.to_vec();
+                body.extend_from_slice(&asn.to_be_bytes());
+                body.extend_from_slice(spki);
+                self.pdu(9, u16::from(flags) << 8, &body)
+            }
+        }
+    }
+
+    /// Returns an Error Report PDU.
+    fn error_pdu(&self, code: ErrorCode, pdu: &[u8], text: &str) -> Vec<u8> {
+        let mut body = Vec::with_capacity(8 + pdu.len() + text.len());
+        body.extend_from_slice(&(pdu.len() as u32).to_be_bytes());
+        body.extend_from_slice(pdu);
+        body.extend_from_slice(&(text.len() as u32).to_be_bytes());
+        body.extend_from_slice(text.as_bytes());
+        self.pdu(10, code.0, &body)
+    }
+}
+
+
+//------------ Payload -------------------------------------------------------
+
+/// A single item of RPKI data transferred to the router.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Payload {
+    /// An IPv4 prefix origin.
+    V4 {
+        prefix: Ipv4Addr,
+        prefix_len: u8,
+        max_len: u8,
+        asn: u32,
+    },
+
+    /// An IPv6 prefix origin.
+    V6 {
+        prefix: Ipv6Addr,
+        prefix_len: u8,
+        max_len: u8,
+        asn: u32,
+    },
+
+    /// A router key.
+    ///
+    /// Router keys are only defined in version 1.
+    RouterKey {
+        /// The subject key identifier.
+        ski: [u8; 20],
+        asn: u32,
+
+        /// The DER encoded subject public key info.
+        spki: Vec<u8>,
+    },
+}
+
+impl Payload {
+    /// Creates an IPv4 prefix origin.
+    pub fn v4(
+        prefix: Ipv4Addr, prefix_len: u8, max_len: u8, asn: u32
+    ) -> Self {
+        Payload::V4 { prefix, prefix_len, max_len, asn }
+    }
+
+    /// Creates an IPv6 prefix origin.
+    pub fn v6(
+        prefix: Ipv6Addr, prefix_len: u8, max_len: u8, asn: u32
+    ) -> Self {
+        Payload::V6 { prefix, prefix_len, max_len, asn }
+    }
+
+    /// Creates a router key.
+    pub fn router_key(ski: [u8; 20], asn: u32, spki: Vec<u8>) -> Self {
+        Payload::RouterKey { ski, asn, spki }
+    }
+}
+
+
+//------------ Timing --------------------------------------------------------
+
+/// The timing parameters of an End of Data PDU in seconds.
+///
+/// The default values are those recommended by RFC 8210.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Timing {
+    pub refresh: u32,
+    pub retry: u32,
+    pub expire: u32,
+}
+
+impl Default for Timing {
+    fn default() -> Self {
+        Timing { refresh: 3600, retry: 600, expire: 7200 }
+    }
+}
+
+
+//------------ ErrorCode -----------------------------------------------------
+
+/// The error code of an Error Report PDU.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct ErrorCode(pub u16);
+
+impl ErrorCode {
+    pub const CORRUPT_DATA: Self = ErrorCode(0);
+    pub const INTERNAL_ERROR: Self = ErrorCode(1);
+    pub const NO_DATA: Self = ErrorCode(2);
+    pub const INVALID_REQUEST: Self = ErrorCode(3);
+    pub const UNSUPPORTED_VERSION: Self = ErrorCode(4);
+    pub const UNSUPPORTED_PDU_TYPE: Self = ErrorCode(5);
+    pub const WITHDRAWAL_OF_UNKNOWN: Self = ErrorCode(6);
+    pub const DUPLICATE_ANNOUNCEMENT: Self = ErrorCode(7);
+    pub const UNEXPECTED_VERSION: Self = ErrorCode(8);
+}
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::{Read, Write};
+    use crate::stream::AssertStream;
+    use crate::stream::extract::rtr_session_id;
+
+    #[test]
+    fn router_reset_sync() {
+        let payloads = [
+            Payload::v4(Ipv4Addr::new(192, 0, 2, 0), 24, 24, 64496),
+        ];
+        let rules = RtrScript::router(1)
+            .reset_sync(0x1234, 7, &payloads, Timing::default())
+            .into_rules();
+        let mut stream = AssertStream::new(rules);
+        let mut buf = vec![0; 512];
+
+        stream.write_all(b"\x01\x02\0\0\0\0\0\x08").unwrap();
+        stream.read_exact(&mut buf[..8]).unwrap();
+        assert_eq!(&buf[..8], b"\x01\x03\x12\x34\0\0\0\x08");
+        stream.read_exact(&mut buf[..20]).unwrap();
+        assert_eq!(
+            &buf[..20],
+            b"\x01\x04\0\0\0\0\0\x14\x01\x18\x18\0\
+              \xc0\0\x02\0\0\0\xfb\xf0"
+        );
+        stream.read_exact(&mut buf[..24]).unwrap();
+        assert_eq!(rtr_session_id(&buf[..24]), Some(0x1234));
+        assert_eq!(
+            &buf[..24],
+            b"\x01\x07\x12\x34\0\0\0\x18\0\0\0\x07\
+              \0\0\x0e\x10\0\0\x02\x58\0\0\x1c\x20"
+        );
+        assert!(stream.is_finished());
+    }
+
+    #[test]
+    fn cache_errors() {
+        let rules = RtrScript::cache(0)
+            .serial_reset(1, 2)
+            .query_error(ErrorCode::UNSUPPORTED_VERSION, "v")
+            .into_rules();
+        let mut stream = AssertStream::new(rules);
+        let mut buf = vec![0; 512];
+
+        let len = stream.read(&mut buf).unwrap();
+        assert_eq!(&buf[..len], b"\0\x01\0\x01\0\0\0\x0c\0\0\0\x02");
+        stream.write_all(b"\0\x08\0\0\0\0\0\x08").unwrap();
+        let len = stream.read(&mut buf).unwrap();
+        assert_eq!(&buf[..len], b"\0\x02\0\0\0\0\0\x08");
+        stream.write_all(
+            b"\0\x0a\0\x04\0\0\0\x19\0\0\0\x08\
+              \0\x02\0\0\0\0\0\x08\0\0\0\x01v"
+        ).unwrap();
+        stream.shutdown().unwrap();
+    }
+}
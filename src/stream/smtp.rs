@@ -0,0 +1,281 @@
+//! Scripts for SMTP conversations.
+//!
+//! This module provides [`SmtpScript`], a helper for building
+//! [`AssertRules`] for an SMTP conversation from a high-level description
+//! of its steps. The script can be created for testing either an SMTP
+//! client or an SMTP server. In the first case, the commands are expected
+//! to be sent by the implementation and the replies are received by it,
+//! in the second case it is the other way round.
+//!
+//! Mail data can be transferred with correct dot-stuffing or with one of
+//! several deliberately broken variants, see [`DotStuffing`].
+
+use super::{AssertRules, FragmentRule, Role};
+
+
+//------------ SmtpScript ----------------------------------------------------
+
+/// A builder for the rules of an SMTP conversation.
+///
+/// The builder is created via [`client`][Self::client] or
+/// [`server`][Self::server] depending on which side of the conversation
+/// is being tested. Steps are then added in order through the various
+/// methods. Finally, [`into_rules`][Self::into_rules] produces the rules.
+#[derive(Clone, Debug)]
+pub struct SmtpScript {
+    /// The role of the implementation under test.
+    role: Role,
+
+    /// The rules produced so far.
+    fragments: Vec<FragmentRule>,
+}
+
+impl SmtpScript {
+    /// Creates a new script for testing an SMTP client.
+    pub fn client() -> Self {
+        Self::new(Role::Client)
+    }
+
+    /// Creates a new script for testing an SMTP server.
+    pub fn server() -> Self {
+        Self::new(Role::Server)
+    }
+
+    /// Creates a new script for the given role.
+    pub fn new(role: Role) -> Self {
+        SmtpScript { role, fragments: Vec::new() }
+    }
+
+    /// Returns the assert rules for the conversation.
+    pub fn into_rules(self) -> AssertRules {
+        AssertRules { fragments: self.fragments }
+    }
+
+    /// Adds data sent by the client.
+    pub fn client_data(mut self, data: impl Into<Vec<u8>>) -> Self {
+        let data = data.into();
+        self.fragments.push(match self.role {
+            Role::Client => FragmentRule::SendAll(data),
+            Role::Server => FragmentRule::RecvAll(data),
+        });
+        self
+    }
+
+    /// Adds data sent by the server.
+    pub fn server_data(mut self, data: impl Into<Vec<u8>>) -> Self {
+        let data = data.into();
+        self.fragments.push(match self.role {
+            Role::Client => FragmentRule::RecvAll(data),
+            Role::Server => FragmentRule::SendAll(data),
+        });
+        self
+    }
+
+    /// Adds a command line sent by the client.
+    ///
+    /// The line must be given without the final CRLF.
+    pub fn command(self, line: &str) -> Self {
+        self.client_data(format!("{}\r\n", line))
+    }
+
+    /// Adds a possibly multi-line reply sent by the server.
+    ///
+    /// The lines must be given without reply code and final CRLF. If
+    /// _lines_ is empty, a single line with only the code is added.
+    pub fn reply(self, code: u16, lines: &[&str]) -> Self {
+        self.server_data(reply_text(code, lines))
+    }
+
+    /// Adds the server greeting.
+    pub fn banner(self, domain: &str) -> Self {
+        self.reply(220, &[&format!("{} ESMTP", domain)])
+    }
+
+    /// Adds an EHLO command and the server’s reply.
+    ///
+    /// The reply starts with the server’s domain and lists the given
+    /// capabilities each on a line of its own.
+    pub fn ehlo(
+        self, client_domain: &str, server_domain: &str, capabilities: &[&str]
+    ) -> Self {
+        let mut lines = vec![server_domain];
+        lines.extend_from_slice(capabilities);
+        self.command(&format!("EHLO {}", client_domain))
+            .reply(250, &lines)
+    }
+
+    /// Adds a HELO command and the server’s reply.
+    pub fn helo(self, client_domain: &str, server_domain: &str) -> Self {
+        self.command(&format!("HELO {}", client_domain))
+            .reply(250, &[server_domain])
+    }
+
+    /// Adds a MAIL command and a positive reply.
+    ///
+    /// The reverse path is given without the angle brackets.
+    pub fn mail_from(self, reverse_path: &str) -> Self {
+        self.command(&format!("MAIL FROM:<{}>", reverse_path))
+            .reply(250, &["OK"])
+    }
+
+    /// Adds a RCPT command and a positive reply.
+    ///
+    /// The forward path is given without the angle brackets.
+    pub fn rcpt_to(self, forward_path: &str) -> Self {
+        self.command(&format!("RCPT TO:<{}>", forward_path))
+            .reply(250, &["OK"])
+    }
+
+    /// Adds a mail transaction’s DATA command, the mail data, and replies.
+    ///
+    /// The mail data is given as it would be passed to the client, i.e.,
+    /// without dot-stuffing. How the data appears in the conversation is
+    /// determined by _stuffing._
+    pub fn data(self, content: &[u8], stuffing: DotStuffing) -> Self {
+        self.command("DATA")
+            .reply(354, &["Start mail input; end with <CRLF>.<CRLF>"])
+            .client_data(stuffing.encode(content))
+            .reply(250, &["OK"])
+    }
+
+    /// Adds a QUIT command, its reply, and the server closing the
+    /// connection.
+    pub fn quit(mut self, server_domain: &str) -> Self {
+        self = self.command("QUIT").reply(
+            221, &[&format!("{} Service closing transmission channel",
+                   server_domain)]
+        );
+        self.fragments.push(match self.role {
+            Role::Client => FragmentRule::RecvClose,
+            Role::Server => FragmentRule::SendClose,
+        });
+        self
+    }
+}
+
+
+//------------ DotStuffing ---------------------------------------------------
+
+/// How mail data is transferred after the DATA command.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum DotStuffing {
+    /// The data is transferred correctly.
+    ///
+    /// Lines starting with a period get an additional period prepended,
+    /// a final CRLF is added if the content doesn’t end in one, and the
+    /// data is terminated by a line containing only a period.
+    Stuffed,
+
+    /// Lines starting with a period are left alone.
+    ///
+    /// The data is otherwise terminated correctly. Content that contains
+    /// a line consisting of only a period will thus end the data early.
+    Unstuffed,
+
+    /// The data is correctly stuffed but terminated with bare line feeds.
+    ///
+    /// The final sequence is `"\n.\n"` instead of `"\r\n.\r\n"`, as used in
+    /// SMTP smuggling attacks.
+    BareLf,
+
+    /// The data is correctly stuffed but the terminating line is missing.
+    Unterminated,
+}
+
+impl DotStuffing {
+    /// Returns the data as it appears in the conversation.
+    pub fn encode(self, content: &[u8]) -> Vec<u8> {
+        let mut res = Vec::with_capacity(content.len() + 5);
+        let mut line_start = true;
+        for &ch in content {
+            if line_start && ch == b'.' && self != DotStuffing::Unstuffed {
+                res.push(b'.');
+            }
+            res.push(ch);
+            line_start = ch == b'\n';
+        }
+        match self {
+            DotStuffing::Stuffed | DotStuffing::Unstuffed => {
+                if !res.ends_with(b"\r\n") {
+                    res.extend_from_slice(b"\r\n");
+                }
+                res.extend_from_slice(b".\r\n");
+            }
+            DotStuffing::BareLf => {
+                if res.ends_with(b"\r\n") {
+                    res.truncate(res.len() - 2);
+                }
+                res.extend_from_slice(b"\n.\n");
+            }
+            DotStuffing::Unterminated => { }
+        }
+        res
+    }
+}
+
+
+//------------ Helpers -------------------------------------------------------
+
+/// Returns the text of a reply.
+fn reply_text(code: u16, lines: &[&str]) -> String {
+    if lines.is_empty() {
+        return format!("{}\r\n", code)
+    }
+    let mut res = String::new();
+    for (idx, line) in lines.iter().enumerate() {
+        let sep = if idx + 1 == lines.len() { ' ' } else { '-' };
+        res.push_str(&format!("{}{}{}\r\n", code, sep, line));
+    }
+    res
+}
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::{Read, Write};
+    use crate::stream::AssertStream;
+
+    #[test]
+    fn stuffing() {
+        assert_eq!(
+            DotStuffing::Stuffed.encode(b"a\r\n.b\r\n."),
+            b"a\r\n..b\r\n..\r\n.\r\n"
+        );
+        assert_eq!(
+            DotStuffing::Unstuffed.encode(b"a\r\n.b"),
+            b"a\r\n.b\r\n.\r\n"
+        );
+        assert_eq!(
+            DotStuffing::BareLf.encode(b".a\r\n"),
+            b"..a\n.\n"
+        );
+        assert_eq!(DotStuffing::Unterminated.encode(b".a"), b"..a");
+    }
+
+    #[test]
+    fn client_conversation() {
+        let rules = SmtpScript::client()
+            .banner("mx.example.com")
+            .ehlo("client.example.org", "mx.example.com", &["8BITMIME"])
+            .quit("mx.example.com")
+            .into_rules();
+        let mut stream = AssertStream::new(rules);
+        let mut buf = vec![0; 512];
+
+        let len = stream.read(&mut buf).unwrap();
+        assert_eq!(&buf[..len], b"220 mx.example.com ESMTP\r\n");
+        stream.write_all(b"EHLO client.example.org\r\n").unwrap();
+        let len = stream.read(&mut buf).unwrap();
+        assert_eq!(
+            &buf[..len],
+            b"250-mx.example.com\r\n250 8BITMIME\r\n"
+        );
+        stream.write_all(b"QUIT\r\n").unwrap();
+        let len = stream.read(&mut buf).unwrap();
+        assert!(buf[..len].starts_with(b"221 "));
+        assert_eq!(stream.read(&mut buf).unwrap(), 0);
+    }
+}
@@ -0,0 +1,230 @@
+//! Splitting data into rules along record boundaries.
+//!
+//! Many protocols send a sequence of length-prefixed records over a
+//! stream. When turning a corpus of such data into a conversation script,
+//! each record typically becomes a rule of its own. The functions in this
+//! module do this automatically given a [`Framing`] that describes where
+//! the length of a record can be found. They can also produce rules split
+//! on deliberately wrong boundaries to check that an implementation
+//! doesn’t assume that a read returns exactly one record.
+
+use crate::recipe::Recipe;
+use super::FragmentRule;
+
+
+//------------ Framing -------------------------------------------------------
+
+/// A description of how records are framed.
+///
+/// Each record starts with a header of a fixed length that contains an
+/// unsigned integer giving the length of the record. By default, the
+/// length is in network byte order and gives the length of the record
+/// without the header.
+#[derive(Clone, Copy, Debug)]
+pub struct Framing {
+    /// The length of the record header.
+    header_len: usize,
+
+    /// The position of the length field within the header.
+    length_offset: usize,
+
+    /// The size of the length field in octets.
+    length_size: usize,
+
+    /// Is the length field in little-endian byte order?
+    little_endian: bool,
+
+    /// Does the length include the header?
+    inclusive: bool,
+}
+
+impl Framing {
+    /// Creates a framing with the length at the given offset and size.
+    ///
+    /// The header ends right after the length field. The size must be at
+    /// most eight octets.
+    pub fn new(length_offset: usize, length_size: usize) -> Self {
+        assert!(length_size <= 8, "length field too long");
+        Framing {
+            header_len: length_offset + length_size,
+            length_offset,
+            length_size,
+            little_endian: false,
+            inclusive: false,
+        }
+    }
+
+    /// Creates the framing of a plain two octet length prefix.
+    pub fn prefix16() -> Self {
+        Self::new(0, 2)
+    }
+
+    /// Creates the framing of a plain four octet length prefix.
+    pub fn prefix32() -> Self {
+        Self::new(0, 4)
+    }
+
+    /// Sets the length of the record header.
+    pub fn header_len(mut self, len: usize) -> Self {
+        self.header_len = len;
+        self
+    }
+
+    /// Sets the length field to little-endian byte order.
+    pub fn little_endian(mut self) -> Self {
+        self.little_endian = true;
+        self
+    }
+
+    /// Sets the length field to include the header.
+    pub fn inclusive(mut self) -> Self {
+        self.inclusive = true;
+        self
+    }
+
+    /// Splits data into records.
+    ///
+    /// If the data ends with an incomplete record, whatever is there of
+    /// it is returned as the last item. A record with an inclusive length
+    /// shorter than its header is treated as being just the header.
+    pub fn split<'a>(&self, mut data: &'a [u8]) -> Vec<&'a [u8]> {
+        let mut res = Vec::new();
+        while !data.is_empty() {
+            let len = self.record_len(data).unwrap_or(data.len());
+            let (head, tail) = data.split_at(len.min(data.len()));
+            res.push(head);
+            data = tail;
+        }
+        res
+    }
+
+    /// Returns the length of the record at the start of _data._
+    ///
+    /// Returns `None` if the header is incomplete.
+    fn record_len(&self, data: &[u8]) -> Option<usize> {
+        let field = data.get(
+            self.length_offset..self.length_offset + self.length_size
+        )?;
+        if data.len() < self.header_len {
+            return None
+        }
+        let mut buf = [0u8; 8];
+        let len = if self.little_endian {
+            buf[..field.len()].copy_from_slice(field);
+            u64::from_le_bytes(buf)
+        }
+        else {
+            buf[8 - field.len()..].copy_from_slice(field);
+            u64::from_be_bytes(buf)
+        };
+        let len = usize::try_from(len).unwrap_or(usize::MAX);
+        Some(
+            if self.inclusive { len.max(self.header_len) }
+            else { len.saturating_add(self.header_len) }
+        )
+    }
+}
+
+
+//------------ send_records and recv_records ---------------------------------
+
+/// Returns rules expecting the data of a recipe to be sent record by record.
+///
+/// Each record becomes a `SendAll(_)` rule.
+pub fn send_records(
+    data: impl Recipe, framing: &Framing
+) -> Vec<FragmentRule> {
+    let data = data.to_fragment();
+    framing.split(&data).into_iter().map(|record| {
+        FragmentRule::SendAll(record.into())
+    }).collect()
+}
+
+/// Returns rules providing the data of a recipe record by record.
+///
+/// Each record becomes a `Recv(_)` rule, so each read receives exactly
+/// one record.
+pub fn recv_records(
+    data: impl Recipe, framing: &Framing
+) -> Vec<FragmentRule> {
+    recv_shifted(data, framing, 0)
+}
+
+/// Returns rules providing the data with record boundaries shifted.
+///
+/// Each boundary between records is moved by _shift_ octets, forwards if
+/// positive and backwards if negative, and each resulting piece becomes a
+/// `Recv(_)` rule. Thus, each read receives either a partial record or a
+/// record plus the start of the next one.
+pub fn recv_shifted(
+    data: impl Recipe, framing: &Framing, shift: isize
+) -> Vec<FragmentRule> {
+    let data = data.to_fragment();
+    let mut res = Vec::new();
+    let mut start = 0;
+    let mut boundary = 0;
+    for record in framing.split(&data) {
+        boundary += record.len();
+        let end = boundary.saturating_add_signed(shift).min(data.len());
+        if end > start {
+            res.push(FragmentRule::Recv(data[start..end].into()));
+            start = end;
+        }
+    }
+    if start < data.len() {
+        res.push(FragmentRule::Recv(data[start..].into()));
+    }
+    res
+}
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::recipe::core::hex;
+
+    fn data(rules: &[FragmentRule]) -> Vec<&[u8]> {
+        rules.iter().map(|rule| match *rule {
+            FragmentRule::SendAll(ref data) => data.as_slice(),
+            FragmentRule::Recv(ref data) => data.as_slice(),
+            _ => panic!("unexpected rule"),
+        }).collect()
+    }
+
+    #[test]
+    fn split() {
+        assert_eq!(
+            Framing::prefix16().split(b"\0\x01a\0\0\0\x02b"),
+            [b"\0\x01a".as_ref(), b"\0\0", b"\0\x02b"]
+        );
+        let tls = Framing::new(3, 2);
+        assert_eq!(
+            data(&send_records(hex("1603010001ff 1703030000"), &tls)),
+            [b"\x16\x03\x01\x00\x01\xff".as_ref(), b"\x17\x03\x03\x00\x00"]
+        );
+        let rtr = Framing::new(4, 4).inclusive();
+        assert_eq!(
+            Framing::prefix16().little_endian().split(b"\x01\0ab").len(), 2
+        );
+        assert_eq!(rtr.split(&[0, 0, 0, 0, 0, 0, 0, 9, 1]).len(), 1);
+    }
+
+    #[test]
+    fn shifted() {
+        let data_in = hex("0001aa 0002bbbb");
+        assert_eq!(
+            data(&recv_records(&data_in, &Framing::prefix16())),
+            [b"\0\x01\xaa".as_ref(), b"\0\x02\xbb\xbb"]
+        );
+        assert_eq!(
+            data(&recv_shifted(&data_in, &Framing::prefix16(), 1)),
+            [b"\0\x01\xaa\0".as_ref(), b"\x02\xbb\xbb"]
+        );
+        assert_eq!(
+            data(&recv_shifted(&data_in, &Framing::prefix16(), -1)),
+            [b"\0\x01".as_ref(), b"\xaa\0\x02\xbb", b"\xbb"]
+        );
+    }
+}
@@ -0,0 +1,347 @@
+//! Matching TLS messages written by the protocol implementation.
+//!
+//! The exact bytes of a TLS ClientHello depend on the TLS library and its
+//! version, the order of its extensions, random values, and much more.
+//! Asserting on them via golden files is brittle. Instead, a
+//! [`ClientHelloMatch`] parses the ClientHello just enough to check the
+//! fields a test is actually interested in. It is used via the
+//! [`FragmentRule::SendClientHello`] rule.
+//!
+//! [`FragmentRule::SendClientHello`]: super::FragmentRule::SendClientHello
+
+use std::fmt;
+use serde::{Deserialize, Serialize};
+
+
+//------------ ClientHelloMatch ----------------------------------------------
+
+/// The expected values of selected ClientHello fields.
+///
+/// Fields that are `None` are not checked.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(default)]
+pub struct ClientHelloMatch {
+    /// The expected host name in the server name indication extension.
+    ///
+    /// An empty string expects the extension to be absent.
+    pub server_name: Option<String>,
+
+    /// The expected list of protocols in the ALPN extension.
+    ///
+    /// An empty list expects the extension to be absent.
+    pub alpn: Option<Vec<String>>,
+
+    /// The expected list of offered versions.
+    ///
+    /// The list is taken from the supported versions extension if present
+    /// or consists of only the legacy version field otherwise.
+    pub versions: Option<Vec<u16>>,
+}
+
+impl ClientHelloMatch {
+    /// Returns the length of the TLS record at the start of _data._
+    ///
+    /// Returns `None` if there isn’t enough data for the record header.
+    pub(super) fn record_len(data: &[u8]) -> Option<usize> {
+        let header = data.get(..5)?;
+        Some(5 + usize::from(u16::from_be_bytes([header[3], header[4]])))
+    }
+
+    /// Checks a TLS record containing a ClientHello.
+    pub fn check(&self, record: &[u8]) -> Result<(), HelloMismatch> {
+        let hello = ClientHello::parse(record).ok_or_else(|| {
+            HelloMismatch("not a ClientHello record".into())
+        })?;
+        if let Some(ref expected) = self.server_name {
+            let actual = hello.server_name.unwrap_or_default();
+            if actual != *expected {
+                return Err(HelloMismatch(format!(
+                    "server name: expected '{}', got '{}'",
+                    expected, actual
+                )))
+            }
+        }
+        if let Some(ref expected) = self.alpn {
+            if hello.alpn != *expected {
+                return Err(HelloMismatch(format!(
+                    "ALPN: expected {:?}, got {:?}", expected, hello.alpn
+                )))
+            }
+        }
+        if let Some(ref expected) = self.versions {
+            if hello.versions != *expected {
+                return Err(HelloMismatch(format!(
+                    "versions: expected {:04x?}, got {:04x?}",
+                    expected, hello.versions
+                )))
+            }
+        }
+        Ok(())
+    }
+}
+
+
+//------------ ClientHello ---------------------------------------------------
+
+/// The fields of a ClientHello we are interested in.
+#[derive(Default)]
+struct ClientHello {
+    server_name: Option<String>,
+    alpn: Vec<String>,
+    versions: Vec<u16>,
+}
+
+impl ClientHello {
+    /// Parses a ClientHello from a TLS record.
+    fn parse(record: &[u8]) -> Option<Self> {
+        let mut record = Parser(record);
+        if record.u8()? != 22 {
+            return None
+        }
+        record.take(2)?;
+        let mut body = record.vec16()?;
+        if body.u8()? != 1 {
+            return None
+        }
+        let len = body.take(3)?;
+        let len = usize::from(len[0]) << 16 | usize::from(len[1]) << 8
+            | usize::from(len[2]);
+        let mut body = Parser(body.take(len)?);
+        let legacy_version = body.u16()?;
+        body.take(32)?;
+        body.vec8()?;
+        body.vec16()?;
+        body.vec8()?;
+
+        let mut res = ClientHello::default();
+        let mut extensions = if body.0.is_empty() {
+            Parser(b"")
+        }
+        else {
+            body.vec16()?
+        };
+        while !extensions.0.is_empty() {
+            let ext_type = extensions.u16()?;
+            let mut data = extensions.vec16()?;
+            match ext_type {
+                0 => {
+                    let mut list = data.vec16()?;
+                    while !list.0.is_empty() {
+                        let name_type = list.u8()?;
+                        let name = list.vec16()?;
+                        if name_type == 0 {
+                            res.server_name = Some(
+                                String::from_utf8_lossy(name.0).into()
+                            );
+                        }
+                    }
+                }
+                16 => {
+                    let mut list = data.vec16()?;
+                    while !list.0.is_empty() {
+                        res.alpn.push(
+                            String::from_utf8_lossy(list.vec8()?.0).into()
+                        );
+                    }
+                }
+                43 => {
+                    let mut list = data.vec8()?;
+                    while !list.0.is_empty() {
+                        res.versions.push(list.u16()?);
+                    }
+                }
+                _ => { }
+            }
+        }
+        if res.versions.is_empty() {
+            res.versions.push(legacy_version);
+        }
+        Some(res)
+    }
+}
+
+
+//------------ Parser --------------------------------------------------------
+
+/// A minimal parser for TLS structures.
+struct Parser<'a>(&'a [u8]);
+
+impl<'a> Parser<'a> {
+    fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        if self.0.len() < len {
+            return None
+        }
+        let (head, tail) = self.0.split_at(len);
+        self.0 = tail;
+        Some(head)
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        self.take(1).map(|data| data[0])
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        self.take(2).map(|data| u16::from_be_bytes([data[0], data[1]]))
+    }
+
+    fn vec8(&mut self) -> Option<Parser<'a>> {
+        let len = self.u8()?;
+        self.take(len.into()).map(Parser)
+    }
+
+    fn vec16(&mut self) -> Option<Parser<'a>> {
+        let len = self.u16()?;
+        self.take(len.into()).map(Parser)
+    }
+}
+
+
+//------------ HelloMismatch -------------------------------------------------
+
+/// A ClientHello didn’t match the expectations.
+#[derive(Clone, Debug)]
+pub struct HelloMismatch(String);
+
+impl fmt::Display for HelloMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for HelloMismatch { }
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Write;
+    use crate::recipe::Recipe;
+    use crate::recipe::core::{be, literal};
+    use crate::recipe::tls::{
+        extension, extensions, handshake, record, vec8, vec16
+    };
+    use crate::stream::{AssertRules, AssertStream, FragmentRule};
+
+    fn client_hello() -> Vec<u8> {
+        record(22, 0x0301, handshake(1, (
+            be(0x0303u16), literal([0u8; 32]), vec8(literal(b"")),
+            vec16(be(0x1301u16)), vec8(be(0u8)),
+            extensions((
+                extension(0, vec16((
+                    be(0u8), vec16(literal("example.com"))
+                ))),
+                extension(16, vec16((
+                    vec8(literal("h2")), vec8(literal("x"))
+                ))),
+                extension(43, vec8((be(0x0304u16), be(0x0303u16)))),
+            ))
+        ))).to_fragment().into_vec()
+    }
+
+    #[test]
+    fn check() {
+        let hello = client_hello();
+        assert_eq!(ClientHelloMatch::record_len(&hello), Some(hello.len()));
+        assert!(ClientHelloMatch {
+            server_name: Some("example.com".into()),
+            alpn: Some(vec!["h2".into(), "x".into()]),
+            versions: Some(vec![0x0304, 0x0303]),
+        }.check(&hello).is_ok());
+        assert_eq!(
+            ClientHelloMatch {
+                server_name: Some("example.org".into()),
+                ..Default::default()
+            }.check(&hello).unwrap_err().to_string(),
+            "server name: expected 'example.org', got 'example.com'"
+        );
+        assert!(ClientHelloMatch::default().check(&hello[..20]).is_err());
+    }
+
+    #[test]
+    fn malformed() {
+        let not_hello = |data: &[u8]| {
+            ClientHelloMatch::default().check(data).unwrap_err().to_string()
+                == "not a ClientHello record"
+        };
+        let mut hello = client_hello();
+        hello[0] = 23;
+        assert!(not_hello(&hello));
+        let mut hello = client_hello();
+        hello[5] = 2;
+        assert!(not_hello(&hello));
+
+        // An extension list longer than the remaining data.
+        let broken = record(22, 0x0301, handshake(1, (
+            be(0x0303u16), literal([0u8; 32]), vec8(literal(b"")),
+            vec16(be(0x1301u16)), vec8(be(0u8)),
+            (be(10u16), extension(0, literal(b""))),
+        ))).to_fragment();
+        assert!(not_hello(&broken));
+        assert!(not_hello(b""));
+        assert_eq!(
+            ClientHelloMatch::record_len(b"\x16\x03\x01\x00"), None
+        );
+    }
+
+    #[test]
+    fn without_extensions() {
+        let hello = record(22, 0x0301, handshake(1, (
+            be(0x0303u16), literal([0u8; 32]), vec8(literal(b"")),
+            vec16(be(0x1301u16)), vec8(be(0u8)),
+        ))).to_fragment();
+        assert!(ClientHelloMatch {
+            server_name: Some(String::new()),
+            alpn: Some(Vec::new()),
+            versions: Some(vec![0x0303]),
+        }.check(&hello).is_ok());
+        assert_eq!(
+            ClientHelloMatch {
+                alpn: Some(vec!["h2".into()]),
+                ..Default::default()
+            }.check(&hello).unwrap_err().to_string(),
+            "ALPN: expected [\"h2\"], got []"
+        );
+        assert_eq!(
+            ClientHelloMatch {
+                versions: Some(vec![0x0304]),
+                ..Default::default()
+            }.check(&hello).unwrap_err().to_string(),
+            "versions: expected [0304], got [0303]"
+        );
+    }
+
+    #[test]
+    fn stream() {
+        let mut hello = client_hello();
+        let mut stream = AssertStream::new(AssertRules {
+            fragments: vec![
+                FragmentRule::SendClientHello(ClientHelloMatch {
+                    alpn: Some(vec!["h2".into(), "x".into()]),
+                    ..Default::default()
+                }),
+                FragmentRule::Send(b"end".to_vec()),
+            ]
+        });
+        let len = hello.len();
+        hello.extend_from_slice(b"end");
+        assert_eq!(stream.write(&hello[..3]).unwrap(), 3);
+        assert_eq!(stream.write(&hello[3..]).unwrap(), len - 3);
+        stream.write_all(&hello[len..]).unwrap();
+    }
+
+    #[test]
+    #[should_panic(expected = "ClientHello mismatch in rule 0: versions")]
+    fn stream_mismatch() {
+        let mut stream = AssertStream::new(AssertRules {
+            fragments: vec![
+                FragmentRule::SendClientHello(ClientHelloMatch {
+                    versions: Some(vec![0x0304]),
+                    ..Default::default()
+                }),
+            ]
+        });
+        stream.write_all(&client_hello()).unwrap();
+    }
+}
@@ -0,0 +1,220 @@
+//! A textual transcript format for rules.
+//!
+//! Next to RON, rules can be written in a format that resembles the way
+//! conversations are usually transcribed from specifications or packet
+//! captures:
+//!
+//! ```text
+//! # ClientHello
+//! >> 16 03 01 00 05
+//!    01 00 00 01 00
+//! # ServerHello
+//! << 16 03 03 00 02 0203
+//! << close
+//! ```
+//!
+//! As with the rules themselves, the direction is seen from the protocol
+//! implementation: a line starting with `>>` starts data the
+//! implementation sends and becomes a `SendAll(_)` rule, a line starting
+//! with `<<` starts data it receives and becomes a `RecvAll(_)` rule. The
+//! data is given in hex with optional white space between octets. Lines
+//! starting with white space continue the data of the previous line. The
+//! word `close` instead of data results in a `SendClose` or `RecvClose`
+//! rule, respectively. Everything after a `#` is a comment.
+
+use std::fmt;
+use std::fmt::Write as _;
+use super::{AssertRules, FragmentRule};
+
+
+//------------ parse ---------------------------------------------------------
+
+/// Parses rules from a transcript.
+pub fn parse(s: &str) -> Result<AssertRules, TranscriptError> {
+    let mut fragments = Vec::new();
+    let mut open = false;
+    for (idx, line) in s.lines().enumerate() {
+        let err = |msg| TranscriptError { line: idx + 1, msg };
+        let line = match line.find('#') {
+            Some(pos) => &line[..pos],
+            None => line
+        }.trim_end();
+        if line.trim_start().is_empty() {
+            continue
+        }
+        let (send, rest) = if let Some(rest) = line.strip_prefix(">>") {
+            (true, rest.trim())
+        }
+        else if let Some(rest) = line.strip_prefix("<<") {
+            (false, rest.trim())
+        }
+        else if line.starts_with(char::is_whitespace) {
+            let data = match fragments.last_mut() {
+                Some(FragmentRule::SendAll(ref mut data)) |
+                Some(FragmentRule::RecvAll(ref mut data)) if open => data,
+                _ => return Err(err("continuation without data line")),
+            };
+            parse_hex(line, data).map_err(err)?;
+            continue
+        }
+        else {
+            return Err(err("expected '>>' or '<<'"))
+        };
+        if rest.eq_ignore_ascii_case("close") {
+            fragments.push(
+                if send { FragmentRule::SendClose }
+                else { FragmentRule::RecvClose }
+            );
+            open = false;
+        }
+        else {
+            let mut data = Vec::new();
+            parse_hex(rest, &mut data).map_err(err)?;
+            fragments.push(
+                if send { FragmentRule::SendAll(data) }
+                else { FragmentRule::RecvAll(data) }
+            );
+            open = true;
+        }
+    }
+    Ok(AssertRules { fragments })
+}
+
+/// Parses hex data and appends it to a vec.
+fn parse_hex(s: &str, target: &mut Vec<u8>) -> Result<(), &'static str> {
+    let mut digits = s.chars().filter(|ch| !ch.is_whitespace()).map(|ch| {
+        ch.to_digit(16).map(|digit| digit as u8).ok_or("invalid hex digit")
+    });
+    while let Some(high) = digits.next() {
+        let low = digits.next().ok_or("odd number of hex digits")?;
+        target.push((high? << 4) | low?);
+    }
+    Ok(())
+}
+
+
+//------------ format --------------------------------------------------------
+
+/// Formats rules as a transcript.
+///
+/// Returns an error if the rules contain a rule that cannot be expressed
+/// in the transcript format. All of `Send(_)`, `SendAll(_)`, and
+/// `SendFirst(_)` as well as `Recv(_)` and `RecvAll(_)` are written the
+/// same way, so they can’t be told apart any more when parsed.
+pub fn format(rules: &AssertRules) -> Result<String, TranscriptError> {
+    let mut res = String::new();
+    for (idx, rule) in rules.fragments.iter().enumerate() {
+        match *rule {
+            FragmentRule::Send(ref data) | FragmentRule::SendAll(ref data) |
+            FragmentRule::SendFirst(ref data) => {
+                format_data(">>", data, &mut res)
+            }
+            FragmentRule::Recv(ref data) | FragmentRule::RecvAll(ref data) => {
+                format_data("<<", data, &mut res)
+            }
+            FragmentRule::SendClose => res.push_str(">> close\n"),
+            FragmentRule::RecvClose => res.push_str("<< close\n"),
+            _ => {
+                return Err(TranscriptError {
+                    line: idx + 1, msg: "rule not supported in transcript"
+                })
+            }
+        }
+    }
+    Ok(res)
+}
+
+/// Formats data with sixteen octets per line.
+fn format_data(prefix: &str, data: &[u8], target: &mut String) {
+    target.push_str(prefix);
+    if data.is_empty() {
+        target.push('\n');
+    }
+    for (idx, chunk) in data.chunks(16).enumerate() {
+        if idx > 0 {
+            target.push_str("  ");
+        }
+        for octet in chunk {
+            write!(target, " {:02x}", octet).unwrap();
+        }
+        target.push('\n');
+    }
+}
+
+
+//------------ TranscriptError -----------------------------------------------
+
+/// A transcript could not be parsed or formatted.
+///
+/// When parsing, the error refers to a line of the transcript. When
+/// formatting, it refers to a rule, counting from one.
+#[derive(Clone, Debug)]
+pub struct TranscriptError {
+    line: usize,
+    msg: &'static str,
+}
+
+impl fmt::Display for TranscriptError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.msg)
+    }
+}
+
+impl std::error::Error for TranscriptError { }
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let rules = parse(
+            "# hello\n\
+             >> 16 03 01 # record\n\
+             \x20  0000\n\
+             << 1603\n\
+             \n\
+             << CLOSE\n"
+        ).unwrap();
+        assert!(matches!(
+            rules.fragments.as_slice(),
+            [
+                FragmentRule::SendAll(ref send),
+                FragmentRule::RecvAll(ref recv),
+                FragmentRule::RecvClose,
+            ] if send == b"\x16\x03\x01\x00\x00" && recv == b"\x16\x03"
+        ));
+        let text = format(&AssertRules {
+            fragments: vec![
+                FragmentRule::Send((0..18).collect()),
+                FragmentRule::Recv(Vec::new()),
+                FragmentRule::SendClose,
+            ]
+        }).unwrap();
+        assert_eq!(
+            text,
+            ">> 00 01 02 03 04 05 06 07 08 09 0a 0b 0c 0d 0e 0f\n   \
+             10 11\n\
+             <<\n\
+             >> close\n"
+        );
+        assert_eq!(format(&parse(&text).unwrap()).unwrap(), text);
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(
+            parse(">> 01\n<< 0").unwrap_err().to_string(),
+            "line 2: odd number of hex digits"
+        );
+        assert_eq!(
+            parse("  01").unwrap_err().to_string(),
+            "line 1: continuation without data line"
+        );
+        assert!(parse("01").is_err());
+        assert!(parse(">> close\n  01").is_err());
+    }
+}
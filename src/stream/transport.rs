@@ -0,0 +1,265 @@
+//! Running scripted conversations over real transports.
+//!
+//! An [`AssertStream`] is normally handed directly to the protocol
+//! implementation under test. Black-box implementations, such as
+//! external binaries, need a real file descriptor instead. A [`Pump`]
+//! provides this by running the conversation in a background thread,
+//! moving data between an OS level [`Transport`] and the stream.
+//!
+//! Transports are provided for TCP and, on Unix systems, for Unix domain
+//! sockets. There are no adapters for Windows named pipes as the standard
+//! library offers no API for them.
+//!
+//! Because a stream transport doesn’t preserve packet boundaries, the
+//! data sent by the implementation should be matched by rules that don’t
+//! rely on them, i.e., `SendAll(_)` rather than `Send(_)`.
+
+use std::{io, thread};
+use std::io::{Read, Write};
+use std::net::{Shutdown, TcpListener, TcpStream};
+use super::{AssertStream, FragmentRule};
+#[cfg(unix)]
+use std::os::unix::net::{UnixListener, UnixStream};
+#[cfg(unix)]
+use std::path::Path;
+
+
+//------------ Transport -----------------------------------------------------
+
+/// An OS level transport that can carry a conversation.
+pub trait Transport: Read + Write + Send + 'static {
+    /// Shuts down the transport in the given direction.
+    fn shutdown(&self, how: Shutdown) -> Result<(), io::Error>;
+}
+
+impl Transport for TcpStream {
+    fn shutdown(&self, how: Shutdown) -> Result<(), io::Error> {
+        TcpStream::shutdown(self, how)
+    }
+}
+
+#[cfg(unix)]
+impl Transport for UnixStream {
+    fn shutdown(&self, how: Shutdown) -> Result<(), io::Error> {
+        UnixStream::shutdown(self, how)
+    }
+}
+
+
+//------------ Pump ----------------------------------------------------------
+
+/// A conversation running over a transport in a background thread.
+///
+/// The thread reads data the implementation sends from the transport and
+/// writes it to the stream whenever the current rule expects a send, and
+/// writes data read from the stream to the transport otherwise. It ends
+/// once all rules have been processed, after a `RecvClose` rule, or
+/// after the connection was reset. For a `RecvClose` rule and a reset,
+/// the transport is shut down.
+///
+/// If the conversation fails, the thread panics. The panic is passed on
+/// by [`join`][Self::join].
+pub struct Pump {
+    handle: thread::JoinHandle<AssertStream>,
+}
+
+impl Pump {
+    /// Starts running a conversation over a transport.
+    pub fn spawn(stream: AssertStream, transport: impl Transport) -> Self {
+        Pump {
+            handle: thread::spawn(move || run(stream, transport))
+        }
+    }
+
+    /// Starts a conversation with the first connection to a listener.
+    ///
+    /// The connection is accepted in the background thread.
+    pub fn accept_tcp(stream: AssertStream, listener: TcpListener) -> Self {
+        Pump {
+            handle: thread::spawn(move || {
+                match listener.accept() {
+                    Ok((sock, _)) => run(stream, sock),
+                    Err(err) => stream.fail(format_args!(
+                        "failed to accept connection: {}", err
+                    ))
+                }
+            })
+        }
+    }
+
+    /// Creates a connected Unix socket pair running a conversation.
+    ///
+    /// Returns the socket for the implementation under test and the
+    /// pump running the conversation over the other socket.
+    #[cfg(unix)]
+    pub fn unix_pair(
+        stream: AssertStream
+    ) -> Result<(UnixStream, Self), io::Error> {
+        let (sock, peer) = UnixStream::pair()?;
+        Ok((sock, Self::spawn(stream, peer)))
+    }
+
+    /// Listens on a Unix socket and runs a conversation over it.
+    ///
+    /// Binds a listener to _path_ and runs the conversation with the
+    /// first connection. The socket file is not removed afterwards.
+    #[cfg(unix)]
+    pub fn listen_unix(
+        stream: AssertStream, path: impl AsRef<Path>
+    ) -> Result<Self, io::Error> {
+        let listener = UnixListener::bind(path)?;
+        Ok(Pump {
+            handle: thread::spawn(move || {
+                match listener.accept() {
+                    Ok((sock, _)) => run(stream, sock),
+                    Err(err) => stream.fail(format_args!(
+                        "failed to accept connection: {}", err
+                    ))
+                }
+            })
+        })
+    }
+
+    /// Returns whether the conversation has ended.
+    pub fn is_finished(&self) -> bool {
+        self.handle.is_finished()
+    }
+
+    /// Waits for the conversation to end and returns the stream.
+    ///
+    /// # Panics
+    ///
+    /// If the conversation failed, the panic of the background thread is
+    /// resumed.
+    pub fn join(self) -> AssertStream {
+        match self.handle.join() {
+            Ok(stream) => stream,
+            Err(err) => std::panic::resume_unwind(err),
+        }
+    }
+}
+
+
+//------------ run -----------------------------------------------------------
+
+/// Runs a conversation over a transport.
+fn run(
+    mut stream: AssertStream, mut transport: impl Transport
+) -> AssertStream {
+    use io::ErrorKind::{ConnectionReset, WouldBlock};
+
+    let mut buf = vec![0; 16384];
+    loop {
+        match stream.rules.fragments.get(stream.rule_index) {
+            None => break,
+            Some(FragmentRule::RecvClose) => {
+                let _ = transport.shutdown(Shutdown::Write);
+                break
+            }
+            Some(FragmentRule::SendClose) => {
+                let len = recv(&stream, &mut transport, &mut buf);
+                if len == 0 {
+                    let _ = stream.shutdown();
+                    break
+                }
+                // This fails with the appropriate message.
+                let _ = stream.write(&buf[..len]);
+            }
+            _ => {
+                match stream.read(&mut buf) {
+                    Ok(len) => {
+                        if let Err(err) = transport.write_all(&buf[..len]) {
+                            stream.fail(format_args!(
+                                "failed to write to transport: {}", err
+                            ))
+                        }
+                    }
+                    Err(err) if err.kind() == WouldBlock => {
+                        let len = recv(&stream, &mut transport, &mut buf);
+                        if len == 0 {
+                            stream.fail("unexpected close by implementation")
+                        }
+                        match stream.write_all(&buf[..len]) {
+                            Ok(()) => { }
+                            Err(err) if err.kind() == ConnectionReset => {
+                                let _ = transport.shutdown(Shutdown::Both);
+                                break
+                            }
+                            Err(err) => {
+                                stream.fail(format_args!(
+                                    "write failed: {}", err
+                                ))
+                            }
+                        }
+                    }
+                    Err(err) if err.kind() == ConnectionReset => {
+                        let _ = transport.shutdown(Shutdown::Both);
+                        break
+                    }
+                    Err(err) => {
+                        stream.fail(format_args!("read failed: {}", err))
+                    }
+                }
+            }
+        }
+    }
+    stream
+}
+
+/// Receives data from the transport.
+fn recv(
+    stream: &AssertStream, transport: &mut impl Transport, buf: &mut [u8]
+) -> usize {
+    loop {
+        match transport.read(buf) {
+            Ok(len) => return len,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => { }
+            Err(err) => stream.fail(format_args!(
+                "failed to read from transport: {}", err
+            ))
+        }
+    }
+}
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::stream::AssertRules;
+
+    fn rules() -> AssertStream {
+        AssertStream::new(AssertRules {
+            fragments: vec![
+                FragmentRule::SendAll(b"ping".to_vec()),
+                FragmentRule::Recv(b"pong".to_vec()),
+                FragmentRule::SendClose,
+            ]
+        })
+    }
+
+    #[test]
+    fn tcp() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let pump = Pump::accept_tcp(rules(), listener);
+        let mut sock = TcpStream::connect(addr).unwrap();
+        sock.write_all(b"pi").unwrap();
+        sock.write_all(b"ng").unwrap();
+        let mut buf = [0u8; 4];
+        sock.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"pong");
+        sock.shutdown(Shutdown::Write).unwrap();
+        assert_eq!(pump.join().recorded_rules().fragments.len(), 2);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    #[should_panic(expected = "data mismatch in rule 0")]
+    fn unix_mismatch() {
+        let (mut sock, pump) = Pump::unix_pair(rules()).unwrap();
+        sock.write_all(b"pang").unwrap();
+        pump.join();
+    }
+}
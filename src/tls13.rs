@@ -0,0 +1,631 @@
+//! Hooks for the TLS 1.3 key schedule.
+//!
+//! Scripts for TLS conversations typically control every byte of the
+//! plaintext handshake. After the ServerHello, however, everything is
+//! encrypted with keys derived from the key exchange. This module allows
+//! computing these keys from a scripted ClientHello and ServerHello if the
+//! private keys of the key shares are known so that scripts can contain
+//! correctly encrypted records.
+//!
+//! Only the X25519 key exchange and the TLS_AES_128_GCM_SHA256 cipher
+//! suite are supported. The handshake traffic secrets and the first
+//! application traffic secrets are derived, key updates are not
+//! supported.
+//!
+//! The derived secrets can be exported in the key log format understood
+//! by Wireshark, so that captures of scripted conversations can be
+//! decrypted. See [`HandshakeSecrets::write_keylog`] and
+//! [`ApplicationSecrets::write_keylog`].
+//!
+//! This module is only available with the `tls13` feature.
+
+use std::{env, io};
+use std::fmt::Write as _;
+use std::fs::OpenOptions;
+use std::io::Write as _;
+use aes_gcm::{Aes128Gcm, KeyInit, Nonce};
+use aes_gcm::aead::{Aead, Payload};
+use hkdf::Hkdf;
+use sha2::{Digest, Sha256};
+use crate::recipe::{Fragment, Recipe};
+
+
+//------------ X25519 --------------------------------------------------------
+
+/// Returns the X25519 public key for a private key.
+///
+/// This is the value to be included in a scripted key share.
+pub fn x25519_public_key(private_key: [u8; 32]) -> [u8; 32] {
+    let secret = x25519_dalek::StaticSecret::from(private_key);
+    x25519_dalek::PublicKey::from(&secret).to_bytes()
+}
+
+/// Returns the X25519 shared secret.
+///
+/// The shared secret can be calculated from either side’s private key and
+/// the other side’s public key.
+pub fn x25519_shared_secret(
+    private_key: [u8; 32], peer_public_key: [u8; 32]
+) -> [u8; 32] {
+    let secret = x25519_dalek::StaticSecret::from(private_key);
+    secret.diffie_hellman(
+        &x25519_dalek::PublicKey::from(peer_public_key)
+    ).to_bytes()
+}
+
+
+//------------ HandshakeSecrets ----------------------------------------------
+
+/// The secrets of the handshake stage of the TLS 1.3 key schedule.
+///
+/// The secrets are derived as described in section 7.1 of RFC 8446 without
+/// a pre-shared key.
+#[derive(Clone, Debug)]
+pub struct HandshakeSecrets {
+    /// The handshake secret.
+    pub handshake_secret: [u8; 32],
+
+    /// The client handshake traffic secret.
+    pub client_handshake_traffic_secret: [u8; 32],
+
+    /// The server handshake traffic secret.
+    pub server_handshake_traffic_secret: [u8; 32],
+}
+
+impl HandshakeSecrets {
+    /// Derives the secrets from the shared secret and the hello messages.
+    ///
+    /// The two messages need to be given as complete handshake messages,
+    /// i.e., including the handshake header but without the record header.
+    pub fn new(
+        shared_secret: &[u8], client_hello: &[u8], server_hello: &[u8]
+    ) -> Self {
+        let mut hash = Sha256::new();
+        hash.update(client_hello);
+        hash.update(server_hello);
+        Self::from_transcript_hash(shared_secret, &hash.finalize().into())
+    }
+
+    /// Derives the secrets from the shared secret and the transcript hash.
+    ///
+    /// The transcript hash is the SHA-256 hash of the ClientHello and
+    /// ServerHello messages.
+    pub fn from_transcript_hash(
+        shared_secret: &[u8], transcript_hash: &[u8; 32]
+    ) -> Self {
+        let (early_secret, _) = Hkdf::<Sha256>::extract(
+            Some(&[0; 32]), &[0; 32]
+        );
+        let derived = derive_secret(
+            &early_secret, b"derived", &Sha256::digest(b"").into()
+        );
+        let (handshake_secret, _) = Hkdf::<Sha256>::extract(
+            Some(&derived), shared_secret
+        );
+        let handshake_secret: [u8; 32] = handshake_secret.into();
+        HandshakeSecrets {
+            client_handshake_traffic_secret: derive_secret(
+                &handshake_secret, b"c hs traffic", transcript_hash
+            ),
+            server_handshake_traffic_secret: derive_secret(
+                &handshake_secret, b"s hs traffic", transcript_hash
+            ),
+            handshake_secret,
+        }
+    }
+
+    /// Returns the keys for records sent by the client.
+    pub fn client_keys(&self) -> TrafficKeys {
+        TrafficKeys::from_secret(&self.client_handshake_traffic_secret)
+    }
+
+    /// Returns the keys for records sent by the server.
+    pub fn server_keys(&self) -> TrafficKeys {
+        TrafficKeys::from_secret(&self.server_handshake_traffic_secret)
+    }
+
+    /// Derives the secrets of the application stage.
+    ///
+    /// The _messages_ need to be all handshake messages from the
+    /// ClientHello up to and including the server’s Finished message,
+    /// each including its handshake header.
+    pub fn application_secrets(&self, messages: &[u8]) -> ApplicationSecrets {
+        ApplicationSecrets::from_transcript_hash(
+            &self.handshake_secret, &Sha256::digest(messages).into()
+        )
+    }
+
+    /// Returns the secrets in key log format.
+    ///
+    /// The connection is identified by the _client_random_ of the
+    /// ClientHello. The result contains one line for each of the two
+    /// handshake traffic secrets.
+    pub fn keylog(&self, client_random: &[u8; 32]) -> String {
+        keylog(client_random, [
+            (
+                "CLIENT_HANDSHAKE_TRAFFIC_SECRET",
+                &self.client_handshake_traffic_secret
+            ),
+            (
+                "SERVER_HANDSHAKE_TRAFFIC_SECRET",
+                &self.server_handshake_traffic_secret
+            ),
+        ])
+    }
+
+    /// Appends the secrets to the key log file if one is configured.
+    ///
+    /// As with common TLS libraries, the key log file is configured via
+    /// the environment variable named by [`KEYLOG_VAR`]. If the variable
+    /// isn’t set, nothing happens.
+    pub fn write_keylog(
+        &self, client_random: &[u8; 32]
+    ) -> Result<(), io::Error> {
+        write_keylog(&self.keylog(client_random))
+    }
+}
+
+
+//------------ ApplicationSecrets --------------------------------------------
+
+/// The secrets of the application stage of the TLS 1.3 key schedule.
+///
+/// The secrets are derived from the handshake secret as described in
+/// section 7.1 of RFC 8446. Values are usually created via
+/// [`HandshakeSecrets::application_secrets`].
+#[derive(Clone, Debug)]
+pub struct ApplicationSecrets {
+    /// The master secret.
+    pub master_secret: [u8; 32],
+
+    /// The first client application traffic secret.
+    pub client_application_traffic_secret_0: [u8; 32],
+
+    /// The first server application traffic secret.
+    pub server_application_traffic_secret_0: [u8; 32],
+}
+
+impl ApplicationSecrets {
+    /// Derives the secrets from the handshake secret and transcript hash.
+    ///
+    /// The transcript hash is the SHA-256 hash of all handshake messages
+    /// from the ClientHello up to and including the server’s Finished
+    /// message.
+    pub fn from_transcript_hash(
+        handshake_secret: &[u8; 32], transcript_hash: &[u8; 32]
+    ) -> Self {
+        let derived = derive_secret(
+            handshake_secret, b"derived", &Sha256::digest(b"").into()
+        );
+        let (master_secret, _) = Hkdf::<Sha256>::extract(
+            Some(&derived), &[0; 32]
+        );
+        let master_secret: [u8; 32] = master_secret.into();
+        ApplicationSecrets {
+            client_application_traffic_secret_0: derive_secret(
+                &master_secret, b"c ap traffic", transcript_hash
+            ),
+            server_application_traffic_secret_0: derive_secret(
+                &master_secret, b"s ap traffic", transcript_hash
+            ),
+            master_secret,
+        }
+    }
+
+    /// Returns the keys for records sent by the client.
+    pub fn client_keys(&self) -> TrafficKeys {
+        TrafficKeys::from_secret(&self.client_application_traffic_secret_0)
+    }
+
+    /// Returns the keys for records sent by the server.
+    pub fn server_keys(&self) -> TrafficKeys {
+        TrafficKeys::from_secret(&self.server_application_traffic_secret_0)
+    }
+
+    /// Returns the secrets in key log format.
+    ///
+    /// The connection is identified by the _client_random_ of the
+    /// ClientHello. The result contains one line for each of the two
+    /// application traffic secrets.
+    pub fn keylog(&self, client_random: &[u8; 32]) -> String {
+        keylog(client_random, [
+            (
+                "CLIENT_TRAFFIC_SECRET_0",
+                &self.client_application_traffic_secret_0
+            ),
+            (
+                "SERVER_TRAFFIC_SECRET_0",
+                &self.server_application_traffic_secret_0
+            ),
+        ])
+    }
+
+    /// Appends the secrets to the key log file if one is configured.
+    ///
+    /// The key log file is configured the same way as for
+    /// [`HandshakeSecrets::write_keylog`].
+    pub fn write_keylog(
+        &self, client_random: &[u8; 32]
+    ) -> Result<(), io::Error> {
+        write_keylog(&self.keylog(client_random))
+    }
+}
+
+
+//------------ KEYLOG_VAR ----------------------------------------------------
+
+/// The environment variable with the path of the key log file.
+pub const KEYLOG_VAR: &str = "SSLKEYLOGFILE";
+
+
+//------------ TrafficKeys ---------------------------------------------------
+
+/// The key and IV for protecting records in one direction.
+#[derive(Clone, Debug)]
+pub struct TrafficKeys {
+    /// The AES-128-GCM key.
+    pub key: [u8; 16],
+
+    /// The static part of the nonce.
+    pub iv: [u8; 12],
+}
+
+impl TrafficKeys {
+    /// Derives the keys from a traffic secret.
+    pub fn from_secret(secret: &[u8; 32]) -> Self {
+        let mut key = [0; 16];
+        let mut iv = [0; 12];
+        expand_label(secret, b"key", b"", &mut key);
+        expand_label(secret, b"iv", b"", &mut iv);
+        TrafficKeys { key, iv }
+    }
+
+    /// Returns the nonce for the record with the given sequence number.
+    pub fn nonce(&self, seq: u64) -> [u8; 12] {
+        let mut res = self.iv;
+        for (nonce, seq) in res[4..].iter_mut().zip(seq.to_be_bytes()) {
+            *nonce ^= seq
+        }
+        res
+    }
+
+    /// Returns a complete encrypted record.
+    ///
+    /// The record is the TLSCiphertext including the record header for the
+    /// given _plaintext_ of the given inner _content_type_ protected with
+    /// the given sequence number. No padding is added.
+    pub fn encrypt_record(
+        &self, seq: u64, content_type: u8, plaintext: &[u8]
+    ) -> Vec<u8> {
+        let mut inner = Vec::with_capacity(plaintext.len() + 1);
+        inner.extend_from_slice(plaintext);
+        inner.push(content_type);
+        let len = u16::try_from(inner.len() + 16).expect("record too long");
+        let mut res = vec![0x17, 0x03, 0x03];
+        res.extend_from_slice(&len.to_be_bytes());
+        let cipher = Aes128Gcm::new(&self.key.into());
+        let ciphertext = cipher.encrypt(
+            Nonce::from_slice(&self.nonce(seq)),
+            Payload { msg: &inner, aad: &res }
+        ).expect("encryption failed");
+        res.extend_from_slice(&ciphertext);
+        res
+    }
+
+    /// Returns a recipe for an encrypted record.
+    ///
+    /// The plaintext of the record is assembled from the _content_ recipe
+    /// each time the recipe is assembled.
+    pub fn record<R: Recipe>(
+        &self, seq: u64, content_type: u8, content: R
+    ) -> EncryptedRecord<R> {
+        EncryptedRecord {
+            keys: self.clone(), seq, content_type, content
+        }
+    }
+}
+
+
+//------------ EncryptedRecord -----------------------------------------------
+
+/// A recipe for an encrypted record.
+///
+/// Values of this type are created via [`TrafficKeys::record`].
+pub struct EncryptedRecord<R> {
+    keys: TrafficKeys,
+    seq: u64,
+    content_type: u8,
+    content: R,
+}
+
+impl<R: Recipe> Recipe for EncryptedRecord<R> {
+    fn assemble(&self, target: &mut Fragment) {
+        let content = target.assemble_child(&self.content);
+        target.extend_from_slice(
+            &self.keys.encrypt_record(self.seq, self.content_type, &content)
+        )
+    }
+}
+
+
+//------------ Helpers -------------------------------------------------------
+
+/// Implements HKDF-Expand-Label from section 7.1 of RFC 8446.
+fn expand_label(secret: &[u8], label: &[u8], context: &[u8], out: &mut [u8]) {
+    let mut info = Vec::with_capacity(4 + 6 + label.len() + context.len());
+    info.extend_from_slice(&(out.len() as u16).to_be_bytes());
+    info.push((label.len() + 6) as u8);
+    info.extend_from_slice(b"tls13 ");
+    info.extend_from_slice(label);
+    info.push(context.len() as u8);
+    info.extend_from_slice(context);
+    Hkdf::<Sha256>::from_prk(secret).expect("short PRK").expand(
+        &info, out
+    ).expect("output too long")
+}
+
+/// Returns key log lines for the given secrets.
+fn keylog<'a>(
+    client_random: &[u8; 32],
+    secrets: impl IntoIterator<Item = (&'a str, &'a [u8; 32])>
+) -> String {
+    let mut res = String::new();
+    for (label, secret) in secrets {
+        res.push_str(label);
+        res.push(' ');
+        client_random.iter().for_each(|ch| {
+            write!(res, "{:02x}", ch).unwrap()
+        });
+        res.push(' ');
+        secret.iter().for_each(|ch| write!(res, "{:02x}", ch).unwrap());
+        res.push('\n');
+    }
+    res
+}
+
+/// Appends key log lines to the key log file if one is configured.
+fn write_keylog(lines: &str) -> Result<(), io::Error> {
+    let path = match env::var_os(KEYLOG_VAR) {
+        Some(path) => path,
+        None => return Ok(())
+    };
+    OpenOptions::new().create(true).append(true).open(path)?
+        .write_all(lines.as_bytes())
+}
+
+/// Implements Derive-Secret from section 7.1 of RFC 8446.
+fn derive_secret(
+    secret: &[u8], label: &[u8], transcript_hash: &[u8; 32]
+) -> [u8; 32] {
+    let mut res = [0; 32];
+    expand_label(secret, label, transcript_hash, &mut res);
+    res
+}
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn from_hex(s: &str) -> Vec<u8> {
+        crate::recipe::core::hex(s).to_fragment().as_slice().into()
+    }
+
+    // Values from the simple 1-RTT handshake in section 3 of RFC 8448.
+
+    #[test]
+    fn rfc8448_shared_secret() {
+        let client_private = from_hex(
+            "49af42ba7f7994852d713ef2784bcbcaa7911de26adc5642cb634540e7ea5005"
+        );
+        let server_public = from_hex(
+            "c9828876112095fe66762bdbf7c672e156d6cc253b833df1dd69b1b04e751f0f"
+        );
+        assert_eq!(
+            x25519_shared_secret(
+                client_private.try_into().unwrap(),
+                server_public.try_into().unwrap()
+            ).as_ref(),
+            from_hex(
+                "8bd4054fb55b9d63fdfbacf9f04b9f0d\
+                 35e6d63f537563efd46272900f89492d"
+            )
+        );
+    }
+
+    #[test]
+    fn rfc8448_handshake_secrets() {
+        let secrets = HandshakeSecrets::from_transcript_hash(
+            &from_hex(
+                "8bd4054fb55b9d63fdfbacf9f04b9f0d\
+                 35e6d63f537563efd46272900f89492d"
+            ),
+            &from_hex(
+                "860c06edc07858ee8e78f0e7428c58ed\
+                 d6b43f2ca3e6e95f02ed063cf0e1cad8"
+            ).try_into().unwrap()
+        );
+        assert_eq!(
+            secrets.handshake_secret.as_ref(),
+            from_hex(
+                "1dc826e93606aa6fdc0aadc12f741b01\
+                 046aa6b99f691ed221a9f0ca043fbeac"
+            )
+        );
+        assert_eq!(
+            secrets.client_handshake_traffic_secret.as_ref(),
+            from_hex(
+                "b3eddb126e067f35a780b3abf45e2d8f\
+                 3b1a950738f52e9600746a0e27a55a21"
+            )
+        );
+        assert_eq!(
+            secrets.server_handshake_traffic_secret.as_ref(),
+            from_hex(
+                "b67b7d690cc16c4e75e54213cb2d37b4\
+                 e9c912bcded9105d42befd59d391ad38"
+            )
+        );
+        assert!(secrets.keylog(&[0xab; 32]).starts_with(
+            "CLIENT_HANDSHAKE_TRAFFIC_SECRET abababab"
+        ));
+        assert!(secrets.keylog(&[0xab; 32]).ends_with(
+            "abab b67b7d690cc16c4e75e54213cb2d37b4\
+             e9c912bcded9105d42befd59d391ad38\n"
+        ));
+        let keys = secrets.server_keys();
+        assert_eq!(
+            keys.key.as_ref(), from_hex("3fce516009c21727d0f2e4e86ee403bc")
+        );
+        assert_eq!(
+            keys.iv.as_ref(), from_hex("5d313eb2671276ee13000b30")
+        );
+    }
+
+    // Values from the resumed 0-RTT handshake in section 4 of RFC 8448.
+    // Only the early secret differs with a pre-shared key, so the
+    // application secrets can be derived from its handshake secret.
+
+    #[test]
+    fn rfc8448_application_secrets() {
+        let secrets = ApplicationSecrets::from_transcript_hash(
+            &from_hex(
+                "005cb112fd8eb4ccc623bb88a07c64b3\
+                 ede1605363fc7d0df8c7ce4ff0fb4ae6"
+            ).try_into().unwrap(),
+            &from_hex(
+                "b0aeffc46a2cfe33114e6fd7d51f9f04\
+                 b1ca3c497dab08934a774a9d9ad7dbf3"
+            ).try_into().unwrap()
+        );
+        assert_eq!(
+            secrets.master_secret.as_ref(),
+            from_hex(
+                "e2d32d4ed66dd37897a0e80c84107503\
+                 ce58bf8aad4cb55a5002d77ecb890ece"
+            )
+        );
+        assert_eq!(
+            secrets.client_application_traffic_secret_0.as_ref(),
+            from_hex(
+                "2abbf2b8e381d23dbebe1dd2a7d16a8b\
+                 f484cb4950d23fb7fb7fa8547062d9a1"
+            )
+        );
+        assert_eq!(
+            secrets.server_application_traffic_secret_0.as_ref(),
+            from_hex(
+                "cc21f1bf8feb7dd5fa505bd9c4b468a9\
+                 984d554a993dc49e6d285598fb672691"
+            )
+        );
+        let keylog = secrets.keylog(&[0xab; 32]);
+        assert!(keylog.starts_with("CLIENT_TRAFFIC_SECRET_0 abababab"));
+        assert!(keylog.contains("\nSERVER_TRAFFIC_SECRET_0 abababab"));
+        assert!(keylog.ends_with(
+            "abab cc21f1bf8feb7dd5fa505bd9c4b468a9\
+             984d554a993dc49e6d285598fb672691\n"
+        ));
+    }
+
+    // The encrypted records from section 3 of RFC 8448. The first record
+    // carries the server’s EncryptedExtensions, Certificate,
+    // CertificateVerify, and Finished messages, the second the client’s
+    // Finished message. Both are the first record protected with their
+    // handshake keys.
+
+    #[test]
+    fn rfc8448_encrypt_record() {
+        let secrets = HandshakeSecrets::from_transcript_hash(
+            &from_hex(
+                "8bd4054fb55b9d63fdfbacf9f04b9f0d\
+                 35e6d63f537563efd46272900f89492d"
+            ),
+            &from_hex(
+                "860c06edc07858ee8e78f0e7428c58ed\
+                 d6b43f2ca3e6e95f02ed063cf0e1cad8"
+            ).try_into().unwrap()
+        );
+        let server_plaintext = from_hex(
+            "080000240022000a00140012001d00170018001901000101010201030104001c\
+             00024001000000000b0001b9000001b50001b0308201ac30820115a003020102\
+             020102300d06092a864886f70d01010b0500300e310c300a0603550403130372\
+             7361301e170d3136303733303031323335395a170d3236303733303031323335\
+             395a300e310c300a0603550403130372736130819f300d06092a864886f70d01\
+             0101050003818d0030818902818100b4bb498f8279303d980836399b36c6988c\
+             0c68de55e1bdb826d3901a2461eafd2de49a91d015abbc9a95137ace6c1af19e\
+             aa6af98c7ced43120998e187a80ee0ccb0524b1b018c3e0b63264d449a6d38e2\
+             2a5fda430846748030530ef0461c8ca9d9efbfae8ea6d1d03e2bd193eff0ab9a\
+             8002c47428a6d35a8d88d79f7f1e3f0203010001a31a301830090603551d1304\
+             023000300b0603551d0f0404030205a0300d06092a864886f70d01010b050003\
+             81810085aad2a0e5b9276b908c65f73a7267170618a54c5f8a7b337d2df7a594\
+             365417f2eae8f8a58c8f8172f9319cf36b7fd6c55b80f21a03015156726096fd\
+             335e5e67f2dbf102702e608ccae6bec1fc63a42a99be5c3eb7107c3c54e9b9eb\
+             2bd5203b1c3b84e0a8b2f759409ba3eac9d91d402dcc0cc8f8961229ac9187b4\
+             2b4de100000f000084080400805a747c5d88fa9bd2e55ab085a61015b7211f82\
+             4cd484145ab3ff52f1fda8477b0b7abc90db78e2d33a5c141a078653fa6bef78\
+             0c5ea248eeaaa785c4f394cab6d30bbe8d4859ee511f602957b15411ac027671\
+             459e46445c9ea58c181e818e95b8c3fb0bf3278409d3be152a3da5043e063dda\
+             65cdf5aea20d53dfacd42f74f3140000209b9b141d906337fbd2cbdce71df4de\
+             da4ab42c309572cb7fffee5454b78f0718"
+        );
+        let server_record = from_hex(
+            "17030302a2d1ff334a56f5bff6594a07cc87b580233f500f45e489e7f33af35e\
+             df7869fcf40aa40aa2b8ea73f848a7ca07612ef9f945cb960b4068905123ea78\
+             b111b429ba9191cd05d2a389280f526134aadc7fc78c4b729df828b5ecf7b13b\
+             d9aefb0e57f271585b8ea9bb355c7c79020716cfb9b1183ef3ab20e37d57a6b9\
+             d7477609aee6e122a4cf51427325250c7d0e509289444c9b3a648f1d71035d2e\
+             d65b0e3cdd0cbae8bf2d0b227812cbb360987255cc744110c453baa4fcd61092\
+             8d809810e4b7ed1a8fd991f06aa6248204797e36a6a73b70a2559c09ead68694\
+             5ba246ab66e5edd8044b4c6de3fcf2a89441ac66272fd8fb330ef8190579b368\
+             4596c960bd596eea520a56a8d650f563aad27409960dca63d3e688611ea5e22f\
+             4415cf9538d51a200c27034272968a264ed6540c84838d89f72c24461aad6d26\
+             f59ecaba9acbbb317b66d902f4f292a36ac1b639c637ce343117b65962224531\
+             7b49eeda0c6258f100d7d961ffb138647e92ea330faeea6dfa31c7a84dc3bd7e\
+             1b7a6c7178af36879018e3f252107f243d243dc7339d5684c8b0378bf30244da\
+             8c87c843f5e56eb4c5e8280a2b48052cf93b16499a66db7cca71e4599426f7d4\
+             61e66f99882bd89fc50800becca62d6c74116dbd2972fda1fa80f85df881edbe\
+             5a37668936b335583b599186dc5c6918a396fa48a181d6b6fa4f9d62d513afbb\
+             992f2b992f67f8afe67f76913fa388cb5630c8ca01e0c65d11c66a1e2ac4c859\
+             77b7c7a6999bbf10dc35ae69f5515614636c0b9b68c19ed2e31c0b3b66763038\
+             ebba42f3b38edc0399f3a9f23faa63978c317fc9fa66a73f60f0504de93b5b84\
+             5e275592c12335ee340bbc4fddd502784016e4b3be7ef04dda49f4b440a30cb5\
+             d2af939828fd4ae3794e44f94df5a631ede42c1719bfdabf0253fe5175be898e\
+             750edc53370d2b"
+        );
+        assert_eq!(
+            secrets.server_keys().encrypt_record(0, 0x16, &server_plaintext),
+            server_record
+        );
+
+        let client_finished = from_hex(
+            "14000020a8ec436d677634ae525ac1fc\
+             ebe11a039ec17694fac6e98527b642f2\
+             edd5ce61"
+        );
+        let client_record = from_hex(
+            "170303003575ec4dc238cce60b298044\
+             a71e219c56cc77b0517fe9b93c7a4bfc\
+             44d87f38f80338ac98fc46deb384bd1c\
+             aeacab6867d726c40546"
+        );
+        let keys = secrets.client_keys();
+        assert_eq!(
+            keys.encrypt_record(0, 0x16, &client_finished), client_record
+        );
+        assert_eq!(
+            keys.record(
+                0, 0x16, crate::recipe::core::literal(client_finished)
+            ).to_fragment().as_slice(),
+            client_record
+        );
+        assert_ne!(
+            keys.record(1, 0x16, crate::recipe::core::literal(b""))
+                .to_fragment().as_slice(),
+            keys.record(0, 0x16, crate::recipe::core::literal(b""))
+                .to_fragment().as_slice(),
+        );
+    }
+}